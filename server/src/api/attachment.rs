@@ -0,0 +1,158 @@
+use axum::{
+    extract::{
+        DefaultBodyLimit,
+        Multipart,
+        Path,
+        State
+    },
+    http::HeaderMap,
+    response::{
+        Json,
+        Response
+    },
+    routing::{
+        get,
+        post
+    },
+    Router
+};
+use serde::{
+    Deserialize,
+    Serialize
+};
+use crate::config::Settings;
+use crate::core::{
+    attachments,
+    downloads
+};
+use crate::core::auth::AuthUser;
+use crate::error::AppError;
+use crate::storage::Attachment;
+
+// 共享应用状态
+use super::AppState;
+
+// 附件上传响应
+#[derive(Serialize)]
+pub struct UploadAttachmentResponse {
+    pub success: bool,
+    pub message: String,
+    pub attachment: Attachment,
+}
+
+// 批量查询附件信息请求
+#[derive(Deserialize)]
+pub struct AttachmentInfoRequest {
+    pub attachment_ids: Vec<String>,
+}
+
+// 附件信息响应（只含有权访问的附件）
+#[derive(Serialize)]
+pub struct AttachmentInfoResponse {
+    pub success: bool,
+    pub message: String,
+    pub attachments: Vec<Attachment>,
+}
+
+// 上传附件处理器（multipart 字段名为 file，类型按内容识别）
+pub async fn upload_attachment_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    mut multipart: Multipart,
+) -> Result<Json<UploadAttachmentResponse>, AppError> {
+    while let Some(field) = multipart.next_field().await.map_err(|e| AppError::BadRequest(e.to_string()))? {
+        if field.name() != Some("file") {
+            continue;
+        }
+        let filename = field.file_name().unwrap_or_default().to_string();
+        let attachment = attachments::save_attachment_stream(&state, &auth_user.user_id, &filename, field).await?;
+
+        return Ok(Json(UploadAttachmentResponse {
+            success: true,
+            message: "附件上传成功".into(),
+            attachment,
+        }));
+    }
+
+    Err(AppError::BadRequest("未找到附件文件".into()))
+}
+
+// 批量查询附件信息处理器
+pub async fn get_attachment_info_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Json(req): Json<AttachmentInfoRequest>,
+) -> Result<Json<AttachmentInfoResponse>, AppError> {
+    if req.attachment_ids.len() > state.settings.limits.max_history_page_size {
+        return Err(AppError::BadRequest(format!(
+            "一次最多查询 {} 个附件",
+            state.settings.limits.max_history_page_size
+        )));
+    }
+    let attachments = attachments::attachment_infos(&state, &auth_user.user_id, req.attachment_ids).await?;
+
+    Ok(Json(AttachmentInfoResponse {
+        success: true,
+        message: "获取附件信息成功".into(),
+        attachments,
+    }))
+}
+
+// 下载附件处理器（仅上传者与附件所在会话的参与者）
+pub async fn download_attachment_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Path(attachment_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let (user, id) = (auth_user.user_id.clone(), attachment_id.clone());
+    let attachment = state.db_pool.run(move |db| attachments::load_attachment(db, &user, &id)).await?;
+
+    let path = attachments::attachment_path(&state.settings, &attachment);
+    let download = downloads::Download {
+        content_type: attachment.mime_type.clone(),
+        content_disposition: Some(attachments::content_disposition(&attachment)),
+        cache_control: downloads::CACHE_PRIVATE,
+        not_found: "附件文件不存在",
+    };
+    downloads::serve_file(&headers, &path, download).await
+}
+
+// 获取图片附件缩略图处理器（权限同下载）
+pub async fn attachment_thumbnail_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Path(attachment_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let (user, id) = (auth_user.user_id.clone(), attachment_id.clone());
+    let attachment = state.db_pool.run(move |db| attachments::load_attachment(db, &user, &id)).await?;
+
+    const NOT_FOUND: &str = "该附件没有缩略图";
+    let path = attachments::thumbnail_path(&state.settings, &attachment)
+        .ok_or_else(|| AppError::NotFound(NOT_FOUND.into()))?;
+    // 缩略图为 JPEG 或 PNG（有透明通道时），按文件开头识别
+    let head = downloads::read_head(&path, 16, NOT_FOUND).await?;
+    let download = downloads::Download {
+        content_type: infer::get(&head).map(|t| t.mime_type()).unwrap_or("image/jpeg").to_string(),
+        content_disposition: None,
+        cache_control: downloads::CACHE_PRIVATE,
+        not_found: NOT_FOUND,
+    };
+    downloads::serve_file(&headers, &path, download).await
+}
+
+/// 注册附件相关路由
+pub fn register_routes(settings: &Settings) -> Router<AppState> {
+    // 上传请求体上限按最大的附件类型放宽（预留 multipart 边界开销）
+    let upload_body_limit = attachments::max_upload_bytes(settings) + 64 * 1024;
+
+    Router::new()
+        .route(
+            "/attachments/upload",
+            post(upload_attachment_handler).layer(DefaultBodyLimit::max(upload_body_limit)),
+        )
+        .route("/attachments/info", post(get_attachment_info_handler))
+        .route("/attachments/{attachment_id}", get(download_attachment_handler))
+        .route("/attachments/{attachment_id}/thumbnail", get(attachment_thumbnail_handler))
+}
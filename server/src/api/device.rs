@@ -0,0 +1,111 @@
+use axum::{
+    extract::State,
+    response::Json,
+    routing::post,
+    Router
+};
+use serde::{
+    Deserialize,
+    Serialize
+};
+use crate::error::AppError;
+use crate::storage::StorageError;
+use crate::core::auth::AuthUser;
+
+// 共享应用状态
+use super::AppState;
+
+// 设备管理相关结构体
+
+#[derive(Serialize)]
+pub struct ListDevicesResponse {
+    pub success: bool,
+    pub message: String,
+    pub devices: Vec<DeviceInfo>,
+}
+
+// 一个登录设备（即一个有效的登录会话）
+#[derive(Serialize)]
+pub struct DeviceInfo {
+    pub session_id: String,
+    pub device_name: Option<String>,
+    pub created_at: i64,            // 登录时间戳
+    pub last_used_at: i64,          // 最近一次刷新令牌的时间戳
+    pub online: bool,               // 是否有打开的 WebSocket 连接
+    pub connected_at: Option<i64>,  // 本次上线时间戳（离线为 null）
+    pub current: bool,              // 是否为发起请求的设备
+}
+
+#[derive(Deserialize)]
+pub struct SignOutDeviceRequest {
+    pub session_id: String,
+}
+
+#[derive(Serialize)]
+pub struct SignOutDeviceResponse {
+    pub success: bool,
+    pub message: String,
+}
+
+// 列出当前用户的全部登录设备
+pub async fn list_devices_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+) -> Result<Json<ListDevicesResponse>, AppError> {
+    let user_id = auth_user.user_id.clone();
+    let sessions = state.db_pool.run(move |db| db.get_active_sessions(&user_id)).await?;
+
+    let online = state.online_sessions(&auth_user.user_id);
+    let devices = sessions.into_iter().map(|session| {
+        let connected_at = online.get(&session.id).copied();
+        DeviceInfo {
+            current: session.id == auth_user.session_id,
+            online: connected_at.is_some(),
+            connected_at,
+            session_id: session.id,
+            device_name: session.device_name,
+            created_at: session.created_at,
+            last_used_at: session.last_used_at,
+        }
+    }).collect();
+
+    Ok(Json(ListDevicesResponse {
+        success: true,
+        message: "获取设备列表成功".into(),
+        devices,
+    }))
+}
+
+// 退出指定设备：吊销其会话并断开该设备的全部连接
+pub async fn sign_out_device_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Json(req): Json<SignOutDeviceRequest>,
+) -> Result<Json<SignOutDeviceResponse>, AppError> {
+    // 只能退出自己名下仍然有效的会话
+    let (session_id, user_id) = (req.session_id.clone(), auth_user.user_id.clone());
+    let owned = state.db_pool.run(move |db| {
+        if !db.is_session_active(&session_id, &user_id)? {
+            return Ok(false);
+        }
+        db.revoke_session(&session_id)?;
+        Ok::<_, StorageError>(true)
+    }).await?;
+    if !owned {
+        return Err(AppError::NotFound("设备不存在或已退出登录".into()));
+    }
+
+    state.disconnect_session(&auth_user.user_id, &req.session_id);
+
+    Ok(Json(SignOutDeviceResponse {
+        success: true,
+        message: "设备已退出登录".into(),
+    }))
+}
+
+/// 注册设备管理相关路由
+pub fn register_routes() -> Router<AppState> {
+    Router::new()
+        .route("/devices/list", post(list_devices_handler))
+        .route("/devices/sign-out", post(sign_out_device_handler))
+}
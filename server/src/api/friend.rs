@@ -8,8 +8,11 @@ use serde::{
     Deserialize, 
     Serialize
 };
-use serde_json::{json};
 use crate::error::AppError;
+use crate::storage::StorageError;
+use crate::core::auth::AuthUser;
+use crate::core::protocol::ServerFrame;
+use crate::core::sync;
 
 // 共享应用状态
 use super::AppState;
@@ -36,7 +39,6 @@ pub struct SearchUser {
 
 #[derive(Deserialize)]
 pub struct SendFriendRequestRequest {
-    pub from_user_id: String,
     pub to_username: String,
 }
 
@@ -47,11 +49,6 @@ pub struct SendFriendRequestResponse {
     pub request_id: Option<String>,
 }
 
-#[derive(Deserialize)]
-pub struct GetFriendRequestsRequest {
-    pub user_id: String,
-}
-
 #[derive(Serialize)]
 pub struct GetFriendRequestsResponse {
     pub success: bool,
@@ -70,7 +67,6 @@ pub struct FriendRequestInfo {
 #[derive(Deserialize)]
 pub struct RespondToFriendRequestRequest {
     pub request_id: String,
-    pub user_id: String,
     pub response: String, // "accepted" or "rejected"
 }
 
@@ -81,11 +77,6 @@ pub struct RespondToFriendRequestResponse {
     pub friendship: Option<FriendInfo>,
 }
 
-#[derive(Deserialize)]
-pub struct GetFriendsRequest {
-    pub user_id: String,
-}
-
 #[derive(Serialize)]
 pub struct GetFriendsResponse {
     pub success: bool,
@@ -99,9 +90,23 @@ pub struct FriendInfo {
     pub username: String,
 }
 
+#[derive(Serialize)]
+pub struct GetFriendsPresenceResponse {
+    pub success: bool,
+    pub message: String,
+    pub friends: Vec<FriendPresence>,
+}
+
+#[derive(Serialize)]
+pub struct FriendPresence {
+    pub user_id: String,
+    pub username: String,
+    pub status: String,          // "online"、"away" 或 "offline"
+    pub last_seen: Option<i64>,  // 最近在线时间戳
+}
+
 #[derive(Deserialize)]
 pub struct RemoveFriendRequest {
-    pub user_id: String,
     pub friend_id: String,
 }
 
@@ -114,10 +119,10 @@ pub struct RemoveFriendResponse {
 // 搜索用户
 pub async fn search_users_handler(
     State(state): State<AppState>,
+    _auth_user: AuthUser,
     Json(req): Json<SearchUsersRequest>,
 ) -> Result<Json<SearchUsersResponse>, AppError> {
-    let users = state.db_pool.search_users(&req.query)
-        .map_err(|e| AppError::Database(e.to_string()))?;
+    let users = state.db_pool.run(move |db| db.search_users(&req.query)).await?;
 
     let search_users: Vec<SearchUser> = users.into_iter().map(|user| SearchUser {
         id: user.id,
@@ -134,43 +139,28 @@ pub async fn search_users_handler(
 // 发送好友请求
 pub async fn send_friend_request_handler(
     State(state): State<AppState>,
+    auth_user: AuthUser,
     Json(req): Json<SendFriendRequestRequest>,
 ) -> Result<Json<SendFriendRequestResponse>, AppError> {
-
-    
-    let result = state.db_pool.send_friend_request(&req.from_user_id, &req.to_username)
-        .map_err(|e| {
-            match e {
-                rusqlite::Error::QueryReturnedNoRows => {
-                    AppError::FriendOperation("目标用户不存在".into())
-                }
-                        rusqlite::Error::SqliteFailure(_, Some(msg)) if msg == "Already friends" => {
-                            AppError::FriendOperation("已经是好友".into())
-                        }
-                        rusqlite::Error::SqliteFailure(_, Some(msg)) if msg == "Friend request already sent" => {
-                            AppError::FriendOperation("好友请求已发送".into())
-                        }
-                        // 捕获 SQLite 的唯一约束错误并映射为友好提示
-                        rusqlite::Error::SqliteFailure(_, Some(msg)) if msg.contains("UNIQUE constraint failed") => {
-                            AppError::FriendOperation("好友请求已存在".into())
-                        }
-                _ => AppError::Database(e.to_string())
-            }
+    let result = state.db_pool.run(move |db| db.send_friend_request(&auth_user.user_id, &req.to_username)).await
+        .map_err(|e| match e {
+            StorageError::NotFound => AppError::FriendOperation("目标用户不存在".into()),
+            StorageError::AlreadyFriends => AppError::FriendOperation("已经是好友".into()),
+            StorageError::FriendRequestAlreadySent => AppError::FriendOperation("好友请求已发送".into()),
+            // 唯一约束冲突（曾被拒绝的请求等）映射为友好提示
+            StorageError::Conflict(_) => AppError::FriendOperation("好友请求已存在".into()),
+            e => e.into(),
         })?;
 
-    // 尝试通知接收者（若其已通过 WebSocket 标识并连接）
-    let notify = json! ({
-        "type": "friend_request",
-        "request_id": result.id,
-        "from_user_id": result.from_user_id,
-        "to_user_id": result.to_user_id,
-        "message": "您收到新的好友请求"
-    })
-    .to_string();
+    // 记入接收者的事件流并通知其在线设备
+    let notify = ServerFrame::FriendRequest {
+        friend_request_id: result.id.clone(),
+        from_user_id: result.from_user_id.clone(),
+        to_user_id: result.to_user_id.clone(),
+        message: "您收到新的好友请求".into(),
+    };
 
-    if let Some(tx) = state.get_clients().lock().unwrap().get(&result.to_user_id) {
-        let _ = tx.send(notify);
-    }
+    sync::publish(&state, vec![result.to_user_id.clone()], notify).await;
 
     Ok(Json(SendFriendRequestResponse {
         success: true,
@@ -182,38 +172,26 @@ pub async fn send_friend_request_handler(
 // 获取收到的好友请求
 pub async fn get_friend_requests_handler(
     State(state): State<AppState>,
-    Json(req): Json<GetFriendRequestsRequest>,
+    auth_user: AuthUser,
 ) -> Result<Json<GetFriendRequestsResponse>, AppError> {
-    let requests = state.db_pool.get_received_friend_requests(&req.user_id)
-        .map_err(|e| AppError::Database(e.to_string()))?;
-
-    let mut request_infos: Vec<FriendRequestInfo> = requests.into_iter().map(|req| {
-        // 这里需获取发送者的用户名，联表查询并返回友好名称
-        // 注意：在持有连接锁的情况下按顺序查询用户名，若性能成为问题可改为联表查询一次性获取
-        FriendRequestInfo {
-            id: req.id,
-            from_user_id: req.from_user_id,
-            from_username: "".to_string(), // 占位，后面会替换
-            created_at: req.created_at,
-        }
-    }).collect();
-    // 填充 from_username 字段（从 users 表查找用户名）
-    if !request_infos.is_empty() {
-        let conn = state.db_pool.0.lock().unwrap();
-        for info in request_infos.iter_mut() {
-            let uname: Result<String, rusqlite::Error> = conn.query_row(
-                "SELECT username FROM users WHERE id = ?",
-                [&info.from_user_id],
-                |row| row.get(0),
-            );
-            if let Ok(name) = uname {
-                info.from_username = name;
-            } else {
-                // 若查询失败，保留原始 user_id 以便前端回退显示
-                info.from_username = info.from_user_id.clone();
+    let request_infos = state.db_pool.run(move |db| {
+        let requests = db.get_received_friend_requests(&auth_user.user_id)?;
+
+        // 填充 from_username 字段（从 users 表查找用户名）
+        let request_infos: Vec<FriendRequestInfo> = requests.into_iter().map(|req| {
+            // 若查询失败，保留原始 user_id 以便前端回退显示
+            let from_username = db.get_username(&req.from_user_id)
+                .unwrap_or_else(|_| req.from_user_id.clone());
+            FriendRequestInfo {
+                id: req.id,
+                from_user_id: req.from_user_id,
+                from_username,
+                created_at: req.created_at,
             }
-        }
-    }
+        }).collect();
+        Ok::<_, StorageError>(request_infos)
+    }).await?;
+
     Ok(Json(GetFriendRequestsResponse {
         success: true,
         message: "获取好友请求成功".into(),
@@ -224,68 +202,45 @@ pub async fn get_friend_requests_handler(
 // 响应好友请求
 pub async fn respond_to_friend_request_handler(
     State(state): State<AppState>,
+    auth_user: AuthUser,
     Json(req): Json<RespondToFriendRequestRequest>,
 ) -> Result<Json<RespondToFriendRequestResponse>, AppError> {
     // 调用存储层并获取结果（如果被接受，会返回创建的 Friendship）
-    let friendship = state.db_pool.respond_to_friend_request(&req.request_id, &req.user_id, &req.response)
-        .map_err(|e| {
-            match e {
-                rusqlite::Error::SqliteFailure(_, Some(msg)) if msg == "Friend request already processed" => {
-                    AppError::FriendOperation("好友请求已处理".into())
-                }
-                _ => AppError::Database(e.to_string())
-            }
+    let request_id = req.request_id.clone();
+    let response = req.response.clone();
+    let friendship = state.db_pool.run(move |db| db.respond_to_friend_request(&request_id, &auth_user.user_id, &response)).await
+        .map_err(|e| match e {
+            StorageError::NotFound => AppError::NotFound("好友请求不存在".into()),
+            e => e.into(),
         })?;
 
     let mut friendship_info: Option<FriendInfo> = None;
 
     let message = if req.response == "accepted" {
         // 如果接受，查询双方用户名并通知双方刷新好友列表（若在线）
-        let conn = state.db_pool.0.lock().unwrap();
-        let from_username: String = conn.query_row(
-            "SELECT username FROM users WHERE id = ?",
-            [&friendship.user_id],
-            |row| row.get(0),
-        ).unwrap_or_else(|_| "".to_string());
-
-        let to_username: String = conn.query_row(
-            "SELECT username FROM users WHERE id = ?",
-            [&friendship.friend_id],
-            |row| row.get(0),
-        ).unwrap_or_else(|_| "".to_string());
-
-        let notify = json! ({
-            "type": "friend_added",
-            "user_id": friendship.friend_id,
-            "friend_id": friendship.user_id,
-            "friend_username": from_username,
-            "message": "您已成为好友"
-        })
-        .to_string();
-
-        let reverse_notify = json! ({
-            "type": "friend_added",
-            "user_id": friendship.user_id,
-            "friend_id": friendship.friend_id,
-            "friend_username": to_username,
-            "message": "您已成为好友"
-        })
-        .to_string();
-
-        // 尝试向发送者和接收者发送通知（如果他们通过 websocket 标识并连接）
-        let clients = state.get_clients().lock().unwrap();
-        if let Some(tx) = clients.get(&friendship.friend_id) {
-            println!("Sending friend_added notify to {}: {}", friendship.friend_id, notify);
-            let _ = tx.send(notify.clone());
-        } else {
-            println!("No websocket client for {} when sending notify", friendship.friend_id);
-        }
-        if let Some(tx) = clients.get(&friendship.user_id) {
-            println!("Sending friend_added notify to {}: {}", friendship.user_id, reverse_notify);
-            let _ = tx.send(reverse_notify.clone());
-        } else {
-            println!("No websocket client for {} when sending notify", friendship.user_id);
-        }
+        let (user_id, friend_id) = (friendship.user_id.clone(), friendship.friend_id.clone());
+        let (from_username, to_username) = state.db_pool.run(move |db| (
+            db.get_username(&user_id).unwrap_or_default(),
+            db.get_username(&friend_id).unwrap_or_default(),
+        )).await;
+
+        let notify = ServerFrame::FriendAdded {
+            user_id: friendship.friend_id.clone(),
+            friend_id: friendship.user_id.clone(),
+            friend_username: from_username.clone(),
+            message: "您已成为好友".into(),
+        };
+
+        let reverse_notify = ServerFrame::FriendAdded {
+            user_id: friendship.user_id.clone(),
+            friend_id: friendship.friend_id.clone(),
+            friend_username: to_username,
+            message: "您已成为好友".into(),
+        };
+
+        // 记入双方的事件流并通知其在线设备
+        sync::publish(&state, vec![friendship.friend_id.clone()], notify).await;
+        sync::publish(&state, vec![friendship.user_id.clone()], reverse_notify).await;
 
         // 准备返回的好友信息（用于前端立即更新）——对调用者（接收者）返回对方信息
         friendship_info = Some(FriendInfo { id: friendship.user_id.clone(), username: from_username.clone() });
@@ -304,10 +259,9 @@ pub async fn respond_to_friend_request_handler(
 // 获取好友列表
 pub async fn get_friends_handler(
     State(state): State<AppState>,
-    Json(req): Json<GetFriendsRequest>,
+    auth_user: AuthUser,
 ) -> Result<Json<GetFriendsResponse>, AppError> {
-    let friends = state.db_pool.get_friends(&req.user_id)
-        .map_err(|e| AppError::Database(e.to_string()))?;
+    let friends = state.db_pool.run(move |db| db.get_friends(&auth_user.user_id)).await?;
 
     let friend_infos: Vec<FriendInfo> = friends.into_iter().map(|friend| FriendInfo {
         id: friend.id,
@@ -321,13 +275,34 @@ pub async fn get_friends_handler(
     }))
 }
 
+// 批量获取好友在线状态
+pub async fn get_friends_presence_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+) -> Result<Json<GetFriendsPresenceResponse>, AppError> {
+    let friends = state.db_pool.run(move |db| db.get_friends(&auth_user.user_id)).await?;
+
+    let presences = friends.into_iter().map(|friend| FriendPresence {
+        status: state.presence_of(&friend.id).to_string(),
+        user_id: friend.id,
+        username: friend.username,
+        last_seen: friend.last_seen,
+    }).collect();
+
+    Ok(Json(GetFriendsPresenceResponse {
+        success: true,
+        message: "获取好友在线状态成功".into(),
+        friends: presences,
+    }))
+}
+
 // 删除好友
 pub async fn remove_friend_handler(
     State(state): State<AppState>,
+    auth_user: AuthUser,
     Json(req): Json<RemoveFriendRequest>,
 ) -> Result<Json<RemoveFriendResponse>, AppError> {
-    state.db_pool.remove_friend(&req.user_id, &req.friend_id)
-        .map_err(|e| AppError::Database(e.to_string()))?;
+    state.db_pool.run(move |db| db.remove_friend(&auth_user.user_id, &req.friend_id)).await?;
 
     Ok(Json(RemoveFriendResponse {
         success: true,
@@ -345,5 +320,6 @@ pub fn register_routes() -> Router<AppState> {
         .route("/get-friend-requests", post(get_friend_requests_handler))
         .route("/respond-to-friend-request", post(respond_to_friend_request_handler))
         .route("/get-friends", post(get_friends_handler))
+        .route("/friends/presence", post(get_friends_presence_handler))
         .route("/remove-friend", post(remove_friend_handler))
 }
\ No newline at end of file
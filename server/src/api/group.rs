@@ -0,0 +1,481 @@
+use axum::{
+    extract::{State},
+    response::Json,
+    routing::{post},
+    Router
+};
+use serde::{
+    Deserialize,
+    Serialize
+};
+use crate::error::AppError;
+use crate::core::auth::AuthUser;
+use crate::core::protocol::ServerFrame;
+use crate::core::sync;
+use crate::storage::{
+    Group,
+    Storage,
+    StorageError
+};
+
+// 共享应用状态
+use super::AppState;
+
+/// 群名称最大字符数
+const MAX_GROUP_NAME_CHARS: usize = 50;
+
+// 群聊功能相关结构体
+
+#[derive(Deserialize)]
+pub struct CreateGroupRequest {
+    pub name: String,
+    #[serde(default)]
+    pub member_ids: Vec<String>, // 初始成员（不含创建者）
+}
+
+#[derive(Serialize)]
+pub struct CreateGroupResponse {
+    pub success: bool,
+    pub message: String,
+    pub group: Option<GroupInfo>,
+}
+
+#[derive(Serialize)]
+pub struct GroupInfo {
+    pub id: String,
+    pub name: String,
+    pub creator_id: String,
+    pub created_at: i64,
+    pub role: String, // 当前用户在群中的角色："owner"、"admin"或"member"
+    pub muted: bool,  // 当前用户是否设置了消息免打扰
+}
+
+#[derive(Serialize)]
+pub struct GetGroupsResponse {
+    pub success: bool,
+    pub message: String,
+    pub groups: Vec<GroupInfo>,
+}
+
+// 只携带群ID的请求（查看成员、退出群聊、解散群聊）
+#[derive(Deserialize)]
+pub struct GroupIdRequest {
+    pub group_id: String,
+}
+
+#[derive(Serialize)]
+pub struct GetGroupMembersResponse {
+    pub success: bool,
+    pub message: String,
+    pub members: Vec<GroupMemberInfo>,
+}
+
+#[derive(Serialize)]
+pub struct GroupMemberInfo {
+    pub user_id: String,
+    pub username: String,
+    pub role: String,
+    pub joined_at: i64,
+}
+
+#[derive(Deserialize)]
+pub struct GroupMemberRequest {
+    pub group_id: String,
+    pub user_id: String,
+}
+
+#[derive(Deserialize)]
+pub struct SetMemberRoleRequest {
+    pub group_id: String,
+    pub user_id: String,
+    pub role: String, // "admin" 或 "member"
+}
+
+#[derive(Deserialize)]
+pub struct RenameGroupRequest {
+    pub group_id: String,
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+pub struct MuteGroupRequest {
+    pub group_id: String,
+    pub muted: bool, // 是否免打扰
+}
+
+#[derive(Serialize)]
+pub struct GroupOperationResponse {
+    pub success: bool,
+    pub message: String,
+}
+
+// 校验并规范化群名称
+fn validate_group_name(name: &str) -> Result<String, AppError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(AppError::BadRequest("群名称不能为空".into()));
+    }
+    if name.chars().count() > MAX_GROUP_NAME_CHARS {
+        return Err(AppError::BadRequest(format!("群名称不能超过 {} 个字符", MAX_GROUP_NAME_CHARS)));
+    }
+    Ok(name.to_string())
+}
+
+// 查询群聊及当前用户的角色：群不存在返回 404，不是成员返回 403
+fn require_member(db: &dyn Storage, group_id: &str, user_id: &str) -> Result<(Group, String), AppError> {
+    let group = db.get_group(group_id).map_err(|e| match e {
+        StorageError::NotFound => AppError::NotFound("群聊不存在".into()),
+        e => e.into(),
+    })?;
+    let role = db.get_member_role(group_id, user_id)?
+        .ok_or_else(|| AppError::Forbidden("不是该群成员".into()))?;
+    Ok((group, role))
+}
+
+// 要求当前用户是群主
+fn require_owner(db: &dyn Storage, group_id: &str, user_id: &str) -> Result<Group, AppError> {
+    let (group, role) = require_member(db, group_id, user_id)?;
+    if role != "owner" {
+        return Err(AppError::Forbidden("只有群主可以执行该操作".into()));
+    }
+    Ok(group)
+}
+
+// 成员变动通知（发往群广播通道，所有在线成员都会收到）
+fn members_changed_frame(group_id: &str, action: &'static str, user_id: &str, operator_id: &str) -> ServerFrame {
+    ServerFrame::GroupMembersChanged {
+        group_id: group_id.to_string(),
+        action, // "added"、"removed" 或 "left"
+        user_id: user_id.to_string(),
+        operator_id: operator_id.to_string(),
+    }
+}
+
+// 创建群聊
+pub async fn create_group_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Json(req): Json<CreateGroupRequest>,
+) -> Result<Json<CreateGroupResponse>, AppError> {
+    let name = validate_group_name(&req.name)?;
+    let creator_id = auth_user.user_id.clone();
+    let member_ids: Vec<String> = req.member_ids.into_iter()
+        .filter(|id| *id != creator_id)
+        .collect();
+
+    let (group, member_ids) = state.db_pool.run(move |db| {
+        for member_id in &member_ids {
+            if !db.user_exists_by_id(member_id)? {
+                return Err(AppError::BadRequest(format!("用户 {} 不存在", member_id)));
+            }
+        }
+        let group = db.create_group(&creator_id, &name, &member_ids)?;
+        Ok((group, member_ids))
+    }).await?;
+
+    // 所有在线成员订阅新群，并通知全体成员（群主的其他设备也据此同步）
+    state.subscribe_to_group(&auth_user.user_id, &group.id);
+    for member_id in &member_ids {
+        state.subscribe_to_group(member_id, &group.id);
+    }
+    let mut recipients = member_ids;
+    recipients.push(auth_user.user_id.clone());
+    sync::publish(&state, recipients, ServerFrame::GroupJoined {
+        group_id: group.id.clone(),
+        group_name: group.name.clone(),
+        operator_id: auth_user.user_id.clone(),
+    }).await;
+
+    Ok(Json(CreateGroupResponse {
+        success: true,
+        message: "创建群聊成功".into(),
+        group: Some(GroupInfo {
+            id: group.id,
+            name: group.name,
+            creator_id: group.creator_id,
+            created_at: group.created_at,
+            role: "owner".into(),
+            muted: false,
+        }),
+    }))
+}
+
+// 获取我加入的群聊
+pub async fn get_groups_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+) -> Result<Json<GetGroupsResponse>, AppError> {
+    let groups = state.db_pool.run(move |db| db.get_user_groups(&auth_user.user_id)).await?;
+
+    let group_infos: Vec<GroupInfo> = groups.into_iter().map(|(group, member)| GroupInfo {
+        id: group.id,
+        name: group.name,
+        creator_id: group.creator_id,
+        created_at: group.created_at,
+        role: member.role,
+        muted: member.muted,
+    }).collect();
+
+    Ok(Json(GetGroupsResponse {
+        success: true,
+        message: "获取群聊列表成功".into(),
+        groups: group_infos,
+    }))
+}
+
+// 获取群成员列表（仅群成员可查看）
+pub async fn get_group_members_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Json(req): Json<GroupIdRequest>,
+) -> Result<Json<GetGroupMembersResponse>, AppError> {
+    let members = state.db_pool.run(move |db| {
+        require_member(db, &req.group_id, &auth_user.user_id)?;
+        let members = db.get_group_members(&req.group_id)?
+            .into_iter()
+            .map(|member| GroupMemberInfo {
+                // 若查询失败，保留原始 user_id 以便前端回退显示
+                username: db.get_username(&member.user_id).unwrap_or_else(|_| member.user_id.clone()),
+                user_id: member.user_id,
+                role: member.role,
+                joined_at: member.joined_at,
+            })
+            .collect::<Vec<_>>();
+        Ok::<_, AppError>(members)
+    }).await?;
+
+    Ok(Json(GetGroupMembersResponse {
+        success: true,
+        message: "获取群成员成功".into(),
+        members,
+    }))
+}
+
+// 添加群成员（仅群主）
+pub async fn add_group_member_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Json(req): Json<GroupMemberRequest>,
+) -> Result<Json<GroupOperationResponse>, AppError> {
+    let (group_id, user_id) = (req.group_id.clone(), req.user_id.clone());
+    let owner_id = auth_user.user_id.clone();
+    let (group, member_ids) = state.db_pool.run(move |db| {
+        let group = require_owner(db, &group_id, &owner_id)?;
+        if !db.user_exists_by_id(&user_id)? {
+            return Err(AppError::NotFound("用户不存在".into()));
+        }
+        db.add_group_member(&group_id, &user_id, "member").map_err(|e| match e {
+            StorageError::Conflict(_) => AppError::Conflict("该用户已是群成员".into()),
+            e => e.into(),
+        })?;
+        Ok((group, sync::group_member_ids(db, &group_id)?))
+    }).await?;
+
+    // 新成员订阅群聊并收到入群通知，全体成员（含新成员）收到成员变动通知
+    state.subscribe_to_group(&req.user_id, &req.group_id);
+    sync::publish(&state, vec![req.user_id.clone()], ServerFrame::GroupJoined {
+        group_id: group.id,
+        group_name: group.name,
+        operator_id: auth_user.user_id.clone(),
+    }).await;
+    sync::publish(&state, member_ids, members_changed_frame(&req.group_id, "added", &req.user_id, &auth_user.user_id)).await;
+
+    Ok(Json(GroupOperationResponse {
+        success: true,
+        message: "添加群成员成功".into(),
+    }))
+}
+
+// 移除群成员（仅群主，不能移除自己）
+pub async fn remove_group_member_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Json(req): Json<GroupMemberRequest>,
+) -> Result<Json<GroupOperationResponse>, AppError> {
+    if req.user_id == auth_user.user_id {
+        return Err(AppError::BadRequest("群主不能移除自己，请解散群聊".into()));
+    }
+
+    let (group_id, user_id) = (req.group_id.clone(), req.user_id.clone());
+    let owner_id = auth_user.user_id.clone();
+    // 移除前的成员列表，被移除者也会收到成员变动通知
+    let member_ids = state.db_pool.run(move |db| {
+        require_owner(db, &group_id, &owner_id)?;
+        let member_ids = sync::group_member_ids(db, &group_id)?;
+        if !db.remove_group_member(&group_id, &user_id)? {
+            return Err(AppError::NotFound("该用户不是群成员".into()));
+        }
+        Ok(member_ids)
+    }).await?;
+
+    state.unsubscribe_from_group(&req.user_id, &req.group_id);
+    sync::publish(&state, member_ids, members_changed_frame(&req.group_id, "removed", &req.user_id, &auth_user.user_id)).await;
+    sync::publish(&state, vec![req.user_id.clone()], ServerFrame::GroupRemoved {
+        group_id: req.group_id.clone(),
+        operator_id: auth_user.user_id.clone(),
+    }).await;
+
+    Ok(Json(GroupOperationResponse {
+        success: true,
+        message: "移除群成员成功".into(),
+    }))
+}
+
+// 设置成员角色（仅群主）：任命或撤销管理员，管理员可以撤回群内任意消息、@全体成员
+pub async fn set_member_role_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Json(req): Json<SetMemberRoleRequest>,
+) -> Result<Json<GroupOperationResponse>, AppError> {
+    if !matches!(req.role.as_str(), "admin" | "member") {
+        return Err(AppError::BadRequest("角色只能是 admin 或 member".into()));
+    }
+    if req.user_id == auth_user.user_id {
+        return Err(AppError::BadRequest("不能修改群主自己的角色".into()));
+    }
+
+    let (group_id, user_id, role) = (req.group_id.clone(), req.user_id.clone(), req.role.clone());
+    let owner_id = auth_user.user_id.clone();
+    let member_ids = state.db_pool.run(move |db| {
+        require_owner(db, &group_id, &owner_id)?;
+        db.set_member_role(&group_id, &user_id, &role).map_err(|e| match e {
+            StorageError::NotFound => AppError::NotFound("该用户不是群成员".into()),
+            e => e.into(),
+        })?;
+        Ok::<_, AppError>(sync::group_member_ids(db, &group_id)?)
+    }).await?;
+
+    sync::publish(&state, member_ids, ServerFrame::GroupRoleChanged {
+        group_id: req.group_id.clone(),
+        user_id: req.user_id.clone(),
+        role: req.role.clone(),
+        operator_id: auth_user.user_id.clone(),
+    }).await;
+
+    Ok(Json(GroupOperationResponse {
+        success: true,
+        message: if req.role == "admin" { "已设为管理员".into() } else { "已取消管理员".into() },
+    }))
+}
+
+// 退出群聊（群主不能退出，只能解散）
+pub async fn leave_group_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Json(req): Json<GroupIdRequest>,
+) -> Result<Json<GroupOperationResponse>, AppError> {
+    let group_id = req.group_id.clone();
+    let user_id = auth_user.user_id.clone();
+    let mut recipients = state.db_pool.run(move |db| {
+        let (_, role) = require_member(db, &group_id, &user_id)?;
+        if role == "owner" {
+            return Err(AppError::BadRequest("群主不能退出群聊，请解散群聊".into()));
+        }
+        db.remove_group_member(&group_id, &user_id)?;
+        Ok(sync::group_member_ids(db, &group_id)?)
+    }).await?;
+
+    // 剩余成员与退出者的其他设备都收到成员变动通知
+    state.unsubscribe_from_group(&auth_user.user_id, &req.group_id);
+    recipients.push(auth_user.user_id.clone());
+    sync::publish(&state, recipients, members_changed_frame(&req.group_id, "left", &auth_user.user_id, &auth_user.user_id)).await;
+
+    Ok(Json(GroupOperationResponse {
+        success: true,
+        message: "已退出群聊".into(),
+    }))
+}
+
+// 修改群名称（仅群主）
+pub async fn rename_group_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Json(req): Json<RenameGroupRequest>,
+) -> Result<Json<GroupOperationResponse>, AppError> {
+    let name = validate_group_name(&req.name)?;
+    let (group_id, new_name) = (req.group_id.clone(), name.clone());
+    let owner_id = auth_user.user_id.clone();
+    let member_ids = state.db_pool.run(move |db| {
+        require_owner(db, &group_id, &owner_id)?;
+        db.rename_group(&group_id, &new_name)?;
+        Ok::<_, AppError>(sync::group_member_ids(db, &group_id)?)
+    }).await?;
+
+    sync::publish(&state, member_ids, ServerFrame::GroupRenamed {
+        group_id: req.group_id.clone(),
+        group_name: name,
+        operator_id: auth_user.user_id.clone(),
+    }).await;
+
+    Ok(Json(GroupOperationResponse {
+        success: true,
+        message: "修改群名称成功".into(),
+    }))
+}
+
+// 设置群消息免打扰（仅影响自己；被@时仍会收到高优先级提醒）
+pub async fn mute_group_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Json(req): Json<MuteGroupRequest>,
+) -> Result<Json<GroupOperationResponse>, AppError> {
+    let (group_id, user_id, muted) = (req.group_id.clone(), auth_user.user_id.clone(), req.muted);
+    state.db_pool.run(move |db| {
+        require_member(db, &group_id, &user_id)?;
+        Ok::<_, AppError>(db.set_group_muted(&group_id, &user_id, muted)?)
+    }).await?;
+
+    // 同步到自己的其他设备
+    sync::publish(&state, vec![auth_user.user_id.clone()], ServerFrame::GroupMuted {
+        group_id: req.group_id.clone(),
+        muted: req.muted,
+    }).await;
+
+    Ok(Json(GroupOperationResponse {
+        success: true,
+        message: if req.muted { "已开启消息免打扰".into() } else { "已关闭消息免打扰".into() },
+    }))
+}
+
+// 解散群聊（仅群主）
+pub async fn dissolve_group_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Json(req): Json<GroupIdRequest>,
+) -> Result<Json<GroupOperationResponse>, AppError> {
+    let group_id = req.group_id.clone();
+    let owner_id = auth_user.user_id.clone();
+    let member_ids = state.db_pool.run(move |db| {
+        require_owner(db, &group_id, &owner_id)?;
+        let member_ids = sync::group_member_ids(db, &group_id)?;
+        db.delete_group(&group_id)?;
+        Ok::<_, AppError>(member_ids)
+    }).await?;
+
+    // 关闭群广播通道并结束转发任务，再通知每个成员
+    state.close_group(&req.group_id);
+    sync::publish(&state, member_ids, ServerFrame::GroupDissolved {
+        group_id: req.group_id.clone(),
+        operator_id: auth_user.user_id.clone(),
+    }).await;
+
+    Ok(Json(GroupOperationResponse {
+        success: true,
+        message: "群聊已解散".into(),
+    }))
+}
+
+/// 注册群聊相关路由
+pub fn register_routes() -> Router<AppState> {
+    Router::new()
+        .route("/groups/create", post(create_group_handler))
+        .route("/groups/list", post(get_groups_handler))
+        .route("/groups/members", post(get_group_members_handler))
+        .route("/groups/add-member", post(add_group_member_handler))
+        .route("/groups/remove-member", post(remove_group_member_handler))
+        .route("/groups/set-role", post(set_member_role_handler))
+        .route("/groups/leave", post(leave_group_handler))
+        .route("/groups/rename", post(rename_group_handler))
+        .route("/groups/mute", post(mute_group_handler))
+        .route("/groups/dissolve", post(dissolve_group_handler))
+}
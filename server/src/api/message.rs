@@ -1,17 +1,31 @@
 use axum::{
-    extract::{State}, 
+    extract::{Path, Query, State}, 
     response::Json, 
-    routing::{post}, 
+    routing::{get, post}, 
     Router
 };
 use serde::{
     Deserialize, 
     Serialize
 };
+use std::collections::HashMap;
 use crate::storage::{
-    Message
+    Conversation,
+    HistoryCursor,
+    Message,
+    MessageEdit,
+    MessageExtras,
+    MessageSearch,
+    ReactionCount,
+    Storage,
+    StorageError,
+    StorageResult
 };
 use crate::error::AppError;
+use crate::core::auth::AuthUser;
+use crate::core::messaging;
+use crate::core::reactions;
+use crate::core::search::{self, Snippet};
 
 // 共享应用状态
 use super::AppState;
@@ -19,10 +33,18 @@ use super::AppState;
 // 消息请求体
 #[derive(Deserialize)]
 pub struct SendMessageRequest {
-    pub sender_id: String,
     pub receiver_id: String,
-    pub content: String,
+    #[serde(default)]
+    pub content: String,             // 带附件时可以为空
     pub message_type: String, // "private"或"group"
+    #[serde(default)]
+    pub reply_to_id: Option<String>, // 回复（引用）同一会话中的消息
+    #[serde(default)]
+    pub mentions: Vec<String>,       // @的群成员ID（仅群聊）
+    #[serde(default)]
+    pub mention_all: bool,           // @全体成员（仅群聊，仅群主与管理员）
+    #[serde(default)]
+    pub attachments: Vec<String>,    // 已上传的附件ID（按顺序）
 }
 
 // 消息响应体
@@ -33,12 +55,6 @@ pub struct SendMessageResponse {
     pub message_id: Option<String>,
 }
 
-// 获取未读消息请求
-#[derive(Deserialize)]
-pub struct GetUnreadMessagesRequest {
-    pub user_id: String,
-}
-
 // 获取未读消息响应
 #[derive(Serialize)]
 pub struct GetUnreadMessagesResponse {
@@ -58,22 +74,187 @@ pub struct MarkMessagesAsReadRequest {
 pub struct MarkMessagesAsReadResponse {
     pub success: bool,
     pub message: String,
+    pub updated: usize, // 本次新标记的条数
+}
+
+// 标记消息为已送达请求
+#[derive(Deserialize)]
+pub struct MarkMessagesDeliveredRequest {
+    pub message_ids: Vec<String>,
+}
+
+// 群消息已读请求：已读到该群的哪条消息
+#[derive(Deserialize)]
+pub struct MarkGroupReadRequest {
+    pub group_id: String,
+    pub message_id: String,
+}
+
+// 查询消息回执请求
+#[derive(Deserialize)]
+pub struct MessageReceiptRequest {
+    pub message_id: String,
+}
+
+// 消息回执响应：私聊返回送达/已读时间，群聊返回已读人数
+#[derive(Serialize)]
+pub struct MessageReceiptResponse {
+    pub success: bool,
+    pub message: String,
+    pub message_id: String,
+    pub status: String,                // "sent"、"delivered" 或 "read"（群聊为 "sent"）
+    pub delivered_at: Option<i64>,
+    pub read_at: Option<i64>,
+    pub read_count: Option<i64>,       // 群聊：已读成员数
+    pub member_count: Option<i64>,     // 群聊：除发送者外的成员数
+}
+
+// 历史消息查询参数（before 与 after 至多指定一个）
+#[derive(Deserialize)]
+pub struct MessageHistoryQuery {
+    pub before: Option<String>, // 获取该消息之前（更早）的消息
+    pub after: Option<String>,  // 获取该消息之后（更新）的消息
+    pub limit: Option<usize>,   // 每页条数
+}
+
+// 历史消息响应（消息按时间升序）
+#[derive(Serialize)]
+pub struct MessageHistoryResponse {
+    pub success: bool,
+    pub message: String,
+    pub messages: Vec<Message>,
+    pub has_more: bool, // 翻页方向上是否还有更多消息
+    pub reactions: HashMap<String, Vec<ReactionCount>>, // 消息ID → 表情回应汇总（没有回应的消息不出现）
+}
+
+// 话题历史响应（`messages` 为话题内的回复，按时间升序）
+#[derive(Serialize)]
+pub struct ThreadHistoryResponse {
+    pub success: bool,
+    pub message: String,
+    pub root: Message,  // 话题根消息
+    pub messages: Vec<Message>,
+    pub has_more: bool,
+    pub reactions: HashMap<String, Vec<ReactionCount>>, // 回复的表情回应汇总
+}
+
+// 消息搜索请求（peer_id 与 group_id 至多指定一个）
+#[derive(Deserialize)]
+pub struct SearchMessagesRequest {
+    pub query: String,             // 检索词，空白分隔
+    pub sender_id: Option<String>, // 只看该用户发送的消息
+    pub peer_id: Option<String>,   // 只在与该用户的私聊中搜索
+    pub group_id: Option<String>,  // 只在该群中搜索
+    pub since: Option<i64>,        // 发送时间下限（Unix秒）
+    pub until: Option<i64>,        // 发送时间上限（Unix秒）
+    pub before: Option<String>,    // 分页游标：上一页最后一条结果的消息ID
+    pub limit: Option<usize>,      // 每页条数
+}
+
+// 单条搜索结果
+#[derive(Serialize)]
+pub struct MessageSearchResult {
+    pub message: Message,
+    pub snippet: Snippet,
+}
+
+// 消息搜索响应（结果从新到旧）
+#[derive(Serialize)]
+pub struct SearchMessagesResponse {
+    pub success: bool,
+    pub message: String,
+    pub results: Vec<MessageSearchResult>,
+    pub has_more: bool,
+}
+
+// "@我的"消息请求
+#[derive(Deserialize)]
+pub struct MentionsRequest {
+    pub before: Option<String>,    // 分页游标：上一页最后一条的消息ID
+    pub limit: Option<usize>,      // 每页条数
+}
+
+// "@我的"消息响应（从新到旧）
+#[derive(Serialize)]
+pub struct MentionsResponse {
+    pub success: bool,
+    pub message: String,
+    pub messages: Vec<Message>,
+    pub has_more: bool,
+}
+
+// 编辑消息请求
+#[derive(Deserialize)]
+pub struct EditMessageRequest {
+    pub message_id: String,
+    pub content: String, // 新内容
+}
+
+// 只携带消息ID的请求（撤回、仅对自己删除、查看编辑历史与表情回应）
+#[derive(Deserialize)]
+pub struct MessageIdRequest {
+    pub message_id: String,
+}
+
+// 编辑或撤回后的消息
+#[derive(Serialize)]
+pub struct UpdateMessageResponse {
+    pub success: bool,
+    pub message: String,
+    pub result: Message,
+}
+
+// 消息编辑历史响应（`edits` 为历次编辑前的内容，按时间升序）
+#[derive(Serialize)]
+pub struct MessageEditsResponse {
+    pub success: bool,
+    pub message: String,
+    pub current: Message,
+    pub edits: Vec<MessageEdit>,
 }
 
+// 表情回应请求
+#[derive(Deserialize)]
+pub struct ReactionRequest {
+    pub message_id: String,
+    pub emoji: String,
+}
+
+// 表情回应汇总响应（单条消息）
+#[derive(Serialize)]
+pub struct ReactionsResponse {
+    pub success: bool,
+    pub message: String,
+    pub reactions: Vec<ReactionCount>,
+}
+
+// 通用消息操作响应
+#[derive(Serialize)]
+pub struct MessageOperationResponse {
+    pub success: bool,
+    pub message: String,
+}
+
+/// 未指定 limit 时的每页条数
+const DEFAULT_HISTORY_PAGE_SIZE: usize = 50;
+
 // 发送消息处理器
 pub async fn send_message_handler(
     State(state): State<AppState>,
+    auth_user: AuthUser,
     Json(req): Json<SendMessageRequest>,
 ) -> Result<Json<SendMessageResponse>, AppError> {
-
-    
-    let message = state.db_pool.send_message(
-        &req.sender_id,
-        &req.receiver_id,
-        &req.content,
-        &req.message_type,
-    )
-    .map_err(|e| AppError::Database(e.to_string()))?;
+    // 私聊消息需互为好友，群聊消息需为群成员；保存后实时推送
+    let extras = MessageExtras {
+        mentions: req.mentions,
+        mention_all: req.mention_all,
+        attachment_ids: req.attachments,
+    };
+    let message = match req.message_type.as_str() {
+        "private" => messaging::send_private_message(&state, &auth_user.user_id, &req.receiver_id, &req.content, req.reply_to_id.as_deref(), extras).await?,
+        "group" => messaging::send_group_message(&state, &auth_user.user_id, &req.receiver_id, &req.content, req.reply_to_id.as_deref(), extras).await?,
+        _ => return Err(AppError::BadRequest("message_type 只能是 private 或 group".into())),
+    };
 
     Ok(Json(SendMessageResponse {
         success: true,
@@ -85,10 +266,9 @@ pub async fn send_message_handler(
 // 获取未读消息处理器
 pub async fn get_unread_messages_handler(
     State(state): State<AppState>,
-    Json(req): Json<GetUnreadMessagesRequest>,
+    auth_user: AuthUser,
 ) -> Result<Json<GetUnreadMessagesResponse>, AppError> {
-    let messages = state.db_pool.get_unread_messages(&req.user_id)
-        .map_err(|e| AppError::Database(e.to_string()))?;
+    let messages = state.db_pool.run(move |db| db.get_unread_messages(&auth_user.user_id)).await?;
     
 
 
@@ -99,17 +279,430 @@ pub async fn get_unread_messages_handler(
     }))
 }
 
-// 标记消息为已读处理器
+// 标记消息为已读处理器（只能标记发给自己的私聊消息，发送方会收到已读回执）
 pub async fn mark_messages_as_read_handler(
     State(state): State<AppState>,
+    auth_user: AuthUser,
     Json(req): Json<MarkMessagesAsReadRequest>,
 ) -> Result<Json<MarkMessagesAsReadResponse>, AppError> {
-    state.db_pool.mark_messages_as_read(&req.message_ids)
-        .map_err(|e| AppError::Database(e.to_string()))?;
+    let updated = messaging::mark_read(&state, &auth_user.user_id, req.message_ids).await?;
 
     Ok(Json(MarkMessagesAsReadResponse {
         success: true,
         message: "消息已标记为已读".into(),
+        updated,
+    }))
+}
+
+// 标记消息为已送达处理器
+pub async fn mark_messages_delivered_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Json(req): Json<MarkMessagesDeliveredRequest>,
+) -> Result<Json<MarkMessagesAsReadResponse>, AppError> {
+    let updated = messaging::mark_delivered(&state, &auth_user.user_id, req.message_ids).await?;
+
+    Ok(Json(MarkMessagesAsReadResponse {
+        success: true,
+        message: "消息已标记为已送达".into(),
+        updated,
+    }))
+}
+
+// 群消息已读处理器
+pub async fn mark_group_read_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Json(req): Json<MarkGroupReadRequest>,
+) -> Result<Json<MarkMessagesAsReadResponse>, AppError> {
+    messaging::mark_group_read(&state, &auth_user.user_id, &req.group_id, &req.message_id).await?;
+
+    Ok(Json(MarkMessagesAsReadResponse {
+        success: true,
+        message: "群消息已读位置已更新".into(),
+        updated: 1,
+    }))
+}
+
+// 查询消息回执处理器（私聊双方或群成员可查询）
+pub async fn get_message_receipt_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Json(req): Json<MessageReceiptRequest>,
+) -> Result<Json<MessageReceiptResponse>, AppError> {
+    let (message, read_count) = state.db_pool.run(move |db| {
+        let message = db.get_message(&req.message_id).map_err(|e| match e {
+            StorageError::NotFound => AppError::NotFound("消息不存在".into()),
+            e => e.into(),
+        })?;
+        if message.message_type == "group" {
+            if !db.is_group_member(&message.receiver_id, &auth_user.user_id)? {
+                return Err(AppError::NotFound("消息不存在".into()));
+            }
+            let count = db.count_group_readers(&message)?;
+            Ok((message, Some(count)))
+        } else {
+            if message.sender_id != auth_user.user_id && message.receiver_id != auth_user.user_id {
+                return Err(AppError::NotFound("消息不存在".into()));
+            }
+            Ok((message, None))
+        }
+    }).await?;
+
+    let status = if message.read_at.is_some() {
+        "read"
+    } else if message.delivered_at.is_some() {
+        "delivered"
+    } else {
+        "sent"
+    };
+
+    Ok(Json(MessageReceiptResponse {
+        success: true,
+        message: "获取消息回执成功".into(),
+        message_id: message.id,
+        status: status.into(),
+        delivered_at: message.delivered_at,
+        read_at: message.read_at,
+        read_count: read_count.as_ref().map(|count| count.read),
+        member_count: read_count.as_ref().map(|count| count.total),
+    }))
+}
+
+// 按查询参数分页读取会话历史，附带本页消息的表情回应
+//
+// 多取一条用于判断翻页方向上是否还有更多消息
+async fn load_history(
+    state: &AppState,
+    viewer_id: String,
+    query: MessageHistoryQuery,
+    fetch: impl FnOnce(&dyn Storage, &str, HistoryCursor, usize) -> StorageResult<Vec<Message>> + Send + 'static,
+) -> Result<MessageHistoryResponse, AppError> {
+    let limit = query.limit
+        .unwrap_or(DEFAULT_HISTORY_PAGE_SIZE)
+        .min(state.settings.limits.max_history_page_size);
+    if limit == 0 {
+        return Err(AppError::BadRequest("limit 必须大于 0".into()));
+    }
+    let newer = query.after.is_some();
+
+    let (messages, has_more, reactions) = state.db_pool.run(move |db| {
+        let cursor = match (&query.before, &query.after) {
+            (Some(_), Some(_)) => return Err(AppError::BadRequest("before 与 after 不能同时指定".into())),
+            (Some(id), None) => HistoryCursor::Before(id),
+            (None, Some(id)) => HistoryCursor::After(id),
+            (None, None) => HistoryCursor::Latest,
+        };
+        let mut messages = fetch(db, &viewer_id, cursor, limit + 1).map_err(|e| match e {
+            StorageError::NotFound => AppError::NotFound("游标消息不存在".into()),
+            e => e.into(),
+        })?;
+
+        let has_more = messages.len() > limit;
+        if has_more {
+            // 向后翻页时多出的是最新一条，否则是最早一条
+            if newer {
+                messages.pop();
+            } else {
+                messages.remove(0);
+            }
+        }
+        let reactions = reactions::reactions_by_message(db, &viewer_id, &messages)?;
+        Ok((messages, has_more, reactions))
+    }).await?;
+
+    Ok(MessageHistoryResponse {
+        success: true,
+        message: "获取历史消息成功".into(),
+        messages,
+        has_more,
+        reactions,
+    })
+}
+
+// 获取私聊历史消息处理器
+pub async fn get_private_history_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Path(peer_id): Path<String>,
+    Query(query): Query<MessageHistoryQuery>,
+) -> Result<Json<MessageHistoryResponse>, AppError> {
+    let peer = peer_id.clone();
+    if !state.db_pool.run(move |db| db.user_exists_by_id(&peer)).await? {
+        return Err(AppError::NotFound("用户不存在".into()));
+    }
+
+    let response = load_history(&state, auth_user.user_id, query, move |db, viewer, cursor, limit| {
+        db.get_message_history(viewer, Conversation::Private(viewer, &peer_id), cursor, limit)
+    }).await?;
+    Ok(Json(response))
+}
+
+// 获取群聊历史消息处理器（仅群成员可查看）
+pub async fn get_group_history_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Path(group_id): Path<String>,
+    Query(query): Query<MessageHistoryQuery>,
+) -> Result<Json<MessageHistoryResponse>, AppError> {
+    let (group, user) = (group_id.clone(), auth_user.user_id.clone());
+    state.db_pool.run(move |db| {
+        if !db.group_exists(&group)? {
+            return Err(AppError::NotFound("群聊不存在".into()));
+        }
+        if !db.is_group_member(&group, &user)? {
+            return Err(AppError::Forbidden("不是该群成员".into()));
+        }
+        Ok(())
+    }).await?;
+
+    let response = load_history(&state, auth_user.user_id, query, move |db, viewer, cursor, limit| {
+        db.get_message_history(viewer, Conversation::Group(&group_id), cursor, limit)
+    }).await?;
+    Ok(Json(response))
+}
+
+// 获取群聊话题历史处理器（仅群成员可查看；传入话题中的任意回复时定位到其根消息）
+pub async fn get_thread_history_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Path(message_id): Path<String>,
+    Query(query): Query<MessageHistoryQuery>,
+) -> Result<Json<ThreadHistoryResponse>, AppError> {
+    let user = auth_user.user_id.clone();
+    let root = state.db_pool.run(move |db| {
+        let not_found = |e| match e {
+            StorageError::NotFound => AppError::NotFound("消息不存在".into()),
+            e => e.into(),
+        };
+        let message = db.get_message(&message_id).map_err(not_found)?;
+        if message.message_type != "group" {
+            return Err(AppError::BadRequest("只有群聊消息才有话题".into()));
+        }
+        if !db.is_group_member(&message.receiver_id, &user)? {
+            return Err(AppError::Forbidden("不是该群成员".into()));
+        }
+        match &message.thread_root_id {
+            Some(root_id) => db.get_message(root_id).map_err(not_found),
+            None => Ok(message),
+        }
+    }).await?;
+
+    let root_id = root.id.clone();
+    let history = load_history(&state, auth_user.user_id, query, move |db, viewer, cursor, limit| {
+        db.get_message_history(viewer, Conversation::Thread(&root_id), cursor, limit)
+    }).await?;
+    Ok(Json(ThreadHistoryResponse {
+        success: true,
+        message: "获取话题消息成功".into(),
+        root,
+        messages: history.messages,
+        has_more: history.has_more,
+        reactions: history.reactions,
+    }))
+}
+
+// 搜索消息处理器
+pub async fn search_messages_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Json(req): Json<SearchMessagesRequest>,
+) -> Result<Json<SearchMessagesResponse>, AppError> {
+    if req.query.trim().is_empty() {
+        return Err(AppError::BadRequest("搜索内容不能为空".into()));
+    }
+    if req.peer_id.is_some() && req.group_id.is_some() {
+        return Err(AppError::BadRequest("peer_id 与 group_id 不能同时指定".into()));
+    }
+    if let (Some(since), Some(until)) = (req.since, req.until)
+        && since > until
+    {
+        return Err(AppError::BadRequest("since 不能晚于 until".into()));
+    }
+    let limit = req.limit
+        .unwrap_or(DEFAULT_HISTORY_PAGE_SIZE)
+        .min(state.settings.limits.max_history_page_size);
+    if limit == 0 {
+        return Err(AppError::BadRequest("limit 必须大于 0".into()));
+    }
+
+    let query = req.query.clone();
+    let mut messages = state.db_pool.run(move |db| {
+        if let Some(group_id) = &req.group_id {
+            if !db.group_exists(group_id)? {
+                return Err(AppError::NotFound("群聊不存在".into()));
+            }
+            if !db.is_group_member(group_id, &auth_user.user_id)? {
+                return Err(AppError::Forbidden("不是该群成员".into()));
+            }
+        }
+
+        let conversation = match (&req.peer_id, &req.group_id) {
+            (Some(peer_id), _) => Some(Conversation::Private(&auth_user.user_id, peer_id)),
+            (_, Some(group_id)) => Some(Conversation::Group(group_id)),
+            _ => None,
+        };
+        let search = MessageSearch {
+            user_id: &auth_user.user_id,
+            query: &req.query,
+            sender_id: req.sender_id.as_deref(),
+            conversation,
+            since: req.since,
+            until: req.until,
+            before: req.before.as_deref(),
+            limit: limit + 1,
+        };
+        db.search_messages(&search).map_err(|e| match e {
+            StorageError::NotFound => AppError::NotFound("游标消息不存在".into()),
+            e => e.into(),
+        })
+    }).await?;
+
+    let has_more = messages.len() > limit;
+    messages.truncate(limit);
+    let results = messages
+        .into_iter()
+        .map(|message| MessageSearchResult {
+            snippet: search::snippet(&message.content, &query),
+            message,
+        })
+        .collect();
+
+    Ok(Json(SearchMessagesResponse {
+        success: true,
+        message: "搜索成功".into(),
+        results,
+        has_more,
+    }))
+}
+
+// "@我的"消息处理器（含@全体成员，不含已撤回的消息）
+pub async fn get_mentions_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Json(req): Json<MentionsRequest>,
+) -> Result<Json<MentionsResponse>, AppError> {
+    let limit = req.limit
+        .unwrap_or(DEFAULT_HISTORY_PAGE_SIZE)
+        .min(state.settings.limits.max_history_page_size);
+    if limit == 0 {
+        return Err(AppError::BadRequest("limit 必须大于 0".into()));
+    }
+
+    let mut messages = messaging::mentions_of(&state, &auth_user.user_id, req.before, limit + 1).await?;
+    let has_more = messages.len() > limit;
+    messages.truncate(limit);
+
+    Ok(Json(MentionsResponse {
+        success: true,
+        message: "获取@我的消息成功".into(),
+        messages,
+        has_more,
+    }))
+}
+
+// 编辑消息处理器（只能编辑自己发送且未撤回的消息）
+pub async fn edit_message_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Json(req): Json<EditMessageRequest>,
+) -> Result<Json<UpdateMessageResponse>, AppError> {
+    let message = messaging::edit_message(&state, &auth_user.user_id, &req.message_id, &req.content).await?;
+
+    Ok(Json(UpdateMessageResponse {
+        success: true,
+        message: "消息已编辑".into(),
+        result: message,
+    }))
+}
+
+// 查看消息编辑历史处理器（会话参与者可查看）
+pub async fn get_message_edits_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Json(req): Json<MessageIdRequest>,
+) -> Result<Json<MessageEditsResponse>, AppError> {
+    let (current, edits) = messaging::message_edits(&state, &auth_user.user_id, &req.message_id).await?;
+
+    Ok(Json(MessageEditsResponse {
+        success: true,
+        message: "获取编辑历史成功".into(),
+        current,
+        edits,
+    }))
+}
+
+// 撤回消息处理器（发送者在时限内撤回，群主和管理员可撤回群内任意消息）
+pub async fn recall_message_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Json(req): Json<MessageIdRequest>,
+) -> Result<Json<UpdateMessageResponse>, AppError> {
+    let message = messaging::recall_message(&state, &auth_user.user_id, &req.message_id).await?;
+
+    Ok(Json(UpdateMessageResponse {
+        success: true,
+        message: "消息已撤回".into(),
+        result: message,
+    }))
+}
+
+// 删除消息处理器（仅对自己删除，其他参与者不受影响）
+pub async fn delete_message_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Json(req): Json<MessageIdRequest>,
+) -> Result<Json<MessageOperationResponse>, AppError> {
+    messaging::hide_message(&state, &auth_user.user_id, &req.message_id).await?;
+
+    Ok(Json(MessageOperationResponse {
+        success: true,
+        message: "消息已删除".into(),
+    }))
+}
+
+// 添加表情回应处理器（会话参与者可回应，变化推送给会话参与者）
+pub async fn add_reaction_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Json(req): Json<ReactionRequest>,
+) -> Result<Json<ReactionsResponse>, AppError> {
+    let reactions = reactions::react(&state, &auth_user.user_id, &req.message_id, &req.emoji, true).await?;
+
+    Ok(Json(ReactionsResponse {
+        success: true,
+        message: "已添加表情回应".into(),
+        reactions,
+    }))
+}
+
+// 取消表情回应处理器
+pub async fn remove_reaction_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Json(req): Json<ReactionRequest>,
+) -> Result<Json<ReactionsResponse>, AppError> {
+    let reactions = reactions::react(&state, &auth_user.user_id, &req.message_id, &req.emoji, false).await?;
+
+    Ok(Json(ReactionsResponse {
+        success: true,
+        message: "已取消表情回应".into(),
+        reactions,
+    }))
+}
+
+// 查看消息表情回应处理器（会话参与者可查看）
+pub async fn get_reactions_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Json(req): Json<MessageIdRequest>,
+) -> Result<Json<ReactionsResponse>, AppError> {
+    let reactions = reactions::message_reactions(&state, &auth_user.user_id, &req.message_id).await?;
+
+    Ok(Json(ReactionsResponse {
+        success: true,
+        message: "获取表情回应成功".into(),
+        reactions,
     }))
 }
 
@@ -119,4 +712,19 @@ pub fn register_routes() -> Router<AppState> {
         .route("/send-message", post(send_message_handler))
         .route("/messages/unread", post(get_unread_messages_handler))
         .route("/messages/read", post(mark_messages_as_read_handler))
+        .route("/messages/delivered", post(mark_messages_delivered_handler))
+        .route("/messages/group-read", post(mark_group_read_handler))
+        .route("/messages/receipt", post(get_message_receipt_handler))
+        .route("/messages/private/{user_id}", get(get_private_history_handler))
+        .route("/messages/group/{group_id}", get(get_group_history_handler))
+        .route("/messages/thread/{message_id}", get(get_thread_history_handler))
+        .route("/messages/search", post(search_messages_handler))
+        .route("/messages/mentions", post(get_mentions_handler))
+        .route("/messages/edit", post(edit_message_handler))
+        .route("/messages/edits", post(get_message_edits_handler))
+        .route("/messages/recall", post(recall_message_handler))
+        .route("/messages/delete", post(delete_message_handler))
+        .route("/messages/react", post(add_reaction_handler))
+        .route("/messages/unreact", post(remove_reaction_handler))
+        .route("/messages/reactions", post(get_reactions_handler))
 }
\ No newline at end of file
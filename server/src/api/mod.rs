@@ -1,28 +1,63 @@
-use axum::{Router};
+use axum::{
+    extract::DefaultBodyLimit,
+    Router
+};
+use crate::config::Settings;
 
 // 导入子模块
 mod user;
+mod attachment;
 mod friend;
+mod group;
 mod message;
+mod device;
+mod sync;
+mod upload;
 mod ws;
 
 // 重新导出AppState，以便其他模块可以通过super::AppState导入
 pub use ws::AppState;
 
 /// 注册所有API路由
-pub fn register_routes(db_pool: crate::storage::DbPool) -> Router {
+pub async fn register_routes(db_pool: crate::storage::DbPool, settings: Settings) -> Result<Router, crate::error::AppError> {
+    // 加载令牌签名密钥
+    let token_signer = crate::core::auth::TokenSigner::load(&settings.auth, &db_pool).await?;
+
+    let body_limit = settings.limits.max_request_body_bytes;
+    let user_routes = user::register_routes(&settings);
+    let attachment_routes = attachment::register_routes(&settings);
+
+    // 后台回收不再被引用的文件
+    crate::core::blobs::spawn_gc_task(db_pool.clone(), settings.storage.clone());
+
     // 创建共享应用状态
-    let app_state = ws::AppState::new(db_pool);
+    let app_state = ws::AppState::new(db_pool, settings, token_signer);
+
+    // 后台清理闲置过期的分块上传
+    crate::core::uploads::spawn_expiry_task(app_state.clone());
     
     // 主路由器配置
-    Router::new()
+    let router = Router::new()
         // WebSocket路由
         .merge(ws::register_ws_route())
         // 用户相关路由
-        .merge(user::register_routes())
+        .merge(user_routes)
+        // 附件相关路由
+        .merge(attachment_routes)
+        // 分块上传路由
+        .merge(upload::register_routes())
         // 好友相关路由
         .merge(friend::register_routes())
+        // 群聊相关路由
+        .merge(group::register_routes())
         // 消息相关路由
         .merge(message::register_routes())
-        .with_state(app_state)
+        // 设备管理路由
+        .merge(device::register_routes())
+        // 离线同步路由
+        .merge(sync::register_routes())
+        .layer(DefaultBodyLimit::max(body_limit))
+        .with_state(app_state);
+
+    Ok(router)
 }
@@ -0,0 +1,59 @@
+use axum::{
+    extract::State,
+    response::Json,
+    routing::post,
+    Router
+};
+use serde::{
+    Deserialize,
+    Serialize
+};
+use serde_json::Value;
+use crate::error::AppError;
+use crate::core::auth::AuthUser;
+use crate::core::sync;
+
+// 共享应用状态
+use super::AppState;
+
+// 离线同步相关结构体
+
+#[derive(Deserialize)]
+pub struct SyncRequest {
+    #[serde(default)]
+    pub since: Option<i64>,    // 已收到的最大事件序号，缺省时使用本设备上次确认的位置
+    #[serde(default)]
+    pub limit: Option<usize>,  // 本批最多事件数
+}
+
+#[derive(Serialize)]
+pub struct SyncResponse {
+    pub success: bool,
+    pub message: String,
+    pub events: Vec<Value>,
+    pub last_seq: i64,
+    pub has_more: bool,
+}
+
+// 拉取本设备错过的事件（与 WebSocket 的 sync 帧相同）
+pub async fn sync_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Json(req): Json<SyncRequest>,
+) -> Result<Json<SyncResponse>, AppError> {
+    let batch = sync::sync(&state, &auth_user, req.since, req.limit).await?;
+
+    Ok(Json(SyncResponse {
+        success: true,
+        message: "同步成功".into(),
+        events: batch.events,
+        last_seq: batch.last_seq,
+        has_more: batch.has_more,
+    }))
+}
+
+/// 注册离线同步相关路由
+pub fn register_routes() -> Router<AppState> {
+    Router::new()
+        .route("/sync", post(sync_handler))
+}
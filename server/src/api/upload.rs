@@ -0,0 +1,163 @@
+use axum::{
+    body::Body,
+    extract::{
+        Path,
+        State
+    },
+    http::HeaderMap,
+    response::Json,
+    routing::{
+        get,
+        post
+    },
+    Router
+};
+use serde::{
+    Deserialize,
+    Serialize
+};
+use crate::core::auth::AuthUser;
+use crate::core::uploads;
+use crate::error::AppError;
+use crate::storage::{
+    Attachment,
+    Upload
+};
+
+// 共享应用状态
+use super::AppState;
+
+/// 分块的起始位置请求头
+const UPLOAD_OFFSET_HEADER: &str = "upload-offset";
+
+// 创建分块上传请求（`sha256` 可在完成时再提供）
+#[derive(Deserialize)]
+pub struct CreateUploadRequest {
+    pub filename: String,
+    pub size: i64,
+    #[serde(default)]
+    pub sha256: Option<String>,
+}
+
+// 分块上传进度响应（`upload.received` 为下一块的起始位置）
+#[derive(Serialize)]
+pub struct UploadProgressResponse {
+    pub success: bool,
+    pub message: String,
+    pub upload: Upload,
+}
+
+// 完成分块上传请求
+#[derive(Deserialize)]
+pub struct CompleteUploadRequest {
+    #[serde(default)]
+    pub sha256: Option<String>,
+}
+
+// 完成分块上传响应
+#[derive(Serialize)]
+pub struct CompleteUploadResponse {
+    pub success: bool,
+    pub message: String,
+    pub attachment: Attachment,
+}
+
+// 取消分块上传响应
+#[derive(Serialize)]
+pub struct CancelUploadResponse {
+    pub success: bool,
+    pub message: String,
+}
+
+// 创建分块上传处理器
+pub async fn create_upload_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Json(req): Json<CreateUploadRequest>,
+) -> Result<Json<UploadProgressResponse>, AppError> {
+    let upload = uploads::create_upload(&state, &auth_user.user_id, &req.filename, req.size, req.sha256.as_deref()).await?;
+
+    Ok(Json(UploadProgressResponse {
+        success: true,
+        message: "上传已创建".into(),
+        upload,
+    }))
+}
+
+// 查询分块上传进度处理器
+pub async fn upload_progress_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Path(upload_id): Path<String>,
+) -> Result<Json<UploadProgressResponse>, AppError> {
+    let upload = uploads::upload_progress(&state, &auth_user.user_id, &upload_id).await?;
+
+    Ok(Json(UploadProgressResponse {
+        success: true,
+        message: "获取上传进度成功".into(),
+        upload,
+    }))
+}
+
+// 上传一块数据处理器（请求头 Upload-Offset 为起始位置，请求体为原始字节）
+pub async fn append_chunk_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Path(upload_id): Path<String>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Json<UploadProgressResponse>, AppError> {
+    let offset = headers
+        .get(UPLOAD_OFFSET_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<i64>().ok())
+        .ok_or_else(|| AppError::BadRequest("缺少或无效的 Upload-Offset 请求头".into()))?;
+    let upload = uploads::append_chunk(&state, &auth_user.user_id, &upload_id, offset, body).await?;
+
+    Ok(Json(UploadProgressResponse {
+        success: true,
+        message: "分块上传成功".into(),
+        upload,
+    }))
+}
+
+// 完成分块上传处理器（校验 SHA-256 后登记为附件）
+pub async fn complete_upload_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Path(upload_id): Path<String>,
+    Json(req): Json<CompleteUploadRequest>,
+) -> Result<Json<CompleteUploadResponse>, AppError> {
+    let attachment = uploads::complete_upload(&state, &auth_user.user_id, &upload_id, req.sha256.as_deref()).await?;
+
+    Ok(Json(CompleteUploadResponse {
+        success: true,
+        message: "上传完成".into(),
+        attachment,
+    }))
+}
+
+// 取消分块上传处理器
+pub async fn cancel_upload_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Path(upload_id): Path<String>,
+) -> Result<Json<CancelUploadResponse>, AppError> {
+    uploads::cancel_upload(&state, &auth_user.user_id, &upload_id).await?;
+
+    Ok(Json(CancelUploadResponse {
+        success: true,
+        message: "上传已取消".into(),
+    }))
+}
+
+/// 注册分块上传相关路由
+pub fn register_routes() -> Router<AppState> {
+    Router::new()
+        .route("/uploads", post(create_upload_handler))
+        .route(
+            "/uploads/{upload_id}",
+            get(upload_progress_handler).patch(append_chunk_handler).delete(cancel_upload_handler),
+        )
+        .route("/uploads/{upload_id}/complete", post(complete_upload_handler))
+}
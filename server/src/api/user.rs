@@ -1,12 +1,14 @@
 use axum::{
     extract::{
+        DefaultBodyLimit,
         State, 
         Multipart, 
-        Path
+        Path,
+        Query
     },
     response::{
         Json, 
-        IntoResponse
+        Response
     }, 
     routing::{
         post, 
@@ -20,16 +22,28 @@ use serde::{
     Serialize
 };
 use crate::error::AppError;
+use crate::storage::StorageError;
 use bcrypt::{
-    verify
+    hash,
+    verify,
+    DEFAULT_COST
 };
-use std::fs;
-use std::path::Path as FilePath;
-use uuid::Uuid;
+use tokio::fs;
 use http::{
-    header::CONTENT_TYPE
+    header::USER_AGENT,
+    HeaderMap
+};
+use crate::config::Settings;
+use crate::core::auth::{
+    self,
+    AuthUser
+};
+use crate::core::{
+    attachments,
+    blobs,
+    downloads,
+    images
 };
-use mime_guess::from_path;
 // 共享应用状态
 use super::AppState;
 
@@ -53,6 +67,8 @@ pub struct RegisterResponse {
 pub struct LoginRequest {
     pub username: String,
     pub password: String, // 明文密码（后端验证）
+    #[serde(default)]
+    pub device_name: Option<String>, // 设备名称（缺省时取 User-Agent）
 }
 
 // 登录响应体（返回给前端）
@@ -62,6 +78,27 @@ pub struct LoginResponse {
     pub message: String,
     pub user_id: Option<String>, // 成功时返回用户ID
     pub username: Option<String>, // 成功时返回用户名
+    pub access_token: Option<String>, // 访问令牌（请求头 Authorization: Bearer 使用）
+    pub refresh_token: Option<String>, // 刷新令牌
+    pub expires_at: Option<i64>, // 访问令牌过期时间戳
+    pub refresh_expires_at: Option<i64>, // 刷新令牌过期时间戳
+}
+
+// 刷新令牌请求体
+#[derive(Deserialize)]
+pub struct RefreshTokenRequest {
+    pub refresh_token: String,
+}
+
+// 刷新令牌响应体
+#[derive(Serialize)]
+pub struct RefreshTokenResponse {
+    pub success: bool,
+    pub message: String,
+    pub access_token: String,
+    pub refresh_token: String,
+    pub expires_at: i64,
+    pub refresh_expires_at: i64,
 }
 
 // 用户存在检查
@@ -83,6 +120,13 @@ pub struct AvatarUploadResponse {
     pub success: bool,
     pub message: String,
     pub avatar_url: Option<String>,
+    pub avatar_sizes: Vec<u32>, // 可用的头像尺寸（通过 avatar_url?size=N 获取）
+}
+
+// 获取头像的查询参数
+#[derive(Deserialize)]
+pub struct AvatarQuery {
+    pub size: Option<u32>, // 期望的边长（像素），返回不小于该尺寸的最小头像
 }
 
 // 通用成功响应体
@@ -114,13 +158,15 @@ pub async fn register_handler(
 ) -> Result<Json<RegisterResponse>, AppError> {
 
     
-    // 调用存储层注册用户（使用原始密码）
-    let user = state.db_pool.register_user(&req.username, "", &req.password)
-        .map_err(|e| match e {
-            rusqlite::Error::SqliteFailure(_, Some(msg)) if msg.contains("用户名已存在") =>
-                AppError::UserExists(msg),
-            _ => AppError::Database(e.to_string()),
-        })?;
+    // bcrypt 哈希计算耗时，放到阻塞线程池中执行
+    let password = req.password;
+    let password_hash = tokio::task::spawn_blocking(move || hash(password, DEFAULT_COST))
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))??;
+
+    // 调用存储层注册用户（用户名已存在时返回 409）
+    let username = req.username;
+    let user = state.db_pool.run(move |db| db.register_user(&username, &password_hash)).await?;
 
     // 返回成功响应
     Ok(Json(RegisterResponse {
@@ -133,41 +179,81 @@ pub async fn register_handler(
 // 登录处理器（核心API逻辑）
 pub async fn login_handler(
     State(state): State<AppState>, // 注入共享状态
+    headers: HeaderMap,            // 请求头（读取 User-Agent）
     Json(req): Json<LoginRequest>, // 解析JSON请求体
 ) -> Result<Json<LoginResponse>, AppError> {
     // 使用私有算法和公有算法加密密码（与注册时相同）
 
     
     // 调用存储层获取用户
-    let conn = state.db_pool.0.lock().unwrap();
-    let user = conn.query_row(
-        "SELECT id, username, password_hash FROM users WHERE username = ?",
-        [&req.username],
-        |row| {
-            Ok((
-                row.get::<_, String>(0)?,
-                row.get::<_, String>(1)?,
-                row.get::<_, String>(2)?,
-            ))
-        },
-    ).map_err(|e| match e {
-        rusqlite::Error::QueryReturnedNoRows =>
-            AppError::InvalidCredentials("用户名或密码错误".into()),
-        _ => AppError::Database(e.to_string()),
-    })?;
-
-    // 验证密码（使用解密后的原始密码）
-    let (id, username, password_hash) = user;
-    if !verify(&req.password, &password_hash).map_err(|_| AppError::Internal("密码验证失败".into()))? {
+    let username = req.username.clone();
+    let user = state.db_pool.run(move |db| db.get_user_by_username(&username)).await
+        .map_err(|e| match e {
+            StorageError::NotFound =>
+                AppError::InvalidCredentials("用户名或密码错误".into()),
+            e => e.into(),
+        })?;
+
+    // 验证密码（bcrypt 计算耗时，放到阻塞线程池中执行）
+    let password_hash = user.password_hash.clone();
+    let verified = tokio::task::spawn_blocking(move || verify(&req.password, &password_hash))
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?
+        .map_err(|_| AppError::Internal("密码验证失败".into()))?;
+    if !verified {
         return Err(AppError::InvalidCredentials("用户名或密码错误".into()));
     }
 
+    // 创建会话并签发令牌（设备名称优先使用客户端提供的值）
+    let device_name = req.device_name.as_deref()
+        .or_else(|| headers.get(USER_AGENT).and_then(|v| v.to_str().ok()))
+        .and_then(auth::normalize_device_name);
+    let tokens = auth::create_session(&state, &user.id, device_name).await?;
+
     // 返回成功响应
     Ok(Json(LoginResponse {
         success: true,
         message: "登录成功".into(),
-        user_id: Some(id),
-        username: Some(username),
+        user_id: Some(user.id),
+        username: Some(user.username),
+        access_token: Some(tokens.access_token),
+        refresh_token: Some(tokens.refresh_token),
+        expires_at: Some(tokens.expires_at),
+        refresh_expires_at: Some(tokens.refresh_expires_at),
+    }))
+}
+
+// 刷新令牌处理器
+pub async fn refresh_token_handler(
+    State(state): State<AppState>,
+    Json(req): Json<RefreshTokenRequest>,
+) -> Result<Json<RefreshTokenResponse>, AppError> {
+    let tokens = auth::refresh_session(&state, &req.refresh_token).await?;
+
+    Ok(Json(RefreshTokenResponse {
+        success: true,
+        message: "令牌刷新成功".into(),
+        access_token: tokens.access_token,
+        refresh_token: tokens.refresh_token,
+        expires_at: tokens.expires_at,
+        refresh_expires_at: tokens.refresh_expires_at,
+    }))
+}
+
+// 退出登录处理器（吊销当前会话）
+pub async fn logout_handler(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+) -> Result<Json<SuccessResponse>, AppError> {
+    let session_id = auth_user.session_id.clone();
+    state.db_pool.run(move |db| db.revoke_session(&session_id)).await?;
+
+    // 关闭当前设备上仍然打开的 WebSocket 连接
+    state.disconnect_session(&auth_user.user_id, &auth_user.session_id);
+
+    Ok(Json(SuccessResponse {
+        success: true,
+        message: "已退出登录".into(),
     }))
 }
 
@@ -176,8 +262,7 @@ pub async fn user_exists_handler(
     State(state): State<AppState>,
     Json(req): Json<UserExistsRequest>,
 ) -> Result<Json<UserExistsResponse>, AppError> {
-    let exists = state.db_pool.user_exists_by_id(&req.user_id)
-        .map_err(|e| AppError::Database(e.to_string()))?;
+    let exists = state.db_pool.run(move |db| db.user_exists_by_id(&req.user_id)).await?;
 
     Ok(Json(UserExistsResponse {
         success: true,
@@ -192,7 +277,7 @@ pub async fn get_user_info_handler(
     Path(user_id): Path<String>,
 ) -> Result<Json<UserInfoResponse>, AppError> {
     // 获取用户信息
-    let user = state.db_pool.get_user_by_id(&user_id).map_err(|e| AppError::Database(e.to_string()))?;
+    let user = state.db_pool.run(move |db| db.get_user_by_id(&user_id)).await?;
     
     // 转换为JSON值，不包含敏感信息
     let user_json = serde_json::json!({
@@ -212,82 +297,141 @@ pub async fn get_user_info_handler(
 // 头像上传处理器
 pub async fn upload_avatar_handler(
     State(state): State<AppState>,
+    auth_user: AuthUser,
     Path(user_id): Path<String>,
     mut multipart: Multipart,
 ) -> Result<Json<AvatarUploadResponse>, AppError> {
-    // 创建上传目录
-    let upload_dir = FilePath::new("./uploads/avatars");
-    if !upload_dir.exists() {
-        fs::create_dir_all(upload_dir).map_err(|e| AppError::Internal(e.to_string()))?;
+    // 只能修改自己的头像
+    if auth_user.user_id != user_id {
+        return Err(AppError::Forbidden("不能修改其他用户的头像".into()));
     }
 
     // 处理文件上传
-    while let Some(field) = multipart.next_field().await.map_err(|e| AppError::Internal(e.to_string()))? {
+    while let Some(mut field) = multipart.next_field().await.map_err(|e| AppError::Internal(e.to_string()))? {
         let name = field.name().unwrap_or("file");
         if name != "avatar" {
             continue;
         }
 
-        let filename = field.file_name().unwrap_or("");
-        let extension = FilePath::new(filename).extension().and_then(|ext| ext.to_str()).unwrap_or("png");
-        
-        // 生成唯一文件名
-        let unique_filename = format!("{}.{}", Uuid::new_v4(), extension);
-        let filepath = upload_dir.join(&unique_filename);
-        
-        // 读取文件内容
-        let file_content = field.bytes().await.map_err(|e| AppError::Internal(e.to_string()))?;
-        
-        // 保存文件
-        fs::write(&filepath, file_content).map_err(|e| AppError::Internal(e.to_string()))?;
-        
-        // 更新用户头像URL
-        let avatar_url = format!("/uploads/avatars/{}", unique_filename);
-        state.db_pool.update_user_avatar(&user_id, &avatar_url).map_err(|e| AppError::Database(e.to_string()))?;
+        // 逐块读取文件内容，超过头像上限时立即拒绝，不再继续接收
+        let limit = state.settings.limits.max_avatar_bytes;
+        let mut file_content = Vec::new();
+        while let Some(chunk) = field.chunk().await.map_err(|e| AppError::BadRequest(e.to_string()))? {
+            if file_content.len() + chunk.len() > limit {
+                return Err(AppError::PayloadTooLarge(format!("头像文件不能超过 {} 字节", limit)));
+            }
+            file_content.extend_from_slice(&chunk);
+        }
         
+        // 按内容识别并解码图片，裁剪缩放为各尺寸的 PNG（同时去掉了全部元数据）
+        let (kind, mime_type) = attachments::sniff(&file_content);
+        if kind != "image" {
+            return Err(AppError::BadRequest("头像必须是 PNG、JPEG、GIF 或 WebP 图片".into()));
+        }
+        let avatar = tokio::task::spawn_blocking(move || images::process_avatar(&file_content, mime_type))
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))??;
+
+        // 按内容保存到文件库（文件名为内容的 SHA-256），同时引用新头像、释放旧头像
+        let sha256 = blobs::sha256_hex(&avatar.content);
+        let avatar_url = format!("/uploads/avatars/{}.png", sha256);
+        let temp_path = blobs::write_temp(&state.settings.storage, &avatar.content).await?;
+        let (url, hash, size) = (avatar_url.clone(), sha256.clone(), avatar.content.len() as i64);
+        let old_url = blobs::store(&state.db_pool, &state.settings.storage, &temp_path, &sha256, avatar.variants, move |db| {
+            db.update_user_avatar(&user_id, &url, &hash, size)
+        }).await?;
+
+        // 旧版头像不在文件库中，替换后直接删除
+        if let Some(old_filename) = old_url.strip_prefix("/uploads/avatars/")
+            && avatar_blob_sha256(old_filename).is_none()
+            && downloads::is_safe_filename(old_filename)
+        {
+            let _ = fs::remove_file(state.settings.storage.avatar_dir().join(old_filename)).await;
+        }
+
         // 返回成功响应
+        let mut avatar_sizes = images::AVATAR_VARIANT_SIZES.to_vec();
+        avatar_sizes.push(images::AVATAR_SIZE);
         return Ok(Json(AvatarUploadResponse {
             success: true,
             message: "头像上传成功".into(),
             avatar_url: Some(avatar_url),
+            avatar_sizes,
         }));
     }
 
     Err(AppError::Internal("未找到头像文件".into()))
 }
 
-// 获取头像处理器
+// 文件库中的头像以 `{sha256}.{扩展名}` 命名，返回其中的 SHA-256
+fn avatar_blob_sha256(filename: &str) -> Option<&str> {
+    let stem = filename.split('.').next().unwrap_or_default();
+    (stem.len() == 64 && stem.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))).then_some(stem)
+}
+
+// 获取头像处理器（文件库中的头像可通过 `size` 选择尺寸）
 pub async fn get_avatar_handler(
+    State(state): State<AppState>,
     Path(filename): Path<String>,
-) -> Result<impl IntoResponse, AppError> {
-    let filepath = FilePath::new("./uploads/avatars").join(filename);
-    
-    if !filepath.exists() {
+    Query(query): Query<AvatarQuery>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    // 文件名会拼接到目录下，先排除路径分隔符等字符
+    if !downloads::is_safe_filename(&filename) {
         return Err(AppError::NotFound("头像文件不存在".into()));
     }
-    
-    // 读取文件内容
-    let file_content = fs::read(&filepath).map_err(|e| AppError::Internal(e.to_string()))?;
-    
-    // 猜测MIME类型
-    let mime_type = from_path(&filepath).first_or_octet_stream().to_string();
-    
-    // 构建响应
-    Ok((
-        [(CONTENT_TYPE, mime_type)],
-        file_content,
-    ))
+    const NOT_FOUND: &str = "头像文件不存在";
+    let blob_sha256 = avatar_blob_sha256(&filename);
+    let content_type = match blob_sha256 {
+        // 文件库中还有附件等私有文件，只公开某个用户当前的头像；头像及各尺寸均为 PNG
+        Some(sha256) => {
+            let hash = sha256.to_string();
+            if !state.db_pool.run(move |db| db.is_avatar_blob(&hash)).await? {
+                return Err(AppError::NotFound(NOT_FOUND.into()));
+            }
+            "image/png"
+        }
+        // 旧版头像按文件内容识别类型，不是图片的不返回
+        None => {
+            let head = downloads::read_head(&state.settings.storage.avatar_dir().join(&filename), 16, NOT_FOUND).await?;
+            match attachments::sniff(&head) {
+                ("image", mime_type) => mime_type,
+                _ => return Err(AppError::NotFound(NOT_FOUND.into())),
+            }
+        }
+    };
+    let filepath = match blob_sha256 {
+        Some(sha256) => match query.size.and_then(|size| images::AVATAR_VARIANT_SIZES.into_iter().find(|&s| s >= size)) {
+            Some(size) => blobs::variant_path(&state.settings.storage, sha256, &images::avatar_variant(size)),
+            None => blobs::blob_path(&state.settings.storage, sha256),
+        },
+        None => state.settings.storage.avatar_dir().join(&filename),
+    };
+
+    // 类型不取自URL中的扩展名；以内容命名的头像不会改变，可以长期缓存
+    let download = downloads::Download {
+        content_type: content_type.to_string(),
+        content_disposition: None,
+        cache_control: if blob_sha256.is_some() { downloads::CACHE_IMMUTABLE } else { downloads::CACHE_REVALIDATE },
+        not_found: NOT_FOUND,
+    };
+    downloads::serve_file(&headers, &filepath, download).await
 }
 
 // 更新用户信息处理器
 pub async fn update_user_info_handler(
     State(state): State<AppState>,
+    auth_user: AuthUser,
     Path(user_id): Path<String>,
     Json(req): Json<UpdateUserRequest>,
 ) -> Result<Json<SuccessResponse>, AppError> {
+    // 只能修改自己的信息
+    if auth_user.user_id != user_id {
+        return Err(AppError::Forbidden("不能修改其他用户的信息".into()));
+    }
+
     // 更新用户信息
-    state.db_pool.update_user_info(&user_id, &req.username, &req.email)
-        .map_err(|e| AppError::Database(e.to_string()))?;
+    state.db_pool.run(move |db| db.update_user_info(&user_id, &req.username, &req.email)).await?;
     
     Ok(Json(SuccessResponse {
         success: true,
@@ -296,13 +440,21 @@ pub async fn update_user_info_handler(
 }
 
 /// 注册用户相关路由
-pub fn register_routes() -> Router<AppState> {
+pub fn register_routes(settings: &Settings) -> Router<AppState> {
+    // 头像上传的请求体上限按头像大小限制放宽（预留 multipart 边界开销）
+    let avatar_body_limit = settings.limits.max_avatar_bytes + 64 * 1024;
+
     Router::new()
         .route("/register", post(register_handler))
         .route("/login", post(login_handler))
+        .route("/auth/refresh", post(refresh_token_handler))
+        .route("/logout", post(logout_handler))
         .route("/user/exists", post(user_exists_handler))
         .route("/user/{user_id}", get(get_user_info_handler))
         .route("/user/{user_id}", put(update_user_info_handler))
-        .route("/user/{user_id}/avatar", post(upload_avatar_handler))
+        .route(
+            "/user/{user_id}/avatar",
+            post(upload_avatar_handler).layer(DefaultBodyLimit::max(avatar_body_limit)),
+        )
         .route("/uploads/avatars/{filename}", get(get_avatar_handler))
 }
\ No newline at end of file
@@ -1,19 +1,24 @@
 use axum::{
     extract::{
+        Query,
         State,
         ws::{
+            CloseFrame,
             WebSocketUpgrade, 
             Message, 
             WebSocket
         }
     },
+    http::{
+        header::AUTHORIZATION,
+        HeaderMap
+    },
     routing::get,
     Router
 };
-use serde_json::{
-    Value
-};
-use std::collections::HashMap;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
 use std::sync::{
     Arc, 
     Mutex
@@ -22,194 +27,546 @@ use futures_util::{
     SinkExt, 
     StreamExt
 };
-use tokio::sync::broadcast;
+use std::time::Duration;
+use tokio::sync::{
+    broadcast,
+    Notify
+};
+use tokio::task::JoinHandle;
 use uuid::Uuid;
+use crate::config::Settings;
+use crate::core::auth::{
+    self,
+    AuthUser
+};
+use crate::core::{
+    messaging,
+    presence,
+    protocol::{
+        self,
+        ClientEnvelope,
+        ClientFrame,
+        PresenceStatus,
+        ServerFrame
+    },
+    reactions,
+    sync
+};
+use crate::error::AppError;
+use crate::storage::{
+    now_secs,
+    MessageExtras
+};
+
+/// 单个用户的群聊转发任务（群ID -> 任务）
+type GroupTasks = HashMap<String, JoinHandle<()>>;
+
+/// 一条在线的 WebSocket 连接
+///
+/// 同一用户可在多个设备（登录会话）上同时在线，同一设备也可能有多条连接。
+struct DeviceConnection {
+    /// 连接所属的登录会话ID
+    session_id: String,
+    /// 连接建立时间戳
+    connected_at: i64,
+    /// 连接专用推送通道
+    tx: broadcast::Sender<String>,
+    /// 通知发送任务关闭连接（设备被退出登录时使用）
+    closer: Arc<Notify>,
+}
+
+/// 单个用户的在线连接（连接ID -> 连接）
+type UserConnections = HashMap<String, DeviceConnection>;
 
 /// 共享应用状态
 #[derive(Clone)]
 pub struct AppState {
     pub db_pool: crate::storage::DbPool,
-    /// 用户ID到WebSocket广播通道的映射
-    clients: Arc<Mutex<HashMap<String, broadcast::Sender<String>>>>,
-    /// 客户端ID到用户ID的映射，用于断开连接时清理资源
-    client_user_map: Arc<Mutex<HashMap<String, String>>>,
-    /// 全局广播通道，用于向所有客户端发送消息
-    broadcaster: broadcast::Sender<String>,
+    /// 服务器配置
+    pub settings: Arc<Settings>,
+    /// 访问令牌签名器
+    pub token_signer: Arc<crate::core::auth::TokenSigner>,
+    /// 用户ID到其全部在线连接的映射，推送时扇出到每条连接
+    clients: Arc<Mutex<HashMap<String, UserConnections>>>,
     pub group_chat_broadcast_channel_map: Arc<Mutex<HashMap<String, broadcast::Sender<String>>>>,
+    /// 用户ID到其群聊转发任务的映射（群ID -> 任务），成员变动时增减订阅
+    group_subscriptions: Arc<Mutex<HashMap<String, GroupTasks>>>,
+    /// 在线但标记为离开的用户
+    away_users: Arc<Mutex<HashSet<String>>>,
 }
 
 impl AppState {
     /// 创建新的应用状态
-    pub fn new(db_pool: crate::storage::DbPool) -> Self {
-        let (broadcaster, _) = broadcast::channel(100);
+    pub fn new(db_pool: crate::storage::DbPool, settings: Settings, token_signer: crate::core::auth::TokenSigner) -> Self {
         Self {
             db_pool,
+            settings: Arc::new(settings),
+            token_signer: Arc::new(token_signer),
             clients: Arc::new(Mutex::new(HashMap::new())),
-            client_user_map: Arc::new(Mutex::new(HashMap::new())),
-            broadcaster,
-            group_chat_broadcast_channel_map: Arc::new(Mutex::new(HashMap::new()))
+            group_chat_broadcast_channel_map: Arc::new(Mutex::new(HashMap::new())),
+            group_subscriptions: Arc::new(Mutex::new(HashMap::new())),
+            away_users: Arc::new(Mutex::new(HashSet::new())),
         }
     }
     
-    /// 获取客户端映射（用于消息推送）
-    pub fn get_clients(&self) -> &Arc<Mutex<HashMap<String, broadcast::Sender<String>>>> {
-        &self.clients
+    // 登记新连接，返回关闭通知句柄以及这是否为用户的第一条连接
+    fn add_connection(&self, user_id: &str, connection_id: &str, session_id: &str, tx: broadcast::Sender<String>) -> (Arc<Notify>, bool) {
+        let closer = Arc::new(Notify::new());
+        let mut clients = self.clients.lock().unwrap();
+        let connections = clients.entry(user_id.to_string()).or_default();
+        let first = connections.is_empty();
+        connections.insert(connection_id.to_string(), DeviceConnection {
+            session_id: session_id.to_string(),
+            connected_at: now_secs(),
+            tx,
+            closer: closer.clone(),
+        });
+        (closer, first)
+    }
+
+    // 移除断开的连接，返回用户是否已没有任何在线连接
+    //
+    // 最后一条连接断开时在同一把锁内终止群聊转发任务，
+    // 避免与紧接着建立的新连接的订阅交错。
+    fn remove_connection(&self, user_id: &str, connection_id: &str) -> bool {
+        let mut clients = self.clients.lock().unwrap();
+        let Some(connections) = clients.get_mut(user_id) else {
+            return false;
+        };
+        connections.remove(connection_id);
+        if !connections.is_empty() {
+            return false;
+        }
+        clients.remove(user_id);
+        self.unsubscribe_all(user_id);
+        true
+    }
+
+    /// 向在线用户的全部连接推送一帧（离线时忽略）
+    pub fn send_to_user(&self, user_id: &str, frame: String) {
+        if let Some(connections) = self.clients.lock().unwrap().get(user_id) {
+            for connection in connections.values() {
+                let _ = connection.tx.send(frame.clone());
+            }
+        }
+    }
+
+    /// 用户在线的设备：会话ID -> 该会话最早一条连接的建立时间
+    pub fn online_sessions(&self, user_id: &str) -> HashMap<String, i64> {
+        let mut sessions: HashMap<String, i64> = HashMap::new();
+        if let Some(connections) = self.clients.lock().unwrap().get(user_id) {
+            for connection in connections.values() {
+                sessions
+                    .entry(connection.session_id.clone())
+                    .and_modify(|at| *at = (*at).min(connection.connected_at))
+                    .or_insert(connection.connected_at);
+            }
+        }
+        sessions
+    }
+
+    /// 断开某个登录会话的全部连接，断开前推送 `signed_out` 帧
+    pub fn disconnect_session(&self, user_id: &str, session_id: &str) {
+        let frame = ServerFrame::SignedOut {
+            session_id: session_id.to_string(),
+        }.encode();
+        if let Some(connections) = self.clients.lock().unwrap().get(user_id) {
+            for connection in connections.values().filter(|c| c.session_id == session_id) {
+                let _ = connection.tx.send(frame.clone());
+                connection.closer.notify_one();
+            }
+        }
+    }
+
+    /// 用户当前的在线状态："online"、"away" 或 "offline"
+    pub fn presence_of(&self, user_id: &str) -> &'static str {
+        if !self.clients.lock().unwrap().contains_key(user_id) {
+            "offline"
+        } else if self.away_users.lock().unwrap().contains(user_id) {
+            "away"
+        } else {
+            "online"
+        }
     }
+
+    /// 设置在线用户是否处于离开状态，返回状态是否发生变化
+    pub fn set_away(&self, user_id: &str, away: bool) -> bool {
+        let mut away_users = self.away_users.lock().unwrap();
+        if away {
+            away_users.insert(user_id.to_string())
+        } else {
+            away_users.remove(user_id)
+        }
+    }
+
+    /// 向群聊广播通道推送一帧（没有在线成员时忽略）
+    pub fn broadcast_to_group(&self, group_id: &str, frame: String) {
+        if let Some(tx) = self.group_chat_broadcast_channel_map.lock().unwrap().get(group_id) {
+            let _ = tx.send(frame);
+        }
+    }
+
+    /// 为在线用户订阅群聊广播通道（离线时忽略，下次连接时按 group_members 订阅）
+    ///
+    /// 群广播通道不存在时创建；每个用户每个群只有一个转发任务，
+    /// 群消息经 `send_to_user` 扇出到用户的全部连接。
+    pub fn subscribe_to_group(&self, user_id: &str, group_id: &str) {
+        if !self.clients.lock().unwrap().contains_key(user_id) {
+            return;
+        }
+        let mut rx = self.group_chat_broadcast_channel_map.lock().unwrap()
+            .entry(group_id.to_string())
+            .or_insert_with(|| broadcast::channel::<String>(self.settings.channels.group_capacity).0)
+            .subscribe();
+
+        let state = self.clone();
+        let member_id = user_id.to_string();
+        let task = tokio::spawn(async move {
+            loop {
+                match rx.recv().await {
+                    Ok(msg) => state.send_to_user(&member_id, msg),
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+
+        let mut subscriptions = self.group_subscriptions.lock().unwrap();
+        if let Some(old) = subscriptions.entry(user_id.to_string()).or_default().insert(group_id.to_string(), task) {
+            old.abort();
+        }
+    }
+
+    /// 取消用户对群聊的订阅
+    pub fn unsubscribe_from_group(&self, user_id: &str, group_id: &str) {
+        let mut subscriptions = self.group_subscriptions.lock().unwrap();
+        if let Some(task) = subscriptions.get_mut(user_id).and_then(|groups| groups.remove(group_id)) {
+            task.abort();
+        }
+    }
+
+    /// 解散群聊后移除其广播通道，所有订阅随之结束
+    pub fn close_group(&self, group_id: &str) {
+        let mut subscriptions = self.group_subscriptions.lock().unwrap();
+        for groups in subscriptions.values_mut() {
+            if let Some(task) = groups.remove(group_id) {
+                task.abort();
+            }
+        }
+        self.group_chat_broadcast_channel_map.lock().unwrap().remove(group_id);
+    }
+
+    // 用户最后一条连接断开时终止其全部群聊转发任务
+    fn unsubscribe_all(&self, user_id: &str) {
+        if let Some(groups) = self.group_subscriptions.lock().unwrap().remove(user_id) {
+            for task in groups.into_values() {
+                task.abort();
+            }
+        }
+    }
+}
+
+/// 认证失败时使用的关闭码（4000-4999 为应用自定义区间）
+const CLOSE_UNAUTHORIZED: u16 = 4001;
+/// 设备被退出登录时使用的关闭码
+const CLOSE_SIGNED_OUT: u16 = 4002;
+/// 客户端协议版本不受支持时使用的关闭码
+const CLOSE_UNSUPPORTED_VERSION: u16 = 4003;
+/// 等待认证帧的超时时间
+const AUTH_FRAME_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// WebSocket 升级时可携带的查询参数
+#[derive(Deserialize)]
+struct WsAuthQuery {
+    token: Option<String>,
+    /// 客户端支持的最高协议版本
+    version: Option<u32>,
 }
 
 /// WebSocket连接升级处理器
+///
+/// 访问令牌可通过 `?token=` 查询参数或 `Authorization: Bearer` 请求头携带；
+/// 两者都没有时，客户端需在连接后的第一帧发送 `{"type":"identify","token":"...","version":1}`。
+/// 协议版本通过 `?version=` 或认证帧的 `version` 声明，缺省为当前版本。
 async fn ws_handler(
     upgrade: WebSocketUpgrade,
     State(state): State<AppState>,
+    Query(query): Query<WsAuthQuery>,
+    headers: HeaderMap,
 ) -> impl axum::response::IntoResponse {
-    upgrade.on_upgrade(|socket| handle_websocket(socket, state))
+    let credential = query.token.or_else(|| {
+        headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(|token| token.trim().to_string())
+    });
+    let version = query.version;
+    upgrade.on_upgrade(move |socket| handle_websocket(socket, state, credential, version))
+}
+
+/// 握手被拒绝：关闭码与原因
+type Rejection = (u16, String);
+
+/// 认证 WebSocket 连接并协商协议版本，返回当前用户与选定的版本
+///
+/// 握手时未携带令牌则读取第一帧作为认证帧。
+async fn authenticate_socket(
+    socket: &mut WebSocket,
+    state: &AppState,
+    credential: Option<String>,
+    version: Option<u32>,
+) -> Result<(AuthUser, u32), Rejection> {
+    let unauthorized = |reason: &str| (CLOSE_UNAUTHORIZED, reason.to_string());
+    let (token, version) = match credential {
+        Some(token) => (token, version),
+        None => {
+            let frame = tokio::time::timeout(AUTH_FRAME_TIMEOUT, socket.recv())
+                .await
+                .map_err(|_| unauthorized("等待认证超时"))?;
+            let Some(Ok(Message::Text(text))) = frame else {
+                return Err(unauthorized("缺少认证帧"));
+            };
+            match serde_json::from_str::<ClientEnvelope>(&text) {
+                Ok(ClientEnvelope { frame: ClientFrame::Identify { token, version: declared }, .. }) => {
+                    (token, declared.or(version))
+                }
+                Ok(_) => return Err(unauthorized("第一帧必须是认证帧")),
+                Err(_) => return Err(unauthorized("认证帧格式错误")),
+            }
+        }
+    };
+
+    let version = protocol::negotiate_version(version).ok_or_else(|| (
+        CLOSE_UNSUPPORTED_VERSION,
+        format!("不支持的协议版本，服务端支持 {} 到 {}", protocol::MIN_PROTOCOL_VERSION, protocol::PROTOCOL_VERSION),
+    ))?;
+    let auth_user = auth::authenticate(state, &token).await.map_err(|e| unauthorized(&e.to_string()))?;
+    Ok((auth_user, version))
+}
+
+// 尽量从无法解析的帧中取出 request_id，便于客户端对应错误
+fn request_id_of(text: &str) -> Option<String> {
+    let value: Value = serde_json::from_str(text).ok()?;
+    value.get("request_id")
+        .or_else(|| value.get("client_msg_id"))
+        .and_then(|x| x.as_str())
+        .map(str::to_string)
+}
+
+/// 处理一个已解析的客户端请求
+///
+/// 返回 `Some` 时为该请求的结果帧；返回 `None` 表示处理成功且没有专门的结果帧。
+async fn dispatch(
+    state: &AppState,
+    auth_user: &AuthUser,
+    request_id: &Option<String>,
+    frame: ClientFrame,
+) -> Result<Option<ServerFrame>, AppError> {
+    let user_id = auth_user.user_id.as_str();
+    match frame {
+        ClientFrame::Identify { .. } => Err(AppError::BadRequest("连接已认证".into())),
+        // 好友消息：保存后推送给会话双方，并向当前连接回执
+        ClientFrame::FriendMessage { receiver_id, content, reply_to_id, attachments } => {
+            let extras = MessageExtras { attachment_ids: attachments, ..Default::default() };
+            let message = messaging::send_private_message(state, user_id, &receiver_id, &content, reply_to_id.as_deref(), extras).await?;
+            Ok(Some(ServerFrame::MessageAck {
+                request_id: request_id.clone(),
+                message_id: message.id,
+                receiver_id: Some(message.receiver_id),
+                group_id: None,
+                created_at: message.created_at,
+            }))
+        }
+        // 群聊消息：校验成员身份后保存，再推送给群成员
+        ClientFrame::GroupChat { group_id, content, reply_to_id, mentions, mention_all, attachments } => {
+            let extras = MessageExtras { mentions, mention_all, attachment_ids: attachments };
+            let message = messaging::send_group_message(state, user_id, &group_id, &content, reply_to_id.as_deref(), extras).await?;
+            Ok(Some(ServerFrame::MessageAck {
+                request_id: request_id.clone(),
+                message_id: message.id,
+                receiver_id: None,
+                group_id: Some(message.receiver_id),
+                created_at: message.created_at,
+            }))
+        }
+        // 送达确认：发送方收到 delivered 回执
+        ClientFrame::Delivered { message_ids } => {
+            messaging::mark_delivered(state, user_id, message_ids).await?;
+            Ok(None)
+        }
+        // 已读确认：私聊带 message_ids，群聊带 group_id 与已读到的 message_id
+        ClientFrame::Read { message_ids, group_id, message_id } => {
+            match (group_id, message_id, message_ids) {
+                (Some(group_id), Some(message_id), _) => {
+                    messaging::mark_group_read(state, user_id, &group_id, &message_id).await?;
+                }
+                (None, None, Some(message_ids)) => {
+                    messaging::mark_read(state, user_id, message_ids).await?;
+                }
+                _ => return Err(AppError::BadRequest("缺少 message_ids 或 group_id/message_id".into())),
+            }
+            Ok(None)
+        }
+        // 输入状态：只转发不保存
+        ClientFrame::Typing { receiver_id, group_id, typing } => {
+            messaging::relay_typing(state, user_id, receiver_id.as_deref(), group_id.as_deref(), typing).await?;
+            Ok(None)
+        }
+        // 表情回应：变化推送给会话参与者
+        ClientFrame::React { message_id, emoji, remove } => {
+            reactions::react(state, user_id, &message_id, &emoji, !remove).await?;
+            Ok(None)
+        }
+        // 在线状态：客户端切换 "away"（离开）与 "online"
+        ClientFrame::Presence { status } => {
+            presence::set_away(state, user_id, matches!(status, PresenceStatus::Away)).await;
+            Ok(None)
+        }
+        // 离线同步：客户端带上已收到的最大序号 since，按批补齐错过的事件
+        ClientFrame::Sync { since, limit } => {
+            let batch = sync::sync(state, auth_user, since, limit).await?;
+            Ok(Some(ServerFrame::SyncBatch {
+                request_id: request_id.clone(),
+                events: batch.events,
+                last_seq: batch.last_seq,
+                has_more: batch.has_more,
+            }))
+        }
+        ClientFrame::Unknown => Err(AppError::BadRequest("未知的帧类型".into())),
+    }
+}
+
+/// 处理一帧客户端文本，返回需要回复给当前连接的帧
+///
+/// 带 `request_id` 的请求总是得到一帧回复；不带的请求只在出错或有结果帧时回复。
+async fn handle_text_frame(state: &AppState, auth_user: &AuthUser, text: &str) -> Option<ServerFrame> {
+    let envelope = match serde_json::from_str::<ClientEnvelope>(text) {
+        Ok(envelope) => envelope,
+        Err(e) => return Some(ServerFrame::error(request_id_of(text), "bad_frame", format!("帧格式错误: {}", e))),
+    };
+    let ClientEnvelope { request_id, frame } = envelope;
+    if matches!(frame, ClientFrame::Unknown) {
+        return Some(ServerFrame::error(request_id, "unknown_type", "未知的帧类型"));
+    }
+
+    match dispatch(state, auth_user, &request_id, frame).await {
+        Ok(Some(reply)) => Some(reply),
+        Ok(None) => request_id.map(|id| ServerFrame::Ack { request_id: Some(id) }),
+        Err(e) => Some(ServerFrame::from_error(request_id, &e)),
+    }
 }
 
 /// 处理WebSocket连接
-async fn handle_websocket(socket: WebSocket, state: AppState) {
+async fn handle_websocket(mut socket: WebSocket, state: AppState, credential: Option<String>, version: Option<u32>) {
+    // 认证或版本协商失败时以自定义关闭码断开连接
+    let (auth_user, protocol_version) = match authenticate_socket(&mut socket, &state, credential, version).await {
+        Ok(accepted) => accepted,
+        Err((code, reason)) => {
+            println!("WebSocket握手失败: {}", reason);
+            let _ = socket.send(Message::Close(Some(CloseFrame {
+                code,
+                reason: reason.into(),
+            }))).await;
+            return;
+        }
+    };
+    let user_id = auth_user.user_id.clone();
+
     let (mut sender, mut receiver) = socket.split();
     let client_id = Uuid::new_v4().to_string();
     
     // 创建客户端专用广播通道
-    let (self_tx, mut self_rx) = broadcast::channel(100);
-    
-    println!("新WebSocket客户端连接: {}", client_id);
+    let (self_tx, mut self_rx) = broadcast::channel(state.settings.channels.client_capacity);
     
-    // 广播新客户端连接消息
-    let _ = state.broadcaster.send(format!("Client {} joined", client_id));
-//---------------------------------------------------------------------------------------------------------------------------------------------------------------------
-    // 初始化或获取用户加入的所有群聊的订阅广播通道
-    let head = if let Some(Ok(Message::Text(text))) = receiver.next().await{
-        let head: Value=serde_json::from_str(&text).unwrap(); //注意unwrap后续修复 
-        println!("调试打印: {{来自ws的消息: {head}}}");
-        if let Some(list_of_group_chats)=head["list_of_group_chats"].as_array() {
-            println!("调试打印: {{群聊功能初始化: 此用户存在群}}");
-            for group_id_value in list_of_group_chats { //为每个群聊创建一个广播通道
-                if let Value::String(group_id)=group_id_value {
-                    let mut group_chat_broadcast_channel_map= state.group_chat_broadcast_channel_map.lock().unwrap(); //注意unwrap后续修复
-                    // 当前群广播通道已经创建过了直接克隆订阅端通道,并开启群消息接收任务
-                    if group_chat_broadcast_channel_map.contains_key(group_id) {
-                        let mut rx=group_chat_broadcast_channel_map[group_id].subscribe();
-                        let self_tx=self_tx.clone();
-                        tokio::spawn(async move {
-                            while let Ok(msg) = rx.recv().await {
-                                if self_tx.send(msg).is_err() {
-                                    break;
-                                }
-                            }
-                        });
-                        continue;
-                    }
-                    // 当前群没有创建过群聊广播通道则创建,并开启消息接收任务
-                    let (tx, mut rx) = broadcast::channel::<String>(100);
-                    group_chat_broadcast_channel_map.insert(group_id.clone(),tx);
-                    let self_tx=self_tx.clone();
-                    tokio::spawn(async move {
-                        while let Ok(msg) = rx.recv().await {
-                            if self_tx.send(msg).is_err() {
-                                break;
-                            }   
-                        }
-                    });
-                }
-            }
-        }else{
-            println!("调试打印: {{群聊功能初始化: 此用户没有群}}");
+    println!("新WebSocket客户端连接: {} (用户 {}, 协议版本 {})", client_id, user_id, protocol_version);
+
+    // 登记连接，同一用户的其他设备连接不受影响
+    let (closer, first_connection) = state.add_connection(&user_id, &client_id, &auth_user.session_id, self_tx.clone());
+
+    // 根据 group_members 表查询用户所在的全部群聊
+    let uid = user_id.clone();
+    let group_ids = match state.db_pool.run(move |db| db.get_user_group_ids(&uid)).await {
+        Ok(group_ids) => group_ids,
+        Err(e) => {
+            println!("查询用户 {} 的群聊失败: {}", user_id, e);
+            Vec::new()
         }
-        head
-    }else {
-        Value::Null
     };
-//-----------------------------------------------------------------------------------------------------------------------------------------------------------------
+
+    // 用户的第一条连接负责订阅群聊并通知好友上线，其余连接共享这些订阅
+    if first_connection {
+        for group_id in &group_ids {
+            state.subscribe_to_group(&user_id, group_id);
+        }
+        presence::user_connected(&state, &user_id).await;
+    }
+
+    // 通知客户端认证成功
+    let _ = self_tx.send(ServerFrame::Identified {
+        protocol_version,
+        user_id: user_id.clone(),
+        session_id: auth_user.session_id.clone(),
+        group_ids,
+    }.encode());
+
     let state_clone = state.clone();
-    let client_id_clone = client_id.clone();
-    // 身份初始化
-    if let Some(msg_type) = head.get("type").and_then(|x| x.as_str()) {
-        match msg_type {
-            "identify" => {
-                // 处理客户端身份标识
-                if let Some(user_id) = head.get("user_id").and_then(|x| x.as_str()) {
-                    // 将客户端通道映射到用户ID，方便推送定向通知
-                    let mut clients_map = state_clone.clients.lock().unwrap();
-                    clients_map.insert(user_id.to_string(), self_tx.clone());
-                    // 记录客户端ID到用户ID的映射，便于断开时清理
-                    state_clone.client_user_map.lock().unwrap().insert(client_id_clone.clone(), user_id.to_string());
-                    println!("WebSocket客户端 {} 标识为用户 {}", client_id_clone, user_id);
-                }
-            },
-            _=>()
-        }
-    }
-//----------------------------------------------------------------------------------------------------------------------------------------------------------------------
-// 身份初始化和群聊初始化先后顺序好像搞反了但不影响运行
-
-    // 处理接收消息的任务
-    let recv_task = tokio::spawn(async move {
-        while let Some(Ok(Message::Text(text))) = receiver.next().await {
-            // 尝试解析为JSON以处理特殊类型消息
-            if let Ok(v) = serde_json::from_str::<Value>(&text) {
-                println!("调试打印: {{来自ws的消息: {v}}}");
-                if let Some(msg_type) = v.get("type").and_then(|x| x.as_str()) {
-                    match msg_type {
-                        // 好友消息分支
-                        "friend_Message"=>{
-
-                        },
-                        // 群聊消息分支
-                        "group_chat"  => {
-                            if let Some(group_id)=v.get("group_id").and_then(|x| x.as_str()) {
-                                let group_chat_broadcast_channel_map=state_clone.group_chat_broadcast_channel_map.lock().unwrap();
-                                let content=if let Some(msg)= v.get("content").and_then(|x| x.as_str()) {
-                                    msg
-                                }else{
-                                    continue;
-                                };
-                                group_chat_broadcast_channel_map[group_id].send(content.to_string()).unwrap();
-                            }
-                        },
-                        _ => {}
-                    }
-                }
+    let reply_tx = self_tx.clone();
+
+    // 处理接收消息的任务：每帧的回复只发给当前连接
+    let mut recv_task = tokio::spawn(async move {
+        while let Some(Ok(message)) = receiver.next().await {
+            let reply = match message {
+                Message::Text(text) => handle_text_frame(&state_clone, &auth_user, &text).await,
+                Message::Binary(_) => Some(ServerFrame::error(None, "bad_frame", "只支持文本帧")),
+                Message::Close(_) => break,
+                // Ping/Pong 由底层自动处理
+                _ => None,
+            };
+            if let Some(reply) = reply {
+                let _ = reply_tx.send(reply.encode());
             }
-            println!("从客户端 {} 收到消息: {}", client_id_clone, text);
-            // 广播消息给所有客户端
-            let _ = state_clone.broadcaster.send(format!("{}: {}", client_id_clone, text));
         }
     });
     
-    // 处理发送消息的任务
-    let send_task = tokio::spawn(async move {
-        while let Ok(msg) = self_rx.recv().await {
-            if sender.send(Message::Text(msg.into())).await.is_err() {
-                break;
+    // 处理发送消息的任务；设备被退出登录时先送出已排队的帧再关闭连接
+    let mut send_task = tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                biased;
+                msg = self_rx.recv() => match msg {
+                    Ok(msg) => {
+                        if sender.send(Message::Text(msg.into())).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                _ = closer.notified() => {
+                    let _ = sender.send(Message::Close(Some(CloseFrame {
+                        code: CLOSE_SIGNED_OUT,
+                        reason: "设备已退出登录".into(),
+                    }))).await;
+                    break;
+                }
             }
         }
     });
     
-    // 等待任一任务结束
+    // 等待任一任务结束，并终止另一个
     tokio::select! {
-        _ = recv_task => (),
-        _ = send_task => (),
+        _ = &mut recv_task => send_task.abort(),
+        _ = &mut send_task => recv_task.abort(),
     }
     
-    // 清理资源：移除客户端映射
-    {
-        let mut clients = state.clients.lock().unwrap();
-        clients.remove(&client_id);
-    }
-
-    // 清理用户ID映射（如果存在）
-    {
-        let mut client_user_map = state.client_user_map.lock().unwrap();
-        if let Some(user_id) = client_user_map.remove(&client_id) {
-            let mut clients = state.clients.lock().unwrap();
-            clients.remove(&user_id);
-        }
+    // 移除本连接；用户的最后一条连接断开时停止群消息转发，
+    // 记录最近在线时间并通知好友下线
+    if state.remove_connection(&user_id, &client_id) {
+        presence::user_disconnected(&state, &user_id).await;
     }
 
     println!("WebSocket客户端断开连接: {}", client_id);
-    // 广播客户端断开连接消息
-    let _ = state.broadcaster.send(format!("Client {} left", client_id));
 }
 
 /// 注册WebSocket路由
@@ -0,0 +1,176 @@
+use clap::Parser;
+use std::net::SocketAddr;
+use std::path::{
+    Path,
+    PathBuf
+};
+use std::str::FromStr;
+use super::settings::{
+    ConfigError,
+    DatabaseBackend,
+    Settings
+};
+
+/// 未指定配置文件时尝试读取的默认路径
+const DEFAULT_CONFIG_FILE: &str = "yueling.toml";
+/// 环境变量前缀
+const ENV_PREFIX: &str = "YUELING_";
+
+/// 命令行参数
+#[derive(Debug, Default, Parser)]
+#[command(name = "server", about = "月灵聊天服务器")]
+pub struct Cli {
+    /// 配置文件路径（也可通过 YUELING_CONFIG 指定）
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+    /// 监听地址，例如 0.0.0.0:2025
+    #[arg(long)]
+    pub bind: Option<SocketAddr>,
+    /// SQLite 数据库文件路径
+    #[arg(long)]
+    pub database: Option<PathBuf>,
+    /// PostgreSQL 连接串（指定后使用 postgres 后端）
+    #[arg(long)]
+    pub database_url: Option<String>,
+    /// 上传文件根目录
+    #[arg(long)]
+    pub upload_root: Option<PathBuf>,
+    /// 允许的跨域来源，可重复指定
+    #[arg(long = "cors-origin")]
+    pub cors_origins: Vec<String>,
+    /// 只执行数据库迁移，完成后退出
+    #[arg(long)]
+    pub migrate_only: bool,
+    /// 试运行待执行的数据库迁移（在事务中执行后回滚），不修改数据库并退出
+    #[arg(long)]
+    pub dry_run: bool,
+    /// 立即回收不再被引用的附件与文件，输出回收的空间后退出
+    #[arg(long)]
+    pub gc: bool,
+}
+
+/// 解析命令行参数并加载配置
+pub fn load() -> Result<(Cli, Settings), ConfigError> {
+    let cli = Cli::parse();
+    let settings = load_with(&cli, |key| std::env::var(key).ok())?;
+    Ok((cli, settings))
+}
+
+/// 按 默认值 → 配置文件 → 环境变量 → 命令行参数 的顺序加载配置并校验
+///
+/// `env` 用于读取环境变量，便于在不修改进程环境的情况下加载配置。
+pub fn load_with(cli: &Cli, env: impl Fn(&str) -> Option<String>) -> Result<Settings, ConfigError> {
+    // 确定配置文件：命令行 > 环境变量 > 当前目录下的默认文件
+    let config_path = cli.config.clone()
+        .or_else(|| env("YUELING_CONFIG").map(PathBuf::from))
+        .or_else(|| {
+            let default = PathBuf::from(DEFAULT_CONFIG_FILE);
+            default.exists().then_some(default)
+        });
+
+    let mut settings = match config_path {
+        Some(path) => read_file(&path)?,
+        None => Settings::default(),
+    };
+    if settings.channels.broadcast_capacity.is_some() {
+        println!("配置项 channels.broadcast_capacity 已废弃（全局广播通道已移除），将被忽略");
+    }
+
+    apply_env(&mut settings, &env)?;
+    apply_cli(&mut settings, cli);
+
+    settings.validate()?;
+    Ok(settings)
+}
+
+/// 读取并解析 TOML 配置文件
+fn read_file(path: &Path) -> Result<Settings, ConfigError> {
+    let text = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    toml::from_str(&text).map_err(|source| ConfigError::Parse {
+        path: path.to_path_buf(),
+        source: Box::new(source),
+    })
+}
+
+/// 读取单个环境变量并解析到目标字段
+fn env_override<T: FromStr>(
+    env: &impl Fn(&str) -> Option<String>,
+    name: &str,
+    target: &mut T,
+) -> Result<(), ConfigError> {
+    let key = format!("{}{}", ENV_PREFIX, name);
+    if let Some(value) = env(&key) {
+        *target = value.parse().map_err(|_| ConfigError::Env { key, value })?;
+    }
+    Ok(())
+}
+
+/// 应用 `YUELING_*` 环境变量覆盖
+fn apply_env(settings: &mut Settings, env: &impl Fn(&str) -> Option<String>) -> Result<(), ConfigError> {
+    env_override(env, "BIND", &mut settings.server.bind)?;
+    env_override(env, "DB_BACKEND", &mut settings.database.backend)?;
+    env_override(env, "DATABASE_PATH", &mut settings.database.path)?;
+    env_override(env, "DB_POOL_SIZE", &mut settings.database.pool_size)?;
+    env_override(env, "DB_BUSY_TIMEOUT_MS", &mut settings.database.busy_timeout_ms)?;
+    env_override(env, "UPLOAD_ROOT", &mut settings.storage.upload_root)?;
+    env_override(env, "GC_GRACE_SECS", &mut settings.storage.gc_grace_secs)?;
+    env_override(env, "GC_INTERVAL_SECS", &mut settings.storage.gc_interval_secs)?;
+    env_override(env, "CLIENT_CAPACITY", &mut settings.channels.client_capacity)?;
+    env_override(env, "GROUP_CAPACITY", &mut settings.channels.group_capacity)?;
+    env_override(env, "MAX_REQUEST_BODY_BYTES", &mut settings.limits.max_request_body_bytes)?;
+    env_override(env, "MAX_AVATAR_BYTES", &mut settings.limits.max_avatar_bytes)?;
+    env_override(env, "MAX_IMAGE_BYTES", &mut settings.limits.max_image_bytes)?;
+    env_override(env, "MAX_VOICE_BYTES", &mut settings.limits.max_voice_bytes)?;
+    env_override(env, "MAX_FILE_BYTES", &mut settings.limits.max_file_bytes)?;
+    env_override(env, "MAX_RESUMABLE_UPLOAD_BYTES", &mut settings.limits.max_resumable_upload_bytes)?;
+    env_override(env, "MAX_UPLOAD_CHUNK_BYTES", &mut settings.limits.max_upload_chunk_bytes)?;
+    env_override(env, "UPLOAD_EXPIRY_SECS", &mut settings.limits.upload_expiry_secs)?;
+    env_override(env, "MAX_MESSAGE_CHARS", &mut settings.limits.max_message_chars)?;
+    env_override(env, "MAX_HISTORY_PAGE_SIZE", &mut settings.limits.max_history_page_size)?;
+    env_override(env, "MAX_SYNC_BATCH_SIZE", &mut settings.limits.max_sync_batch_size)?;
+    env_override(env, "RECALL_WINDOW_SECS", &mut settings.limits.recall_window_secs)?;
+    env_override(env, "ACCESS_TOKEN_TTL_SECS", &mut settings.auth.access_token_ttl_secs)?;
+    env_override(env, "REFRESH_TOKEN_TTL_SECS", &mut settings.auth.refresh_token_ttl_secs)?;
+
+    // 逗号分隔的来源列表
+    if let Some(origins) = env(&format!("{}CORS_ORIGINS", ENV_PREFIX)) {
+        settings.cors.allowed_origins = origins
+            .split(',')
+            .map(|origin| origin.trim().to_string())
+            .filter(|origin| !origin.is_empty())
+            .collect();
+    }
+
+    if let Some(url) = env(&format!("{}DATABASE_URL", ENV_PREFIX)) {
+        settings.database.url = Some(url);
+    }
+
+    if let Some(secret) = env(&format!("{}TOKEN_SECRET", ENV_PREFIX)) {
+        settings.auth.token_secret = Some(secret);
+    }
+
+    Ok(())
+}
+
+/// 应用命令行参数覆盖
+fn apply_cli(settings: &mut Settings, cli: &Cli) {
+    if let Some(bind) = cli.bind {
+        settings.server.bind = bind;
+    }
+    if let Some(database) = &cli.database {
+        settings.database.path = database.clone();
+    }
+    if let Some(url) = &cli.database_url {
+        settings.database.backend = DatabaseBackend::Postgres;
+        settings.database.url = Some(url.clone());
+    }
+    if let Some(upload_root) = &cli.upload_root {
+        settings.storage.upload_root = upload_root.clone();
+    }
+    if !cli.cors_origins.is_empty() {
+        settings.cors.allowed_origins = cli.cors_origins.clone();
+    }
+}
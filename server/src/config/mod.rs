@@ -1,3 +1,5 @@
 pub mod loader;
 pub mod settings;
 
+pub use settings::Settings;
+
@@ -0,0 +1,333 @@
+use std::path::{
+    Path,
+    PathBuf
+};
+use axum::body::Bytes;
+use futures_util::{
+    Stream,
+    StreamExt
+};
+use sha2::{
+    Digest,
+    Sha256
+};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use crate::api::AppState;
+use crate::config::Settings;
+use crate::core::{
+    blobs,
+    images
+};
+use crate::error::AppError;
+use crate::storage::{
+    Attachment,
+    NewAttachment,
+    Storage,
+    StorageError
+};
+
+/// 单条消息最多附件数
+pub const MAX_ATTACHMENTS_PER_MESSAGE: usize = 9;
+
+/// 附件文件名最多字符数
+const MAX_FILENAME_CHARS: usize = 255;
+
+/// 识别类型时读取的开头字节数
+pub const SNIFF_BYTES: usize = 8 * 1024;
+
+/// 作为图片展示的格式（其余图片格式按普通文件处理）
+const IMAGE_MIME_TYPES: [&str; 4] = ["image/png", "image/jpeg", "image/gif", "image/webp"];
+
+/// 浏览器可能当作页面或脚本执行的文本格式，降级为 text/plain
+const ACTIVE_MIME_TYPES: [&str; 3] = ["text/html", "text/xml", "text/x-shellscript"];
+
+/// Windows 保留的设备名（不区分大小写，带扩展名同样保留）
+const RESERVED_FILENAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL",
+    "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// 按文件内容识别附件类型与MIME类型，不信任客户端提供的文件名和 Content-Type
+///
+/// 返回 `("image" | "voice" | "file", mime_type)`；无法识别的 UTF-8 文本以及 HTML 等可执行的文本视为 text/plain，
+/// 其余视为 application/octet-stream。`content` 可以只是文件开头，末尾被截断的多字节字符不影响判断。
+pub fn sniff(content: &[u8]) -> (&'static str, &'static str) {
+    match infer::get(content) {
+        Some(detected) if ACTIVE_MIME_TYPES.contains(&detected.mime_type()) => ("file", "text/plain"),
+        Some(detected) => {
+            let mime_type = detected.mime_type();
+            let kind = if IMAGE_MIME_TYPES.contains(&mime_type) {
+                "image"
+            } else if mime_type.starts_with("audio/") {
+                "voice"
+            } else {
+                "file"
+            };
+            (kind, mime_type)
+        }
+        None if !content.contains(&0) && is_utf8_prefix(content) => ("file", "text/plain"),
+        None => ("file", "application/octet-stream"),
+    }
+}
+
+// 是否为合法 UTF-8（允许末尾的字符不完整）
+fn is_utf8_prefix(content: &[u8]) -> bool {
+    match std::str::from_utf8(content) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    }
+}
+
+/// 各类附件的大小上限（字节）
+pub fn max_bytes(settings: &Settings, kind: &str) -> usize {
+    match kind {
+        "image" => settings.limits.max_image_bytes,
+        "voice" => settings.limits.max_voice_bytes,
+        _ => settings.limits.max_file_bytes,
+    }
+}
+
+/// 任意类型附件的最大上限（上传请求体限制以此为准）
+pub fn max_upload_bytes(settings: &Settings) -> usize {
+    ["image", "voice", "file"].into_iter().map(|kind| max_bytes(settings, kind)).max().unwrap_or_default()
+}
+
+/// 规范化客户端提供的文件名：只保留最后一段路径，去掉控制字符与双向文本控制符并限制长度
+///
+/// 末尾的点和空格会被去掉，Windows 保留的设备名（如 `CON`、`nul.txt`）前加 `_`。
+pub fn sanitize_filename(filename: &str) -> String {
+    let base = filename.rsplit(['/', '\\']).next().unwrap_or_default();
+    let cleaned: String = base.chars()
+        .filter(|c| !c.is_control() && !is_bidi_control(*c))
+        .take(MAX_FILENAME_CHARS)
+        .collect();
+    let name = cleaned.trim().trim_end_matches(['.', ' ']);
+    if name.is_empty() {
+        return "file".into();
+    }
+    let stem = name.split('.').next().unwrap_or_default().trim_end();
+    if RESERVED_FILENAMES.iter().any(|reserved| reserved.eq_ignore_ascii_case(stem)) {
+        return format!("_{}", name);
+    }
+    name.to_string()
+}
+
+// 可以让文件名显示顺序与实际不同的双向文本控制符（例如把 `gpj.exe` 显示为 `exe.jpg`）
+fn is_bidi_control(c: char) -> bool {
+    matches!(c, '\u{200E}' | '\u{200F}' | '\u{061C}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}')
+}
+
+/// 下载时的 Content-Disposition：图片与语音内联展示，其他文件作为下载
+///
+/// 非 ASCII 文件名按 RFC 6266 放在 `filename*` 中，`filename` 只保留 ASCII 回退名。
+pub fn content_disposition(attachment: &Attachment) -> String {
+    let disposition = match attachment.kind.as_str() {
+        "image" | "voice" => "inline",
+        _ => "attachment",
+    };
+    let fallback: String = attachment.filename.chars()
+        .map(|c| if (c.is_ascii_graphic() && c != '"' && c != '\\') || c == ' ' { c } else { '_' })
+        .collect();
+    let encoded: String = attachment.filename.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'.' | b'-' | b'_' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect();
+    format!("{}; filename=\"{}\"; filename*=UTF-8''{}", disposition, fallback, encoded)
+}
+
+/// 附件文件路径（旧附件没有 SHA-256，仍以附件ID命名）
+pub fn attachment_path(settings: &Settings, attachment: &Attachment) -> PathBuf {
+    match &attachment.sha256 {
+        Some(sha256) => blobs::blob_path(&settings.storage, sha256),
+        None => settings.storage.attachment_dir().join(&attachment.id),
+    }
+}
+
+/// 保存上传的附件
+///
+/// 按内容识别类型并检查该类型的大小上限，然后放入文件库（相同内容只保存一份）。
+/// 图片会校验能否解码、去除元数据并生成缩略图，见 [`images::process_image`]。
+pub async fn save_attachment(state: &AppState, uploader_id: &str, filename: &str, content: &[u8]) -> Result<Attachment, AppError> {
+    if content.is_empty() {
+        return Err(AppError::BadRequest("文件不能为空".into()));
+    }
+    let (kind, mime_type) = sniff(content);
+    let limit = max_bytes(&state.settings, kind);
+    if content.len() > limit {
+        return Err(AppError::PayloadTooLarge(format!("该类型的附件不能超过 {} 字节", limit)));
+    }
+
+    let (content, image, variants) = if kind == "image" {
+        let original = content.to_vec();
+        let processed = tokio::task::spawn_blocking(move || images::process_image(&original, mime_type))
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))??;
+        let variants = vec![(images::THUMBNAIL_VARIANT.to_string(), processed.thumbnail)];
+        (processed.content, Some(processed.info), variants)
+    } else {
+        (content.to_vec(), None, Vec::new())
+    };
+
+    let new = NewAttachment {
+        kind: kind.into(),
+        mime_type: mime_type.into(),
+        size: content.len() as i64,
+        filename: filename.into(),
+        sha256: blobs::sha256_hex(&content),
+        image,
+    };
+    let temp_path = blobs::write_temp(&state.settings.storage, &content).await?;
+    store_file(state, uploader_id, &temp_path, new, variants).await
+}
+
+/// 以流的方式保存上传的附件：边接收边写入文件库的临时文件并计算 SHA-256
+///
+/// 收到足够识别类型的开头后即按该类型的大小上限检查，超过时立即返回 `PayloadTooLarge`，不再继续接收。
+/// 图片不超过图片上限，收完后读入内存按 [`save_attachment`] 处理。
+pub async fn save_attachment_stream<S, E>(state: &AppState, uploader_id: &str, filename: &str, stream: S) -> Result<Attachment, AppError>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: std::fmt::Display,
+{
+    let (temp_path, file) = blobs::create_temp(&state.settings.storage).await?;
+    let (sha256, size, kind, mime_type) = match receive(&state.settings, file, stream).await {
+        Ok(received) => received,
+        Err(e) => {
+            let _ = fs::remove_file(&temp_path).await;
+            return Err(e);
+        }
+    };
+
+    if kind == "image" {
+        let content = fs::read(&temp_path).await.map_err(|e| AppError::Internal(e.to_string()));
+        let _ = fs::remove_file(&temp_path).await;
+        return save_attachment(state, uploader_id, filename, &content?).await;
+    }
+    let new = NewAttachment {
+        kind: kind.into(),
+        mime_type: mime_type.into(),
+        size: size as i64,
+        filename: filename.into(),
+        sha256,
+        image: None,
+    };
+    store_file(state, uploader_id, &temp_path, new, Vec::new()).await
+}
+
+// 把上传内容写入文件，返回 SHA-256、大小、附件类型与MIME类型
+//
+// 开头不足以识别类型时先按所有类型中最大的上限检查。
+async fn receive<S, E>(settings: &Settings, mut file: fs::File, mut stream: S) -> Result<(String, u64, &'static str, &'static str), AppError>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: std::fmt::Display,
+{
+    let io_error = |e: std::io::Error| AppError::Internal(e.to_string());
+    let too_large = |limit: usize| AppError::PayloadTooLarge(format!("该类型的附件不能超过 {} 字节", limit));
+    let mut hasher = Sha256::new();
+    let mut head = Vec::with_capacity(SNIFF_BYTES);
+    let mut limit = max_upload_bytes(settings);
+    let mut size: usize = 0;
+    while let Some(data) = stream.next().await {
+        let data = data.map_err(|e| AppError::BadRequest(format!("上传中断: {}", e)))?;
+        if head.len() < SNIFF_BYTES {
+            let take = data.len().min(SNIFF_BYTES - head.len());
+            head.extend_from_slice(&data[..take]);
+            if head.len() == SNIFF_BYTES {
+                limit = max_bytes(settings, sniff(&head).0);
+            }
+        }
+        size += data.len();
+        if size > limit {
+            return Err(too_large(limit));
+        }
+        hasher.update(&data);
+        file.write_all(&data).await.map_err(io_error)?;
+    }
+    if size == 0 {
+        return Err(AppError::BadRequest("文件不能为空".into()));
+    }
+    let (kind, mime_type) = sniff(&head);
+    if size > max_bytes(settings, kind) {
+        return Err(too_large(max_bytes(settings, kind)));
+    }
+    file.flush().await.map_err(io_error)?;
+    file.sync_data().await.map_err(io_error)?;
+    Ok((hex::encode(hasher.finalize()), size as u64, kind, mime_type))
+}
+
+/// 将已写入磁盘、识别过类型并算好 SHA-256 的文件登记为附件并放入文件库
+///
+/// 登记失败时删除该文件。`temp_path` 必须与文件库位于同一文件系统，`variants` 为派生文件（如缩略图）。
+pub async fn store_file(
+    state: &AppState,
+    uploader_id: &str,
+    temp_path: &Path,
+    mut new: NewAttachment,
+    variants: Vec<(String, Vec<u8>)>,
+) -> Result<Attachment, AppError> {
+    new.filename = sanitize_filename(&new.filename);
+    let (uploader, sha256) = (uploader_id.to_string(), new.sha256.clone());
+    blobs::store(&state.db_pool, &state.settings.storage, temp_path, &sha256, variants, move |db| {
+        db.create_attachment(&uploader, &new)
+    }).await
+}
+
+/// 附件缩略图路径（只有保存在文件库中的图片附件有缩略图）
+pub fn thumbnail_path(settings: &Settings, attachment: &Attachment) -> Option<PathBuf> {
+    match (&attachment.sha256, attachment.kind.as_str()) {
+        (Some(sha256), "image") => Some(blobs::variant_path(&settings.storage, sha256, images::THUMBNAIL_VARIANT)),
+        _ => None,
+    }
+}
+
+/// 校验消息引用的附件：去重后不超过上限，且发送者能访问每个附件（本人上传或转发自己所在会话中的附件）
+pub fn check_attachments(db: &dyn Storage, sender_id: &str, attachment_ids: Vec<String>) -> Result<Vec<String>, AppError> {
+    let mut checked: Vec<String> = Vec::with_capacity(attachment_ids.len());
+    for attachment_id in attachment_ids {
+        if !checked.contains(&attachment_id) {
+            checked.push(attachment_id);
+        }
+    }
+    if checked.len() > MAX_ATTACHMENTS_PER_MESSAGE {
+        return Err(AppError::BadRequest(format!("单条消息最多 {} 个附件", MAX_ATTACHMENTS_PER_MESSAGE)));
+    }
+    for attachment_id in &checked {
+        if !db.can_access_attachment(sender_id, attachment_id)? {
+            return Err(AppError::BadRequest("附件不存在".into()));
+        }
+    }
+    Ok(checked)
+}
+
+/// 读取用户可以访问的附件，否则视为不存在
+pub fn load_attachment(db: &dyn Storage, user_id: &str, attachment_id: &str) -> Result<Attachment, AppError> {
+    let not_found = || AppError::NotFound("附件不存在".into());
+    if !db.can_access_attachment(user_id, attachment_id)? {
+        return Err(not_found());
+    }
+    db.get_attachment(attachment_id).map_err(|e| match e {
+        StorageError::NotFound => not_found(),
+        e => e.into(),
+    })
+}
+
+/// 批量获取用户可以访问的附件信息（无权访问或不存在的ID忽略）
+pub async fn attachment_infos(state: &AppState, user_id: &str, attachment_ids: Vec<String>) -> Result<Vec<Attachment>, AppError> {
+    let user = user_id.to_string();
+    state.db_pool.run(move |db| {
+        let mut attachments = Vec::with_capacity(attachment_ids.len());
+        for attachment_id in &attachment_ids {
+            match load_attachment(db, &user, attachment_id) {
+                Ok(attachment) => attachments.push(attachment),
+                Err(AppError::NotFound(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(attachments)
+    }).await
+}
@@ -0,0 +1,206 @@
+use axum::{
+    extract::FromRequestParts,
+    http::{
+        header::AUTHORIZATION,
+        request::Parts
+    }
+};
+use base64::{
+    engine::general_purpose::URL_SAFE_NO_PAD,
+    Engine
+};
+use hmac::{
+    Hmac,
+    Mac
+};
+use rand::RngCore;
+use serde::Serialize;
+use sha2::{
+    Digest,
+    Sha256
+};
+use crate::api::AppState;
+use crate::config::settings::AuthSettings;
+use crate::error::AppError;
+use crate::storage::{
+    now_secs,
+    DbPool,
+    StorageResult
+};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// 登录或刷新后签发给客户端的令牌对
+#[derive(Debug, Serialize)]
+pub struct TokenPair {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub expires_at: i64,         // 访问令牌过期时间戳
+    pub refresh_expires_at: i64, // 刷新令牌过期时间戳
+}
+
+/// 访问令牌的签发与校验
+///
+/// 访问令牌格式为 `base64(session_id:user_id:expires_at).base64(hmac)`，
+/// 签名通过后仍需在 sessions 表中确认会话未被吊销。
+pub struct TokenSigner {
+    secret: Vec<u8>,
+}
+
+impl TokenSigner {
+    pub fn new(secret: Vec<u8>) -> Self {
+        Self { secret }
+    }
+
+    /// 优先使用配置中的签名密钥，未配置时从数据库读取（不存在则生成并持久化）
+    pub async fn load(settings: &AuthSettings, db_pool: &DbPool) -> StorageResult<Self> {
+        if let Some(secret) = &settings.token_secret {
+            return Ok(Self::new(secret.clone().into_bytes()));
+        }
+        let candidate = hex::encode(random_bytes());
+        let secret = db_pool.run(move |db| db.get_or_create_token_secret(&candidate)).await?;
+        Ok(Self::new(secret.into_bytes()))
+    }
+
+    fn sign(&self, payload: &[u8]) -> Vec<u8> {
+        let mut mac = HmacSha256::new_from_slice(&self.secret).expect("HMAC 接受任意长度的密钥");
+        mac.update(payload);
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    /// 为会话签发访问令牌
+    pub fn issue_access_token(&self, session_id: &str, user_id: &str, expires_at: i64) -> String {
+        let payload = format!("{}:{}:{}", session_id, user_id, expires_at);
+        let signature = self.sign(payload.as_bytes());
+        format!("{}.{}", URL_SAFE_NO_PAD.encode(payload), URL_SAFE_NO_PAD.encode(signature))
+    }
+
+    /// 校验访问令牌的签名与有效期，返回 (session_id, user_id)
+    pub fn verify_access_token(&self, token: &str) -> Result<(String, String), AppError> {
+        let invalid = || AppError::Unauthorized("无效的访问令牌".into());
+
+        let (payload_b64, signature_b64) = token.split_once('.').ok_or_else(invalid)?;
+        let payload = URL_SAFE_NO_PAD.decode(payload_b64).map_err(|_| invalid())?;
+        let signature = URL_SAFE_NO_PAD.decode(signature_b64).map_err(|_| invalid())?;
+
+        let mut mac = HmacSha256::new_from_slice(&self.secret).expect("HMAC 接受任意长度的密钥");
+        mac.update(&payload);
+        mac.verify_slice(&signature).map_err(|_| invalid())?;
+
+        let payload = String::from_utf8(payload).map_err(|_| invalid())?;
+        let mut parts = payload.splitn(3, ':');
+        let (Some(session_id), Some(user_id), Some(expires_at)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(invalid());
+        };
+        let expires_at: i64 = expires_at.parse().map_err(|_| invalid())?;
+        if expires_at <= now_secs() {
+            return Err(AppError::Unauthorized("访问令牌已过期".into()));
+        }
+
+        Ok((session_id.to_string(), user_id.to_string()))
+    }
+}
+
+/// 生成32字节随机数
+fn random_bytes() -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes
+}
+
+/// 计算刷新令牌的存储哈希（数据库中不保存明文）
+pub fn hash_refresh_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// 设备名称最大长度（字符数，超出部分截断）
+const MAX_DEVICE_NAME_CHARS: usize = 64;
+
+/// 规范化设备名称：去除首尾空白并截断，空名称返回 None
+pub fn normalize_device_name(name: &str) -> Option<String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return None;
+    }
+    Some(name.chars().take(MAX_DEVICE_NAME_CHARS).collect())
+}
+
+/// 为用户创建新会话并签发令牌对
+pub async fn create_session(state: &AppState, user_id: &str, device_name: Option<String>) -> Result<TokenPair, AppError> {
+    let now = now_secs();
+    let refresh_token = URL_SAFE_NO_PAD.encode(random_bytes());
+    let expires_at = now + state.settings.auth.access_token_ttl_secs;
+    let refresh_expires_at = now + state.settings.auth.refresh_token_ttl_secs;
+
+    let owner_id = user_id.to_string();
+    let refresh_token_hash = hash_refresh_token(&refresh_token);
+    let session = state.db_pool
+        .run(move |db| db.create_session(&owner_id, &refresh_token_hash, refresh_expires_at, device_name.as_deref()))
+        .await?;
+
+    Ok(TokenPair {
+        access_token: state.token_signer.issue_access_token(&session.id, user_id, expires_at),
+        refresh_token,
+        expires_at,
+        refresh_expires_at,
+    })
+}
+
+/// 使用刷新令牌换取新的令牌对（刷新令牌随之轮换，旧令牌失效）
+pub async fn refresh_session(state: &AppState, refresh_token: &str) -> Result<TokenPair, AppError> {
+    let now = now_secs();
+    let new_refresh_token = URL_SAFE_NO_PAD.encode(random_bytes());
+    let expires_at = now + state.settings.auth.access_token_ttl_secs;
+    let refresh_expires_at = now + state.settings.auth.refresh_token_ttl_secs;
+
+    let old_token_hash = hash_refresh_token(refresh_token);
+    let new_token_hash = hash_refresh_token(&new_refresh_token);
+    let session = state.db_pool
+        .run(move |db| db.rotate_refresh_token(&old_token_hash, &new_token_hash, refresh_expires_at))
+        .await?
+        .ok_or_else(|| AppError::Unauthorized("刷新令牌无效或已过期".into()))?;
+
+    Ok(TokenPair {
+        access_token: state.token_signer.issue_access_token(&session.id, &session.user_id, expires_at),
+        refresh_token: new_refresh_token,
+        expires_at,
+        refresh_expires_at,
+    })
+}
+
+/// 校验访问令牌并确认对应会话仍然有效
+pub async fn authenticate(state: &AppState, token: &str) -> Result<AuthUser, AppError> {
+    let (session_id, user_id) = state.token_signer.verify_access_token(token)?;
+
+    let (sid, uid) = (session_id.clone(), user_id.clone());
+    let active = state.db_pool.run(move |db| db.is_session_active(&sid, &uid)).await?;
+    if !active {
+        return Err(AppError::Unauthorized("会话已失效，请重新登录".into()));
+    }
+
+    Ok(AuthUser { user_id, session_id })
+}
+
+/// 已认证的调用者
+///
+/// 作为处理器参数使用时从 `Authorization: Bearer <token>` 解析当前用户，
+/// 处理器不再从请求体中读取操作者身份。
+#[derive(Debug, Clone)]
+pub struct AuthUser {
+    pub user_id: String,
+    pub session_id: String,
+}
+
+impl FromRequestParts<AppState> for AuthUser {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let token = parts.headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| AppError::Unauthorized("缺少访问令牌".into()))?;
+
+        authenticate(state, token.trim()).await
+    }
+}
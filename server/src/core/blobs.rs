@@ -0,0 +1,224 @@
+// 按 SHA-256 寻址的文件库
+//
+// 附件与头像的内容以 SHA-256 命名保存在 blobs 目录（按前两位分子目录），相同内容只保存一份。
+// 由内容派生的文件（缩略图、各尺寸头像）保存在同名的 `.variants` 目录，随原文件一起删除。
+// 引用数由附件和用户头像维护；引用数归零超过宽限期后由垃圾回收删除文件，文件删除成功后才删除记录，
+// 删除失败的文件在下次回收时重试。
+use std::io::ErrorKind;
+use std::path::{
+    Path,
+    PathBuf
+};
+use std::time::Duration;
+use sha2::{
+    Digest,
+    Sha256
+};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+use crate::config::settings::StorageSettings;
+use crate::error::AppError;
+use crate::storage::{
+    now_secs,
+    DbPool,
+    Storage,
+    StorageResult
+};
+
+// 保存文件时持有读锁，垃圾回收时持有写锁：
+// 避免回收删除记录后、删除文件前，新的上传恰好引用同一内容而文件随后被删掉
+static GC_LOCK: RwLock<()> = RwLock::const_new(());
+
+/// 一次垃圾回收的结果
+#[derive(Debug, Default)]
+pub struct GcReport {
+    /// 删除的未被消息引用的附件数
+    pub attachments_removed: usize,
+    /// 删除的文件数
+    pub blobs_removed: usize,
+    /// 回收的空间（字节）
+    pub bytes_reclaimed: u64,
+}
+
+/// 计算内容的 SHA-256（小写十六进制）
+pub fn sha256_hex(content: &[u8]) -> String {
+    hex::encode(Sha256::digest(content))
+}
+
+/// 文件在文件库中的路径
+pub fn blob_path(storage: &StorageSettings, sha256: &str) -> PathBuf {
+    storage.blob_dir().join(&sha256[..2]).join(sha256)
+}
+
+/// 派生文件的路径
+pub fn variant_path(storage: &StorageSettings, sha256: &str, name: &str) -> PathBuf {
+    variant_dir(storage, sha256).join(name)
+}
+
+// 派生文件目录
+fn variant_dir(storage: &StorageSettings, sha256: &str) -> PathBuf {
+    storage.blob_dir().join(&sha256[..2]).join(format!("{}.variants", sha256))
+}
+
+/// 在文件库目录下写入临时文件（与正式文件位于同一文件系统，可直接改名）
+pub async fn write_temp(storage: &StorageSettings, content: &[u8]) -> Result<PathBuf, AppError> {
+    let (temp_path, mut file) = create_temp(storage).await?;
+    let result = async {
+        file.write_all(content).await?;
+        file.flush().await
+    }.await;
+    if let Err(e) = result {
+        let _ = fs::remove_file(&temp_path).await;
+        return Err(AppError::Internal(e.to_string()));
+    }
+    Ok(temp_path)
+}
+
+/// 在文件库目录下创建空的临时文件，供边接收边写入
+pub async fn create_temp(storage: &StorageSettings) -> Result<(PathBuf, fs::File), AppError> {
+    let dir = storage.blob_dir();
+    fs::create_dir_all(&dir).await.map_err(|e| AppError::Internal(e.to_string()))?;
+    let temp_path = dir.join(format!(".upload-{}", Uuid::new_v4()));
+    let file = fs::File::create(&temp_path).await.map_err(|e| AppError::Internal(e.to_string()))?;
+    Ok((temp_path, file))
+}
+
+/// 将内容为 `sha256` 的临时文件放入文件库
+///
+/// 先登记文件记录并放入文件与派生文件，最后由 `reference` 在数据库中登记引用（必须增加该文件的引用数），
+/// 数据库中的引用因此总是指向已写入的文件。文件库中已有相同内容时直接删除临时文件；
+/// 任何一步失败时同样删除临时文件，已放入但未被引用的文件由垃圾回收删除。
+/// `variants` 为派生文件（名称与内容），已存在的不会重复写入。
+pub async fn store<T, F>(
+    db_pool: &DbPool,
+    storage: &StorageSettings,
+    temp_path: &Path,
+    sha256: &str,
+    variants: Vec<(String, Vec<u8>)>,
+    reference: F,
+) -> Result<T, AppError>
+where
+    F: FnOnce(&dyn Storage) -> StorageResult<T> + Send + 'static,
+    T: Send + 'static,
+{
+    let _guard = GC_LOCK.read().await;
+    let result = place(db_pool, storage, temp_path, sha256, variants).await;
+    let result = match result {
+        Ok(()) => db_pool.run(reference).await.map_err(AppError::from),
+        Err(e) => Err(e),
+    };
+    // 临时文件已改名时删除会失败，忽略即可
+    let _ = fs::remove_file(temp_path).await;
+    result
+}
+
+// 登记文件记录，放入派生文件与文件本身
+async fn place(
+    db_pool: &DbPool,
+    storage: &StorageSettings,
+    temp_path: &Path,
+    sha256: &str,
+    variants: Vec<(String, Vec<u8>)>,
+) -> Result<(), AppError> {
+    let io_error = |e: std::io::Error| AppError::Internal(e.to_string());
+    let size = fs::metadata(temp_path).await.map_err(io_error)?.len() as i64;
+    let hash = sha256.to_string();
+    db_pool.run(move |db| db.register_blob(&hash, size)).await?;
+
+    if !variants.is_empty() {
+        fs::create_dir_all(variant_dir(storage, sha256)).await.map_err(io_error)?;
+    }
+    for (name, content) in variants {
+        let path = variant_path(storage, sha256, &name);
+        if !fs::try_exists(&path).await.unwrap_or(false) {
+            fs::write(&path, content).await.map_err(io_error)?;
+        }
+    }
+
+    let path = blob_path(storage, sha256);
+    if fs::try_exists(&path).await.unwrap_or(false) {
+        return Ok(());
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).await.map_err(io_error)?;
+    }
+    fs::rename(temp_path, &path).await.map_err(io_error)
+}
+
+// 删除文件及其派生文件，返回释放的字节数（派生文件目录不存在时忽略）
+async fn remove_blob(storage: &StorageSettings, sha256: &str) -> std::io::Result<u64> {
+    let mut reclaimed = 0;
+    let dir = variant_dir(storage, sha256);
+    match fs::read_dir(&dir).await {
+        Ok(mut entries) => {
+            while let Some(entry) = entries.next_entry().await? {
+                reclaimed += entry.metadata().await?.len();
+            }
+            fs::remove_dir_all(&dir).await?;
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    let path = blob_path(storage, sha256);
+    reclaimed += fs::metadata(&path).await?.len();
+    fs::remove_file(&path).await?;
+    Ok(reclaimed)
+}
+
+/// 回收一次：先删除超过宽限期仍未被消息引用的附件，再删除引用数归零超过宽限期的文件
+///
+/// 不在文件库中的旧附件没有引用数，记录删除后直接删除其文件。
+pub async fn collect_garbage(db_pool: &DbPool, storage: &StorageSettings) -> Result<GcReport, AppError> {
+    let _guard = GC_LOCK.write().await;
+    let cutoff = now_secs() - storage.gc_grace_secs;
+    let attachments = db_pool.run(move |db| db.delete_orphan_attachments(cutoff)).await?;
+
+    let mut report = GcReport { attachments_removed: attachments.len(), ..Default::default() };
+    for attachment in attachments.iter().filter(|a| a.sha256.is_none()) {
+        let path = storage.attachment_dir().join(&attachment.id);
+        match fs::remove_file(&path).await {
+            Ok(()) => report.bytes_reclaimed += attachment.size as u64,
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => println!("删除附件文件 {} 失败: {}", path.display(), e),
+        }
+    }
+
+    // 先删除文件再删除记录：删除失败时保留记录，下次回收重试
+    let blobs = db_pool.run(move |db| db.list_unreferenced_blobs(cutoff)).await?;
+    for blob in blobs {
+        let reclaimed = match remove_blob(storage, &blob.sha256).await {
+            Ok(reclaimed) => reclaimed,
+            Err(e) if e.kind() == ErrorKind::NotFound => 0,
+            Err(e) => {
+                println!("删除文件 {} 失败: {}", blob.sha256, e);
+                continue;
+            }
+        };
+        let sha256 = blob.sha256.clone();
+        if db_pool.run(move |db| db.delete_unreferenced_blob(&sha256)).await? && reclaimed > 0 {
+            report.blobs_removed += 1;
+            report.bytes_reclaimed += reclaimed;
+        }
+    }
+    Ok(report)
+}
+
+/// 启动后台任务，定期回收不再被引用的文件
+pub fn spawn_gc_task(db_pool: DbPool, storage: StorageSettings) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(storage.gc_interval_secs));
+        loop {
+            interval.tick().await;
+            match collect_garbage(&db_pool, &storage).await {
+                Ok(report) if report.attachments_removed > 0 || report.blobs_removed > 0 => println!(
+                    "垃圾回收: 删除 {} 个未发送的附件、{} 个文件，回收 {} 字节",
+                    report.attachments_removed, report.blobs_removed, report.bytes_reclaimed
+                ),
+                Ok(_) => {}
+                Err(e) => println!("垃圾回收失败: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,228 @@
+// 文件下载
+//
+// 从磁盘流式读取文件，支持单个 Range 请求（视频拖动、断点续传）、ETag / Last-Modified 条件请求
+// 与 Cache-Control。多段 Range 与格式不正确的 Range 按规范忽略，返回完整内容。
+use std::io::{
+    ErrorKind,
+    SeekFrom
+};
+use std::path::Path;
+use std::time::{
+    SystemTime,
+    UNIX_EPOCH
+};
+use axum::{
+    body::Body,
+    http::{
+        header::{
+            ACCEPT_RANGES,
+            CACHE_CONTROL,
+            CONTENT_DISPOSITION,
+            CONTENT_LENGTH,
+            CONTENT_RANGE,
+            CONTENT_TYPE,
+            ETAG,
+            IF_MODIFIED_SINCE,
+            IF_NONE_MATCH,
+            IF_RANGE,
+            LAST_MODIFIED,
+            RANGE,
+            X_CONTENT_TYPE_OPTIONS
+        },
+        HeaderMap,
+        HeaderValue,
+        StatusCode
+    },
+    response::Response
+};
+use tokio::fs::File;
+use tokio::io::{
+    AsyncReadExt,
+    AsyncSeekExt
+};
+use tokio_util::io::ReaderStream;
+use crate::error::AppError;
+
+/// 按内容寻址、内容永不改变的文件（如以 SHA-256 命名的头像）
+pub const CACHE_IMMUTABLE: &str = "public, max-age=31536000, immutable";
+
+/// 公开但可能被替换的文件：缓存后每次用 ETag 向服务端确认
+pub const CACHE_REVALIDATE: &str = "public, no-cache";
+
+/// 需要登录才能访问的文件：只允许客户端自己缓存，每次确认以便及时收回权限
+pub const CACHE_PRIVATE: &str = "private, no-cache";
+
+/// 文件名最多字符数
+const MAX_FILENAME_CHARS: usize = 255;
+
+/// 读取文件的缓冲区大小
+const STREAM_BUFFER_BYTES: usize = 64 * 1024;
+
+/// 下载响应的选项
+pub struct Download {
+    /// Content-Type
+    pub content_type: String,
+    /// Content-Disposition（不需要时为 None）
+    pub content_disposition: Option<String>,
+    /// Cache-Control
+    pub cache_control: &'static str,
+    /// 文件不存在时的提示
+    pub not_found: &'static str,
+}
+
+/// 是否为可以直接拼接到目录下的安全文件名
+///
+/// 只允许 ASCII 字母、数字与 `-`、`_`、`.`，且不能以 `.` 开头，排除路径分隔符、`..` 与隐藏文件。
+pub fn is_safe_filename(filename: &str) -> bool {
+    !filename.is_empty()
+        && filename.len() <= MAX_FILENAME_CHARS
+        && !filename.starts_with('.')
+        && filename.bytes().all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'))
+}
+
+/// 读取文件开头最多 `len` 个字节（用于按内容识别类型）
+pub async fn read_head(path: &Path, len: usize, not_found: &'static str) -> Result<Vec<u8>, AppError> {
+    let file = open(path, not_found).await?;
+    let mut head = Vec::with_capacity(len);
+    file.take(len as u64).read_to_end(&mut head).await.map_err(|e| AppError::Internal(e.to_string()))?;
+    Ok(head)
+}
+
+// 打开文件，不存在时返回 NotFound
+async fn open(path: &Path, not_found: &'static str) -> Result<File, AppError> {
+    File::open(path).await.map_err(|e| match e.kind() {
+        ErrorKind::NotFound => AppError::NotFound(not_found.into()),
+        _ => AppError::Internal(e.to_string()),
+    })
+}
+
+/// 按请求头返回文件：304（未修改）、206（部分内容）、416（范围无效）或 200（完整内容）
+pub async fn serve_file(headers: &HeaderMap, path: &Path, download: Download) -> Result<Response, AppError> {
+    let internal = |e: std::io::Error| AppError::Internal(e.to_string());
+    let mut file = open(path, download.not_found).await?;
+    let metadata = file.metadata().await.map_err(internal)?;
+    if !metadata.is_file() {
+        return Err(AppError::NotFound(download.not_found.into()));
+    }
+    let size = metadata.len();
+    let modified = metadata.modified().ok().map(truncate_to_secs);
+    let etag = entity_tag(size, modified);
+    let last_modified = modified.map(httpdate::fmt_http_date);
+
+    let mut response = Response::builder()
+        .header(ETAG, &etag)
+        .header(CACHE_CONTROL, download.cache_control)
+        .header(ACCEPT_RANGES, "bytes");
+    if let Some(last_modified) = &last_modified {
+        response = response.header(LAST_MODIFIED, last_modified);
+    }
+
+    if is_not_modified(headers, &etag, modified) {
+        return response.status(StatusCode::NOT_MODIFIED).body(Body::empty()).map_err(|e| AppError::Internal(e.to_string()));
+    }
+
+    response = response
+        .header(CONTENT_TYPE, download.content_type)
+        .header(X_CONTENT_TYPE_OPTIONS, "nosniff");
+    if let Some(disposition) = download.content_disposition {
+        let value = HeaderValue::from_str(&disposition).map_err(|e| AppError::Internal(e.to_string()))?;
+        response = response.header(CONTENT_DISPOSITION, value);
+    }
+
+    let range = match headers.get(RANGE).and_then(|v| v.to_str().ok()) {
+        Some(range) if if_range_matches(headers, &etag, last_modified.as_deref()) => parse_range(range, size),
+        _ => None,
+    };
+    let (status, start, len) = match range {
+        None => (StatusCode::OK, 0, size),
+        Some(Ok((start, end))) => {
+            response = response.header(CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, size));
+            (StatusCode::PARTIAL_CONTENT, start, end - start + 1)
+        }
+        Some(Err(())) => {
+            return response
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(CONTENT_RANGE, format!("bytes */{}", size))
+                .body(Body::empty())
+                .map_err(|e| AppError::Internal(e.to_string()));
+        }
+    };
+
+    if start > 0 {
+        file.seek(SeekFrom::Start(start)).await.map_err(internal)?;
+    }
+    let stream = ReaderStream::with_capacity(file.take(len), STREAM_BUFFER_BYTES);
+    response
+        .status(status)
+        .header(CONTENT_LENGTH, len)
+        .body(Body::from_stream(stream))
+        .map_err(|e| AppError::Internal(e.to_string()))
+}
+
+// HTTP 日期只精确到秒，比较前先截掉不足一秒的部分
+fn truncate_to_secs(time: SystemTime) -> SystemTime {
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+    UNIX_EPOCH + std::time::Duration::from_secs(secs)
+}
+
+// 由文件大小与修改时间生成强 ETag（文件被替换时随之改变）
+fn entity_tag(size: u64, modified: Option<SystemTime>) -> String {
+    let secs = modified.and_then(|t| t.duration_since(UNIX_EPOCH).ok()).map(|d| d.as_secs()).unwrap_or_default();
+    format!("\"{:x}-{:x}\"", size, secs)
+}
+
+// 条件请求：有 If-None-Match 时只看 ETag（弱比较），否则看 If-Modified-Since
+fn is_not_modified(headers: &HeaderMap, etag: &str, modified: Option<SystemTime>) -> bool {
+    if let Some(if_none_match) = headers.get(IF_NONE_MATCH).and_then(|v| v.to_str().ok()) {
+        return if_none_match
+            .split(',')
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag);
+    }
+    match (headers.get(IF_MODIFIED_SINCE).and_then(|v| v.to_str().ok()), modified) {
+        (Some(since), Some(modified)) => httpdate::parse_http_date(since).is_ok_and(|since| modified <= since),
+        _ => false,
+    }
+}
+
+// If-Range 与当前文件一致（或未提供）时 Range 才生效；ETag 必须强匹配，日期必须完全相同
+fn if_range_matches(headers: &HeaderMap, etag: &str, last_modified: Option<&str>) -> bool {
+    match headers.get(IF_RANGE).and_then(|v| v.to_str().ok()).map(str::trim) {
+        None => true,
+        Some(tag) if tag.starts_with('"') => tag == etag,
+        Some(date) => last_modified == Some(date),
+    }
+}
+
+// 解析单个字节范围，返回闭区间 `(start, end)`
+//
+// 不支持或格式不正确时返回 None（忽略 Range），范围超出文件时返回 Some(Err(()))。
+fn parse_range(range: &str, size: u64) -> Option<Result<(u64, u64), ()>> {
+    let spec = range.trim().strip_prefix("bytes=")?.trim();
+    if spec.contains(',') {
+        return None;
+    }
+    let (first, last) = spec.split_once('-')?;
+    let (first, last) = (first.trim(), last.trim());
+    let parse = |s: &str| if s.bytes().all(|b| b.is_ascii_digit()) { s.parse::<u64>().ok() } else { None };
+
+    let range = if first.is_empty() {
+        // 最后 N 个字节
+        let suffix = parse(last)?;
+        if suffix == 0 || size == 0 {
+            return Some(Err(()));
+        }
+        (size.saturating_sub(suffix), size - 1)
+    } else {
+        let start = parse(first)?;
+        let end = if last.is_empty() { u64::MAX } else { parse(last)? };
+        if end < start {
+            return None;
+        }
+        if start >= size {
+            return Some(Err(()));
+        }
+        (start, end.min(size - 1))
+    };
+    Some(Ok(range))
+}
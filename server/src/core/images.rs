@@ -0,0 +1,391 @@
+// 上传图片的处理
+//
+// 图片必须能够完整解码，否则视为伪装成图片的其他文件而拒绝。
+// 消息图片去掉 EXIF/XMP 等元数据（可能包含拍摄地点）后原样保存，避免重新压缩损失画质和动画；
+// 带拍摄方向的 JPEG 按方向旋转后重新编码（保留 ICC 色彩配置）。同时生成缩略图、尺寸和 BlurHash 占位。
+// 头像裁剪为正方形并缩放为几种固定尺寸，重新编码为 PNG，不保留任何元数据。
+use std::io::Cursor;
+use image::{
+    codecs::jpeg::JpegEncoder,
+    imageops::FilterType,
+    metadata::Orientation,
+    DynamicImage,
+    ImageDecoder,
+    ImageEncoder,
+    ImageError,
+    ImageFormat,
+    ImageReader,
+    Limits
+};
+use crate::error::AppError;
+use crate::storage::ImageInfo;
+
+/// 解码时允许的最大宽高（像素），防止解压炸弹
+const MAX_IMAGE_DIMENSION: u32 = 16_384;
+/// 解码时允许分配的最大内存（字节）
+const MAX_DECODE_ALLOC: u64 = 512 * 1024 * 1024;
+
+/// 头像主图尺寸（像素）
+pub const AVATAR_SIZE: u32 = 512;
+/// 头像的其他尺寸（像素，从小到大）
+pub const AVATAR_VARIANT_SIZES: [u32; 3] = [64, 128, 256];
+
+/// 消息图片缩略图的最长边（像素）
+const THUMBNAIL_SIZE: u32 = 320;
+/// 缩略图的派生文件名
+pub const THUMBNAIL_VARIANT: &str = "thumb";
+/// 重新编码 JPEG 的质量
+const JPEG_QUALITY: u8 = 90;
+/// JPEG 缩略图的质量
+const THUMBNAIL_QUALITY: u8 = 80;
+/// BlurHash 的横向、纵向分量数
+const BLURHASH_COMPONENTS: (u32, u32) = (4, 3);
+
+/// 处理后的消息图片
+pub struct ProcessedImage {
+    /// 去除元数据后的图片内容
+    pub content: Vec<u8>,
+    /// 尺寸与占位信息
+    pub info: ImageInfo,
+    /// 缩略图（JPEG，带透明通道的图片为 PNG）
+    pub thumbnail: Vec<u8>,
+}
+
+/// 处理后的头像
+pub struct ProcessedAvatar {
+    /// 主图（PNG）
+    pub content: Vec<u8>,
+    /// 其他尺寸（派生文件名与 PNG 内容）
+    pub variants: Vec<(String, Vec<u8>)>,
+}
+
+/// 头像指定尺寸的派生文件名
+pub fn avatar_variant(size: u32) -> String {
+    format!("{}.png", size)
+}
+
+// 解码图片，返回图片、拍摄方向与 ICC 色彩配置
+fn decode(content: &[u8], format: ImageFormat) -> Result<(DynamicImage, Orientation, Option<Vec<u8>>), AppError> {
+    let invalid = |e: ImageError| match e {
+        ImageError::Limits(_) => AppError::BadRequest("图片尺寸过大".into()),
+        _ => AppError::BadRequest("图片已损坏或不是有效的图片".into()),
+    };
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_IMAGE_DIMENSION);
+    limits.max_image_height = Some(MAX_IMAGE_DIMENSION);
+    limits.max_alloc = Some(MAX_DECODE_ALLOC);
+
+    let mut reader = ImageReader::with_format(Cursor::new(content), format);
+    reader.limits(limits);
+    let mut decoder = reader.into_decoder().map_err(invalid)?;
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let icc_profile = decoder.icc_profile().ok().flatten();
+    let image = DynamicImage::from_decoder(decoder).map_err(invalid)?;
+    Ok((image, orientation, icc_profile))
+}
+
+// 按格式编码图片
+fn encode(image: &DynamicImage, format: ImageFormat) -> Result<Vec<u8>, AppError> {
+    let mut buf = Vec::new();
+    let result = match format {
+        ImageFormat::WebP => image.to_rgba8().write_to(&mut Cursor::new(&mut buf), format),
+        format => image.write_to(&mut Cursor::new(&mut buf), format),
+    };
+    result.map_err(|e| AppError::Internal(format!("图片编码失败: {}", e)))?;
+    Ok(buf)
+}
+
+// 编码为 JPEG（不支持透明通道），带上原图的 ICC 色彩配置
+fn encode_jpeg(image: &DynamicImage, icc_profile: Option<Vec<u8>>) -> Result<Vec<u8>, AppError> {
+    let mut buf = Vec::new();
+    let mut encoder = JpegEncoder::new_with_quality(&mut buf, JPEG_QUALITY);
+    if let Some(icc_profile) = icc_profile {
+        encoder.set_icc_profile(icc_profile).map_err(|e| AppError::Internal(format!("图片编码失败: {}", e)))?;
+    }
+    encoder.encode_image(&image.to_rgb8()).map_err(|e| AppError::Internal(format!("图片编码失败: {}", e)))?;
+    Ok(buf)
+}
+
+// 生成缩略图：不透明图片用 JPEG，带透明通道的用 PNG
+fn thumbnail(image: &DynamicImage) -> Result<Vec<u8>, AppError> {
+    let small = if image.width() > THUMBNAIL_SIZE || image.height() > THUMBNAIL_SIZE {
+        image.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+    } else {
+        image.clone()
+    };
+    let mut buf = Vec::new();
+    let result = if small.color().has_alpha() {
+        small.write_to(&mut Cursor::new(&mut buf), ImageFormat::Png)
+    } else {
+        JpegEncoder::new_with_quality(&mut buf, THUMBNAIL_QUALITY).encode_image(&small.to_rgb8())
+    };
+    result.map_err(|e| AppError::Internal(format!("缩略图编码失败: {}", e)))?;
+    Ok(buf)
+}
+
+// 计算 BlurHash（在缩小后的图片上计算即可）
+fn blurhash(image: &DynamicImage) -> Result<String, AppError> {
+    let small = image.thumbnail(32, 32).to_rgba8();
+    let (x, y) = BLURHASH_COMPONENTS;
+    blurhash::encode(x, y, small.width(), small.height(), small.as_raw())
+        .map_err(|e| AppError::Internal(format!("BlurHash 计算失败: {}", e)))
+}
+
+/// 处理消息图片：校验能否解码，去除元数据，生成缩略图与占位信息
+///
+/// `mime_type` 为按内容识别出的类型（image/png、image/jpeg、image/gif、image/webp）。
+///
+/// 只有带拍摄方向的 JPEG 会旋转后重新编码。其他格式重新编码会丢失动画、把有损 WebP 变成体积大得多的无损编码，
+/// 因此原样去除元数据；去掉 EXIF 后图片按保存的像素方向显示，尺寸与缩略图也不旋转，与之保持一致。
+pub fn process_image(content: &[u8], mime_type: &str) -> Result<ProcessedImage, AppError> {
+    let format = ImageFormat::from_mime_type(mime_type)
+        .ok_or_else(|| AppError::BadRequest("不支持的图片格式".into()))?;
+    let (mut image, orientation, icc_profile) = decode(content, format)?;
+
+    let content = if format == ImageFormat::Jpeg && orientation != Orientation::NoTransforms {
+        image.apply_orientation(orientation);
+        encode_jpeg(&image, icc_profile)?
+    } else {
+        strip_metadata(content, format).ok_or_else(|| AppError::BadRequest("图片已损坏或不是有效的图片".into()))?
+    };
+
+    Ok(ProcessedImage {
+        content,
+        info: ImageInfo {
+            width: image.width() as i64,
+            height: image.height() as i64,
+            blurhash: blurhash(&image)?,
+        },
+        thumbnail: thumbnail(&image)?,
+    })
+}
+
+/// 处理头像：居中裁剪为正方形，缩放为主图和其他尺寸
+///
+/// `mime_type` 同 [`process_image`]。
+pub fn process_avatar(content: &[u8], mime_type: &str) -> Result<ProcessedAvatar, AppError> {
+    let format = ImageFormat::from_mime_type(mime_type)
+        .ok_or_else(|| AppError::BadRequest("头像必须是 PNG、JPEG、GIF 或 WebP 图片".into()))?;
+    let (mut image, orientation, _) = decode(content, format)?;
+    image.apply_orientation(orientation);
+
+    let side = image.width().min(image.height());
+    let square = image.crop_imm((image.width() - side) / 2, (image.height() - side) / 2, side, side);
+    let main = square.resize_exact(AVATAR_SIZE, AVATAR_SIZE, FilterType::Lanczos3);
+    let mut variants = Vec::with_capacity(AVATAR_VARIANT_SIZES.len());
+    for size in AVATAR_VARIANT_SIZES {
+        let resized = main.resize_exact(size, size, FilterType::Lanczos3);
+        variants.push((avatar_variant(size), encode(&resized, ImageFormat::Png)?));
+    }
+    Ok(ProcessedAvatar {
+        content: encode(&main, ImageFormat::Png)?,
+        variants,
+    })
+}
+
+/// 去除图片中的元数据而不重新编码，格式不合法时返回 None
+///
+/// - JPEG：只保留 APP0（JFIF）、APP2 中的 ICC 色彩配置和 APP14（Adobe）段，去掉 EXIF/XMP/IPTC、MPF 与注释，
+///   以及 EOI 之后附带的内容（如手机照片中带有自己 EXIF 的副图）
+/// - PNG：去掉 eXIf、文本（tEXt/zTXt/iTXt）和 tIME 块
+/// - WebP：去掉 EXIF、XMP 块并清除 VP8X 中对应的标志位
+/// - GIF：去掉注释扩展和除循环播放（NETSCAPE2.0、ANIMEXTS1.0）以外的应用扩展（如 XMP），以及结束符之后的内容
+pub fn strip_metadata(content: &[u8], format: ImageFormat) -> Option<Vec<u8>> {
+    match format {
+        ImageFormat::Jpeg => strip_jpeg(content),
+        ImageFormat::Png => strip_png(content),
+        ImageFormat::WebP => strip_webp(content),
+        ImageFormat::Gif => strip_gif(content),
+        _ => Some(content.to_vec()),
+    }
+}
+
+// JPEG 由若干标记段组成，扫描段（SOS）之后是压缩数据，直到下一个标记为止（渐进式 JPEG 有多个扫描段）；
+// 遇到 EOI 即结束，之后的内容全部丢弃
+fn strip_jpeg(content: &[u8]) -> Option<Vec<u8>> {
+    if !content.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut out = Vec::with_capacity(content.len());
+    out.extend_from_slice(&content[..2]);
+    let mut pos = 2;
+    loop {
+        // 标记前可以有任意个填充字节 0xFF
+        if *content.get(pos)? != 0xFF {
+            return None;
+        }
+        while *content.get(pos + 1)? == 0xFF {
+            pos += 1;
+        }
+        let marker = content[pos + 1];
+        match marker {
+            // 独立标记（RST0-7、TEM）没有长度字段
+            0xD0..=0xD7 | 0x01 => {
+                out.extend_from_slice(&content[pos..pos + 2]);
+                pos += 2;
+                continue;
+            }
+            0xD9 => {
+                out.extend_from_slice(&content[pos..pos + 2]);
+                return Some(out);
+            }
+            _ => {}
+        }
+        let len = u16::from_be_bytes([*content.get(pos + 2)?, *content.get(pos + 3)?]) as usize;
+        let end = pos + 2 + len;
+        if len < 2 || end > content.len() {
+            return None;
+        }
+        if marker == 0xDA {
+            out.extend_from_slice(&content[pos..end]);
+            pos = jpeg_scan_end(content, end)?;
+            out.extend_from_slice(&content[end..pos]);
+            continue;
+        }
+        let keep = match marker {
+            0xE0 | 0xEE => true,
+            0xE2 => content[pos + 4..end].starts_with(b"ICC_PROFILE\0"),
+            0xE1..=0xEF | 0xFE => false,
+            _ => true,
+        };
+        if keep {
+            out.extend_from_slice(&content[pos..end]);
+        }
+        pos = end;
+    }
+}
+
+// 从 `start` 开始跳过压缩数据，返回下一个标记的位置
+//
+// 压缩数据中的 0xFF 后跟 0x00（转义）或 RST0-7，其余情况为下一个标记（或其前的填充字节）。
+fn jpeg_scan_end(content: &[u8], start: usize) -> Option<usize> {
+    let mut pos = start;
+    loop {
+        let at = pos + content.get(pos..)?.iter().position(|&b| b == 0xFF)?;
+        match *content.get(at + 1)? {
+            0x00 | 0xD0..=0xD7 => pos = at + 2,
+            _ => return Some(at),
+        }
+    }
+}
+
+// PNG 由签名和若干 长度-类型-数据-CRC 块组成，删除整块不影响其余块的校验
+fn strip_png(content: &[u8]) -> Option<Vec<u8>> {
+    const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+    if !content.starts_with(&SIGNATURE) {
+        return None;
+    }
+    let mut out = Vec::with_capacity(content.len());
+    out.extend_from_slice(&SIGNATURE);
+    let mut pos = SIGNATURE.len();
+    while pos < content.len() {
+        let len = u32::from_be_bytes(content.get(pos..pos + 4)?.try_into().ok()?) as usize;
+        let chunk_type = content.get(pos + 4..pos + 8)?;
+        let end = pos.checked_add(12 + len)?;
+        if end > content.len() {
+            return None;
+        }
+        if !matches!(chunk_type, b"eXIf" | b"tEXt" | b"zTXt" | b"iTXt" | b"tIME") {
+            out.extend_from_slice(&content[pos..end]);
+        }
+        pos = end;
+        if chunk_type == b"IEND" {
+            break;
+        }
+    }
+    Some(out)
+}
+
+// WebP 是 RIFF 容器：去掉元数据块后需要更新 RIFF 总长度和 VP8X 标志位
+fn strip_webp(content: &[u8]) -> Option<Vec<u8>> {
+    const VP8X_XMP_FLAG: u8 = 0x04;
+    const VP8X_EXIF_FLAG: u8 = 0x08;
+    if content.len() < 12 || &content[..4] != b"RIFF" || &content[8..12] != b"WEBP" {
+        return None;
+    }
+    let mut out = Vec::with_capacity(content.len());
+    out.extend_from_slice(&content[..12]);
+    let mut pos = 12;
+    while pos < content.len() {
+        let fourcc = content.get(pos..pos + 4)?;
+        let len = u32::from_le_bytes(content.get(pos + 4..pos + 8)?.try_into().ok()?) as usize;
+        // 块数据按偶数字节对齐
+        let end = (pos + 8).checked_add(len + (len & 1))?.min(content.len());
+        if pos + 8 + len > content.len() {
+            return None;
+        }
+        match fourcc {
+            b"EXIF" | b"XMP " => {}
+            b"VP8X" if len >= 1 => {
+                let flags_at = out.len() + 8;
+                out.extend_from_slice(&content[pos..end]);
+                out[flags_at] &= !(VP8X_XMP_FLAG | VP8X_EXIF_FLAG);
+            }
+            _ => out.extend_from_slice(&content[pos..end]),
+        }
+        pos = end;
+    }
+    let riff_len = u32::try_from(out.len() - 8).ok()?;
+    out[4..8].copy_from_slice(&riff_len.to_le_bytes());
+    Some(out)
+}
+
+// GIF 由文件头、逻辑屏幕描述符、全局颜色表和若干图像与扩展块组成，数据以子块序列保存
+fn strip_gif(content: &[u8]) -> Option<Vec<u8>> {
+    const HEADER_LEN: usize = 13;
+    if !content.starts_with(b"GIF87a") && !content.starts_with(b"GIF89a") {
+        return None;
+    }
+    let packed = *content.get(10)?;
+    let mut pos = HEADER_LEN + color_table_len(packed);
+    let mut out = Vec::with_capacity(content.len());
+    out.extend_from_slice(content.get(..pos)?);
+    loop {
+        match *content.get(pos)? {
+            // 图像描述符之后可以有局部颜色表，然后是 LZW 最小码长和图像数据
+            0x2C => {
+                let packed = *content.get(pos + 9)?;
+                let data = pos + 10 + color_table_len(packed) + 1;
+                let end = gif_sub_blocks_end(content, data)?;
+                out.extend_from_slice(&content[pos..end]);
+                pos = end;
+            }
+            0x21 => {
+                let label = *content.get(pos + 1)?;
+                let end = gif_sub_blocks_end(content, pos + 2)?;
+                let keep = match label {
+                    0xFE => false,
+                    // 应用扩展的第一个子块是 11 字节的应用标识
+                    0xFF => matches!(content.get(pos + 2..pos + 14), Some(b"\x0BNETSCAPE2.0" | b"\x0BANIMEXTS1.0")),
+                    _ => true,
+                };
+                if keep {
+                    out.extend_from_slice(&content[pos..end]);
+                }
+                pos = end;
+            }
+            0x3B => {
+                out.push(0x3B);
+                return Some(out);
+            }
+            _ => return None,
+        }
+    }
+}
+
+// 颜色表的字节数（标志位最高位表示是否存在，低三位为大小）
+fn color_table_len(packed: u8) -> usize {
+    if packed & 0x80 == 0 { 0 } else { 3 << ((packed & 0x07) + 1) }
+}
+
+// 跳过从 `start` 开始的子块序列（每块以长度字节开头，以长度 0 结束），返回其后的位置
+fn gif_sub_blocks_end(content: &[u8], start: usize) -> Option<usize> {
+    let mut pos = start;
+    loop {
+        let len = *content.get(pos)? as usize;
+        pos += 1 + len;
+        if len == 0 {
+            return (pos <= content.len()).then_some(pos);
+        }
+    }
+}
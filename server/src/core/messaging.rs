@@ -0,0 +1,495 @@
+use std::collections::HashMap;
+use crate::api::AppState;
+use crate::core::attachments;
+use crate::core::protocol::ServerFrame;
+use crate::core::sync;
+use crate::error::AppError;
+use crate::storage::{
+    now_secs,
+    Message,
+    MessageEdit,
+    MessageExtras,
+    Storage,
+    StorageError,
+    StorageResult
+};
+
+/// 校验消息内容：不能为空，长度不超过配置上限
+pub fn validate_content(state: &AppState, content: &str) -> Result<(), AppError> {
+    if content.trim().is_empty() {
+        return Err(AppError::BadRequest("消息内容不能为空".into()));
+    }
+    let max_chars = state.settings.limits.max_message_chars;
+    if content.chars().count() > max_chars {
+        return Err(AppError::PayloadTooLarge(format!("消息内容不能超过 {} 个字符", max_chars)));
+    }
+    Ok(())
+}
+
+// 带附件的消息内容可以为空，但仍受长度上限约束
+fn validate_message_content(state: &AppState, content: &str, extras: &MessageExtras) -> Result<(), AppError> {
+    if extras.attachment_ids.is_empty() || !content.trim().is_empty() {
+        return validate_content(state, content);
+    }
+    Ok(())
+}
+
+/// 推送给会话双方的私聊消息帧
+pub fn private_message_frame(message: &Message) -> ServerFrame {
+    ServerFrame::FriendMessage {
+        message_id: message.id.clone(),
+        sender_id: message.sender_id.clone(),
+        receiver_id: message.receiver_id.clone(),
+        content: message.content.clone(),
+        created_at: message.created_at,
+        edited_at: message.edited_at,
+        deleted_at: message.deleted_at,
+        deleted_by: message.deleted_by.clone(),
+        reply_to_id: message.reply_to_id.clone(),
+        attachments: message.attachments.clone(),
+    }
+}
+
+// 被回复的消息不存在、已撤回或不属于同一会话
+fn reply_error(e: StorageError) -> AppError {
+    match e {
+        StorageError::NotFound => AppError::BadRequest("回复的消息不存在或不属于该会话".into()),
+        e => e.into(),
+    }
+}
+
+/// 发送私聊消息
+///
+/// 校验内容与好友关系后保存消息，记入双方的事件流并推送给双方的在线设备
+/// （发送方的其他设备据此同步已发送的消息）；离线设备之后通过同步补齐。
+/// `reply_to_id` 为引用的同一私聊中的消息；`extras` 只能携带附件，私聊不能@成员。
+pub async fn send_private_message(
+    state: &AppState,
+    sender_id: &str,
+    receiver_id: &str,
+    content: &str,
+    reply_to_id: Option<&str>,
+    extras: MessageExtras,
+) -> Result<Message, AppError> {
+    if !extras.mentions.is_empty() || extras.mention_all {
+        return Err(AppError::BadRequest("只有群聊消息可以@成员".into()));
+    }
+    validate_message_content(state, content, &extras)?;
+
+    let (sender, receiver, content) = (sender_id.to_string(), receiver_id.to_string(), content.to_string());
+    let reply_to = reply_to_id.map(str::to_string);
+    let message = state.db_pool.run(move |db| {
+        if !db.are_friends(&sender, &receiver)? {
+            return Err(AppError::Forbidden("只能给好友发送消息".into()));
+        }
+        let extras = MessageExtras {
+            attachment_ids: attachments::check_attachments(db, &sender, extras.attachment_ids)?,
+            ..Default::default()
+        };
+        db.send_message(&sender, &receiver, &content, "private", reply_to.as_deref(), &extras).map_err(reply_error)
+    }).await?;
+
+    let participants = vec![message.receiver_id.clone(), message.sender_id.clone()];
+    sync::publish(state, participants, private_message_frame(&message)).await;
+
+    Ok(message)
+}
+
+/// 推送给群成员的群聊消息帧
+pub fn group_message_frame(message: &Message) -> ServerFrame {
+    ServerFrame::GroupChat {
+        message_id: message.id.clone(),
+        group_id: message.receiver_id.clone(),
+        sender_id: message.sender_id.clone(),
+        content: message.content.clone(),
+        created_at: message.created_at,
+        edited_at: message.edited_at,
+        deleted_at: message.deleted_at,
+        deleted_by: message.deleted_by.clone(),
+        reply_to_id: message.reply_to_id.clone(),
+        thread_root_id: message.thread_root_id.clone(),
+        mentions: message.mentions.clone(),
+        mention_all: message.mention_all,
+        attachments: message.attachments.clone(),
+    }
+}
+
+/// 是否为群管理者（群主或管理员）：可以撤回群内任意消息、@全体成员
+pub fn is_group_moderator(db: &dyn Storage, group_id: &str, user_id: &str) -> Result<bool, StorageError> {
+    Ok(matches!(db.get_member_role(group_id, user_id)?.as_deref(), Some("owner" | "admin")))
+}
+
+/// 推送给被@成员的高优先级提醒帧
+pub fn mention_frame(message: &Message) -> ServerFrame {
+    ServerFrame::Mention {
+        message_id: message.id.clone(),
+        group_id: message.receiver_id.clone(),
+        sender_id: message.sender_id.clone(),
+        content: message.content.clone(),
+        created_at: message.created_at,
+        mention_all: message.mention_all,
+        priority: "high",
+    }
+}
+
+// 校验并整理@信息：去重、去掉发送者本人，被@的用户必须是群成员，@全体成员仅限群主与管理员
+fn check_mentions(db: &dyn Storage, group_id: &str, sender_id: &str, mention_ids: Vec<String>, mention_all: bool) -> Result<Vec<String>, AppError> {
+    if mention_all && !is_group_moderator(db, group_id, sender_id)? {
+        return Err(AppError::Forbidden("只有群主和管理员可以@全体成员".into()));
+    }
+    let mut user_ids: Vec<String> = Vec::with_capacity(mention_ids.len());
+    for user_id in mention_ids {
+        if user_id == sender_id || user_ids.contains(&user_id) {
+            continue;
+        }
+        if !db.is_group_member(group_id, &user_id)? {
+            return Err(AppError::BadRequest("@的用户不是该群成员".into()));
+        }
+        user_ids.push(user_id);
+    }
+    Ok(user_ids)
+}
+
+/// 发送群聊消息
+///
+/// 校验群聊存在且发送者是群成员后保存消息（`message_type = 'group'`，
+/// `receiver_id` 为群ID），再记入全体成员的事件流并推送给在线成员。
+/// 回复同群消息时归入其所在话题（`thread_root_id`），话题根消息的回复数加一。
+/// 被@的成员（@全体成员时为除发送者外的全部成员）另外收到高优先级的 `mention` 帧，
+/// 不受群消息免打扰影响。
+pub async fn send_group_message(
+    state: &AppState,
+    sender_id: &str,
+    group_id: &str,
+    content: &str,
+    reply_to_id: Option<&str>,
+    extras: MessageExtras,
+) -> Result<Message, AppError> {
+    validate_message_content(state, content, &extras)?;
+
+    let (sender, group, content) = (sender_id.to_string(), group_id.to_string(), content.to_string());
+    let reply_to = reply_to_id.map(str::to_string);
+    let (message, member_ids) = state.db_pool.run(move |db| {
+        if !db.group_exists(&group)? {
+            return Err(AppError::NotFound("群聊不存在".into()));
+        }
+        if !db.is_group_member(&group, &sender)? {
+            return Err(AppError::Forbidden("不是该群成员".into()));
+        }
+        let extras = MessageExtras {
+            mentions: check_mentions(db, &group, &sender, extras.mentions, extras.mention_all)?,
+            mention_all: extras.mention_all,
+            attachment_ids: attachments::check_attachments(db, &sender, extras.attachment_ids)?,
+        };
+        let message = db.send_message(&sender, &group, &content, "group", reply_to.as_deref(), &extras).map_err(reply_error)?;
+        Ok((message, sync::group_member_ids(db, &group)?))
+    }).await?;
+
+    let mentioned: Vec<String> = if message.mention_all {
+        member_ids.iter().filter(|id| **id != message.sender_id).cloned().collect()
+    } else {
+        message.mentions.clone()
+    };
+    sync::publish(state, member_ids, group_message_frame(&message)).await;
+    if !mentioned.is_empty() {
+        sync::publish(state, mentioned, mention_frame(&message)).await;
+    }
+
+    Ok(message)
+}
+
+/// 获取@了自己的群消息（从新到旧），`before` 为上一页最早一条的消息ID
+pub async fn mentions_of(state: &AppState, user_id: &str, before: Option<String>, limit: usize) -> Result<Vec<Message>, AppError> {
+    let user = user_id.to_string();
+    state.db_pool.run(move |db| {
+        db.get_mentions(&user, before.as_deref(), limit).map_err(|e| match e {
+            StorageError::NotFound => AppError::NotFound("游标消息不存在".into()),
+            e => e.into(),
+        })
+    }).await
+}
+
+/// 私聊消息的回执帧（`status` 为 "delivered" 或 "read"）
+pub fn receipt_frame(status: &'static str, receiver_id: &str, message_ids: Vec<String>, at: i64) -> ServerFrame {
+    ServerFrame::Receipt {
+        status,
+        receiver_id: receiver_id.to_string(),
+        message_ids,
+        at,
+    }
+}
+
+// 按发送者分组推送回执：发送方据此更新消息状态，接收方的其他设备据此同步已读状态
+async fn push_receipts(state: &AppState, status: &'static str, receiver_id: &str, messages: &[Message]) {
+    let mut by_sender: HashMap<&str, Vec<String>> = HashMap::new();
+    for message in messages {
+        by_sender.entry(&message.sender_id).or_default().push(message.id.clone());
+    }
+    let at = now_secs();
+    for (sender_id, message_ids) in by_sender {
+        let participants = vec![sender_id.to_string(), receiver_id.to_string()];
+        sync::publish(state, participants, receipt_frame(status, receiver_id, message_ids, at)).await;
+    }
+}
+
+/// 接收方确认私聊消息已送达设备
+///
+/// 只处理发给自己且尚未送达的消息，其余ID忽略；返回本次新标记的条数。
+pub async fn mark_delivered(state: &AppState, receiver_id: &str, message_ids: Vec<String>) -> Result<usize, AppError> {
+    let receiver = receiver_id.to_string();
+    let messages = state.db_pool.run(move |db| db.mark_messages_delivered(&receiver, &message_ids)).await?;
+    push_receipts(state, "delivered", receiver_id, &messages).await;
+    Ok(messages.len())
+}
+
+/// 接收方将私聊消息标记为已读
+///
+/// 只处理发给自己且尚未已读的消息，其余ID忽略；返回本次新标记的条数。
+pub async fn mark_read(state: &AppState, receiver_id: &str, message_ids: Vec<String>) -> Result<usize, AppError> {
+    let receiver = receiver_id.to_string();
+    let messages = state.db_pool.run(move |db| db.mark_messages_as_read(&receiver, &message_ids)).await?;
+    push_receipts(state, "read", receiver_id, &messages).await;
+    Ok(messages.len())
+}
+
+/// 群已读位置变化时推送给群成员的帧
+pub fn group_read_frame(group_id: &str, user_id: &str, message_id: &str, read_at: i64) -> ServerFrame {
+    ServerFrame::GroupRead {
+        group_id: group_id.to_string(),
+        user_id: user_id.to_string(),
+        message_id: message_id.to_string(),
+        read_at,
+    }
+}
+
+/// 群成员报告已读到某条群消息
+///
+/// 已读位置前进时向全体成员推送 `group_read` 帧，发送方据此更新"N/M 人已读"。
+pub async fn mark_group_read(state: &AppState, user_id: &str, group_id: &str, message_id: &str) -> Result<(), AppError> {
+    let (user, group, message) = (user_id.to_string(), group_id.to_string(), message_id.to_string());
+    let member_ids = state.db_pool.run(move |db| {
+        if !db.is_group_member(&group, &user)? {
+            return Err(AppError::Forbidden("不是该群成员".into()));
+        }
+        let advanced = db.advance_group_read_cursor(&group, &user, &message).map_err(|e| match e {
+            StorageError::NotFound => AppError::NotFound("群消息不存在".into()),
+            e => e.into(),
+        })?;
+        if !advanced {
+            return Ok(None);
+        }
+        Ok(Some(sync::group_member_ids(db, &group)?))
+    }).await?;
+
+    if let Some(member_ids) = member_ids {
+        sync::publish(state, member_ids, group_read_frame(group_id, user_id, message_id, now_secs())).await;
+    }
+    Ok(())
+}
+
+// 消息所属会话：私聊为对方ID，群聊为群ID
+fn conversation_ids(message: &Message) -> (Option<String>, Option<String>) {
+    if message.message_type == "group" {
+        (None, Some(message.receiver_id.clone()))
+    } else {
+        (Some(message.receiver_id.clone()), None)
+    }
+}
+
+/// 消息被编辑时推送给会话参与者的帧
+pub fn edited_frame(message: &Message) -> ServerFrame {
+    let (receiver_id, group_id) = conversation_ids(message);
+    ServerFrame::MessageEdited {
+        message_id: message.id.clone(),
+        sender_id: message.sender_id.clone(),
+        receiver_id,
+        group_id,
+        content: message.content.clone(),
+        edited_at: message.edited_at.unwrap_or(message.created_at),
+    }
+}
+
+/// 消息被撤回时推送给会话参与者的帧
+pub fn recalled_frame(message: &Message) -> ServerFrame {
+    let (receiver_id, group_id) = conversation_ids(message);
+    ServerFrame::MessageRecalled {
+        message_id: message.id.clone(),
+        sender_id: message.sender_id.clone(),
+        receiver_id,
+        group_id,
+        deleted_by: message.deleted_by.clone().unwrap_or_default(),
+        deleted_at: message.deleted_at.unwrap_or_default(),
+    }
+}
+
+/// 按消息当前状态重建事件帧（离线同步时使用）
+///
+/// 编辑后的消息帧带最新内容，撤回后的消息帧只剩墓碑；
+/// 已撤回消息的编辑事件与@提醒不再下发，返回 None。
+pub fn message_event_frame(kind: &str, message: &Message) -> Option<ServerFrame> {
+    match kind {
+        "message_edited" | "mention" if message.deleted_at.is_some() => None,
+        "message_edited" => Some(edited_frame(message)),
+        "mention" => Some(mention_frame(message)),
+        "message_recalled" => Some(recalled_frame(message)),
+        _ if message.message_type == "group" => Some(group_message_frame(message)),
+        _ => Some(private_message_frame(message)),
+    }
+}
+
+/// 消息的全部参与者：私聊双方或群聊全体成员
+pub fn participant_ids(db: &dyn Storage, message: &Message) -> StorageResult<Vec<String>> {
+    if message.message_type == "group" {
+        sync::group_member_ids(db, &message.receiver_id)
+    } else {
+        Ok(vec![message.sender_id.clone(), message.receiver_id.clone()])
+    }
+}
+
+/// 读取用户参与的消息（私聊双方或群成员），否则视为不存在
+pub fn load_message(db: &dyn Storage, user_id: &str, message_id: &str) -> Result<Message, AppError> {
+    let message = db.get_message(message_id).map_err(|e| match e {
+        StorageError::NotFound => AppError::NotFound("消息不存在".into()),
+        e => e.into(),
+    })?;
+    let participant = if message.message_type == "group" {
+        db.is_group_member(&message.receiver_id, user_id)?
+    } else {
+        message.sender_id == user_id || message.receiver_id == user_id
+    };
+    if !participant {
+        return Err(AppError::NotFound("消息不存在".into()));
+    }
+    Ok(message)
+}
+
+/// 编辑自己发送的消息
+///
+/// 编辑前的内容记入编辑历史；编辑结果推送给会话全体参与者。
+pub async fn edit_message(state: &AppState, user_id: &str, message_id: &str, content: &str) -> Result<Message, AppError> {
+    validate_content(state, content)?;
+
+    let (user, message_id, content) = (user_id.to_string(), message_id.to_string(), content.to_string());
+    let (message, participants) = state.db_pool.run(move |db| {
+        let message = load_message(db, &user, &message_id)?;
+        if message.sender_id != user {
+            return Err(AppError::Forbidden("只能编辑自己发送的消息".into()));
+        }
+        if message.deleted_at.is_some() {
+            return Err(AppError::Conflict("消息已撤回".into()));
+        }
+        let message = db.edit_message(&message_id, &content).map_err(|e| match e {
+            StorageError::NotFound => AppError::Conflict("消息已撤回".into()),
+            e => e.into(),
+        })?;
+        let participants = participant_ids(db, &message)?;
+        Ok((message, participants))
+    }).await?;
+
+    sync::publish(state, participants, edited_frame(&message)).await;
+    Ok(message)
+}
+
+/// 获取消息及其编辑历史（会话参与者可查看）
+pub async fn message_edits(state: &AppState, user_id: &str, message_id: &str) -> Result<(Message, Vec<MessageEdit>), AppError> {
+    let (user, message_id) = (user_id.to_string(), message_id.to_string());
+    state.db_pool.run(move |db| {
+        let message = load_message(db, &user, &message_id)?;
+        let edits = db.get_message_edits(&message_id)?;
+        Ok((message, edits))
+    }).await
+}
+
+/// 撤回消息（对所有人删除）
+///
+/// 发送者只能在发送后 `limits.recall_window_secs` 秒内撤回；群主与管理员可以随时撤回群内任意消息。
+/// 撤回后内容与编辑历史被清除，会话参与者收到 `message_recalled` 帧，历史与同步中只保留墓碑。
+pub async fn recall_message(state: &AppState, user_id: &str, message_id: &str) -> Result<Message, AppError> {
+    let window = state.settings.limits.recall_window_secs;
+    let (user, message_id) = (user_id.to_string(), message_id.to_string());
+    let (message, participants) = state.db_pool.run(move |db| {
+        let message = load_message(db, &user, &message_id)?;
+        if message.deleted_at.is_some() {
+            return Err(AppError::Conflict("消息已撤回".into()));
+        }
+        let moderator = message.message_type == "group" && is_group_moderator(db, &message.receiver_id, &user)?;
+        if !moderator {
+            if message.sender_id != user {
+                return Err(AppError::Forbidden("只能撤回自己发送的消息".into()));
+            }
+            if now_secs() - message.created_at > window {
+                return Err(AppError::Forbidden(format!("消息发送超过 {} 秒，无法撤回", window)));
+            }
+        }
+        let message = db.recall_message(&message_id, &user).map_err(|e| match e {
+            StorageError::NotFound => AppError::Conflict("消息已撤回".into()),
+            e => e.into(),
+        })?;
+        let participants = participant_ids(db, &message)?;
+        Ok((message, participants))
+    }).await?;
+
+    sync::publish(state, participants, recalled_frame(&message)).await;
+    Ok(message)
+}
+
+/// 仅对自己删除消息
+///
+/// 之后的历史、搜索、未读列表与同步中都不再出现该消息；自己的其他设备收到 `message_hidden` 帧。
+pub async fn hide_message(state: &AppState, user_id: &str, message_id: &str) -> Result<(), AppError> {
+    let (user, message) = (user_id.to_string(), message_id.to_string());
+    let hidden = state.db_pool.run(move |db| {
+        load_message(db, &user, &message)?;
+        Ok::<_, AppError>(db.hide_message(&message, &user)?)
+    }).await?;
+
+    if hidden {
+        let frame = ServerFrame::MessageHidden { message_id: message_id.to_string() };
+        sync::publish(state, vec![user_id.to_string()], frame).await;
+    }
+    Ok(())
+}
+
+/// 转发给会话对方或群成员的输入状态帧（不保存）
+pub fn typing_frame(sender_id: &str, receiver_id: Option<&str>, group_id: Option<&str>, typing: bool) -> ServerFrame {
+    ServerFrame::Typing {
+        sender_id: sender_id.to_string(),
+        receiver_id: receiver_id.map(str::to_string),
+        group_id: group_id.map(str::to_string),
+        typing,
+    }
+}
+
+/// 转发输入状态：私聊发给在线的好友，群聊广播给群成员
+pub async fn relay_typing(
+    state: &AppState,
+    sender_id: &str,
+    receiver_id: Option<&str>,
+    group_id: Option<&str>,
+    typing: bool,
+) -> Result<(), AppError> {
+    let frame = typing_frame(sender_id, receiver_id, group_id, typing).encode();
+    let sender = sender_id.to_string();
+    match (receiver_id, group_id) {
+        (Some(receiver_id), None) => {
+            // 对方离线时无需查询好友关系
+            if state.presence_of(receiver_id) == "offline" {
+                return Ok(());
+            }
+            let receiver = receiver_id.to_string();
+            if !state.db_pool.run(move |db| db.are_friends(&sender, &receiver)).await? {
+                return Err(AppError::Forbidden("只能给好友发送消息".into()));
+            }
+            state.send_to_user(receiver_id, frame);
+        }
+        (None, Some(group_id)) => {
+            let group = group_id.to_string();
+            if !state.db_pool.run(move |db| db.is_group_member(&group, &sender)).await? {
+                return Err(AppError::Forbidden("不是该群成员".into()));
+            }
+            state.broadcast_to_group(group_id, frame);
+        }
+        _ => return Err(AppError::BadRequest("receiver_id 与 group_id 必须且只能指定一个".into())),
+    }
+    Ok(())
+}
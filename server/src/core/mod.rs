@@ -1,2 +1,13 @@
+pub mod attachments;
 pub mod auth;
+pub mod blobs;
+pub mod downloads;
+pub mod images;
+pub mod messaging;
+pub mod presence;
+pub mod protocol;
+pub mod reactions;
+pub mod search;
+pub mod sync;
+pub mod uploads;
 pub mod models;
@@ -0,0 +1,56 @@
+use crate::api::AppState;
+use crate::core::protocol::ServerFrame;
+use crate::storage::now_secs;
+
+/// 推送给好友的在线状态帧
+pub fn presence_frame(user_id: &str, status: &'static str, last_seen: Option<i64>) -> ServerFrame {
+    ServerFrame::Presence {
+        user_id: user_id.to_string(),
+        status,
+        last_seen,
+    }
+}
+
+// 把状态变化推送给在线的好友
+async fn notify_friends(state: &AppState, user_id: &str, frame: ServerFrame) {
+    let frame = frame.encode();
+    let uid = user_id.to_string();
+    match state.db_pool.run(move |db| db.get_friends(&uid)).await {
+        Ok(friends) => {
+            for friend in friends {
+                state.send_to_user(&friend.id, frame.clone());
+            }
+        }
+        Err(e) => println!("查询用户 {} 的好友失败: {}", user_id, e),
+    }
+}
+
+// 记录最近在线时间（失败只记录日志）
+async fn touch_last_seen(state: &AppState, user_id: &str, last_seen: i64) {
+    let uid = user_id.to_string();
+    if let Err(e) = state.db_pool.run(move |db| db.update_last_seen(&uid, last_seen)).await {
+        println!("更新用户 {} 的在线时间失败: {}", user_id, e);
+    }
+}
+
+/// 用户连接后通知好友上线
+pub async fn user_connected(state: &AppState, user_id: &str) {
+    touch_last_seen(state, user_id, now_secs()).await;
+    notify_friends(state, user_id, presence_frame(user_id, "online", None)).await;
+}
+
+/// 用户断开后记录最近在线时间并通知好友下线
+pub async fn user_disconnected(state: &AppState, user_id: &str) {
+    state.set_away(user_id, false);
+    let last_seen = now_secs();
+    touch_last_seen(state, user_id, last_seen).await;
+    notify_friends(state, user_id, presence_frame(user_id, "offline", Some(last_seen))).await;
+}
+
+/// 在线用户切换离开/在线状态，状态变化时通知好友
+pub async fn set_away(state: &AppState, user_id: &str, away: bool) {
+    if state.set_away(user_id, away) {
+        let status = if away { "away" } else { "online" };
+        notify_friends(state, user_id, presence_frame(user_id, status, None)).await;
+    }
+}
@@ -0,0 +1,390 @@
+// WebSocket 协议帧定义
+//
+// 所有帧都是带 `type` 字段的 JSON 文本。客户端请求可携带 `request_id`，
+// 服务端对带 `request_id` 的请求恰好回复一帧：结果帧（`message_ack`、`sync_batch`）、
+// 通用确认 `ack` 或 `error`，回复中原样带回 `request_id`。
+use serde::{
+    Deserialize,
+    Serialize
+};
+use serde_json::Value;
+use crate::error::AppError;
+
+/// 服务端实现的最新协议版本
+pub const PROTOCOL_VERSION: u32 = 1;
+/// 服务端仍然支持的最低协议版本
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// 协商协议版本：客户端声明其支持的最高版本，服务端选用双方都支持的最高版本
+///
+/// 客户端未声明时使用当前版本；低于最低支持版本时返回 None。
+pub fn negotiate_version(requested: Option<u32>) -> Option<u32> {
+    match requested {
+        None => Some(PROTOCOL_VERSION),
+        Some(version) if version >= MIN_PROTOCOL_VERSION => Some(version.min(PROTOCOL_VERSION)),
+        Some(_) => None,
+    }
+}
+
+/// 客户端发来的一帧：`request_id` 加上具体请求
+#[derive(Debug, Deserialize)]
+pub struct ClientEnvelope {
+    /// 客户端生成的请求ID，回复中原样带回（兼容旧字段名 `client_msg_id`）
+    #[serde(default, alias = "client_msg_id")]
+    pub request_id: Option<String>,
+    #[serde(flatten)]
+    pub frame: ClientFrame,
+}
+
+/// 在线状态切换
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PresenceStatus {
+    Online,
+    Away,
+}
+
+fn default_typing() -> bool {
+    true
+}
+
+/// 客户端 → 服务端的帧
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientFrame {
+    /// 认证帧（握手未携带令牌时必须是第一帧），`version` 为客户端支持的最高协议版本
+    #[serde(alias = "auth")]
+    Identify {
+        token: String,
+        #[serde(default)]
+        version: Option<u32>,
+    },
+    /// 发送私聊消息，`reply_to_id` 为引用的消息，`attachments` 为已上传的附件ID
+    FriendMessage {
+        receiver_id: String,
+        #[serde(default)]
+        content: String,
+        #[serde(default)]
+        reply_to_id: Option<String>,
+        #[serde(default)]
+        attachments: Vec<String>,
+    },
+    /// 发送群聊消息，`reply_to_id` 为回复的消息（归入其所在话题），
+    /// `mentions` 为@的成员，`mention_all` 为@全体成员（仅群主与管理员），`attachments` 为附件ID
+    GroupChat {
+        group_id: String,
+        #[serde(default)]
+        content: String,
+        #[serde(default)]
+        reply_to_id: Option<String>,
+        #[serde(default)]
+        mentions: Vec<String>,
+        #[serde(default)]
+        mention_all: bool,
+        #[serde(default)]
+        attachments: Vec<String>,
+    },
+    /// 确认私聊消息已送达
+    Delivered {
+        message_ids: Vec<String>,
+    },
+    /// 已读确认：私聊带 `message_ids`，群聊带 `group_id` 与已读到的 `message_id`
+    Read {
+        #[serde(default)]
+        message_ids: Option<Vec<String>>,
+        #[serde(default)]
+        group_id: Option<String>,
+        #[serde(default)]
+        message_id: Option<String>,
+    },
+    /// 输入状态（只转发不保存）
+    Typing {
+        #[serde(default)]
+        receiver_id: Option<String>,
+        #[serde(default)]
+        group_id: Option<String>,
+        #[serde(default = "default_typing")]
+        typing: bool,
+    },
+    /// 切换在线状态
+    Presence {
+        status: PresenceStatus,
+    },
+    /// 添加或取消（`remove` 为 true）表情回应
+    React {
+        message_id: String,
+        emoji: String,
+        #[serde(default)]
+        remove: bool,
+    },
+    /// 离线同步
+    Sync {
+        #[serde(default)]
+        since: Option<i64>,
+        #[serde(default)]
+        limit: Option<usize>,
+    },
+    /// 未知类型
+    #[serde(other)]
+    Unknown,
+}
+
+/// 服务端 → 客户端的帧
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerFrame {
+    /// 认证成功，带回协商后的协议版本
+    Identified {
+        protocol_version: u32,
+        user_id: String,
+        session_id: String,
+        group_ids: Vec<String>,
+    },
+    /// 请求处理成功（没有专门结果帧的请求）
+    Ack {
+        request_id: Option<String>,
+    },
+    /// 请求处理失败，`code` 为机器可读的错误码
+    Error {
+        request_id: Option<String>,
+        code: &'static str,
+        message: String,
+    },
+    /// 消息已保存
+    MessageAck {
+        request_id: Option<String>,
+        message_id: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        receiver_id: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        group_id: Option<String>,
+        created_at: i64,
+    },
+    /// 一批离线同步事件
+    SyncBatch {
+        request_id: Option<String>,
+        events: Vec<Value>,
+        last_seq: i64,
+        has_more: bool,
+    },
+    /// 私聊消息（同步时按消息当前状态重建，编辑过或已撤回的带 `edited_at` / `deleted_at`）
+    FriendMessage {
+        message_id: String,
+        sender_id: String,
+        receiver_id: String,
+        content: String,
+        created_at: i64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        edited_at: Option<i64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        deleted_at: Option<i64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        deleted_by: Option<String>,
+        /// 引用的消息ID
+        #[serde(skip_serializing_if = "Option::is_none")]
+        reply_to_id: Option<String>,
+        /// 附件ID（附件信息通过 /attachments/info 获取）
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        attachments: Vec<String>,
+    },
+    /// 群聊消息（同上）
+    GroupChat {
+        message_id: String,
+        group_id: String,
+        sender_id: String,
+        content: String,
+        created_at: i64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        edited_at: Option<i64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        deleted_at: Option<i64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        deleted_by: Option<String>,
+        /// 回复的消息ID
+        #[serde(skip_serializing_if = "Option::is_none")]
+        reply_to_id: Option<String>,
+        /// 所属话题的根消息ID（客户端据此更新根消息的回复数）
+        #[serde(skip_serializing_if = "Option::is_none")]
+        thread_root_id: Option<String>,
+        /// 被@的成员ID
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        mentions: Vec<String>,
+        /// 是否@全体成员
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        mention_all: bool,
+        /// 附件ID
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        attachments: Vec<String>,
+    },
+    /// 有人在群聊中@了你（`priority` 固定为 "high"，即使该群设置了免打扰也应提醒）
+    Mention {
+        message_id: String,
+        group_id: String,
+        sender_id: String,
+        content: String,
+        created_at: i64,
+        mention_all: bool,
+        priority: &'static str,
+    },
+    /// 消息被编辑（私聊带 `receiver_id`，群聊带 `group_id`）
+    MessageEdited {
+        message_id: String,
+        sender_id: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        receiver_id: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        group_id: Option<String>,
+        content: String,
+        edited_at: i64,
+    },
+    /// 消息被撤回，客户端应清除内容只保留墓碑（`deleted_by` 为发送者本人、群主或管理员）
+    MessageRecalled {
+        message_id: String,
+        sender_id: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        receiver_id: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        group_id: Option<String>,
+        deleted_by: String,
+        deleted_at: i64,
+    },
+    /// 表情回应变化（`action` 为 "added" 或 "removed"）
+    Reaction {
+        message_id: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        receiver_id: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        group_id: Option<String>,
+        user_id: String,
+        emoji: String,
+        action: &'static str,
+    },
+    /// 用户在其他设备上删除了消息（仅对自己）
+    MessageHidden {
+        message_id: String,
+    },
+    /// 私聊消息回执（`status` 为 "delivered" 或 "read"）
+    Receipt {
+        status: &'static str,
+        receiver_id: String,
+        message_ids: Vec<String>,
+        at: i64,
+    },
+    /// 群成员已读位置前进
+    GroupRead {
+        group_id: String,
+        user_id: String,
+        message_id: String,
+        read_at: i64,
+    },
+    /// 好友在线状态变化（"online"、"away" 或 "offline"）
+    Presence {
+        user_id: String,
+        status: &'static str,
+        last_seen: Option<i64>,
+    },
+    /// 输入状态
+    Typing {
+        sender_id: String,
+        receiver_id: Option<String>,
+        group_id: Option<String>,
+        typing: bool,
+    },
+    /// 收到好友请求（`request_id` 专用于请求与回复的对应，好友请求ID为 `friend_request_id`）
+    FriendRequest {
+        friend_request_id: String,
+        from_user_id: String,
+        to_user_id: String,
+        message: String,
+    },
+    /// 成为好友
+    FriendAdded {
+        user_id: String,
+        friend_id: String,
+        friend_username: String,
+        message: String,
+    },
+    /// 被拉入群聊
+    GroupJoined {
+        group_id: String,
+        group_name: String,
+        operator_id: String,
+    },
+    /// 被移出群聊
+    GroupRemoved {
+        group_id: String,
+        operator_id: String,
+    },
+    /// 群聊改名
+    GroupRenamed {
+        group_id: String,
+        group_name: String,
+        operator_id: String,
+    },
+    /// 自己在其他设备上切换了群消息免打扰
+    GroupMuted {
+        group_id: String,
+        muted: bool,
+    },
+    /// 群聊解散
+    GroupDissolved {
+        group_id: String,
+        operator_id: String,
+    },
+    /// 群成员角色变化（`role` 为 "admin" 或 "member"）
+    GroupRoleChanged {
+        group_id: String,
+        user_id: String,
+        role: String,
+        operator_id: String,
+    },
+    /// 群成员变动（`action` 为 "added"、"removed" 或 "left"）
+    GroupMembersChanged {
+        group_id: String,
+        action: &'static str,
+        user_id: String,
+        operator_id: String,
+    },
+    /// 本设备已被退出登录，连接随后关闭
+    SignedOut {
+        session_id: String,
+    },
+}
+
+impl ServerFrame {
+    /// 错误帧
+    pub fn error(request_id: Option<String>, code: &'static str, message: impl std::fmt::Display) -> Self {
+        ServerFrame::Error {
+            request_id,
+            code,
+            message: message.to_string(),
+        }
+    }
+
+    /// 由应用错误构造的错误帧
+    pub fn from_error(request_id: Option<String>, error: &AppError) -> Self {
+        Self::error(request_id, error.code(), error)
+    }
+
+    /// 与消息内容相关的帧对应的消息ID（离线同步时按消息当前状态重建）
+    pub fn message_id(&self) -> Option<&str> {
+        match self {
+            ServerFrame::FriendMessage { message_id, .. }
+            | ServerFrame::GroupChat { message_id, .. }
+            | ServerFrame::Mention { message_id, .. }
+            | ServerFrame::MessageEdited { message_id, .. }
+            | ServerFrame::MessageRecalled { message_id, .. } => Some(message_id),
+            _ => None,
+        }
+    }
+
+    /// 序列化为 JSON 值
+    pub fn to_value(&self) -> Value {
+        serde_json::to_value(self).expect("协议帧总能序列化为 JSON")
+    }
+
+    /// 序列化为发送给客户端的文本
+    pub fn encode(&self) -> String {
+        serde_json::to_string(self).expect("协议帧总能序列化为 JSON")
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+use crate::api::AppState;
+use crate::core::messaging;
+use crate::core::protocol::ServerFrame;
+use crate::core::sync;
+use crate::error::AppError;
+use crate::storage::{
+    Message,
+    ReactionCount,
+    Storage,
+    StorageResult
+};
+
+/// 单个表情回应最多字符数（含肤色、零宽连接符等组合字符）
+const MAX_EMOJI_CHARS: usize = 16;
+
+/// 校验表情：必须含非 ASCII 字符，不含空白、控制字符和 ASCII 字母
+pub fn validate_emoji(emoji: &str) -> Result<(), AppError> {
+    let valid = !emoji.is_ascii()
+        && emoji.chars().count() <= MAX_EMOJI_CHARS
+        && emoji.chars().all(|c| !c.is_whitespace() && !c.is_control() && !c.is_ascii_alphabetic());
+    if !valid {
+        return Err(AppError::BadRequest("无效的表情".into()));
+    }
+    Ok(())
+}
+
+/// 表情回应变化时推送给会话参与者的帧
+pub fn reaction_frame(message: &Message, user_id: &str, emoji: &str, action: &'static str) -> ServerFrame {
+    let group = message.message_type == "group";
+    ServerFrame::Reaction {
+        message_id: message.id.clone(),
+        receiver_id: (!group).then(|| message.receiver_id.clone()),
+        group_id: group.then(|| message.receiver_id.clone()),
+        user_id: user_id.to_string(),
+        emoji: emoji.to_string(),
+        action, // "added" 或 "removed"
+    }
+}
+
+/// 添加或取消表情回应
+///
+/// 只有会话参与者可以回应，已撤回的消息不能回应；
+/// 状态确有变化时推送给会话全体参与者，返回该消息最新的回应汇总。
+pub async fn react(state: &AppState, user_id: &str, message_id: &str, emoji: &str, add: bool) -> Result<Vec<ReactionCount>, AppError> {
+    validate_emoji(emoji)?;
+
+    let (user, message_id, emoji_owned) = (user_id.to_string(), message_id.to_string(), emoji.to_string());
+    let (message, participants, reactions) = state.db_pool.run(move |db| {
+        let message = messaging::load_message(db, &user, &message_id)?;
+        if message.deleted_at.is_some() {
+            return Err(AppError::Conflict("消息已撤回".into()));
+        }
+        let changed = if add {
+            db.add_reaction(&message_id, &user, &emoji_owned)?
+        } else {
+            db.remove_reaction(&message_id, &user, &emoji_owned)?
+        };
+        let participants = if changed { messaging::participant_ids(db, &message)? } else { Vec::new() };
+        let reactions = db.get_reactions(&user, std::slice::from_ref(&message_id))?;
+        Ok((message, participants, reactions))
+    }).await?;
+
+    if !participants.is_empty() {
+        let action = if add { "added" } else { "removed" };
+        sync::publish(state, participants, reaction_frame(&message, user_id, emoji, action)).await;
+    }
+    Ok(reactions)
+}
+
+/// 获取用户可见消息的表情回应汇总（会话参与者可查看）
+pub async fn message_reactions(state: &AppState, user_id: &str, message_id: &str) -> Result<Vec<ReactionCount>, AppError> {
+    let (user, message_id) = (user_id.to_string(), message_id.to_string());
+    state.db_pool.run(move |db| {
+        messaging::load_message(db, &user, &message_id)?;
+        Ok(db.get_reactions(&user, std::slice::from_ref(&message_id))?)
+    }).await
+}
+
+/// 按消息ID分组汇总一页消息的表情回应（没有回应的消息不出现）
+pub fn reactions_by_message(db: &dyn Storage, viewer_id: &str, messages: &[Message]) -> StorageResult<HashMap<String, Vec<ReactionCount>>> {
+    let message_ids: Vec<String> = messages.iter().map(|message| message.id.clone()).collect();
+    let mut grouped: HashMap<String, Vec<ReactionCount>> = HashMap::new();
+    for reaction in db.get_reactions(viewer_id, &message_ids)? {
+        grouped.entry(reaction.message_id.clone()).or_default().push(reaction);
+    }
+    Ok(grouped)
+}
@@ -0,0 +1,78 @@
+use serde::Serialize;
+
+/// 摘要中命中位置前保留的字符数
+const CONTEXT_CHARS: usize = 20;
+/// 摘要最多字符数（不含省略号）
+const SNIPPET_CHARS: usize = 80;
+const ELLIPSIS: char = '…';
+
+/// 带高亮位置的消息摘要
+#[derive(Debug, Serialize)]
+pub struct Snippet {
+    pub text: String,
+    /// 命中的字符区间 `[start, end)`（按 Unicode 字符计数，相对于 `text`）
+    pub highlights: Vec<[usize; 2]>,
+}
+
+// 不区分大小写比较两个字符
+fn chars_eq(a: char, b: char) -> bool {
+    a == b || a.to_lowercase().eq(b.to_lowercase())
+}
+
+// 查找所有检索词在内容中的命中区间（已排序并合并重叠部分）
+fn find_matches(content: &[char], query: &str) -> Vec<[usize; 2]> {
+    let mut ranges = Vec::new();
+    for term in query.split_whitespace() {
+        let term: Vec<char> = term.chars().collect();
+        if term.len() > content.len() {
+            continue;
+        }
+        for start in 0..=content.len() - term.len() {
+            if content[start..start + term.len()].iter().zip(&term).all(|(&a, &b)| chars_eq(a, b)) {
+                ranges.push([start, start + term.len()]);
+            }
+        }
+    }
+    ranges.sort();
+
+    let mut merged: Vec<[usize; 2]> = Vec::new();
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range[0] <= last[1] => last[1] = last[1].max(range[1]),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// 截取第一个命中位置附近的内容作为摘要，并标出所有检索词的位置
+///
+/// 前端按 `highlights` 自行渲染高亮，摘要本身是纯文本，不需要转义。
+pub fn snippet(content: &str, query: &str) -> Snippet {
+    let chars: Vec<char> = content.chars().collect();
+    let matches = find_matches(&chars, query);
+
+    let first = matches.first().map(|range| range[0]).unwrap_or(0);
+    let start = first.saturating_sub(CONTEXT_CHARS);
+    let end = (start + SNIPPET_CHARS).min(chars.len());
+
+    let mut text = String::new();
+    let offset = if start > 0 {
+        text.push(ELLIPSIS);
+        1
+    } else {
+        0
+    };
+    text.extend(&chars[start..end]);
+    if end < chars.len() {
+        text.push(ELLIPSIS);
+    }
+
+    let highlights = matches
+        .into_iter()
+        .filter(|range| range[0] < end && range[1] > start)
+        .map(|range| [range[0].max(start) - start + offset, range[1].min(end) - start + offset])
+        .collect();
+
+    Snippet { text, highlights }
+}
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use serde::Serialize;
+use serde_json::Value;
+use crate::api::AppState;
+use crate::core::auth::AuthUser;
+use crate::core::messaging;
+use crate::core::protocol::ServerFrame;
+use crate::error::AppError;
+use crate::storage::{
+    Message,
+    Storage,
+    StorageResult,
+    UserEvent
+};
+
+/// 一批同步事件
+#[derive(Debug, Serialize)]
+pub struct SyncBatch {
+    /// 按序号升序的事件帧（带 `seq`；消息按当前状态重建，已删除的消息不下发，序号可能不连续）
+    pub events: Vec<Value>,
+    /// 本批最后一个事件的序号（没有事件时为请求的起点），下次同步以此为 `since`
+    pub last_seq: i64,
+    /// 是否还有更多事件
+    pub has_more: bool,
+}
+
+// 给事件帧附加序号
+fn with_seq(mut frame: Value, seq: i64) -> Value {
+    if let Some(object) = frame.as_object_mut() {
+        object.insert("seq".into(), seq.into());
+    }
+    frame
+}
+
+// 把保存的事件还原为推送帧
+//
+// 与消息相关的事件按消息当前状态重建（编辑后的内容、撤回后的墓碑）；
+// 消息已被用户删除或随群解散删除时跳过该事件。
+fn event_frame(event: &UserEvent, messages: &HashMap<String, Message>) -> Option<Value> {
+    let frame = match &event.message_id {
+        Some(message_id) => messaging::message_event_frame(&event.kind, messages.get(message_id)?)?.to_value(),
+        None => serde_json::from_str(&event.payload).unwrap_or(Value::Null),
+    };
+    Some(with_seq(frame, event.seq))
+}
+
+/// 获取群聊全部成员的用户ID
+pub fn group_member_ids(db: &dyn Storage, group_id: &str) -> StorageResult<Vec<String>> {
+    Ok(db.get_group_members(group_id)?.into_iter().map(|member| member.user_id).collect())
+}
+
+/// 记录事件并推送给每个用户的全部在线设备
+///
+/// 每个用户的事件流各自分配序号，推送的帧带上该用户的 `seq`；
+/// 保存失败时仍然实时推送（不带 `seq`），只是离线设备之后无法补齐该事件。
+pub async fn publish(state: &AppState, user_ids: Vec<String>, frame: ServerFrame) {
+    let message_id = frame.message_id().map(str::to_string);
+    let frame = frame.to_value();
+    let kind = frame.get("type").and_then(|x| x.as_str()).unwrap_or_default().to_string();
+    let payload = frame.to_string();
+
+    let (recipients, event_kind, stored) = (user_ids.clone(), kind.clone(), payload.clone());
+    let appended = state.db_pool.run(move |db| {
+        db.append_user_events(&recipients, &event_kind, &stored, message_id.as_deref())
+    }).await;
+    match appended {
+        Ok(events) => {
+            for event in events {
+                state.send_to_user(&event.user_id, with_seq(frame.clone(), event.seq).to_string());
+            }
+        }
+        Err(e) => {
+            println!("记录 {} 事件失败: {}", kind, e);
+            for user_id in &user_ids {
+                state.send_to_user(user_id, payload.clone());
+            }
+        }
+    }
+}
+
+/// 拉取设备错过的事件
+///
+/// `since` 是客户端已收到的最大序号，同时作为确认推进设备的同步位置；
+/// 缺省时从设备上次确认的位置继续。每批最多 `limit` 条（不超过配置上限）。
+pub async fn sync(state: &AppState, auth_user: &AuthUser, since: Option<i64>, limit: Option<usize>) -> Result<SyncBatch, AppError> {
+    if since.is_some_and(|since| since < 0) {
+        return Err(AppError::BadRequest("since 不能为负数".into()));
+    }
+    let max_batch = state.settings.limits.max_sync_batch_size;
+    let limit = limit.unwrap_or(max_batch).clamp(1, max_batch);
+
+    let (user_id, session_id) = (auth_user.user_id.clone(), auth_user.session_id.clone());
+    let (since, events, has_more, messages) = state.db_pool.run(move |db| {
+        let since = match since {
+            Some(since) => {
+                db.advance_sync_cursor(&session_id, since)?;
+                since
+            }
+            None => db.get_sync_cursor(&session_id)?,
+        };
+        // 多取一条用于判断是否还有更多
+        let mut events = db.get_user_events(&user_id, since, limit + 1)?;
+        let has_more = events.len() > limit;
+        events.truncate(limit);
+
+        let mut message_ids: Vec<String> = events.iter().filter_map(|event| event.message_id.clone()).collect();
+        message_ids.sort_unstable();
+        message_ids.dedup();
+        let messages: HashMap<String, Message> = db.get_visible_messages(&user_id, &message_ids)?
+            .into_iter()
+            .map(|message| (message.id.clone(), message))
+            .collect();
+        Ok::<_, AppError>((since, events, has_more, messages))
+    }).await?;
+
+    Ok(SyncBatch {
+        last_seq: events.last().map_or(since, |event| event.seq),
+        events: events.iter().filter_map(|event| event_frame(event, &messages)).collect(),
+        has_more,
+    })
+}
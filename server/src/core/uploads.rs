@@ -0,0 +1,356 @@
+// 分块（可续传）上传
+//
+// 客户端先创建上传会话声明文件大小（可同时声明 SHA-256），然后按顺序逐块提交，
+// 每块带上起始位置；已落盘的字节数记录在库中，客户端或服务端重启后查询进度即可从断点继续。
+// 全部收到后校验 SHA-256 并登记为附件。闲置超过期限的上传由后台任务清理。
+// 同一上传的分块写入、完成、取消与清理依次执行，并发请求不会交错写入同一文件。
+use std::collections::HashMap;
+use std::io::{
+    ErrorKind,
+    Read,
+    SeekFrom
+};
+use std::path::PathBuf;
+use std::sync::{
+    Arc,
+    LazyLock,
+    Mutex
+};
+use std::time::Duration;
+use axum::body::Body;
+use futures_util::StreamExt;
+use sha2::{
+    Digest,
+    Sha256
+};
+use tokio::fs::{
+    self,
+    OpenOptions
+};
+use tokio::io::{
+    AsyncSeekExt,
+    AsyncWriteExt
+};
+use tokio::sync::{
+    Mutex as AsyncMutex,
+    OwnedMutexGuard
+};
+use crate::api::AppState;
+use crate::config::Settings;
+use crate::core::attachments;
+use crate::error::AppError;
+use crate::storage::{
+    now_secs,
+    Attachment,
+    NewAttachment,
+    Storage,
+    StorageError,
+    Upload
+};
+
+/// 过期上传的清理间隔
+const EXPIRY_SWEEP_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+// 正在处理的上传ID到其锁的映射（没有请求使用时移除）
+static UPLOAD_LOCKS: LazyLock<Mutex<HashMap<String, Arc<AsyncMutex<()>>>>> = LazyLock::new(Default::default);
+
+// 独占一个上传，释放时清理不再有人等待的锁
+struct UploadGuard {
+    upload_id: String,
+    _guard: OwnedMutexGuard<()>,
+}
+
+impl Drop for UploadGuard {
+    fn drop(&mut self) {
+        let mut locks = UPLOAD_LOCKS.lock().unwrap();
+        // 映射与本守卫各持有一份引用，再多说明还有请求在等待
+        if locks.get(&self.upload_id).is_some_and(|lock| Arc::strong_count(lock) == 2) {
+            locks.remove(&self.upload_id);
+        }
+    }
+}
+
+// 等待并独占一个上传
+async fn lock_upload(upload_id: &str) -> UploadGuard {
+    let lock = UPLOAD_LOCKS.lock().unwrap().entry(upload_id.to_string()).or_default().clone();
+    UploadGuard {
+        upload_id: upload_id.to_string(),
+        _guard: lock.lock_owned().await,
+    }
+}
+
+/// 未完成上传的文件路径
+pub fn partial_path(settings: &Settings, upload_id: &str) -> PathBuf {
+    settings.storage.partial_upload_dir().join(upload_id)
+}
+
+/// 规范化客户端提供的 SHA-256（64 位十六进制，统一为小写）
+pub fn normalize_sha256(sha256: &str) -> Result<String, AppError> {
+    let sha256 = sha256.trim().to_ascii_lowercase();
+    if sha256.len() != 64 || !sha256.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(AppError::BadRequest("SHA-256 必须是 64 位十六进制字符串".into()));
+    }
+    Ok(sha256)
+}
+
+/// 读取用户自己未过期的上传会话，否则视为不存在
+pub fn load_upload(db: &dyn Storage, user_id: &str, upload_id: &str) -> Result<Upload, AppError> {
+    let not_found = || AppError::NotFound("上传不存在或已过期".into());
+    match db.get_upload(upload_id) {
+        Ok(upload) if upload.user_id == user_id && upload.expires_at >= now_secs() => Ok(upload),
+        Ok(_) | Err(StorageError::NotFound) => Err(not_found()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// 创建上传会话并建立空的未完成文件
+pub async fn create_upload(
+    state: &AppState,
+    user_id: &str,
+    filename: &str,
+    size: i64,
+    sha256: Option<&str>,
+) -> Result<Upload, AppError> {
+    if size <= 0 {
+        return Err(AppError::BadRequest("文件大小必须大于 0".into()));
+    }
+    let limit = state.settings.limits.max_resumable_upload_bytes;
+    if size as u64 > limit {
+        return Err(AppError::PayloadTooLarge(format!("文件不能超过 {} 字节", limit)));
+    }
+    let sha256 = sha256.map(normalize_sha256).transpose()?;
+
+    let dir = state.settings.storage.partial_upload_dir();
+    fs::create_dir_all(&dir).await.map_err(|e| AppError::Internal(e.to_string()))?;
+
+    let (user, name) = (user_id.to_string(), attachments::sanitize_filename(filename));
+    let expires_at = now_secs() + state.settings.limits.upload_expiry_secs;
+    let upload = state.db_pool.run(move |db| db.create_upload(&user, &name, size, sha256.as_deref(), expires_at)).await?;
+    if let Err(e) = fs::File::create(partial_path(&state.settings, &upload.id)).await {
+        let upload_id = upload.id.clone();
+        let _ = state.db_pool.run(move |db| db.delete_upload(&upload_id)).await;
+        return Err(AppError::Internal(e.to_string()));
+    }
+    Ok(upload)
+}
+
+/// 查询上传进度
+pub async fn upload_progress(state: &AppState, user_id: &str, upload_id: &str) -> Result<Upload, AppError> {
+    let (user, id) = (user_id.to_string(), upload_id.to_string());
+    state.db_pool.run(move |db| load_upload(db, &user, &id)).await
+}
+
+/// 从 `offset` 处追加一块数据，返回更新后的上传会话
+///
+/// `offset` 必须等于已接收的字节数，否则返回 `Conflict`，客户端应查询进度后从正确位置重传。
+/// 请求体以流的方式写入磁盘；传输中断时已写入的部分仍然保留，下次从中断处继续。
+/// 同一上传的并发请求依次执行，后到的请求看到已前进的位置而返回 `Conflict`。
+pub async fn append_chunk(
+    state: &AppState,
+    user_id: &str,
+    upload_id: &str,
+    offset: i64,
+    body: Body,
+) -> Result<Upload, AppError> {
+    let _lock = lock_upload(upload_id).await;
+    let mut upload = upload_progress(state, user_id, upload_id).await?;
+    if offset != upload.received {
+        return Err(AppError::Conflict(format!("上传位置不匹配，已接收 {} 字节", upload.received)));
+    }
+
+    let path = partial_path(&state.settings, &upload.id);
+    let mut file = OpenOptions::new().write(true).open(&path).await.map_err(|e| match e.kind() {
+        ErrorKind::NotFound => AppError::NotFound("上传文件已丢失，请重新上传".into()),
+        _ => AppError::Internal(e.to_string()),
+    })?;
+    let io_error = |e: std::io::Error| AppError::Internal(e.to_string());
+    // 丢弃上次中断时写入但未记录的部分
+    file.set_len(offset as u64).await.map_err(io_error)?;
+    file.seek(SeekFrom::Start(offset as u64)).await.map_err(io_error)?;
+
+    let remaining = (upload.size - offset) as u64;
+    let chunk_limit = state.settings.limits.max_upload_chunk_bytes as u64;
+    let mut written: u64 = 0;
+    let mut stream = body.into_data_stream();
+    let mut failure = None;
+    while let Some(data) = stream.next().await {
+        let data = match data {
+            Ok(data) => data,
+            Err(e) => {
+                failure = Some(AppError::BadRequest(format!("上传中断: {}", e)));
+                break;
+            }
+        };
+        if written + data.len() as u64 > remaining {
+            failure = Some(AppError::BadRequest("超出创建上传时声明的文件大小".into()));
+            break;
+        }
+        if written + data.len() as u64 > chunk_limit {
+            failure = Some(AppError::PayloadTooLarge(format!("每块不能超过 {} 字节", chunk_limit)));
+            break;
+        }
+        if let Err(e) = file.write_all(&data).await {
+            failure = Some(io_error(e));
+            break;
+        }
+        written += data.len() as u64;
+    }
+    file.flush().await.map_err(io_error)?;
+    file.sync_data().await.map_err(io_error)?;
+
+    // 只记录完整写入的字节，超出限制的那一帧不计入
+    if written > 0 {
+        let (id, to) = (upload.id.clone(), offset + written as i64);
+        let expires_at = now_secs() + state.settings.limits.upload_expiry_secs;
+        let advanced = state.db_pool.run(move |db| db.advance_upload(&id, offset, to, expires_at)).await?;
+        if !advanced {
+            return Err(AppError::Conflict("上传位置已被并发请求改变，请查询进度后重试".into()));
+        }
+        upload.received = to;
+        upload.expires_at = expires_at;
+    }
+    match failure {
+        Some(e) => Err(e),
+        None => Ok(upload),
+    }
+}
+
+// 计算文件的 SHA-256，并返回开头的若干字节用于识别类型
+fn hash_file(path: PathBuf) -> std::io::Result<(String, Vec<u8>)> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut head = Vec::with_capacity(attachments::SNIFF_BYTES);
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        if head.len() < attachments::SNIFF_BYTES {
+            let take = n.min(attachments::SNIFF_BYTES - head.len());
+            head.extend_from_slice(&buf[..take]);
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok((hex::encode(hasher.finalize()), head))
+}
+
+/// 完成上传：校验 SHA-256 与该类型的大小上限后登记为附件
+///
+/// 以请求中的 `sha256` 为准，未提供时使用创建时声明的值；两者都没有时拒绝。
+/// 校验失败的上传无法修复，会被直接丢弃。
+pub async fn complete_upload(
+    state: &AppState,
+    user_id: &str,
+    upload_id: &str,
+    sha256: Option<&str>,
+) -> Result<Attachment, AppError> {
+    let _lock = lock_upload(upload_id).await;
+    let upload = upload_progress(state, user_id, upload_id).await?;
+    if upload.received < upload.size {
+        return Err(AppError::Conflict(format!("上传尚未完成，已接收 {}/{} 字节", upload.received, upload.size)));
+    }
+    let expected = match (sha256.map(normalize_sha256).transpose()?, upload.sha256.clone()) {
+        (Some(given), Some(declared)) if given != declared => {
+            return Err(AppError::BadRequest("SHA-256 与创建上传时声明的不一致".into()));
+        }
+        (Some(sha256), _) | (None, Some(sha256)) => sha256,
+        (None, None) => return Err(AppError::BadRequest("缺少 SHA-256".into())),
+    };
+
+    let path = partial_path(&state.settings, &upload.id);
+    let (actual, head) = tokio::task::spawn_blocking({
+        let path = path.clone();
+        move || hash_file(path)
+    })
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?
+        .map_err(|e| match e.kind() {
+            ErrorKind::NotFound => AppError::NotFound("上传文件已丢失，请重新上传".into()),
+            _ => AppError::Internal(e.to_string()),
+        })?;
+    if actual != expected {
+        discard_upload(state, &upload.id).await?;
+        return Err(AppError::BadRequest("SHA-256 校验失败，上传已丢弃".into()));
+    }
+
+    let (kind, mime_type) = attachments::sniff(&head);
+    let limit = match kind {
+        "file" => state.settings.limits.max_resumable_upload_bytes,
+        kind => attachments::max_bytes(&state.settings, kind) as u64,
+    };
+    if upload.size as u64 > limit {
+        discard_upload(state, &upload.id).await?;
+        return Err(AppError::PayloadTooLarge(format!("该类型的附件不能超过 {} 字节", limit)));
+    }
+
+    // 先删除会话再登记，同一上传的并发完成请求只有一个能继续
+    let id = upload.id.clone();
+    if !state.db_pool.run(move |db| db.delete_upload(&id)).await? {
+        return Err(AppError::NotFound("上传不存在或已过期".into()));
+    }
+
+    // 图片不超过图片上限，读入内存按普通上传处理（校验、去除元数据、生成缩略图）
+    if kind == "image" {
+        let content = fs::read(&path).await.map_err(|e| AppError::Internal(e.to_string()));
+        remove_partial(&state.settings, &upload.id).await;
+        return attachments::save_attachment(state, user_id, &upload.filename, &content?).await;
+    }
+    let new = NewAttachment {
+        kind: kind.into(),
+        mime_type: mime_type.into(),
+        size: upload.size,
+        filename: upload.filename,
+        sha256: actual,
+        image: None,
+    };
+    attachments::store_file(state, user_id, &path, new, Vec::new()).await
+}
+
+/// 取消上传
+pub async fn cancel_upload(state: &AppState, user_id: &str, upload_id: &str) -> Result<(), AppError> {
+    let _lock = lock_upload(upload_id).await;
+    let upload = upload_progress(state, user_id, upload_id).await?;
+    discard_upload(state, &upload.id).await
+}
+
+// 删除上传会话及其未完成文件
+async fn discard_upload(state: &AppState, upload_id: &str) -> Result<(), AppError> {
+    let id = upload_id.to_string();
+    state.db_pool.run(move |db| db.delete_upload(&id)).await?;
+    remove_partial(&state.settings, upload_id).await;
+    Ok(())
+}
+
+// 删除未完成文件（文件已不存在时忽略）
+async fn remove_partial(settings: &Settings, upload_id: &str) {
+    if let Err(e) = fs::remove_file(partial_path(settings, upload_id)).await
+        && e.kind() != ErrorKind::NotFound
+    {
+        println!("删除未完成的上传 {} 失败: {}", upload_id, e);
+    }
+}
+
+/// 清理一次过期的上传，返回清理的数量
+pub async fn sweep_expired_uploads(state: &AppState) -> Result<usize, AppError> {
+    let now = now_secs();
+    let expired = state.db_pool.run(move |db| db.delete_expired_uploads(now)).await?;
+    for upload_id in &expired {
+        let _lock = lock_upload(upload_id).await;
+        remove_partial(&state.settings, upload_id).await;
+    }
+    Ok(expired.len())
+}
+
+/// 启动后台任务，定期清理闲置过期的上传（启动时先清理一次）
+pub fn spawn_expiry_task(state: AppState) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(EXPIRY_SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = sweep_expired_uploads(&state).await {
+                println!("清理过期上传失败: {}", e);
+            }
+        }
+    });
+}
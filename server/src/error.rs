@@ -5,6 +5,7 @@ use axum::{
 };
 use serde_json::json;
 use thiserror::Error;
+use crate::storage::StorageError;
 
 #[derive(Error, Debug)]
 pub enum AppError {
@@ -22,6 +23,49 @@ pub enum AppError {
     FriendOperation(String),
     #[error("资源未找到: {0}")]
     NotFound(String),
+    #[error("未认证: {0}")]
+    Unauthorized(String),
+    #[error("无权操作: {0}")]
+    Forbidden(String),
+    #[error("请求参数错误: {0}")]
+    BadRequest(String),
+    #[error("内容过大: {0}")]
+    PayloadTooLarge(String),
+    #[error("数据冲突: {0}")]
+    Conflict(String),
+}
+
+impl AppError {
+    /// 机器可读的错误码（WebSocket 错误帧使用）
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::UserExists(_) => "user_exists",
+            AppError::Database(_) | AppError::Bcrypt(_) | AppError::Internal(_) => "internal",
+            AppError::InvalidCredentials(_) => "invalid_credentials",
+            AppError::FriendOperation(_) => "friend_operation",
+            AppError::NotFound(_) => "not_found",
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::BadRequest(_) => "bad_request",
+            AppError::PayloadTooLarge(_) => "payload_too_large",
+            AppError::Conflict(_) => "conflict",
+        }
+    }
+}
+
+// 存储层错误的默认转换，需要特定提示的场景由处理器自行匹配
+impl From<StorageError> for AppError {
+    fn from(e: StorageError) -> Self {
+        match e {
+            StorageError::NotFound => AppError::NotFound(e.to_string()),
+            StorageError::UsernameTaken => AppError::UserExists(e.to_string()),
+            StorageError::AlreadyFriends
+            | StorageError::FriendRequestAlreadySent
+            | StorageError::FriendRequestAlreadyProcessed => AppError::FriendOperation(e.to_string()),
+            StorageError::Conflict(msg) => AppError::Conflict(msg),
+            StorageError::Pool(_) | StorageError::Backend(_) => AppError::Database(e.to_string()),
+        }
+    }
 }
 
 // 实现axum的错误转换
@@ -35,6 +79,11 @@ impl IntoResponse for AppError {
             AppError::Internal(e) => (StatusCode::INTERNAL_SERVER_ERROR, e),
             AppError::FriendOperation(e) => (StatusCode::BAD_REQUEST, e),
             AppError::NotFound(e) => (StatusCode::NOT_FOUND, e),
+            AppError::Unauthorized(e) => (StatusCode::UNAUTHORIZED, e),
+            AppError::Forbidden(e) => (StatusCode::FORBIDDEN, e),
+            AppError::BadRequest(e) => (StatusCode::BAD_REQUEST, e),
+            AppError::PayloadTooLarge(e) => (StatusCode::PAYLOAD_TOO_LARGE, e),
+            AppError::Conflict(e) => (StatusCode::CONFLICT, e),
         };
         let body = Json(json!({ "success": false, "message": msg }));
         (status, body).into_response()
@@ -14,15 +14,49 @@ pub use api::{
 };
 
 pub use storage::{
-    DbPool
+    postgres,
+    sqlite,
+    DbPool,
+    Migration,
+    PoolOptions,
+    Storage,
+    StorageError,
+    StorageResult,
+    User,
+    Message,
+    Attachment,
+    Blob,
+    ImageInfo,
+    NewAttachment,
+    Upload,
+    MessageEdit,
+    MessageExtras,
+    Friendship,
+    Group,
+    GroupMember,
+    GroupReadCount,
+    ReactionCount,
+    FriendRequest,
+    Session,
+    UserEvent,
+    Conversation,
+    HistoryCursor,
+    MessageSearch
 };
 
 pub use error::{
     AppError
 };
 pub use core::{
+    attachments,
     auth,
-    models
+    blobs,
+    downloads,
+    images,
+    messaging,
+    models,
+    protocol,
+    uploads
 };
 pub use config::{
     loader,
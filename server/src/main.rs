@@ -1,40 +1,105 @@
 use server::{
+    blobs,
     register_routes,
+    loader,
     DbPool
 };
 
 use tokio::net::TcpListener;
-use tower_http::cors::{CorsLayer, Any};
-use axum::http::Method;
+use tower_http::cors::{CorsLayer, Any, AllowOrigin};
+use axum::http::{
+    header::{ACCEPT_RANGES, CONTENT_DISPOSITION, CONTENT_RANGE, ETAG, LAST_MODIFIED},
+    HeaderValue,
+    Method
+};
 
 /// 主函数：启动聊天服务器
-/// 
-/// 1. 初始化数据库连接池
-/// 2. 构建API路由和WebSocket服务
-/// 3. 配置CORS
-/// 4. 启动HTTP和WebSocket服务器
+///
+/// 1. 加载配置（配置文件、环境变量、命令行参数）
+/// 2. 初始化存储后端并执行迁移
+/// 3. 构建API路由和WebSocket服务
+/// 4. 配置CORS
+/// 5. 启动HTTP和WebSocket服务器
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // 初始化数据库连接池
-    let db_pool = DbPool::new("server.db")?;
+    // 加载并校验配置，失败时输出可读的错误信息后退出
+    let (cli, settings) = match loader::load() {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("配置错误: {}", e);
+            std::process::exit(2);
+        }
+    };
+
+    // 按配置打开存储后端（SQLite 或 PostgreSQL）；试运行时不修改数据库
+    let db_pool = if cli.dry_run {
+        DbPool::connect_dry_run(&settings.database)?
+    } else {
+        DbPool::connect(&settings.database)?
+    };
+
+    // 只执行迁移或试运行迁移时不启动服务
+    if cli.migrate_only || cli.dry_run {
+        let dry_run = cli.dry_run;
+        let (from_version, applied, to_version) = db_pool.run(move |db| {
+            let from_version = db.schema_version()?;
+            let applied = db.migrate(dry_run)?;
+            Ok::<_, server::StorageError>((from_version, applied, db.schema_version()?))
+        }).await?;
+        if applied.is_empty() {
+            println!("数据库结构已是最新版本 {}", from_version);
+        } else if cli.dry_run {
+            for migration in &applied {
+                println!("[试运行] 将执行迁移 {:04}_{}", migration.version, migration.name);
+            }
+            println!("[试运行] 当前版本 {}，目标版本 {}，数据库未修改", from_version, db_pool.latest_version());
+        } else {
+            println!("数据库结构版本: {} -> {}", from_version, to_version);
+        }
+        return Ok(());
+    }
+
+    // 启动时自动执行迁移
+    db_pool.run(|db| db.migrate(false)).await?;
+    println!("存储后端: {}", db_pool.backend_name());
+
+    // 立即回收一次不再被引用的文件后退出
+    if cli.gc {
+        let report = blobs::collect_garbage(&db_pool, &settings.storage).await?;
+        println!(
+            "垃圾回收完成: 删除 {} 个未发送的附件、{} 个文件，回收 {} 字节",
+            report.attachments_removed, report.blobs_removed, report.bytes_reclaimed
+        );
+        return Ok(());
+    }
 
     // 配置跨域资源共享（CORS）策略
+    let allow_origin = if settings.cors.allows_any() {
+        AllowOrigin::from(Any)
+    } else {
+        let origins: Vec<HeaderValue> = settings.cors.allowed_origins
+            .iter()
+            .filter_map(|origin| origin.parse().ok())
+            .collect();
+        AllowOrigin::list(origins)
+    };
     let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods([Method::GET, Method::POST, Method::OPTIONS])
-        .allow_headers(Any);
+        .allow_origin(allow_origin)
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::PATCH, Method::DELETE, Method::OPTIONS])
+        .allow_headers(Any)
+        // 浏览器端断点续传与缓存需要读取的下载响应头
+        .expose_headers([ACCEPT_RANGES, CONTENT_DISPOSITION, CONTENT_RANGE, ETAG, LAST_MODIFIED]);
 
     // 构建API路由
-    let app = register_routes(db_pool.clone()).layer(cors);
+    let addr = settings.server.bind;
+    let app = register_routes(db_pool.clone(), settings).await?.layer(cors);
 
     // 启动服务器
-    let port = 2025;
-    let addr = format!("0.0.0.0:{}", port);
-    let listener = TcpListener::bind(&addr).await?;
+    let listener = TcpListener::bind(addr).await?;
     println!("服务器正在监听 http://{} (HTTP) 和 ws://{} (WebSocket)", addr, addr);
-    
+
     // 启动HTTP和WebSocket服务
     axum::serve(listener, app).await?;
-    
+
     Ok(())
 }
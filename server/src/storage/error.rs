@@ -0,0 +1,59 @@
+use thiserror::Error;
+
+/// 与具体数据库无关的存储层错误
+///
+/// 各存储后端把驱动错误转换为这里的变体，业务冲突使用专门的变体表示，
+/// 接口层只需按变体匹配，无需解析驱动返回的错误信息。
+#[derive(Error, Debug)]
+pub enum StorageError {
+    #[error("记录不存在")]
+    NotFound,
+    #[error("用户名已存在")]
+    UsernameTaken,
+    #[error("已经是好友")]
+    AlreadyFriends,
+    #[error("好友请求已发送")]
+    FriendRequestAlreadySent,
+    #[error("好友请求已处理")]
+    FriendRequestAlreadyProcessed,
+    #[error("数据冲突: {0}")]
+    Conflict(String),
+    #[error("获取数据库连接失败: {0}")]
+    Pool(String),
+    #[error("数据库错误: {0}")]
+    Backend(String),
+}
+
+pub type StorageResult<T> = Result<T, StorageError>;
+
+impl From<r2d2::Error> for StorageError {
+    fn from(e: r2d2::Error) -> Self {
+        StorageError::Pool(e.to_string())
+    }
+}
+
+impl From<rusqlite::Error> for StorageError {
+    fn from(e: rusqlite::Error) -> Self {
+        match e {
+            rusqlite::Error::QueryReturnedNoRows => StorageError::NotFound,
+            rusqlite::Error::SqliteFailure(err, msg)
+                if err.code == rusqlite::ErrorCode::ConstraintViolation =>
+            {
+                StorageError::Conflict(msg.unwrap_or_else(|| err.to_string()))
+            }
+            _ => StorageError::Backend(e.to_string()),
+        }
+    }
+}
+
+impl From<postgres::Error> for StorageError {
+    fn from(e: postgres::Error) -> Self {
+        match e.as_db_error() {
+            Some(db) if db.code().code().starts_with("23") => {
+                // 23xxx 为完整性约束冲突（唯一约束、外键等）
+                StorageError::Conflict(db.message().to_string())
+            }
+            _ => StorageError::Backend(e.to_string()),
+        }
+    }
+}
@@ -1,8 +1,21 @@
-use rusqlite::{params, Connection, Result};
-use bcrypt::{hash, DEFAULT_COST};
-use uuid::Uuid;
 use serde::{Serialize, Deserialize};
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
+use std::time::Duration;
+use crate::config::settings::{
+    DatabaseBackend,
+    DatabaseSettings
+};
+
+pub mod error;
+pub mod postgres;
+pub mod sqlite;
+
+pub use error::{
+    StorageError,
+    StorageResult
+};
+use self::postgres::PostgresStorage;
+use self::sqlite::SqliteStorage;
 
 // 用户模型（对应数据库表）
 #[derive(Debug, Serialize, Deserialize)]
@@ -13,6 +26,7 @@ pub struct User {
     pub password_hash: String, // bcrypt哈希后的密码
     pub created_at: i64,     // 创建时间戳（Unix秒）
     pub avatar_url: String,  // 头像URL
+    pub last_seen: Option<i64>, // 最近在线时间戳（从未上线为 None）
 }
 
 // 消息模型
@@ -25,6 +39,112 @@ pub struct Message {
     pub message_type: String, // 消息类型："private"或"group"
     pub created_at: i64,     // 创建时间戳
     pub is_read: bool,       // 是否已读
+    pub delivered_at: Option<i64>, // 送达接收方设备的时间戳（仅私聊）
+    pub read_at: Option<i64>,      // 接收方已读的时间戳（仅私聊）
+    pub edited_at: Option<i64>,    // 最近一次编辑的时间戳
+    pub deleted_at: Option<i64>,   // 撤回的时间戳（撤回后内容为空，只保留墓碑）
+    pub deleted_by: Option<String>, // 撤回操作者ID（发送者本人或群主）
+    pub reply_to_id: Option<String>, // 被回复（引用）的消息ID
+    pub thread_root_id: Option<String>, // 所属话题的根消息ID（仅群聊回复）
+    pub reply_count: i64,          // 话题回复数（仅话题根消息）
+    pub mentions: Vec<String>,     // 被@的成员ID（仅群聊）
+    pub mention_all: bool,         // 是否@全体成员（仅群聊）
+    pub attachments: Vec<String>,  // 附件ID（按顺序）
+}
+
+// 附件模型（文件内容保存在按 SHA-256 寻址的文件库中，相同内容只存一份）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attachment {
+    pub id: String,          // UUID主键
+    pub uploader_id: String, // 上传者ID
+    pub kind: String,        // 类型："image"、"voice" 或 "file"
+    pub mime_type: String,   // 按文件内容识别的MIME类型
+    pub size: i64,           // 文件大小（字节）
+    pub filename: String,    // 原始文件名（仅用于展示与下载）
+    pub created_at: i64,     // 上传时间戳
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>, // 内容的 SHA-256（旧附件为空，文件仍以附件ID命名保存在 attachments 目录）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<i64>,     // 图片宽度（像素，仅图片）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<i64>,    // 图片高度（像素，仅图片）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blurhash: Option<String>, // 图片加载前的模糊占位（BlurHash，仅图片）
+}
+
+// 图片附件的尺寸与占位信息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageInfo {
+    pub width: i64,       // 宽度（像素，已按拍摄方向旋转）
+    pub height: i64,      // 高度（像素）
+    pub blurhash: String, // BlurHash 占位
+}
+
+// 新附件的信息（文件由调用方放入文件库）
+#[derive(Debug, Clone, Default)]
+pub struct NewAttachment {
+    pub kind: String,             // 类型："image"、"voice" 或 "file"
+    pub mime_type: String,        // 按文件内容识别的MIME类型
+    pub size: i64,                // 文件大小（字节）
+    pub filename: String,         // 原始文件名
+    pub sha256: String,           // 内容的 SHA-256
+    pub image: Option<ImageInfo>, // 图片的尺寸与占位信息
+}
+
+// 按内容寻址的文件（被附件和头像引用，引用数归零并超过宽限期后由垃圾回收删除）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Blob {
+    pub sha256: String,                  // 内容的 SHA-256（主键，也是文件名）
+    pub size: i64,                       // 文件大小（字节）
+    pub ref_count: i64,                  // 引用数（附件与用户头像）
+    pub created_at: i64,                 // 首次保存时间戳
+    pub unreferenced_since: Option<i64>, // 引用数归零的时间
+}
+
+// 发送消息时的附加内容
+#[derive(Debug, Clone, Default)]
+pub struct MessageExtras {
+    pub mentions: Vec<String>,       // 被@的成员ID（仅群聊，调用方去重，不含发送者）
+    pub mention_all: bool,           // 是否@全体成员（仅群聊）
+    pub attachment_ids: Vec<String>, // 附件ID（按顺序，调用方校验访问权限）
+}
+
+// 消息编辑历史（每次编辑前的内容）
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MessageEdit {
+    pub id: String,          // UUID主键（随时间递增）
+    pub message_id: String,  // 所属消息ID
+    pub content: String,     // 本次编辑前的内容
+    pub edited_at: i64,      // 编辑时间戳
+}
+
+// 消息表情回应汇总（每条消息的每种表情一条）
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReactionCount {
+    pub message_id: String,  // 消息ID
+    pub emoji: String,       // 表情
+    pub count: i64,          // 回应人数
+    pub reacted: bool,       // 查询者本人是否回应了该表情
+}
+
+// 分块上传会话（未完成的文件保存在上传目录的 partial 子目录）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Upload {
+    pub id: String,             // UUID主键
+    pub user_id: String,        // 上传者ID
+    pub filename: String,       // 原始文件名
+    pub size: i64,              // 声明的文件总大小（字节）
+    pub received: i64,          // 已接收并落盘的字节数（下一块的起始位置）
+    pub sha256: Option<String>, // 创建时声明的 SHA-256（小写十六进制）
+    pub created_at: i64,        // 创建时间戳
+    pub expires_at: i64,        // 过期时间戳（每收到一块顺延）
+}
+
+// 群消息已读统计（不含发送者本人）
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GroupReadCount {
+    pub read: i64,           // 已读成员数
+    pub total: i64,          // 除发送者外的成员数
 }
 
 // 好友关系模型
@@ -53,7 +173,8 @@ pub struct GroupMember {
     pub group_id: String,    // 群聊ID
     pub user_id: String,     // 用户ID
     pub joined_at: i64,      // 加入时间戳
-    pub role: String,        // 角色："owner"或"member"
+    pub role: String,        // 角色："owner"、"admin"或"member"
+    pub muted: bool,         // 是否设置了消息免打扰（@提醒不受影响）
 }
 
 // 好友请求响应
@@ -66,508 +187,400 @@ pub struct FriendRequest {
     pub created_at: i64,
 }
 
-// 数据库连接池（线程安全）
-#[derive(Clone)]
-pub struct DbPool(pub Arc<Mutex<Connection>>);
+// 登录会话模型
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Session {
+    pub id: String,                 // UUID主键
+    pub user_id: String,            // 所属用户ID
+    pub created_at: i64,            // 创建时间戳
+    pub refresh_expires_at: i64,    // 刷新令牌过期时间戳
+    pub last_used_at: i64,          // 最近一次刷新时间戳
+    pub device_name: Option<String>, // 设备名称
+}
 
-impl DbPool {
-    // 初始化数据库连接并创建所有表
-    pub fn new(db_path: &str) -> Result<Self> {
-        let conn = Connection::open(db_path)?;
-        
-        // 创建表（若不存在）
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS users (
-                id TEXT PRIMARY KEY,
-                username TEXT UNIQUE NOT NULL,
-                email TEXT UNIQUE NOT NULL,
-                password_hash TEXT NOT NULL,
-                created_at INTEGER NOT NULL,
-                avatar_url TEXT DEFAULT '',
-                user_data TEXT
-            )",
-            [],
-        )?;
-        
-        // 创建消息表
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS messages (
-                id TEXT PRIMARY KEY,
-                sender_id TEXT NOT NULL,
-                receiver_id TEXT NOT NULL,
-                content TEXT NOT NULL,
-                message_type TEXT NOT NULL,
-                created_at INTEGER NOT NULL,
-                is_read INTEGER NOT NULL DEFAULT 0,
-                FOREIGN KEY(sender_id) REFERENCES users(id)
-            )",
-            [],
-        )?;
-        
-        // 创建好友关系表（修改为支持双向好友关系）
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS friendships (
-                id TEXT PRIMARY KEY,
-                user_id TEXT NOT NULL,
-                friend_id TEXT NOT NULL,
-                status TEXT NOT NULL,
-                created_at INTEGER NOT NULL,
-                FOREIGN KEY(user_id) REFERENCES users(id),
-                FOREIGN KEY(friend_id) REFERENCES users(id),
-                UNIQUE(user_id, friend_id)
-            )",
-            [],
-        )?;
-        
-        // 创建好友请求表
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS friend_requests (
-                id TEXT PRIMARY KEY,
-                from_user_id TEXT NOT NULL,
-                to_user_id TEXT NOT NULL,
-                status TEXT NOT NULL DEFAULT 'pending',
-                created_at INTEGER NOT NULL,
-                FOREIGN KEY(from_user_id) REFERENCES users(id),
-                FOREIGN KEY(to_user_id) REFERENCES users(id),
-                UNIQUE(from_user_id, to_user_id)
-            )",
-            [],
-        )?;
-        
-        // 创建群聊表
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS groups (
-                id TEXT PRIMARY KEY,
-                group_id TEXT NOT NULL,
-                name TEXT NOT NULL,
-                creator_id TEXT NOT NULL,
-                created_at INTEGER NOT NULL,
-                group_user_list TEXT,
-                FOREIGN KEY(creator_id) REFERENCES users(id)
-            )",
-            [],
-        )?;
-        
-        // 创建群聊成员表
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS group_members (
-                id TEXT PRIMARY KEY,
-                group_id TEXT NOT NULL,
-                user_id TEXT NOT NULL,
-                joined_at INTEGER NOT NULL,
-                role TEXT NOT NULL,
-                FOREIGN KEY(group_id) REFERENCES groups(id),
-                FOREIGN KEY(user_id) REFERENCES users(id),
-                UNIQUE(group_id, user_id)
-            )",
-            [],
-        )?;
-        
-        Ok(Self(Arc::new(Mutex::new(conn))))
-    }
+// 用户事件模型（离线同步）
+//
+// `payload` 为推送给客户端的 JSON 帧（不含 seq），`kind` 与帧的 type 相同。
+// 与消息相关的事件带 `message_id`，同步时按消息当前状态（编辑、撤回）重建。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserEvent {
+    pub user_id: String,            // 所属用户ID
+    pub seq: i64,                   // 用户内单调递增的序号（从1开始）
+    pub kind: String,               // 事件类型
+    pub payload: String,            // 事件内容（JSON）
+    pub message_id: Option<String>, // 关联的消息ID
+    pub created_at: i64,            // 创建时间戳
+}
 
-    // 注册新用户（核心逻辑）
-    pub fn register_user(
-        &self,
-        username: &str,
-        _email: &str, // 保留参数但忽略，保持向后兼容
-        password: &str,
-    ) -> Result<User> {
-        let conn = self.0.lock().unwrap();
-        
-        // 检查用户名是否已存在
-        let exists: bool = conn.query_row(
-            "SELECT EXISTS(SELECT 1 FROM users WHERE username = ?)",
-            [username],
-            |row| row.get(0),
-        )?;
-        
-        if exists {
-            return Err(rusqlite::Error::SqliteFailure(
-                rusqlite::ffi::Error::new(0),
-                Some("用户名已存在".to_string())
-            ));
-        }
+// 当前Unix时间戳（秒）
+pub fn now_secs() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
 
-        // 密码哈希（bcrypt）
-        let password_hash = hash(password, DEFAULT_COST).map_err(|e| {
-            rusqlite::Error::ToSqlConversionFailure(Box::new(e))
-        })?;
-
-        // 插入数据库
-        let user_id = Uuid::new_v4().to_string();
-        let created_at = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs() as i64;
-        // 生成唯一占位邮箱（避免使用空字符串导致 UNIQUE 约束冲突）
-        let email_placeholder = format!("{}@local", user_id);
-
-        conn.execute(
-            "INSERT INTO users (id, username, email, password_hash, created_at) 
-             VALUES (?1, ?2, ?3, ?4, ?5)",
-            params![user_id, username, &email_placeholder, &password_hash, created_at],
-        )?;
-
-        // 返回新用户（不含敏感信息）
-        Ok(User {
-            id: user_id,
-            username: username.to_string(),
-            email: email_placeholder,
-            password_hash,
-            created_at,
-            avatar_url: String::new(),
-        })
-    }
-    
-    // 发送消息
-    pub fn send_message(
-        &self,
-        sender_id: &str,
-        receiver_id: &str,
-        content: &str,
-        message_type: &str,
-    ) -> Result<Message> {
-        let conn = self.0.lock().unwrap();
-        
-        let message_id = Uuid::new_v4().to_string();
-        let created_at = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs() as i64;
-        
-        conn.execute(
-            "INSERT INTO messages (id, sender_id, receiver_id, content, message_type, created_at, is_read) 
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-            params![message_id, sender_id, receiver_id, content, message_type, created_at, false],
-        )?;
-        
-        Ok(Message {
-            id: message_id,
-            sender_id: sender_id.to_string(),
-            receiver_id: receiver_id.to_string(),
-            content: content.to_string(),
-            message_type: message_type.to_string(),
-            created_at,
-            is_read: false,
-        })
-    }
-    
-    // 获取用户的未读消息
-    pub fn get_unread_messages(&self, user_id: &str) -> Result<Vec<Message>> {
-        let conn = self.0.lock().unwrap();
-        let mut stmt = conn.prepare(
-            "SELECT id, sender_id, receiver_id, content, message_type, created_at, is_read 
-             FROM messages 
-             WHERE receiver_id = ? AND is_read = 0 AND message_type = 'private'"
-        )?;
-        
-        let messages = stmt.query_map([user_id], |row| {
-            Ok(Message {
-                id: row.get(0)?,
-                sender_id: row.get(1)?,
-                receiver_id: row.get(2)?,
-                content: row.get(3)?,
-                message_type: row.get(4)?,
-                created_at: row.get(5)?,
-                is_read: row.get(6)?,
-            })
-        })?
-        .filter_map(Result::ok)
-        .collect();
-        
-        Ok(messages)
-    }
-    
-    // 将消息标记为已读
-    pub fn mark_messages_as_read(&self, message_ids: &[String]) -> Result<()> {
-        let conn = self.0.lock().unwrap();
-        
-        for message_id in message_ids {
-            conn.execute(
-                "UPDATE messages SET is_read = 1 WHERE id = ?",
-                [message_id],
-            )?;
-        }
-        
-        Ok(())
-    }
-    
-    // 获取用户好友列表
-    pub fn get_friends(&self, user_id: &str) -> Result<Vec<User>> {
-        let conn = self.0.lock().unwrap();
-        let mut stmt = conn.prepare(
-            "SELECT u.id, u.username, u.email, u.password_hash, u.created_at, u.avatar_url 
-             FROM users u 
-             JOIN friendships f ON u.id = f.friend_id 
-             WHERE f.user_id = ? AND f.status = 'accepted'"
-        )?;
-        
-        let friends = stmt.query_map([user_id], |row| {
-            Ok(User {
-                id: row.get(0)?,
-                username: row.get(1)?,
-                email: row.get(2)?,
-                password_hash: row.get(3)?,
-                created_at: row.get(4)?,
-                avatar_url: row.get(5)?,
-            })
-        })?
-        .filter_map(Result::ok)
-        .collect();
-        
-        Ok(friends)
+// 校验被回复的消息与新消息属于同一会话，返回新消息所属的话题根消息ID（只有群聊形成话题）
+fn reply_thread_root(parent: &Message, sender_id: &str, receiver_id: &str, message_type: &str) -> StorageResult<Option<String>> {
+    let same_conversation = parent.deleted_at.is_none()
+        && parent.message_type == message_type
+        && match message_type {
+            "group" => parent.receiver_id == receiver_id,
+            _ => (parent.sender_id == sender_id && parent.receiver_id == receiver_id)
+                || (parent.sender_id == receiver_id && parent.receiver_id == sender_id),
+        };
+    if !same_conversation {
+        return Err(StorageError::NotFound);
     }
+    Ok((message_type == "group").then(|| parent.thread_root_id.clone().unwrap_or_else(|| parent.id.clone())))
+}
 
-    // 添加好友功能相关方法
-
-    // 搜索用户
-    pub fn search_users(&self, query: &str) -> Result<Vec<User>> {
-        let conn = self.0.lock().unwrap();
-        let mut stmt = conn.prepare(
-            "SELECT id, username, email, password_hash, created_at, avatar_url 
-             FROM users 
-             WHERE username LIKE ? OR id LIKE ? 
-             LIMIT 10"
-        )?;
-        
-        let search_pattern = format!("%{}%", query);
-        let users = stmt.query_map(
-            params![&search_pattern, &search_pattern],
-            |row| {
-                Ok(User {
-                    id: row.get(0)?,
-                    username: row.get(1)?,
-                    email: row.get(2)?,
-                    password_hash: row.get(3)?,
-                    created_at: row.get(4)?,
-                    avatar_url: row.get(5)?,
-                })
-            },
-        )?
-        .filter_map(Result::ok)
-        .collect();
-        
-        Ok(users)
-    }
+// 消息表中ID列表列（@成员、附件）的存储格式（JSON数组）
+fn ids_to_json(ids: &[String]) -> String {
+    serde_json::to_string(ids).unwrap_or_else(|_| "[]".into())
+}
 
-    // 检查用户是否存在（根据用户ID）
-    pub fn user_exists_by_id(&self, user_id: &str) -> Result<bool> {
-        let conn = self.0.lock().unwrap();
-        let exists: bool = conn.query_row(
-            "SELECT EXISTS(SELECT 1 FROM users WHERE id = ?)",
-            [user_id],
-            |row| row.get(0),
-        )?;
-        Ok(exists)
-    }
+// 解析消息表中的ID列表列（只由本模块写入，格式异常时视为空）
+fn ids_from_json(json: &str) -> Vec<String> {
+    serde_json::from_str(json).unwrap_or_default()
+}
 
-    // 发送好友请求
-    pub fn send_friend_request(&self, from_user_id: &str, to_username: &str) -> Result<FriendRequest> {
-        let conn = self.0.lock().unwrap();
-        
-        // 检查目标用户是否存在
-        let to_user_id: String = conn.query_row(
-            "SELECT id FROM users WHERE username = ?",
-            [to_username],
-            |row| row.get(0),
-        )?;
-        
-        // 检查是否已经是好友
-        let is_friend: bool = conn.query_row(
-            "SELECT EXISTS(SELECT 1 FROM friendships WHERE user_id = ? AND friend_id = ? AND status = 'accepted')",
-            params![from_user_id, to_user_id],
-            |row| row.get(0),
-        )?;
-        
-        if is_friend {
-            return Err(rusqlite::Error::SqliteFailure(
-                rusqlite::ffi::Error::new(1),
-                Some("Already friends".to_string())
-            ));
-        }
-        
-        // 检查是否已经发送过请求
-        let has_pending_request: bool = conn.query_row(
-            "SELECT EXISTS(SELECT 1 FROM friend_requests WHERE from_user_id = ? AND to_user_id = ? AND status = 'pending')",
-            params![from_user_id, to_user_id],
-            |row| row.get(0),
-        )?;
-        
-        if has_pending_request {
-            return Err(rusqlite::Error::SqliteFailure(
-                rusqlite::ffi::Error::new(2),
-                Some("Friend request already sent".to_string())
-            ));
+// 连接池参数
+#[derive(Debug, Clone)]
+pub struct PoolOptions {
+    pub max_size: u32,           // 最大连接数
+    pub busy_timeout: Duration,  // 数据库被锁定时的等待时间
+}
+
+impl Default for PoolOptions {
+    fn default() -> Self {
+        Self {
+            max_size: 8,
+            busy_timeout: Duration::from_secs(5),
         }
-        
-        // 创建好友请求
-        let request_id = Uuid::new_v4().to_string();
-        let created_at = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs() as i64;
-        
-        conn.execute(
-            "INSERT INTO friend_requests (id, from_user_id, to_user_id, status, created_at) 
-             VALUES (?1, ?2, ?3, ?4, ?5)",
-            params![request_id, from_user_id, to_user_id, "pending", created_at],
-        )?;
-        
-        Ok(FriendRequest {
-            id: request_id,
-            from_user_id: from_user_id.to_string(),
-            to_user_id,
-            status: "pending".to_string(),
-            created_at,
-        })
     }
+}
 
-    // 获取收到的好友请求
-    pub fn get_received_friend_requests(&self, user_id: &str) -> Result<Vec<FriendRequest>> {
-        let conn = self.0.lock().unwrap();
-        let mut stmt = conn.prepare(
-            "SELECT fr.id, fr.from_user_id, fr.to_user_id, fr.status, fr.created_at
-             FROM friend_requests fr
-             WHERE fr.to_user_id = ? AND fr.status = 'pending'"
-        )?;
-        
-        let requests = stmt.query_map([user_id], |row| {
-            Ok(FriendRequest {
-                id: row.get(0)?,
-                from_user_id: row.get(1)?,
-                to_user_id: row.get(2)?,
-                status: row.get(3)?,
-                created_at: row.get(4)?,
-            })
-        })?
-        .filter_map(Result::ok)
-        .collect();
-        
-        Ok(requests)
-    }
+/// 历史消息所属的会话
+#[derive(Debug, Clone, Copy)]
+pub enum Conversation<'a> {
+    /// 两个用户之间的私聊（双方顺序无关）
+    Private(&'a str, &'a str),
+    /// 群聊（群ID）
+    Group(&'a str),
+    /// 群聊话题（根消息ID），只含回复，不含根消息本身
+    Thread(&'a str),
+}
 
-    // 响应好友请求
-    pub fn respond_to_friend_request(&self, request_id: &str, responder_id: &str, response: &str) -> Result<Friendship> {
-        let conn = self.0.lock().unwrap();
-
-        // 验证请求存在且由该接收方(responder)处理
-        // 查询出原始发送者(from_user_id)和当前状态
-        let (from_user_id, current_status): (String, String) = conn.query_row(
-            "SELECT from_user_id, status FROM friend_requests WHERE id = ? AND to_user_id = ?",
-            params![request_id, responder_id],
-            |row| Ok((row.get(0)?, row.get(1)?)),
-        )?;
-
-        if current_status != "pending" {
-            return Err(rusqlite::Error::SqliteFailure(
-                rusqlite::ffi::Error::new(3),
-                Some("Friend request already processed".to_string())
-            ));
-        }
+/// 历史消息分页位置
+#[derive(Debug, Clone, Copy)]
+pub enum HistoryCursor<'a> {
+    /// 最新的一页
+    Latest,
+    /// 指定消息之前（更早）的一页
+    Before(&'a str),
+    /// 指定消息之后（更新）的一页
+    After(&'a str),
+}
 
-        // 更新好友请求状态
-        conn.execute(
-            "UPDATE friend_requests SET status = ? WHERE id = ?",
-            params![response, request_id],
-        )?;
-
-        if response == "accepted" {
-            // 创建双向好友关系（from_user_id <-> responder_id）
-            let friendship_id = Uuid::new_v4().to_string();
-            let created_at = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs() as i64;
-
-            // 正向关系（发送者的好友是接收者）
-            conn.execute(
-                "INSERT INTO friendships (id, user_id, friend_id, status, created_at) 
-                 VALUES (?1, ?2, ?3, ?4, ?5)",
-                params![friendship_id, from_user_id, responder_id, "accepted", created_at],
-            )?;
-
-            // 反向关系（接收者的好友是发送者）
-            let reverse_friendship_id = Uuid::new_v4().to_string();
-            conn.execute(
-                "INSERT INTO friendships (id, user_id, friend_id, status, created_at) 
-                 VALUES (?1, ?2, ?3, ?4, ?5)",
-                params![reverse_friendship_id, responder_id, from_user_id, "accepted", created_at],
-            )?;
-
-            Ok(Friendship {
-                id: friendship_id,
-                user_id: from_user_id.to_string(),
-                friend_id: responder_id.to_string(),
-                status: "accepted".to_string(),
-                created_at,
-            })
-        } else {
-            // 拒绝请求，只更新状态，不创建好友关系
-            Ok(Friendship {
-                id: request_id.to_string(),
-                user_id: from_user_id.to_string(),
-                friend_id: responder_id.to_string(),
-                status: "rejected".to_string(),
-                created_at: 0,
-            })
-        }
+/// 消息搜索条件
+#[derive(Debug, Clone, Copy)]
+pub struct MessageSearch<'a> {
+    /// 搜索者；只返回其参与的私聊和所在群的消息
+    pub user_id: &'a str,
+    /// 检索词（空白分隔，全部需要匹配）
+    pub query: &'a str,
+    /// 只看该用户发送的消息
+    pub sender_id: Option<&'a str>,
+    /// 只在该会话中搜索
+    pub conversation: Option<Conversation<'a>>,
+    /// 发送时间下限（含，Unix秒）
+    pub since: Option<i64>,
+    /// 发送时间上限（含，Unix秒）
+    pub until: Option<i64>,
+    /// 分页游标：只返回该消息之前（更早）的结果
+    pub before: Option<&'a str>,
+    /// 最多返回条数
+    pub limit: usize,
+}
+
+/// 一次结构迁移
+#[derive(Debug)]
+pub struct Migration {
+    pub version: i64,        // 版本号（严格递增）
+    pub name: &'static str,  // 迁移名称
+    pub sql: &'static str,   // 迁移脚本
+}
+
+/// 存储后端接口
+///
+/// 所有方法都是阻塞调用，异步代码中需通过 `DbPool::run` 在阻塞线程池中执行。
+/// 业务冲突（用户名重复、重复的好友请求等）以 `StorageError` 的专门变体返回。
+pub trait Storage: Send + Sync {
+    /// 后端名称（用于日志）
+    fn backend_name(&self) -> &'static str;
+
+    // 结构迁移
+
+    /// 执行尚未执行的结构迁移，返回本次（或试运行时将要）执行的迁移
+    fn migrate(&self, dry_run: bool) -> StorageResult<Vec<&'static Migration>>;
+    /// 当前数据库结构版本（未执行过任何迁移时为 0）
+    fn schema_version(&self) -> StorageResult<i64>;
+    /// 该后端的最新结构版本
+    fn latest_version(&self) -> i64;
+
+    // 用户
+
+    /// 注册新用户（密码由调用方哈希）
+    fn register_user(&self, username: &str, password_hash: &str) -> StorageResult<User>;
+    /// 根据ID获取用户
+    fn get_user_by_id(&self, user_id: &str) -> StorageResult<User>;
+    /// 根据用户名获取用户（登录时使用）
+    fn get_user_by_username(&self, username: &str) -> StorageResult<User>;
+    /// 根据ID获取用户名
+    fn get_username(&self, user_id: &str) -> StorageResult<String>;
+    /// 检查用户是否存在（根据用户ID）
+    fn user_exists_by_id(&self, user_id: &str) -> StorageResult<bool>;
+    /// 按用户名或ID模糊搜索用户（最多 10 条）
+    fn search_users(&self, query: &str) -> StorageResult<Vec<User>>;
+    /// 更新用户头像URL并引用新头像文件、释放旧头像文件，返回旧的头像URL
+    fn update_user_avatar(&self, user_id: &str, avatar_url: &str, sha256: &str, size: i64) -> StorageResult<String>;
+    /// 文件库中的该文件是否为某个用户当前的头像（只有这样的文件可以公开访问）
+    fn is_avatar_blob(&self, sha256: &str) -> StorageResult<bool>;
+    /// 更新用户信息
+    fn update_user_info(&self, user_id: &str, username: &str, email: &str) -> StorageResult<()>;
+    /// 记录用户最近在线时间
+    fn update_last_seen(&self, user_id: &str, last_seen: i64) -> StorageResult<()>;
+
+    // 消息
+
+    /// 保存一条消息，可回复同一会话中的另一条消息
+    ///
+    /// 被回复的消息不存在、已撤回或不属于同一会话时返回 `NotFound`；
+    /// 群聊回复归入被回复消息所在的话题，话题根消息的回复数加一。
+    /// 群聊消息的@对象记入提醒表（@全体成员展开为除发送者外的全部成员，非成员忽略）；
+    /// 附件与消息关联，会话参与者由此获得下载权限。
+    fn send_message(&self, sender_id: &str, receiver_id: &str, content: &str, message_type: &str, reply_to_id: Option<&str>, extras: &MessageExtras) -> StorageResult<Message>;
+    /// 获取用户的未读私聊消息（不含已撤回和自己删除的消息）
+    fn get_unread_messages(&self, user_id: &str) -> StorageResult<Vec<Message>>;
+    /// 分页获取 `viewer_id` 可见的会话历史消息（不含其自己删除的消息，撤回的消息保留墓碑）
+    ///
+    /// 结果按 `(created_at, id)` 升序排列，最多 `limit` 条；
+    /// 游标消息不存在或不属于该会话时返回 `NotFound`。
+    fn get_message_history(&self, viewer_id: &str, conversation: Conversation, cursor: HistoryCursor, limit: usize) -> StorageResult<Vec<Message>>;
+    /// 搜索消息内容（不含搜索者自己删除的消息）
+    ///
+    /// 结果按 `(created_at, id)` 从新到旧排列，最多 `search.limit` 条；
+    /// 游标消息不存在时返回 `NotFound`。
+    fn search_messages(&self, search: &MessageSearch) -> StorageResult<Vec<Message>>;
+    /// 根据ID获取消息
+    fn get_message(&self, message_id: &str) -> StorageResult<Message>;
+    /// 批量获取 `viewer_id` 可见的消息（不存在或已被其删除的消息不返回）
+    fn get_visible_messages(&self, viewer_id: &str, message_ids: &[String]) -> StorageResult<Vec<Message>>;
+    /// 编辑消息内容，编辑前的内容记入编辑历史
+    ///
+    /// 消息不存在或已撤回时返回 `NotFound`。
+    fn edit_message(&self, message_id: &str, content: &str) -> StorageResult<Message>;
+    /// 获取消息的编辑历史（按编辑时间升序）
+    fn get_message_edits(&self, message_id: &str) -> StorageResult<Vec<MessageEdit>>;
+    /// 撤回消息：清空内容、附件、编辑历史与表情回应，只保留墓碑，同时清除事件流中保存的消息内容
+    ///
+    /// 消息不存在或已撤回时返回 `NotFound`。
+    fn recall_message(&self, message_id: &str, deleted_by: &str) -> StorageResult<Message>;
+    /// 仅对 `user_id` 删除消息，返回是否为新删除
+    fn hide_message(&self, message_id: &str, user_id: &str) -> StorageResult<bool>;
+    /// 添加表情回应，返回是否为新添加
+    fn add_reaction(&self, message_id: &str, user_id: &str, emoji: &str) -> StorageResult<bool>;
+    /// 取消表情回应，返回是否确有取消
+    fn remove_reaction(&self, message_id: &str, user_id: &str, emoji: &str) -> StorageResult<bool>;
+    /// 汇总消息的表情回应（同一消息内按表情首次出现的时间排序），`reacted` 相对 `viewer_id`
+    fn get_reactions(&self, viewer_id: &str, message_ids: &[String]) -> StorageResult<Vec<ReactionCount>>;
+    /// 获取@了 `user_id` 的群消息（含@全体成员，不含已撤回、自己删除和已退出群聊的消息）
+    ///
+    /// 结果按 `(created_at, id)` 从新到旧排列，最多 `limit` 条；
+    /// 只返回 `before` 消息之前（更早）的结果，游标消息不存在时返回 `NotFound`。
+    fn get_mentions(&self, user_id: &str, before: Option<&str>, limit: usize) -> StorageResult<Vec<Message>>;
+    /// 将发给 `receiver_id` 的私聊消息标记为已送达，返回本次新标记的消息
+    fn mark_messages_delivered(&self, receiver_id: &str, message_ids: &[String]) -> StorageResult<Vec<Message>>;
+    /// 将发给 `receiver_id` 的私聊消息标记为已读（同时视为已送达），返回本次新标记的消息
+    fn mark_messages_as_read(&self, receiver_id: &str, message_ids: &[String]) -> StorageResult<Vec<Message>>;
+    /// 把成员的群已读位置推进到指定群消息
+    ///
+    /// 消息不存在或不属于该群时返回 `NotFound`；已读位置已在该消息之后时不变，返回 `false`。
+    fn advance_group_read_cursor(&self, group_id: &str, user_id: &str, message_id: &str) -> StorageResult<bool>;
+    /// 统计群消息的已读成员数
+    fn count_group_readers(&self, message: &Message) -> StorageResult<GroupReadCount>;
+
+    // 附件
+
+    /// 保存附件信息并增加内容文件的引用（文件由调用方写入）
+    fn create_attachment(&self, uploader_id: &str, attachment: &NewAttachment) -> StorageResult<Attachment>;
+    /// 根据ID获取附件
+    fn get_attachment(&self, attachment_id: &str) -> StorageResult<Attachment>;
+    /// 用户能否访问附件：上传者本人，或附件所在（未撤回）消息的会话参与者
+    fn can_access_attachment(&self, user_id: &str, attachment_id: &str) -> StorageResult<bool>;
+
+    /// 删除 `created_before` 之前上传、且没有任何消息引用的附件（从未发送或消息已撤回），
+    /// 同时释放其内容文件的引用，返回被删除的附件（不在文件库中的旧附件的文件由调用方删除）
+    fn delete_orphan_attachments(&self, created_before: i64) -> StorageResult<Vec<Attachment>>;
+
+    // 按内容寻址的文件
+
+    /// 在放入文件前登记文件记录（已存在时不变）
+    ///
+    /// 新记录的引用数为零，之后的引用登记失败时文件由垃圾回收删除。
+    fn register_blob(&self, sha256: &str, size: i64) -> StorageResult<()>;
+    /// 根据 SHA-256 获取文件记录
+    fn get_blob(&self, sha256: &str) -> StorageResult<Blob>;
+    /// 列出在 `unreferenced_before` 之前引用数已归零的文件记录
+    fn list_unreferenced_blobs(&self, unreferenced_before: i64) -> StorageResult<Vec<Blob>>;
+    /// 删除引用数仍为零的文件记录（调用方先删除文件），返回是否删除
+    fn delete_unreferenced_blob(&self, sha256: &str) -> StorageResult<bool>;
+
+    // 分块上传
+
+    /// 创建分块上传会话
+    fn create_upload(&self, user_id: &str, filename: &str, size: i64, sha256: Option<&str>, expires_at: i64) -> StorageResult<Upload>;
+    /// 根据ID获取分块上传会话
+    fn get_upload(&self, upload_id: &str) -> StorageResult<Upload>;
+    /// 已接收字节数仍为 `from` 时推进到 `to` 并顺延过期时间，返回是否推进
+    fn advance_upload(&self, upload_id: &str, from: i64, to: i64, expires_at: i64) -> StorageResult<bool>;
+    /// 删除分块上传会话（完成或取消），返回是否确有删除
+    fn delete_upload(&self, upload_id: &str) -> StorageResult<bool>;
+    /// 删除 `now` 之前过期的分块上传会话，返回被删除的会话ID
+    fn delete_expired_uploads(&self, now: i64) -> StorageResult<Vec<String>>;
+
+    // 好友与好友请求
+
+    /// 两个用户是否互为好友
+    fn are_friends(&self, user_id: &str, friend_id: &str) -> StorageResult<bool>;
+    /// 获取用户好友列表
+    fn get_friends(&self, user_id: &str) -> StorageResult<Vec<User>>;
+    /// 发送好友请求（目标用户不存在时返回 `NotFound`）
+    fn send_friend_request(&self, from_user_id: &str, to_username: &str) -> StorageResult<FriendRequest>;
+    /// 获取收到的待处理好友请求
+    fn get_received_friend_requests(&self, user_id: &str) -> StorageResult<Vec<FriendRequest>>;
+    /// 响应好友请求，接受时创建双向好友关系
+    fn respond_to_friend_request(&self, request_id: &str, responder_id: &str, response: &str) -> StorageResult<Friendship>;
+    /// 删除双向好友关系
+    fn remove_friend(&self, user_id: &str, friend_id: &str) -> StorageResult<()>;
+
+    // 群聊
+
+    /// 创建群聊，创建者为群主，`member_ids` 中的用户作为普通成员加入
+    fn create_group(&self, creator_id: &str, name: &str, member_ids: &[String]) -> StorageResult<Group>;
+    /// 根据ID获取群聊
+    fn get_group(&self, group_id: &str) -> StorageResult<Group>;
+    /// 获取用户加入的全部群聊及其成员信息（角色、免打扰）
+    fn get_user_groups(&self, user_id: &str) -> StorageResult<Vec<(Group, GroupMember)>>;
+    /// 获取群成员列表（按加入时间排序）
+    fn get_group_members(&self, group_id: &str) -> StorageResult<Vec<GroupMember>>;
+    /// 获取用户在群中的角色，不是成员时返回 None
+    fn get_member_role(&self, group_id: &str, user_id: &str) -> StorageResult<Option<String>>;
+    /// 添加群成员（已是成员时返回 `Conflict`）
+    fn add_group_member(&self, group_id: &str, user_id: &str, role: &str) -> StorageResult<GroupMember>;
+    /// 移除群成员，返回是否确有移除
+    fn remove_group_member(&self, group_id: &str, user_id: &str) -> StorageResult<bool>;
+    /// 设置成员角色（"admin" 或 "member"），不是成员或是群主时返回 `NotFound`
+    fn set_member_role(&self, group_id: &str, user_id: &str, role: &str) -> StorageResult<()>;
+    /// 设置成员的群消息免打扰，不是成员时返回 `NotFound`
+    fn set_group_muted(&self, group_id: &str, user_id: &str, muted: bool) -> StorageResult<()>;
+    /// 修改群名称
+    fn rename_group(&self, group_id: &str, name: &str) -> StorageResult<()>;
+    /// 解散群聊：删除群成员、群消息和群聊本身
+    fn delete_group(&self, group_id: &str) -> StorageResult<()>;
+    /// 群聊是否存在
+    fn group_exists(&self, group_id: &str) -> StorageResult<bool>;
+    /// 用户是否为群成员
+    fn is_group_member(&self, group_id: &str, user_id: &str) -> StorageResult<bool>;
+    /// 获取用户加入的全部群聊ID
+    fn get_user_group_ids(&self, user_id: &str) -> StorageResult<Vec<String>>;
+
+    // 会话
+
+    /// 读取令牌签名密钥，不存在时保存 `candidate` 并返回
+    fn get_or_create_token_secret(&self, candidate: &str) -> StorageResult<String>;
+    /// 创建登录会话
+    fn create_session(&self, user_id: &str, refresh_token_hash: &str, refresh_expires_at: i64, device_name: Option<&str>) -> StorageResult<Session>;
+    /// 轮换刷新令牌，旧令牌无效、过期或会话已吊销时返回 None
+    fn rotate_refresh_token(&self, old_token_hash: &str, new_token_hash: &str, refresh_expires_at: i64) -> StorageResult<Option<Session>>;
+    /// 检查会话是否仍然有效（未吊销且未过期）
+    fn is_session_active(&self, session_id: &str, user_id: &str) -> StorageResult<bool>;
+    /// 获取用户全部有效会话（按最近使用时间倒序）
+    fn get_active_sessions(&self, user_id: &str) -> StorageResult<Vec<Session>>;
+    /// 吊销会话（退出登录）
+    fn revoke_session(&self, session_id: &str) -> StorageResult<()>;
+
+    // 离线同步
+
+    /// 为每个用户追加同一事件（重复的用户ID只追加一次），返回各自分配到序号的事件
+    fn append_user_events(&self, user_ids: &[String], kind: &str, payload: &str, message_id: Option<&str>) -> StorageResult<Vec<UserEvent>>;
+    /// 获取用户序号大于 `after_seq` 的事件（按序号升序，最多 `limit` 条）
+    fn get_user_events(&self, user_id: &str, after_seq: i64, limit: usize) -> StorageResult<Vec<UserEvent>>;
+    /// 获取设备（会话）已同步到的事件序号
+    fn get_sync_cursor(&self, session_id: &str) -> StorageResult<i64>;
+    /// 推进设备的同步位置（只前进不后退），返回推进后的序号
+    fn advance_sync_cursor(&self, session_id: &str, seq: i64) -> StorageResult<i64>;
+}
+
+// 数据库句柄（线程安全，可廉价克隆）
+//
+// 包装配置选定的存储后端，解引用为 `dyn Storage`。
+#[derive(Clone)]
+pub struct DbPool(Arc<dyn Storage>);
+
+impl DbPool {
+    // 包装一个存储后端
+    pub fn new(storage: impl Storage + 'static) -> Self {
+        Self(Arc::new(storage))
     }
 
-    // 删除好友
-    pub fn remove_friend(&self, user_id: &str, friend_id: &str) -> Result<()> {
-        let conn = self.0.lock().unwrap();
-        
-        // 删除双向好友关系
-        conn.execute(
-            "DELETE FROM friendships WHERE user_id = ? AND friend_id = ?",
-            params![user_id, friend_id],
-        )?;
-        
-        conn.execute(
-            "DELETE FROM friendships WHERE user_id = ? AND friend_id = ?",
-            params![friend_id, user_id],
-        )?;
-        
-        Ok(())
+    // 按配置打开存储后端（不执行迁移）
+    //
+    // 连接在首次使用时建立，因此可以在异步运行时中调用。
+    pub fn connect(settings: &DatabaseSettings) -> StorageResult<Self> {
+        Self::connect_with(settings, false)
     }
 
-    // 更新用户头像URL
-    pub fn update_user_avatar(&self, user_id: &str, avatar_url: &str) -> Result<()> {
-        let conn = self.0.lock().unwrap();
-        conn.execute(
-            "UPDATE users SET avatar_url = ? WHERE id = ?",
-            params![avatar_url, user_id],
-        )?;
-        Ok(())
+    // 按配置打开存储后端，只用于试运行迁移：SQLite 不创建数据库文件，也不修改日志模式等设置
+    pub fn connect_dry_run(settings: &DatabaseSettings) -> StorageResult<Self> {
+        Self::connect_with(settings, true)
     }
 
-    // 更新用户信息
-    pub fn update_user_info(&self, user_id: &str, username: &str, email: &str) -> Result<()> {
-        let conn = self.0.lock().unwrap();
-        conn.execute(
-            "UPDATE users SET username = ?, email = ? WHERE id = ?",
-            params![username, email, user_id],
-        )?;
-        Ok(())
+    fn connect_with(settings: &DatabaseSettings, dry_run: bool) -> StorageResult<Self> {
+        let options = PoolOptions {
+            max_size: settings.pool_size,
+            busy_timeout: Duration::from_millis(settings.busy_timeout_ms),
+        };
+        match settings.backend {
+            DatabaseBackend::Sqlite if dry_run => Ok(Self::new(SqliteStorage::open_dry_run(&settings.path, options)?)),
+            DatabaseBackend::Sqlite => Ok(Self::new(SqliteStorage::open_with(&settings.path, options)?)),
+            DatabaseBackend::Postgres => {
+                let url = settings.url.as_deref()
+                    .ok_or_else(|| StorageError::Backend("未配置 PostgreSQL 连接串".into()))?;
+                Ok(Self::new(PostgresStorage::open_with(url, options)?))
+            }
+        }
     }
 
-    // 根据ID获取用户
-    pub fn get_user_by_id(&self, user_id: &str) -> Result<User> {
-        let conn = self.0.lock().unwrap();
-        conn.query_row(
-            "SELECT id, username, email, password_hash, created_at, avatar_url FROM users WHERE id = ?",
-            [user_id],
-            |row| {
-                Ok(User {
-                    id: row.get(0)?,
-                    username: row.get(1)?,
-                    email: row.get(2)?,
-                    password_hash: row.get(3)?,
-                    created_at: row.get(4)?,
-                    avatar_url: row.get(5)?,
-                })
-            },
-        )
+    // 在阻塞线程池中执行数据库操作，避免阻塞异步运行时
+    pub async fn run<F, T>(&self, f: F) -> T
+    where
+        F: FnOnce(&dyn Storage) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let storage = self.0.clone();
+        match tokio::task::spawn_blocking(move || f(storage.as_ref())).await {
+            Ok(result) => result,
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
     }
-}
\ No newline at end of file
+}
+
+impl std::ops::Deref for DbPool {
+    type Target = dyn Storage;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
@@ -0,0 +1,172 @@
+use postgres::{Client, Transaction};
+use crate::storage::{
+    now_secs,
+    Migration,
+    StorageResult
+};
+
+/// PostgreSQL 后端按版本排序的全部迁移（只允许追加，已发布的迁移不得修改）
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial",
+        sql: include_str!("migrations/0001_initial.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "message_history_indexes",
+        sql: include_str!("migrations/0002_message_history_indexes.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "message_receipts",
+        sql: include_str!("migrations/0003_message_receipts.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "users_last_seen",
+        sql: include_str!("migrations/0004_users_last_seen.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "session_device_name",
+        sql: include_str!("migrations/0005_session_device_name.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "user_events",
+        sql: include_str!("migrations/0006_user_events.sql"),
+    },
+    Migration {
+        version: 7,
+        name: "message_edits",
+        sql: include_str!("migrations/0007_message_edits.sql"),
+    },
+    Migration {
+        version: 8,
+        name: "message_threads",
+        sql: include_str!("migrations/0008_message_threads.sql"),
+    },
+    Migration {
+        version: 9,
+        name: "message_reactions",
+        sql: include_str!("migrations/0009_message_reactions.sql"),
+    },
+    Migration {
+        version: 10,
+        name: "message_mentions",
+        sql: include_str!("migrations/0010_message_mentions.sql"),
+    },
+    Migration {
+        version: 11,
+        name: "attachments",
+        sql: include_str!("migrations/0011_attachments.sql"),
+    },
+    Migration {
+        version: 12,
+        name: "uploads",
+        sql: include_str!("migrations/0012_uploads.sql"),
+    },
+    Migration {
+        version: 13,
+        name: "blobs",
+        sql: include_str!("migrations/0013_blobs.sql"),
+    },
+    Migration {
+        version: 14,
+        name: "image_metadata",
+        sql: include_str!("migrations/0014_image_metadata.sql"),
+    },
+    Migration {
+        version: 15,
+        name: "friend_request_event_id",
+        sql: include_str!("migrations/0015_friend_request_event_id.sql"),
+    },
+    Migration {
+        version: 16,
+        name: "avatar_sha256_index",
+        sql: include_str!("migrations/0016_avatar_sha256_index.sql"),
+    },
+];
+
+/// 最新结构版本
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+// 创建版本记录表（若不存在）
+fn ensure_version_table(client: &mut Client) -> StorageResult<()> {
+    client.batch_execute(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version BIGINT PRIMARY KEY,
+            name TEXT NOT NULL,
+            applied_at BIGINT NOT NULL
+        )",
+    )?;
+    Ok(())
+}
+
+/// 当前数据库的结构版本（未执行过任何迁移时为 0），不会创建版本记录表
+pub fn current_version(client: &mut Client) -> StorageResult<i64> {
+    let exists: bool = client.query_one("SELECT to_regclass('schema_version') IS NOT NULL", &[])?.get(0);
+    if !exists {
+        return Ok(0);
+    }
+    let row = client.query_one("SELECT COALESCE(MAX(version), 0) FROM schema_version", &[])?;
+    Ok(row.get(0))
+}
+
+/// 依次执行尚未执行的迁移，返回本次执行的迁移
+///
+/// PostgreSQL 支持事务性 DDL：每个迁移在独立事务中执行并记录到 schema_version 表，
+/// `dry_run` 为 true 时全部迁移在同一事务中试运行后回滚。
+/// 为避免多个实例同时迁移，执行期间持有事务级咨询锁。
+pub fn run_migrations(client: &mut Client, dry_run: bool) -> StorageResult<Vec<&'static Migration>> {
+    let current = current_version(client)?;
+    let pending: Vec<&'static Migration> = MIGRATIONS.iter().filter(|m| m.version > current).collect();
+    if pending.is_empty() {
+        return Ok(pending);
+    }
+
+    if dry_run {
+        // 试运行不创建版本记录表，全部迁移回滚
+        let mut tx = client.transaction()?;
+        lock(&mut tx)?;
+        for migration in &pending {
+            tx.batch_execute(migration.sql)?;
+        }
+        tx.rollback()?;
+        return Ok(pending);
+    }
+
+    ensure_version_table(client)?;
+    let mut applied = Vec::new();
+    for migration in pending {
+        let mut tx = client.transaction()?;
+        lock(&mut tx)?;
+        // 取得锁后再次确认，其他实例可能已执行该迁移
+        let done: bool = tx.query_one(
+            "SELECT EXISTS(SELECT 1 FROM schema_version WHERE version = $1)",
+            &[&migration.version],
+        )?.get(0);
+        if done {
+            continue;
+        }
+        tx.batch_execute(migration.sql)?;
+        tx.execute(
+            "INSERT INTO schema_version (version, name, applied_at) VALUES ($1, $2, $3)",
+            &[&migration.version, &migration.name, &now_secs()],
+        )?;
+        tx.commit()?;
+        println!("已执行数据库迁移 {:04}_{}", migration.version, migration.name);
+        applied.push(migration);
+    }
+
+    Ok(applied)
+}
+
+// 获取迁移专用的事务级咨询锁（事务结束时自动释放）
+fn lock(tx: &mut Transaction) -> StorageResult<()> {
+    tx.execute("SELECT pg_advisory_xact_lock(20250101)", &[])?;
+    Ok(())
+}
@@ -0,0 +1,4 @@
+-- 历史消息分页查询使用的索引
+-- 群聊历史按 receiver_id（群ID）过滤，私聊历史按发送方与接收方过滤，均按时间排序
+CREATE INDEX IF NOT EXISTS idx_messages_receiver_created ON messages(receiver_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_messages_conversation_created ON messages(sender_id, receiver_id, created_at);
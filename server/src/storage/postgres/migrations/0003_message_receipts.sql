@@ -0,0 +1,16 @@
+-- 私聊消息的送达与已读时间（NULL 表示尚未送达/已读）
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS delivered_at BIGINT;
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS read_at BIGINT;
+
+-- 已标记为已读的历史消息没有记录具体时间，以发送时间代替
+UPDATE messages SET delivered_at = created_at, read_at = created_at WHERE is_read;
+
+-- 群成员的已读位置：已读到的最后一条群消息（按 (message_created_at, message_id) 排序）
+CREATE TABLE IF NOT EXISTS group_read_cursors (
+    group_id TEXT NOT NULL REFERENCES groups(id) ON DELETE CASCADE,
+    user_id TEXT NOT NULL REFERENCES users(id),
+    message_id TEXT NOT NULL,
+    message_created_at BIGINT NOT NULL,
+    read_at BIGINT NOT NULL,
+    PRIMARY KEY (group_id, user_id)
+);
@@ -0,0 +1,2 @@
+-- 用户最近在线时间（断开连接时更新，从未上线为 NULL）
+ALTER TABLE users ADD COLUMN IF NOT EXISTS last_seen BIGINT;
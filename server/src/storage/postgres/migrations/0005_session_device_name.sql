@@ -0,0 +1,2 @@
+-- 会话对应的设备名称（登录时由客户端提供或取自 User-Agent）
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS device_name TEXT;
@@ -0,0 +1,25 @@
+-- 消息编辑与撤回：最近一次编辑时间；撤回后内容清空，保留墓碑
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS edited_at BIGINT;
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS deleted_at BIGINT;
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS deleted_by TEXT;
+
+-- 编辑历史：每次编辑前的内容
+CREATE TABLE IF NOT EXISTS message_edits (
+    id TEXT PRIMARY KEY,
+    message_id TEXT NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+    content TEXT NOT NULL,
+    edited_at BIGINT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_message_edits_message ON message_edits(message_id, id);
+
+-- 用户“仅对自己删除”的消息
+CREATE TABLE IF NOT EXISTS message_hidden (
+    user_id TEXT NOT NULL REFERENCES users(id),
+    message_id TEXT NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+    hidden_at BIGINT NOT NULL,
+    PRIMARY KEY (user_id, message_id)
+);
+
+-- 与消息相关的事件（同步时按消息当前状态重建）
+ALTER TABLE user_events ADD COLUMN IF NOT EXISTS message_id TEXT;
+CREATE INDEX IF NOT EXISTS idx_user_events_message ON user_events(message_id) WHERE message_id IS NOT NULL;
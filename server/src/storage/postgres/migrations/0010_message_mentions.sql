@@ -0,0 +1,12 @@
+-- @提醒：消息保存@的成员（JSON数组）与是否@全体成员；提醒表按被提醒用户展开，供"@我的"查询
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS mentions TEXT NOT NULL DEFAULT '[]';
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS mention_all BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE IF NOT EXISTS message_mentions (
+    message_id TEXT NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+    user_id TEXT NOT NULL REFERENCES users(id),
+    PRIMARY KEY (user_id, message_id)
+);
+
+-- 群消息免打扰（@提醒仍然推送）
+ALTER TABLE group_members ADD COLUMN IF NOT EXISTS muted BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,20 @@
+-- 附件：文件保存在上传目录，表中只记录按内容识别的类型与大小
+CREATE TABLE IF NOT EXISTS attachments (
+    id TEXT PRIMARY KEY,
+    uploader_id TEXT NOT NULL REFERENCES users(id),
+    kind TEXT NOT NULL,
+    mime_type TEXT NOT NULL,
+    size BIGINT NOT NULL,
+    filename TEXT NOT NULL,
+    created_at BIGINT NOT NULL
+);
+
+-- 消息引用的附件（消息表另存有序的附件ID列表），会话参与者据此获得下载权限
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS attachments TEXT NOT NULL DEFAULT '[]';
+
+CREATE TABLE IF NOT EXISTS message_attachments (
+    message_id TEXT NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+    attachment_id TEXT NOT NULL REFERENCES attachments(id),
+    PRIMARY KEY (message_id, attachment_id)
+);
+CREATE INDEX IF NOT EXISTS idx_message_attachments_attachment ON message_attachments(attachment_id);
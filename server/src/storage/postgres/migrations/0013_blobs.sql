@@ -0,0 +1,18 @@
+-- 按 SHA-256 寻址的文件库：相同内容只保存一份，按引用数回收
+CREATE TABLE IF NOT EXISTS blobs (
+    sha256 TEXT PRIMARY KEY,
+    size BIGINT NOT NULL,
+    ref_count BIGINT NOT NULL DEFAULT 0,
+    created_at BIGINT NOT NULL,
+    unreferenced_since BIGINT
+);
+
+-- 垃圾回收查找引用数已归零的文件
+CREATE INDEX IF NOT EXISTS idx_blobs_unreferenced_since ON blobs(unreferenced_since);
+
+-- 附件与头像引用的文件；旧数据为空，仍按原路径读取
+ALTER TABLE attachments ADD COLUMN IF NOT EXISTS sha256 TEXT;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS avatar_sha256 TEXT;
+
+-- 垃圾回收按上传时间查找未被消息引用的附件
+CREATE INDEX IF NOT EXISTS idx_attachments_created_at ON attachments(created_at);
@@ -0,0 +1,2 @@
+-- 公开访问头像时按 SHA-256 确认文件是某个用户当前的头像
+CREATE INDEX IF NOT EXISTS idx_users_avatar_sha256 ON users(avatar_sha256);
@@ -0,0 +1,1453 @@
+use ::postgres::{types::ToSql, GenericClient, NoTls, Row};
+use r2d2_postgres::PostgresConnectionManager;
+use uuid::Uuid;
+use super::{
+    ids_from_json,
+    ids_to_json,
+    now_secs,
+    reply_thread_root,
+    Attachment,
+    Blob,
+    Conversation,
+    FriendRequest,
+    Friendship,
+    Group,
+    GroupMember,
+    GroupReadCount,
+    HistoryCursor,
+    Message,
+    MessageEdit,
+    MessageExtras,
+    MessageSearch,
+    Migration,
+    NewAttachment,
+    PoolOptions,
+    ReactionCount,
+    Session,
+    Storage,
+    StorageError,
+    StorageResult,
+    Upload,
+    User,
+    UserEvent
+};
+
+pub mod migrations;
+
+type Manager = PostgresConnectionManager<NoTls>;
+
+// 用户表查询列（与 user_from_row 对应）
+const USER_COLUMNS: &str = "id, username, email, password_hash, created_at, avatar_url, last_seen";
+
+// 从查询结果行构造用户
+fn user_from_row(row: &Row) -> User {
+    User {
+        id: row.get(0),
+        username: row.get(1),
+        email: row.get(2),
+        password_hash: row.get(3),
+        created_at: row.get(4),
+        avatar_url: row.get(5),
+        last_seen: row.get(6),
+    }
+}
+
+// 消息表查询列（与 message_from_row 对应）
+const MESSAGE_COLUMNS: &str = "id, sender_id, receiver_id, content, message_type, created_at, is_read, delivered_at, read_at, edited_at, deleted_at, deleted_by, reply_to_id, thread_root_id, reply_count, mentions, mention_all, attachments";
+
+// 从查询结果行构造消息
+fn message_from_row(row: &Row) -> Message {
+    Message {
+        id: row.get(0),
+        sender_id: row.get(1),
+        receiver_id: row.get(2),
+        content: row.get(3),
+        message_type: row.get(4),
+        created_at: row.get(5),
+        is_read: row.get(6),
+        delivered_at: row.get(7),
+        read_at: row.get(8),
+        edited_at: row.get(9),
+        deleted_at: row.get(10),
+        deleted_by: row.get(11),
+        reply_to_id: row.get(12),
+        thread_root_id: row.get(13),
+        reply_count: row.get(14),
+        mentions: ids_from_json(row.get(15)),
+        mention_all: row.get(16),
+        attachments: ids_from_json(row.get(17)),
+    }
+}
+
+// 附件表查询列（与 attachment_from_row 对应）
+const ATTACHMENT_COLUMNS: &str = "id, uploader_id, kind, mime_type, size, filename, created_at, sha256, width, height, blurhash";
+
+// 从查询结果行构造附件
+fn attachment_from_row(row: &Row) -> Attachment {
+    Attachment {
+        id: row.get(0),
+        uploader_id: row.get(1),
+        kind: row.get(2),
+        mime_type: row.get(3),
+        size: row.get(4),
+        filename: row.get(5),
+        created_at: row.get(6),
+        sha256: row.get(7),
+        width: row.get(8),
+        height: row.get(9),
+        blurhash: row.get(10),
+    }
+}
+
+// 文件库查询列（与 blob_from_row 对应）
+const BLOB_COLUMNS: &str = "sha256, size, ref_count, created_at, unreferenced_since";
+
+// 从查询结果行构造文件记录
+fn blob_from_row(row: &Row) -> Blob {
+    Blob {
+        sha256: row.get(0),
+        size: row.get(1),
+        ref_count: row.get(2),
+        created_at: row.get(3),
+        unreferenced_since: row.get(4),
+    }
+}
+
+// 增加文件引用（记录不存在时创建）
+fn acquire_blob(client: &mut impl GenericClient, sha256: &str, size: i64) -> Result<(), ::postgres::Error> {
+    client.execute(
+        "INSERT INTO blobs (sha256, size, ref_count, created_at) VALUES ($1, $2, 1, $3)
+         ON CONFLICT (sha256) DO UPDATE SET ref_count = blobs.ref_count + 1, unreferenced_since = NULL",
+        &[&sha256, &size, &now_secs()],
+    )?;
+    Ok(())
+}
+
+// 释放文件引用，引用数归零时记下时间供垃圾回收判断宽限期
+fn release_blob(client: &mut impl GenericClient, sha256: &str) -> Result<(), ::postgres::Error> {
+    client.execute(
+        "UPDATE blobs SET ref_count = ref_count - 1,
+             unreferenced_since = CASE WHEN ref_count <= 1 THEN $1 ELSE unreferenced_since END
+         WHERE sha256 = $2 AND ref_count > 0",
+        &[&now_secs(), &sha256],
+    )?;
+    Ok(())
+}
+
+// 分块上传表查询列（与 upload_from_row 对应）
+const UPLOAD_COLUMNS: &str = "id, user_id, filename, size, received, sha256, created_at, expires_at";
+
+// 从查询结果行构造分块上传会话
+fn upload_from_row(row: &Row) -> Upload {
+    Upload {
+        id: row.get(0),
+        user_id: row.get(1),
+        filename: row.get(2),
+        size: row.get(3),
+        received: row.get(4),
+        sha256: row.get(5),
+        created_at: row.get(6),
+        expires_at: row.get(7),
+    }
+}
+
+// 排除用户自己删除的消息（`user` 为绑定用户ID的占位符）
+fn not_hidden_filter(user: &str) -> String {
+    format!("NOT EXISTS (SELECT 1 FROM message_hidden h WHERE h.user_id = {} AND h.message_id = messages.id)", user)
+}
+
+// 会话按发送方向拆分的查询条件（私聊两个方向各一条，便于分别走索引）与参数，占位符从 $1 开始
+fn conversation_branches(conversation: Conversation<'_>) -> (Vec<&'static str>, Vec<&str>) {
+    match conversation {
+        Conversation::Private(a, b) => (
+            vec![
+                "message_type = 'private' AND sender_id = $1 AND receiver_id = $2",
+                "message_type = 'private' AND sender_id = $2 AND receiver_id = $1",
+            ],
+            vec![a, b],
+        ),
+        Conversation::Group(group_id) => (vec!["message_type = 'group' AND receiver_id = $1"], vec![group_id]),
+        Conversation::Thread(root_id) => (vec!["thread_root_id = $1"], vec![root_id]),
+    }
+}
+
+// 转义 LIKE 模式中的通配符
+fn escape_like(term: &str) -> String {
+    term.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+/// PostgreSQL 存储后端
+///
+/// 使用同步的 `postgres` 客户端和 r2d2 连接池，与 SQLite 后端一样只在阻塞线程中调用。
+pub struct PostgresStorage {
+    pool: Option<r2d2::Pool<Manager>>,  // 仅在 Drop 时取出
+}
+
+impl PostgresStorage {
+    /// 使用默认参数连接数据库（不执行迁移）
+    pub fn open(url: &str) -> StorageResult<Self> {
+        Self::open_with(url, PoolOptions::default())
+    }
+
+    /// 使用指定参数连接数据库（不执行迁移）
+    ///
+    /// 连接在首次使用时建立，`busy_timeout` 作为获取连接与语句锁等待的超时时间。
+    pub fn open_with(url: &str, options: PoolOptions) -> StorageResult<Self> {
+        let mut config: ::postgres::Config = url.parse()?;
+        // 保留连接串中已有的 options 参数
+        let lock_timeout = format!("-c lock_timeout={}", options.busy_timeout.as_millis());
+        let server_options = match config.get_options() {
+            Some(existing) => format!("{} {}", existing, lock_timeout),
+            None => lock_timeout,
+        };
+        config.options(&server_options);
+        let pool = r2d2::Pool::builder()
+            .max_size(options.max_size)
+            .min_idle(Some(0))
+            .connection_timeout(options.busy_timeout * 2)
+            .build_unchecked(PostgresConnectionManager::new(config, NoTls));
+        Ok(Self { pool: Some(pool) })
+    }
+
+    // 从连接池取出一个连接
+    fn conn(&self) -> StorageResult<r2d2::PooledConnection<Manager>> {
+        let pool = self.pool.as_ref().expect("连接池在 Drop 之前始终存在");
+        Ok(pool.get()?)
+    }
+}
+
+impl Drop for PostgresStorage {
+    // 关闭同步客户端会在内部运行时上阻塞，在异步运行时线程中直接释放会 panic，
+    // 因此在独立线程中释放连接池
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool.take() {
+            let _ = std::thread::spawn(move || drop(pool)).join();
+        }
+    }
+}
+
+impl Storage for PostgresStorage {
+    fn backend_name(&self) -> &'static str {
+        "postgres"
+    }
+
+    fn migrate(&self, dry_run: bool) -> StorageResult<Vec<&'static Migration>> {
+        let mut conn = self.conn()?;
+        migrations::run_migrations(&mut conn, dry_run)
+    }
+
+    fn schema_version(&self) -> StorageResult<i64> {
+        let mut conn = self.conn()?;
+        migrations::current_version(&mut conn)
+    }
+
+    fn latest_version(&self) -> i64 {
+        migrations::latest_version()
+    }
+
+    fn register_user(&self, username: &str, password_hash: &str) -> StorageResult<User> {
+        let mut conn = self.conn()?;
+
+        let user_id = Uuid::new_v4().to_string();
+        let created_at = now_secs();
+        // 生成唯一占位邮箱（避免使用空字符串导致 UNIQUE 约束冲突）
+        let email_placeholder = format!("{}@local", user_id);
+
+        // 用户名唯一约束冲突时不插入，据此判断用户名已存在
+        let inserted = conn.execute(
+            "INSERT INTO users (id, username, email, password_hash, created_at)
+             VALUES ($1, $2, $3, $4, $5)
+             ON CONFLICT (username) DO NOTHING",
+            &[&user_id, &username, &email_placeholder, &password_hash, &created_at],
+        )?;
+        if inserted == 0 {
+            return Err(StorageError::UsernameTaken);
+        }
+
+        Ok(User {
+            id: user_id,
+            username: username.to_string(),
+            email: email_placeholder,
+            password_hash: password_hash.to_string(),
+            created_at,
+            avatar_url: String::new(),
+            last_seen: None,
+        })
+    }
+
+    fn get_user_by_id(&self, user_id: &str) -> StorageResult<User> {
+        let mut conn = self.conn()?;
+        conn.query_opt(&format!("SELECT {} FROM users WHERE id = $1", USER_COLUMNS), &[&user_id])?
+            .map(|row| user_from_row(&row))
+            .ok_or(StorageError::NotFound)
+    }
+
+    fn get_user_by_username(&self, username: &str) -> StorageResult<User> {
+        let mut conn = self.conn()?;
+        conn.query_opt(&format!("SELECT {} FROM users WHERE username = $1", USER_COLUMNS), &[&username])?
+            .map(|row| user_from_row(&row))
+            .ok_or(StorageError::NotFound)
+    }
+
+    fn get_username(&self, user_id: &str) -> StorageResult<String> {
+        let mut conn = self.conn()?;
+        conn.query_opt("SELECT username FROM users WHERE id = $1", &[&user_id])?
+            .map(|row| row.get(0))
+            .ok_or(StorageError::NotFound)
+    }
+
+    fn user_exists_by_id(&self, user_id: &str) -> StorageResult<bool> {
+        let mut conn = self.conn()?;
+        let row = conn.query_one("SELECT EXISTS(SELECT 1 FROM users WHERE id = $1)", &[&user_id])?;
+        Ok(row.get(0))
+    }
+
+    fn search_users(&self, query: &str) -> StorageResult<Vec<User>> {
+        let mut conn = self.conn()?;
+        // ILIKE 与 SQLite 的 LIKE 一样对 ASCII 字母不区分大小写
+        let search_pattern = format!("%{}%", query);
+        let rows = conn.query(
+            &format!("SELECT {} FROM users WHERE username ILIKE $1 OR id ILIKE $1 LIMIT 10", USER_COLUMNS),
+            &[&search_pattern],
+        )?;
+        Ok(rows.iter().map(user_from_row).collect())
+    }
+
+    fn update_user_avatar(&self, user_id: &str, avatar_url: &str, sha256: &str, size: i64) -> StorageResult<String> {
+        let mut conn = self.conn()?;
+        let mut tx = conn.transaction()?;
+        let row = tx.query_opt(
+            "SELECT avatar_url, avatar_sha256 FROM users WHERE id = $1 FOR UPDATE",
+            &[&user_id],
+        )?.ok_or(StorageError::NotFound)?;
+        let (old_url, old_sha256): (String, Option<String>) = (row.get(0), row.get(1));
+        acquire_blob(&mut tx, sha256, size)?;
+        if let Some(old_sha256) = &old_sha256 {
+            release_blob(&mut tx, old_sha256)?;
+        }
+        tx.execute(
+            "UPDATE users SET avatar_url = $1, avatar_sha256 = $2 WHERE id = $3",
+            &[&avatar_url, &sha256, &user_id],
+        )?;
+        tx.commit()?;
+        Ok(old_url)
+    }
+
+    fn is_avatar_blob(&self, sha256: &str) -> StorageResult<bool> {
+        let mut conn = self.conn()?;
+        let row = conn.query_one("SELECT EXISTS(SELECT 1 FROM users WHERE avatar_sha256 = $1)", &[&sha256])?;
+        Ok(row.get(0))
+    }
+
+    fn update_user_info(&self, user_id: &str, username: &str, email: &str) -> StorageResult<()> {
+        let mut conn = self.conn()?;
+        conn.execute(
+            "UPDATE users SET username = $1, email = $2 WHERE id = $3",
+            &[&username, &email, &user_id],
+        )?;
+        Ok(())
+    }
+
+    fn update_last_seen(&self, user_id: &str, last_seen: i64) -> StorageResult<()> {
+        let mut conn = self.conn()?;
+        conn.execute("UPDATE users SET last_seen = $1 WHERE id = $2", &[&last_seen, &user_id])?;
+        Ok(())
+    }
+
+    fn send_message(&self, sender_id: &str, receiver_id: &str, content: &str, message_type: &str, reply_to_id: Option<&str>, extras: &MessageExtras) -> StorageResult<Message> {
+        let mut conn = self.conn()?;
+        let mut tx = conn.transaction()?;
+
+        // UUIDv7 随时间递增，同一秒内的消息按 id 排序即为发送顺序
+        let message_id = Uuid::now_v7().to_string();
+        let created_at = now_secs();
+
+        let thread_root_id = match reply_to_id {
+            Some(parent_id) => {
+                let parent = tx.query_opt(
+                    &format!("SELECT {} FROM messages WHERE id = $1", MESSAGE_COLUMNS),
+                    &[&parent_id],
+                )?.ok_or(StorageError::NotFound)?;
+                reply_thread_root(&message_from_row(&parent), sender_id, receiver_id, message_type)?
+            }
+            None => None,
+        };
+
+        let (mentions_json, attachments_json) = (ids_to_json(&extras.mentions), ids_to_json(&extras.attachment_ids));
+        tx.execute(
+            "INSERT INTO messages (id, sender_id, receiver_id, content, message_type, created_at, is_read, reply_to_id, thread_root_id, mentions, mention_all, attachments)
+             VALUES ($1, $2, $3, $4, $5, $6, FALSE, $7, $8, $9, $10, $11)",
+            &[
+                &message_id, &sender_id, &receiver_id, &content, &message_type, &created_at,
+                &reply_to_id, &thread_root_id, &mentions_json, &extras.mention_all, &attachments_json,
+            ],
+        )?;
+        tx.execute(
+            "INSERT INTO message_attachments (message_id, attachment_id)
+             SELECT $1::TEXT, UNNEST($2::TEXT[])
+             ON CONFLICT DO NOTHING",
+            &[&message_id, &extras.attachment_ids],
+        )?;
+        if let Some(root_id) = &thread_root_id {
+            tx.execute("UPDATE messages SET reply_count = reply_count + 1 WHERE id = $1", &[root_id])?;
+        }
+        // 提醒表只记录群成员；@全体成员展开为除发送者外的全部成员
+        if message_type == "group" {
+            if extras.mention_all {
+                tx.execute(
+                    "INSERT INTO message_mentions (message_id, user_id)
+                     SELECT $1, user_id FROM group_members WHERE group_id = $2 AND user_id != $3
+                     ON CONFLICT DO NOTHING",
+                    &[&message_id, &receiver_id, &sender_id],
+                )?;
+            }
+            tx.execute(
+                "INSERT INTO message_mentions (message_id, user_id)
+                 SELECT $1, user_id FROM group_members WHERE group_id = $2 AND user_id = ANY($3)
+                 ON CONFLICT DO NOTHING",
+                &[&message_id, &receiver_id, &extras.mentions],
+            )?;
+        }
+        tx.commit()?;
+
+        Ok(Message {
+            id: message_id,
+            sender_id: sender_id.to_string(),
+            receiver_id: receiver_id.to_string(),
+            content: content.to_string(),
+            message_type: message_type.to_string(),
+            created_at,
+            is_read: false,
+            delivered_at: None,
+            read_at: None,
+            edited_at: None,
+            deleted_at: None,
+            deleted_by: None,
+            reply_to_id: reply_to_id.map(str::to_string),
+            thread_root_id,
+            reply_count: 0,
+            mentions: extras.mentions.clone(),
+            mention_all: extras.mention_all,
+            attachments: extras.attachment_ids.clone(),
+        })
+    }
+
+    fn get_unread_messages(&self, user_id: &str) -> StorageResult<Vec<Message>> {
+        let mut conn = self.conn()?;
+        let rows = conn.query(
+            &format!(
+                "SELECT {} FROM messages
+                 WHERE receiver_id = $1 AND NOT is_read AND message_type = 'private' AND deleted_at IS NULL AND {}",
+                MESSAGE_COLUMNS, not_hidden_filter("$1")
+            ),
+            &[&user_id],
+        )?;
+
+        Ok(rows.iter().map(message_from_row).collect())
+    }
+
+    fn get_message_history(&self, viewer_id: &str, conversation: Conversation, cursor: HistoryCursor, limit: usize) -> StorageResult<Vec<Message>> {
+        let mut conn = self.conn()?;
+        let (branches, conversation_args) = conversation_branches(conversation);
+        let mut args: Vec<&(dyn ToSql + Sync)> = conversation_args.iter().map(|arg| arg as &(dyn ToSql + Sync)).collect();
+        let next = args.len() + 1;
+
+        // 游标消息的位置；最新一页从最大位置向前取
+        let (created_at, cursor_id, newer) = match cursor {
+            HistoryCursor::Latest => (i64::MAX, "", false),
+            HistoryCursor::Before(id) | HistoryCursor::After(id) => {
+                let filter = branches.iter().map(|filter| format!("({})", filter)).collect::<Vec<_>>().join(" OR ");
+                let mut cursor_args = args.clone();
+                cursor_args.push(&id);
+                let created_at: i64 = conn.query_opt(
+                    &format!("SELECT created_at FROM messages WHERE id = ${} AND ({})", next, filter),
+                    &cursor_args,
+                )?.ok_or(StorageError::NotFound)?.get(0);
+                (created_at, id, matches!(cursor, HistoryCursor::After(_)))
+            }
+        };
+
+        // 每个方向各取一页再合并；向前翻页时倒序取，最后反转为升序
+        let (comparison, order) = if newer { (">", "ASC") } else { ("<", "DESC") };
+        let limit = limit as i64;
+        args.extend([&created_at as &(dyn ToSql + Sync), &cursor_id, &limit, &viewer_id]);
+        let not_hidden = not_hidden_filter(&format!("${}", next + 3));
+        let selects: Vec<String> = branches.iter().map(|filter| format!(
+            "(SELECT {} FROM messages
+              WHERE {} AND {} AND (created_at, id) {} (${}, ${})
+              ORDER BY created_at {order}, id {order} LIMIT ${})",
+            MESSAGE_COLUMNS, filter, not_hidden, comparison, next, next + 1, next + 2, order = order
+        )).collect();
+
+        let rows = conn.query(
+            &format!(
+                "SELECT * FROM ({}) AS history ORDER BY created_at {order}, id {order} LIMIT ${}",
+                selects.join(" UNION ALL "), next + 2, order = order
+            ),
+            &args,
+        )?;
+        let mut messages: Vec<Message> = rows.iter().map(message_from_row).collect();
+
+        if !newer {
+            messages.reverse();
+        }
+        Ok(messages)
+    }
+
+    fn search_messages(&self, search: &MessageSearch) -> StorageResult<Vec<Message>> {
+        // 没有全文索引，每个检索词按不区分大小写的子串匹配
+        let patterns: Vec<String> = search.query
+            .split_whitespace()
+            .filter(|term| term.chars().any(char::is_alphanumeric))
+            .map(|term| format!("%{}%", escape_like(term)))
+            .collect();
+        if patterns.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut conn = self.conn()?;
+
+        // 只在搜索者参与的私聊和所在的群中搜索
+        let mut sql = String::from(
+            "((message_type = 'private' AND (sender_id = $1 OR receiver_id = $1))
+              OR (message_type = 'group' AND receiver_id IN (SELECT group_id FROM group_members WHERE user_id = $1)))"
+        );
+        sql.push_str(" AND ");
+        sql.push_str(&not_hidden_filter("$1"));
+        let mut args: Vec<&(dyn ToSql + Sync)> = vec![&search.user_id];
+
+        for pattern in &patterns {
+            args.push(pattern);
+            sql.push_str(&format!(" AND content ILIKE ${}", args.len()));
+        }
+        if let Some(sender_id) = &search.sender_id {
+            args.push(sender_id);
+            sql.push_str(&format!(" AND sender_id = ${}", args.len()));
+        }
+        match &search.conversation {
+            Some(Conversation::Private(a, b)) => {
+                args.extend([a as &(dyn ToSql + Sync), b]);
+                sql.push_str(&format!(
+                    " AND message_type = 'private'
+                      AND ((sender_id = ${a} AND receiver_id = ${b}) OR (sender_id = ${b} AND receiver_id = ${a}))",
+                    a = args.len() - 1,
+                    b = args.len()
+                ));
+            }
+            Some(Conversation::Group(group_id)) => {
+                args.push(group_id);
+                sql.push_str(&format!(" AND message_type = 'group' AND receiver_id = ${}", args.len()));
+            }
+            Some(Conversation::Thread(root_id)) => {
+                args.push(root_id);
+                sql.push_str(&format!(" AND thread_root_id = ${}", args.len()));
+            }
+            None => {}
+        }
+        if let Some(since) = &search.since {
+            args.push(since);
+            sql.push_str(&format!(" AND created_at >= ${}", args.len()));
+        }
+        if let Some(until) = &search.until {
+            args.push(until);
+            sql.push_str(&format!(" AND created_at <= ${}", args.len()));
+        }
+        let before_at: i64;
+        if let Some(before) = &search.before {
+            before_at = conn.query_opt("SELECT created_at FROM messages WHERE id = $1", &[before])?
+                .ok_or(StorageError::NotFound)?
+                .get(0);
+            args.extend([&before_at as &(dyn ToSql + Sync), before]);
+            sql.push_str(&format!(" AND (created_at, id) < (${}, ${})", args.len() - 1, args.len()));
+        }
+        let limit = search.limit as i64;
+        args.push(&limit);
+
+        let rows = conn.query(
+            &format!(
+                "SELECT {} FROM messages WHERE {} ORDER BY created_at DESC, id DESC LIMIT ${}",
+                MESSAGE_COLUMNS, sql, args.len()
+            ),
+            &args,
+        )?;
+        Ok(rows.iter().map(message_from_row).collect())
+    }
+
+    fn get_message(&self, message_id: &str) -> StorageResult<Message> {
+        let mut conn = self.conn()?;
+        let row = conn.query_opt(
+            &format!("SELECT {} FROM messages WHERE id = $1", MESSAGE_COLUMNS),
+            &[&message_id],
+        )?.ok_or(StorageError::NotFound)?;
+        Ok(message_from_row(&row))
+    }
+
+    fn get_visible_messages(&self, viewer_id: &str, message_ids: &[String]) -> StorageResult<Vec<Message>> {
+        let mut conn = self.conn()?;
+        let rows = conn.query(
+            &format!(
+                "SELECT {} FROM messages WHERE id = ANY($1) AND {} ORDER BY created_at, id",
+                MESSAGE_COLUMNS, not_hidden_filter("$2")
+            ),
+            &[&message_ids, &viewer_id],
+        )?;
+        Ok(rows.iter().map(message_from_row).collect())
+    }
+
+    fn edit_message(&self, message_id: &str, content: &str) -> StorageResult<Message> {
+        let mut conn = self.conn()?;
+        let mut tx = conn.transaction()?;
+        let now = now_secs();
+
+        let previous: String = tx.query_opt(
+            "SELECT content FROM messages WHERE id = $1 AND deleted_at IS NULL FOR UPDATE",
+            &[&message_id],
+        )?.ok_or(StorageError::NotFound)?.get(0);
+        tx.execute(
+            "INSERT INTO message_edits (id, message_id, content, edited_at) VALUES ($1, $2, $3, $4)",
+            &[&Uuid::now_v7().to_string(), &message_id, &previous, &now],
+        )?;
+        let row = tx.query_one(
+            &format!("UPDATE messages SET content = $1, edited_at = $2 WHERE id = $3 RETURNING {}", MESSAGE_COLUMNS),
+            &[&content, &now, &message_id],
+        )?;
+
+        tx.commit()?;
+        Ok(message_from_row(&row))
+    }
+
+    fn get_message_edits(&self, message_id: &str) -> StorageResult<Vec<MessageEdit>> {
+        let mut conn = self.conn()?;
+        let rows = conn.query(
+            "SELECT id, message_id, content, edited_at FROM message_edits WHERE message_id = $1 ORDER BY id",
+            &[&message_id],
+        )?;
+        Ok(rows.iter().map(|row| MessageEdit {
+            id: row.get(0),
+            message_id: row.get(1),
+            content: row.get(2),
+            edited_at: row.get(3),
+        }).collect())
+    }
+
+    fn recall_message(&self, message_id: &str, deleted_by: &str) -> StorageResult<Message> {
+        let mut conn = self.conn()?;
+        let mut tx = conn.transaction()?;
+
+        let row = tx.query_opt(
+            &format!(
+                "UPDATE messages SET content = '', attachments = '[]', deleted_at = $1, deleted_by = $2
+                 WHERE id = $3 AND deleted_at IS NULL
+                 RETURNING {}",
+                MESSAGE_COLUMNS
+            ),
+            &[&now_secs(), &deleted_by, &message_id],
+        )?.ok_or(StorageError::NotFound)?;
+        tx.execute("DELETE FROM message_edits WHERE message_id = $1", &[&message_id])?;
+        tx.execute("DELETE FROM message_attachments WHERE message_id = $1", &[&message_id])?;
+        tx.execute("DELETE FROM message_reactions WHERE message_id = $1", &[&message_id])?;
+        // 事件流中保存的帧含有原文，一并清除（同步时按墓碑重建）
+        tx.execute("UPDATE user_events SET payload = '{}' WHERE message_id = $1", &[&message_id])?;
+
+        tx.commit()?;
+        Ok(message_from_row(&row))
+    }
+
+    fn hide_message(&self, message_id: &str, user_id: &str) -> StorageResult<bool> {
+        let mut conn = self.conn()?;
+        let inserted = conn.execute(
+            "INSERT INTO message_hidden (user_id, message_id, hidden_at) VALUES ($1, $2, $3)
+             ON CONFLICT DO NOTHING",
+            &[&user_id, &message_id, &now_secs()],
+        )?;
+        Ok(inserted > 0)
+    }
+
+    fn add_reaction(&self, message_id: &str, user_id: &str, emoji: &str) -> StorageResult<bool> {
+        let mut conn = self.conn()?;
+        let inserted = conn.execute(
+            "INSERT INTO message_reactions (message_id, user_id, emoji, created_at) VALUES ($1, $2, $3, $4)
+             ON CONFLICT DO NOTHING",
+            &[&message_id, &user_id, &emoji, &now_secs()],
+        )?;
+        Ok(inserted > 0)
+    }
+
+    fn remove_reaction(&self, message_id: &str, user_id: &str, emoji: &str) -> StorageResult<bool> {
+        let mut conn = self.conn()?;
+        let removed = conn.execute(
+            "DELETE FROM message_reactions WHERE message_id = $1 AND user_id = $2 AND emoji = $3",
+            &[&message_id, &user_id, &emoji],
+        )?;
+        Ok(removed > 0)
+    }
+
+    fn get_reactions(&self, viewer_id: &str, message_ids: &[String]) -> StorageResult<Vec<ReactionCount>> {
+        let mut conn = self.conn()?;
+        let rows = conn.query(
+            "SELECT message_id, emoji, COUNT(*), bool_or(user_id = $1) FROM message_reactions
+             WHERE message_id = ANY($2)
+             GROUP BY message_id, emoji
+             ORDER BY message_id, MIN(created_at), emoji",
+            &[&viewer_id, &message_ids],
+        )?;
+        Ok(rows.iter().map(|row| ReactionCount {
+            message_id: row.get(0),
+            emoji: row.get(1),
+            count: row.get(2),
+            reacted: row.get(3),
+        }).collect())
+    }
+
+    fn get_mentions(&self, user_id: &str, before: Option<&str>, limit: usize) -> StorageResult<Vec<Message>> {
+        let mut conn = self.conn()?;
+        // 只看仍在群中的提醒
+        let mut sql = format!(
+            "id IN (SELECT message_id FROM message_mentions WHERE user_id = $1)
+             AND message_type = 'group' AND deleted_at IS NULL
+             AND receiver_id IN (SELECT group_id FROM group_members WHERE user_id = $1)
+             AND {}",
+            not_hidden_filter("$1")
+        );
+        let mut args: Vec<&(dyn ToSql + Sync)> = vec![&user_id];
+        let before_at: i64;
+        if let Some(before) = &before {
+            before_at = conn.query_opt("SELECT created_at FROM messages WHERE id = $1", &[before])?
+                .ok_or(StorageError::NotFound)?
+                .get(0);
+            args.extend([&before_at as &(dyn ToSql + Sync), before]);
+            sql.push_str(&format!(" AND (created_at, id) < (${}, ${})", args.len() - 1, args.len()));
+        }
+        let limit = limit as i64;
+        args.push(&limit);
+
+        let rows = conn.query(
+            &format!(
+                "SELECT {} FROM messages WHERE {} ORDER BY created_at DESC, id DESC LIMIT ${}",
+                MESSAGE_COLUMNS, sql, args.len()
+            ),
+            &args,
+        )?;
+        Ok(rows.iter().map(message_from_row).collect())
+    }
+
+    fn mark_messages_delivered(&self, receiver_id: &str, message_ids: &[String]) -> StorageResult<Vec<Message>> {
+        let mut conn = self.conn()?;
+        let rows = conn.query(
+            &format!(
+                "UPDATE messages SET delivered_at = $1
+                 WHERE id = ANY($2) AND receiver_id = $3 AND message_type = 'private' AND delivered_at IS NULL
+                 RETURNING {}",
+                MESSAGE_COLUMNS
+            ),
+            &[&now_secs(), &message_ids, &receiver_id],
+        )?;
+        Ok(rows.iter().map(message_from_row).collect())
+    }
+
+    fn mark_messages_as_read(&self, receiver_id: &str, message_ids: &[String]) -> StorageResult<Vec<Message>> {
+        let mut conn = self.conn()?;
+        let rows = conn.query(
+            &format!(
+                "UPDATE messages SET is_read = TRUE, read_at = $1, delivered_at = COALESCE(delivered_at, $1)
+                 WHERE id = ANY($2) AND receiver_id = $3 AND message_type = 'private' AND read_at IS NULL
+                 RETURNING {}",
+                MESSAGE_COLUMNS
+            ),
+            &[&now_secs(), &message_ids, &receiver_id],
+        )?;
+        Ok(rows.iter().map(message_from_row).collect())
+    }
+
+    fn advance_group_read_cursor(&self, group_id: &str, user_id: &str, message_id: &str) -> StorageResult<bool> {
+        let mut conn = self.conn()?;
+        let created_at: i64 = conn.query_opt(
+            "SELECT created_at FROM messages WHERE id = $1 AND receiver_id = $2 AND message_type = 'group'",
+            &[&message_id, &group_id],
+        )?.ok_or(StorageError::NotFound)?.get(0);
+
+        // 只向前推进：已有位置不早于该消息时不更新
+        let changed = conn.execute(
+            "INSERT INTO group_read_cursors (group_id, user_id, message_id, message_created_at, read_at)
+             VALUES ($1, $2, $3, $4, $5)
+             ON CONFLICT (group_id, user_id) DO UPDATE SET
+                 message_id = excluded.message_id,
+                 message_created_at = excluded.message_created_at,
+                 read_at = excluded.read_at
+             WHERE (group_read_cursors.message_created_at, group_read_cursors.message_id)
+                 < (excluded.message_created_at, excluded.message_id)",
+            &[&group_id, &user_id, &message_id, &created_at, &now_secs()],
+        )?;
+        Ok(changed > 0)
+    }
+
+    fn count_group_readers(&self, message: &Message) -> StorageResult<GroupReadCount> {
+        let mut conn = self.conn()?;
+        let row = conn.query_one(
+            "SELECT COUNT(*),
+                    COUNT(*) FILTER (WHERE (c.message_created_at, c.message_id) >= ($3, $4))
+             FROM group_members gm
+             LEFT JOIN group_read_cursors c ON c.group_id = gm.group_id AND c.user_id = gm.user_id
+             WHERE gm.group_id = $1 AND gm.user_id != $2",
+            &[&message.receiver_id, &message.sender_id, &message.created_at, &message.id],
+        )?;
+        Ok(GroupReadCount { total: row.get(0), read: row.get(1) })
+    }
+
+    fn create_attachment(&self, uploader_id: &str, new: &NewAttachment) -> StorageResult<Attachment> {
+        let mut conn = self.conn()?;
+        let mut tx = conn.transaction()?;
+        let attachment = Attachment {
+            id: Uuid::new_v4().to_string(),
+            uploader_id: uploader_id.to_string(),
+            kind: new.kind.clone(),
+            mime_type: new.mime_type.clone(),
+            size: new.size,
+            filename: new.filename.clone(),
+            created_at: now_secs(),
+            sha256: Some(new.sha256.clone()),
+            width: new.image.as_ref().map(|image| image.width),
+            height: new.image.as_ref().map(|image| image.height),
+            blurhash: new.image.as_ref().map(|image| image.blurhash.clone()),
+        };
+        acquire_blob(&mut tx, &new.sha256, new.size)?;
+        tx.execute(
+            &format!("INSERT INTO attachments ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)", ATTACHMENT_COLUMNS),
+            &[
+                &attachment.id, &attachment.uploader_id, &attachment.kind, &attachment.mime_type,
+                &attachment.size, &attachment.filename, &attachment.created_at, &attachment.sha256,
+                &attachment.width, &attachment.height, &attachment.blurhash,
+            ],
+        )?;
+        tx.commit()?;
+        Ok(attachment)
+    }
+
+    fn get_attachment(&self, attachment_id: &str) -> StorageResult<Attachment> {
+        let mut conn = self.conn()?;
+        let row = conn.query_opt(
+            &format!("SELECT {} FROM attachments WHERE id = $1", ATTACHMENT_COLUMNS),
+            &[&attachment_id],
+        )?;
+        row.map(|row| attachment_from_row(&row)).ok_or(StorageError::NotFound)
+    }
+
+    fn can_access_attachment(&self, user_id: &str, attachment_id: &str) -> StorageResult<bool> {
+        let mut conn = self.conn()?;
+        let row = conn.query_one(
+            "SELECT EXISTS(SELECT 1 FROM attachments WHERE id = $1 AND uploader_id = $2)
+                 OR EXISTS(
+                    SELECT 1 FROM message_attachments a JOIN messages m ON m.id = a.message_id
+                    WHERE a.attachment_id = $1
+                      AND ((m.message_type = 'private' AND (m.sender_id = $2 OR m.receiver_id = $2))
+                        OR (m.message_type = 'group' AND m.receiver_id IN (SELECT group_id FROM group_members WHERE user_id = $2)))
+                 )",
+            &[&attachment_id, &user_id],
+        )?;
+        Ok(row.get(0))
+    }
+
+    fn delete_orphan_attachments(&self, created_before: i64) -> StorageResult<Vec<Attachment>> {
+        let mut conn = self.conn()?;
+        let mut tx = conn.transaction()?;
+        let rows = tx.query(
+            &format!(
+                "DELETE FROM attachments
+                 WHERE created_at < $1
+                   AND NOT EXISTS (SELECT 1 FROM message_attachments a WHERE a.attachment_id = attachments.id)
+                 RETURNING {}",
+                ATTACHMENT_COLUMNS
+            ),
+            &[&created_before],
+        )?;
+        let removed: Vec<Attachment> = rows.iter().map(attachment_from_row).collect();
+        for sha256 in removed.iter().filter_map(|a| a.sha256.as_deref()) {
+            release_blob(&mut tx, sha256)?;
+        }
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    fn register_blob(&self, sha256: &str, size: i64) -> StorageResult<()> {
+        let mut conn = self.conn()?;
+        let now = now_secs();
+        conn.execute(
+            "INSERT INTO blobs (sha256, size, ref_count, created_at, unreferenced_since) VALUES ($1, $2, 0, $3, $3)
+             ON CONFLICT (sha256) DO NOTHING",
+            &[&sha256, &size, &now],
+        )?;
+        Ok(())
+    }
+
+    fn get_blob(&self, sha256: &str) -> StorageResult<Blob> {
+        let mut conn = self.conn()?;
+        let row = conn.query_opt(
+            &format!("SELECT {} FROM blobs WHERE sha256 = $1", BLOB_COLUMNS),
+            &[&sha256],
+        )?;
+        row.map(|row| blob_from_row(&row)).ok_or(StorageError::NotFound)
+    }
+
+    fn list_unreferenced_blobs(&self, unreferenced_before: i64) -> StorageResult<Vec<Blob>> {
+        let mut conn = self.conn()?;
+        let rows = conn.query(
+            &format!("SELECT {} FROM blobs WHERE ref_count = 0 AND unreferenced_since < $1", BLOB_COLUMNS),
+            &[&unreferenced_before],
+        )?;
+        Ok(rows.iter().map(blob_from_row).collect())
+    }
+
+    fn delete_unreferenced_blob(&self, sha256: &str) -> StorageResult<bool> {
+        let mut conn = self.conn()?;
+        let deleted = conn.execute("DELETE FROM blobs WHERE sha256 = $1 AND ref_count = 0", &[&sha256])?;
+        Ok(deleted > 0)
+    }
+
+    fn create_upload(&self, user_id: &str, filename: &str, size: i64, sha256: Option<&str>, expires_at: i64) -> StorageResult<Upload> {
+        let mut conn = self.conn()?;
+        let upload = Upload {
+            id: Uuid::new_v4().to_string(),
+            user_id: user_id.to_string(),
+            filename: filename.to_string(),
+            size,
+            received: 0,
+            sha256: sha256.map(str::to_string),
+            created_at: now_secs(),
+            expires_at,
+        };
+        conn.execute(
+            &format!("INSERT INTO uploads ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)", UPLOAD_COLUMNS),
+            &[
+                &upload.id, &upload.user_id, &upload.filename, &upload.size,
+                &upload.received, &upload.sha256, &upload.created_at, &upload.expires_at,
+            ],
+        )?;
+        Ok(upload)
+    }
+
+    fn get_upload(&self, upload_id: &str) -> StorageResult<Upload> {
+        let mut conn = self.conn()?;
+        let row = conn.query_opt(
+            &format!("SELECT {} FROM uploads WHERE id = $1", UPLOAD_COLUMNS),
+            &[&upload_id],
+        )?;
+        row.map(|row| upload_from_row(&row)).ok_or(StorageError::NotFound)
+    }
+
+    fn advance_upload(&self, upload_id: &str, from: i64, to: i64, expires_at: i64) -> StorageResult<bool> {
+        let mut conn = self.conn()?;
+        let updated = conn.execute(
+            "UPDATE uploads SET received = $1, expires_at = $2 WHERE id = $3 AND received = $4 AND $1 <= size",
+            &[&to, &expires_at, &upload_id, &from],
+        )?;
+        Ok(updated > 0)
+    }
+
+    fn delete_upload(&self, upload_id: &str) -> StorageResult<bool> {
+        let mut conn = self.conn()?;
+        Ok(conn.execute("DELETE FROM uploads WHERE id = $1", &[&upload_id])? > 0)
+    }
+
+    fn delete_expired_uploads(&self, now: i64) -> StorageResult<Vec<String>> {
+        let mut conn = self.conn()?;
+        let rows = conn.query("DELETE FROM uploads WHERE expires_at < $1 RETURNING id", &[&now])?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+
+    fn are_friends(&self, user_id: &str, friend_id: &str) -> StorageResult<bool> {
+        let mut conn = self.conn()?;
+        let row = conn.query_one(
+            "SELECT EXISTS(SELECT 1 FROM friendships WHERE user_id = $1 AND friend_id = $2 AND status = 'accepted')",
+            &[&user_id, &friend_id],
+        )?;
+        Ok(row.get(0))
+    }
+
+    fn get_friends(&self, user_id: &str) -> StorageResult<Vec<User>> {
+        let mut conn = self.conn()?;
+        let rows = conn.query(
+            "SELECT u.id, u.username, u.email, u.password_hash, u.created_at, u.avatar_url, u.last_seen
+             FROM users u
+             JOIN friendships f ON u.id = f.friend_id
+             WHERE f.user_id = $1 AND f.status = 'accepted'",
+            &[&user_id],
+        )?;
+        Ok(rows.iter().map(user_from_row).collect())
+    }
+
+    fn send_friend_request(&self, from_user_id: &str, to_username: &str) -> StorageResult<FriendRequest> {
+        let mut conn = self.conn()?;
+
+        // 检查目标用户是否存在
+        let to_user_id: String = conn.query_opt("SELECT id FROM users WHERE username = $1", &[&to_username])?
+            .map(|row| row.get(0))
+            .ok_or(StorageError::NotFound)?;
+
+        // 检查是否已经是好友
+        let is_friend: bool = conn.query_one(
+            "SELECT EXISTS(SELECT 1 FROM friendships WHERE user_id = $1 AND friend_id = $2 AND status = 'accepted')",
+            &[&from_user_id, &to_user_id],
+        )?.get(0);
+        if is_friend {
+            return Err(StorageError::AlreadyFriends);
+        }
+
+        // 检查是否已经发送过请求
+        let has_pending_request: bool = conn.query_one(
+            "SELECT EXISTS(SELECT 1 FROM friend_requests WHERE from_user_id = $1 AND to_user_id = $2 AND status = 'pending')",
+            &[&from_user_id, &to_user_id],
+        )?.get(0);
+        if has_pending_request {
+            return Err(StorageError::FriendRequestAlreadySent);
+        }
+
+        let request_id = Uuid::new_v4().to_string();
+        let created_at = now_secs();
+
+        conn.execute(
+            "INSERT INTO friend_requests (id, from_user_id, to_user_id, status, created_at)
+             VALUES ($1, $2, $3, 'pending', $4)",
+            &[&request_id, &from_user_id, &to_user_id, &created_at],
+        )?;
+
+        Ok(FriendRequest {
+            id: request_id,
+            from_user_id: from_user_id.to_string(),
+            to_user_id,
+            status: "pending".to_string(),
+            created_at,
+        })
+    }
+
+    fn get_received_friend_requests(&self, user_id: &str) -> StorageResult<Vec<FriendRequest>> {
+        let mut conn = self.conn()?;
+        let rows = conn.query(
+            "SELECT id, from_user_id, to_user_id, status, created_at
+             FROM friend_requests
+             WHERE to_user_id = $1 AND status = 'pending'",
+            &[&user_id],
+        )?;
+
+        Ok(rows.iter().map(|row| FriendRequest {
+            id: row.get(0),
+            from_user_id: row.get(1),
+            to_user_id: row.get(2),
+            status: row.get(3),
+            created_at: row.get(4),
+        }).collect())
+    }
+
+    fn respond_to_friend_request(&self, request_id: &str, responder_id: &str, response: &str) -> StorageResult<Friendship> {
+        let mut conn = self.conn()?;
+        let mut tx = conn.transaction()?;
+
+        // 验证请求存在且由该接收方(responder)处理，并锁定该请求避免重复处理
+        let row = tx.query_opt(
+            "SELECT from_user_id, status FROM friend_requests WHERE id = $1 AND to_user_id = $2 FOR UPDATE",
+            &[&request_id, &responder_id],
+        )?.ok_or(StorageError::NotFound)?;
+        let from_user_id: String = row.get(0);
+        let current_status: String = row.get(1);
+
+        if current_status != "pending" {
+            return Err(StorageError::FriendRequestAlreadyProcessed);
+        }
+
+        tx.execute(
+            "UPDATE friend_requests SET status = $1 WHERE id = $2",
+            &[&response, &request_id],
+        )?;
+
+        let friendship = if response == "accepted" {
+            // 创建双向好友关系（from_user_id <-> responder_id）
+            let friendship_id = Uuid::new_v4().to_string();
+            let reverse_friendship_id = Uuid::new_v4().to_string();
+            let created_at = now_secs();
+
+            tx.execute(
+                "INSERT INTO friendships (id, user_id, friend_id, status, created_at)
+                 VALUES ($1, $2, $3, 'accepted', $5), ($4, $3, $2, 'accepted', $5)",
+                &[&friendship_id, &from_user_id, &responder_id, &reverse_friendship_id, &created_at],
+            )?;
+
+            Friendship {
+                id: friendship_id,
+                user_id: from_user_id,
+                friend_id: responder_id.to_string(),
+                status: "accepted".to_string(),
+                created_at,
+            }
+        } else {
+            // 拒绝请求，只更新状态，不创建好友关系
+            Friendship {
+                id: request_id.to_string(),
+                user_id: from_user_id,
+                friend_id: responder_id.to_string(),
+                status: "rejected".to_string(),
+                created_at: 0,
+            }
+        };
+
+        tx.commit()?;
+        Ok(friendship)
+    }
+
+    fn remove_friend(&self, user_id: &str, friend_id: &str) -> StorageResult<()> {
+        let mut conn = self.conn()?;
+        conn.execute(
+            "DELETE FROM friendships WHERE (user_id = $1 AND friend_id = $2) OR (user_id = $2 AND friend_id = $1)",
+            &[&user_id, &friend_id],
+        )?;
+        Ok(())
+    }
+
+    fn create_group(&self, creator_id: &str, name: &str, member_ids: &[String]) -> StorageResult<Group> {
+        let mut conn = self.conn()?;
+        let mut tx = conn.transaction()?;
+
+        let group = Group {
+            id: Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            creator_id: creator_id.to_string(),
+            created_at: now_secs(),
+        };
+        tx.execute(
+            "INSERT INTO groups (id, name, creator_id, created_at) VALUES ($1, $2, $3, $4)",
+            &[&group.id, &group.name, &group.creator_id, &group.created_at],
+        )?;
+
+        // 群主在前，重复的成员ID只插入一次
+        let members = std::iter::once((creator_id, "owner"))
+            .chain(member_ids.iter().map(|id| (id.as_str(), "member")));
+        for (user_id, role) in members {
+            tx.execute(
+                "INSERT INTO group_members (id, group_id, user_id, joined_at, role)
+                 VALUES ($1, $2, $3, $4, $5)
+                 ON CONFLICT (group_id, user_id) DO NOTHING",
+                &[&Uuid::new_v4().to_string(), &group.id, &user_id, &group.created_at, &role],
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(group)
+    }
+
+    fn get_group(&self, group_id: &str) -> StorageResult<Group> {
+        let mut conn = self.conn()?;
+        conn.query_opt("SELECT id, name, creator_id, created_at FROM groups WHERE id = $1", &[&group_id])?
+            .map(|row| Group {
+                id: row.get(0),
+                name: row.get(1),
+                creator_id: row.get(2),
+                created_at: row.get(3),
+            })
+            .ok_or(StorageError::NotFound)
+    }
+
+    fn get_user_groups(&self, user_id: &str) -> StorageResult<Vec<(Group, GroupMember)>> {
+        let mut conn = self.conn()?;
+        let rows = conn.query(
+            "SELECT g.id, g.name, g.creator_id, g.created_at, m.id, m.joined_at, m.role, m.muted
+             FROM groups g
+             JOIN group_members m ON m.group_id = g.id
+             WHERE m.user_id = $1
+             ORDER BY m.joined_at",
+            &[&user_id],
+        )?;
+        Ok(rows.iter().map(|row| {
+            let group = Group {
+                id: row.get(0),
+                name: row.get(1),
+                creator_id: row.get(2),
+                created_at: row.get(3),
+            };
+            let member = GroupMember {
+                id: row.get(4),
+                group_id: group.id.clone(),
+                user_id: user_id.to_string(),
+                joined_at: row.get(5),
+                role: row.get(6),
+                muted: row.get(7),
+            };
+            (group, member)
+        }).collect())
+    }
+
+    fn get_group_members(&self, group_id: &str) -> StorageResult<Vec<GroupMember>> {
+        let mut conn = self.conn()?;
+        let rows = conn.query(
+            "SELECT id, group_id, user_id, joined_at, role, muted FROM group_members
+             WHERE group_id = $1 ORDER BY joined_at, role DESC, user_id",
+            &[&group_id],
+        )?;
+        Ok(rows.iter().map(|row| GroupMember {
+            id: row.get(0),
+            group_id: row.get(1),
+            user_id: row.get(2),
+            joined_at: row.get(3),
+            role: row.get(4),
+            muted: row.get(5),
+        }).collect())
+    }
+
+    fn get_member_role(&self, group_id: &str, user_id: &str) -> StorageResult<Option<String>> {
+        let mut conn = self.conn()?;
+        let row = conn.query_opt(
+            "SELECT role FROM group_members WHERE group_id = $1 AND user_id = $2",
+            &[&group_id, &user_id],
+        )?;
+        Ok(row.map(|row| row.get(0)))
+    }
+
+    fn add_group_member(&self, group_id: &str, user_id: &str, role: &str) -> StorageResult<GroupMember> {
+        let mut conn = self.conn()?;
+        let member = GroupMember {
+            id: Uuid::new_v4().to_string(),
+            group_id: group_id.to_string(),
+            user_id: user_id.to_string(),
+            joined_at: now_secs(),
+            role: role.to_string(),
+            muted: false,
+        };
+        conn.execute(
+            "INSERT INTO group_members (id, group_id, user_id, joined_at, role) VALUES ($1, $2, $3, $4, $5)",
+            &[&member.id, &member.group_id, &member.user_id, &member.joined_at, &member.role],
+        )?;
+        Ok(member)
+    }
+
+    fn remove_group_member(&self, group_id: &str, user_id: &str) -> StorageResult<bool> {
+        let mut conn = self.conn()?;
+        let mut tx = conn.transaction()?;
+        let removed = tx.execute(
+            "DELETE FROM group_members WHERE group_id = $1 AND user_id = $2",
+            &[&group_id, &user_id],
+        )?;
+        tx.execute(
+            "DELETE FROM group_read_cursors WHERE group_id = $1 AND user_id = $2",
+            &[&group_id, &user_id],
+        )?;
+        tx.commit()?;
+        Ok(removed > 0)
+    }
+
+    fn set_member_role(&self, group_id: &str, user_id: &str, role: &str) -> StorageResult<()> {
+        let mut conn = self.conn()?;
+        let updated = conn.execute(
+            "UPDATE group_members SET role = $1 WHERE group_id = $2 AND user_id = $3 AND role <> 'owner'",
+            &[&role, &group_id, &user_id],
+        )?;
+        if updated == 0 {
+            return Err(StorageError::NotFound);
+        }
+        Ok(())
+    }
+
+    fn set_group_muted(&self, group_id: &str, user_id: &str, muted: bool) -> StorageResult<()> {
+        let mut conn = self.conn()?;
+        let updated = conn.execute(
+            "UPDATE group_members SET muted = $1 WHERE group_id = $2 AND user_id = $3",
+            &[&muted, &group_id, &user_id],
+        )?;
+        if updated == 0 {
+            return Err(StorageError::NotFound);
+        }
+        Ok(())
+    }
+
+    fn rename_group(&self, group_id: &str, name: &str) -> StorageResult<()> {
+        let mut conn = self.conn()?;
+        conn.execute("UPDATE groups SET name = $1 WHERE id = $2", &[&name, &group_id])?;
+        Ok(())
+    }
+
+    fn delete_group(&self, group_id: &str) -> StorageResult<()> {
+        let mut conn = self.conn()?;
+        let mut tx = conn.transaction()?;
+        tx.execute("DELETE FROM group_members WHERE group_id = $1", &[&group_id])?;
+        tx.execute("DELETE FROM messages WHERE message_type = 'group' AND receiver_id = $1", &[&group_id])?;
+        tx.execute("DELETE FROM groups WHERE id = $1", &[&group_id])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn group_exists(&self, group_id: &str) -> StorageResult<bool> {
+        let mut conn = self.conn()?;
+        let row = conn.query_one("SELECT EXISTS(SELECT 1 FROM groups WHERE id = $1)", &[&group_id])?;
+        Ok(row.get(0))
+    }
+
+    fn is_group_member(&self, group_id: &str, user_id: &str) -> StorageResult<bool> {
+        let mut conn = self.conn()?;
+        let row = conn.query_one(
+            "SELECT EXISTS(SELECT 1 FROM group_members WHERE group_id = $1 AND user_id = $2)",
+            &[&group_id, &user_id],
+        )?;
+        Ok(row.get(0))
+    }
+
+    fn get_user_group_ids(&self, user_id: &str) -> StorageResult<Vec<String>> {
+        let mut conn = self.conn()?;
+        let rows = conn.query("SELECT group_id FROM group_members WHERE user_id = $1", &[&user_id])?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+
+    fn get_or_create_token_secret(&self, candidate: &str) -> StorageResult<String> {
+        let mut conn = self.conn()?;
+        // 并发启动时只有第一个写入生效，其余实例读取已保存的密钥
+        conn.execute(
+            "INSERT INTO server_keys (name, value) VALUES ('token_secret', $1) ON CONFLICT (name) DO NOTHING",
+            &[&candidate],
+        )?;
+        let row = conn.query_one("SELECT value FROM server_keys WHERE name = 'token_secret'", &[])?;
+        Ok(row.get(0))
+    }
+
+    fn create_session(&self, user_id: &str, refresh_token_hash: &str, refresh_expires_at: i64, device_name: Option<&str>) -> StorageResult<Session> {
+        let mut conn = self.conn()?;
+
+        let session_id = Uuid::new_v4().to_string();
+        let created_at = now_secs();
+
+        conn.execute(
+            "INSERT INTO sessions (id, user_id, refresh_token_hash, created_at, refresh_expires_at, last_used_at, device_name)
+             VALUES ($1, $2, $3, $4, $5, $4, $6)",
+            &[&session_id, &user_id, &refresh_token_hash, &created_at, &refresh_expires_at, &device_name],
+        )?;
+
+        Ok(Session {
+            id: session_id,
+            user_id: user_id.to_string(),
+            created_at,
+            refresh_expires_at,
+            last_used_at: created_at,
+            device_name: device_name.map(str::to_string),
+        })
+    }
+
+    fn rotate_refresh_token(&self, old_token_hash: &str, new_token_hash: &str, refresh_expires_at: i64) -> StorageResult<Option<Session>> {
+        let mut conn = self.conn()?;
+        let now = now_secs();
+
+        // 以旧哈希为条件原子更新，并发使用同一刷新令牌时只有一个请求成功
+        let row = conn.query_opt(
+            "UPDATE sessions SET refresh_token_hash = $1, refresh_expires_at = $2, last_used_at = $3
+             WHERE refresh_token_hash = $4 AND NOT revoked AND refresh_expires_at > $3
+             RETURNING id, user_id, created_at, device_name",
+            &[&new_token_hash, &refresh_expires_at, &now, &old_token_hash],
+        )?;
+
+        Ok(row.map(|row| Session {
+            id: row.get(0),
+            user_id: row.get(1),
+            created_at: row.get(2),
+            refresh_expires_at,
+            last_used_at: now,
+            device_name: row.get(3),
+        }))
+    }
+
+    fn is_session_active(&self, session_id: &str, user_id: &str) -> StorageResult<bool> {
+        let mut conn = self.conn()?;
+        let row = conn.query_one(
+            "SELECT EXISTS(SELECT 1 FROM sessions WHERE id = $1 AND user_id = $2 AND NOT revoked AND refresh_expires_at > $3)",
+            &[&session_id, &user_id, &now_secs()],
+        )?;
+        Ok(row.get(0))
+    }
+
+    fn get_active_sessions(&self, user_id: &str) -> StorageResult<Vec<Session>> {
+        let mut conn = self.conn()?;
+        let rows = conn.query(
+            "SELECT id, user_id, created_at, refresh_expires_at, last_used_at, device_name FROM sessions
+             WHERE user_id = $1 AND NOT revoked AND refresh_expires_at > $2
+             ORDER BY last_used_at DESC, created_at DESC",
+            &[&user_id, &now_secs()],
+        )?;
+
+        Ok(rows.iter().map(|row| Session {
+            id: row.get(0),
+            user_id: row.get(1),
+            created_at: row.get(2),
+            refresh_expires_at: row.get(3),
+            last_used_at: row.get(4),
+            device_name: row.get(5),
+        }).collect())
+    }
+
+    fn revoke_session(&self, session_id: &str) -> StorageResult<()> {
+        let mut conn = self.conn()?;
+        conn.execute("UPDATE sessions SET revoked = TRUE WHERE id = $1", &[&session_id])?;
+        Ok(())
+    }
+
+    fn append_user_events(&self, user_ids: &[String], kind: &str, payload: &str, message_id: Option<&str>) -> StorageResult<Vec<UserEvent>> {
+        let mut conn = self.conn()?;
+        let mut tx = conn.transaction()?;
+        let created_at = now_secs();
+
+        // 按固定顺序锁定计数器行，避免并发追加时死锁
+        let mut user_ids: Vec<&str> = user_ids.iter().map(String::as_str).collect();
+        user_ids.sort_unstable();
+        user_ids.dedup();
+
+        let next_seq = tx.prepare(
+            "INSERT INTO user_event_sequences (user_id, last_seq) VALUES ($1, 1)
+             ON CONFLICT (user_id) DO UPDATE SET last_seq = user_event_sequences.last_seq + 1
+             RETURNING last_seq",
+        )?;
+        let insert = tx.prepare(
+            "INSERT INTO user_events (user_id, seq, kind, payload, message_id, created_at) VALUES ($1, $2, $3, $4, $5, $6)",
+        )?;
+
+        let mut events = Vec::with_capacity(user_ids.len());
+        for user_id in user_ids {
+            let seq: i64 = tx.query_one(&next_seq, &[&user_id])?.get(0);
+            tx.execute(&insert, &[&user_id, &seq, &kind, &payload, &message_id, &created_at])?;
+            events.push(UserEvent {
+                user_id: user_id.to_string(),
+                seq,
+                kind: kind.to_string(),
+                payload: payload.to_string(),
+                message_id: message_id.map(str::to_string),
+                created_at,
+            });
+        }
+        tx.commit()?;
+        Ok(events)
+    }
+
+    fn get_user_events(&self, user_id: &str, after_seq: i64, limit: usize) -> StorageResult<Vec<UserEvent>> {
+        let mut conn = self.conn()?;
+        let rows = conn.query(
+            "SELECT user_id, seq, kind, payload, message_id, created_at FROM user_events
+             WHERE user_id = $1 AND seq > $2
+             ORDER BY seq
+             LIMIT $3",
+            &[&user_id, &after_seq, &(limit as i64)],
+        )?;
+
+        Ok(rows.iter().map(|row| UserEvent {
+            user_id: row.get(0),
+            seq: row.get(1),
+            kind: row.get(2),
+            payload: row.get(3),
+            message_id: row.get(4),
+            created_at: row.get(5),
+        }).collect())
+    }
+
+    fn get_sync_cursor(&self, session_id: &str) -> StorageResult<i64> {
+        let mut conn = self.conn()?;
+        let row = conn.query_opt("SELECT sync_seq FROM sessions WHERE id = $1", &[&session_id])?;
+        row.map(|row| row.get(0)).ok_or(StorageError::NotFound)
+    }
+
+    fn advance_sync_cursor(&self, session_id: &str, seq: i64) -> StorageResult<i64> {
+        let mut conn = self.conn()?;
+        let row = conn.query_opt(
+            "UPDATE sessions SET sync_seq = GREATEST(sync_seq, $1) WHERE id = $2 RETURNING sync_seq",
+            &[&seq, &session_id],
+        )?;
+        row.map(|row| row.get(0)).ok_or(StorageError::NotFound)
+    }
+}
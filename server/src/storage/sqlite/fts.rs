@@ -0,0 +1,65 @@
+use rusqlite::functions::FunctionFlags;
+use rusqlite::{Connection, Result};
+
+/// 全文索引分词函数名（迁移中的触发器会调用它）
+pub const SEGMENT_FUNCTION: &str = "yueling_segment";
+
+// 是否按单字切分（汉字、假名、谚文等不以空格分词的文字）
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x2E80..=0x2FDF       // 部首
+        | 0x3040..=0x30FF     // 平假名、片假名
+        | 0x3100..=0x312F     // 注音
+        | 0x3130..=0x318F     // 谚文兼容字母
+        | 0x3400..=0x4DBF     // 扩展 A
+        | 0x4E00..=0x9FFF     // 基本汉字
+        | 0xAC00..=0xD7AF     // 谚文音节
+        | 0xF900..=0xFAFF     // 兼容汉字
+        | 0x20000..=0x3134F   // 扩展 B–G
+    )
+}
+
+/// 为 FTS5 预处理文本：CJK 字符前后插入空格
+///
+/// unicode61 分词器只按空白和标点分词，整段中文会成为一个词而无法检索。
+/// 按单字切分后，查询时把检索词作为短语匹配（相邻单字依次出现），即可做到子串检索。
+pub fn segment(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() * 2);
+    for c in text.chars() {
+        if is_cjk(c) {
+            out.push(' ');
+            out.push(c);
+            out.push(' ');
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// 在连接上注册分词函数（每个新连接都需要注册，否则写入消息时触发器会失败）
+pub fn register_functions(conn: &Connection) -> Result<()> {
+    conn.create_scalar_function(
+        SEGMENT_FUNCTION,
+        1,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| Ok(segment(&ctx.get::<String>(0)?)),
+    )
+}
+
+/// 把用户输入转换为 FTS5 查询表达式
+///
+/// 按空白拆分为多个检索词（全部需要匹配），每个词作为带前缀匹配的短语；
+/// 不含任何字母或数字的词被忽略，全部被忽略时返回 `None`。
+pub fn match_query(query: &str) -> Option<String> {
+    let phrases: Vec<String> = query
+        .split_whitespace()
+        .filter(|term| term.chars().any(char::is_alphanumeric))
+        .map(|term| format!("\"{}\"*", segment(term).trim().replace('"', "\"\"")))
+        .collect();
+    if phrases.is_empty() {
+        None
+    } else {
+        Some(phrases.join(" "))
+    }
+}
@@ -0,0 +1,219 @@
+use rusqlite::{params, Connection, Result};
+use crate::storage::{
+    now_secs,
+    Migration
+};
+
+/// SQLite 后端按版本排序的全部迁移（只允许追加，已发布的迁移不得修改）
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "baseline",
+        sql: include_str!("migrations/0001_baseline.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "sessions",
+        sql: include_str!("migrations/0002_sessions.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "groups_drop_legacy_columns",
+        sql: include_str!("migrations/0003_groups_drop_legacy_columns.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "message_history_indexes",
+        sql: include_str!("migrations/0004_message_history_indexes.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "messages_fts",
+        sql: include_str!("migrations/0005_messages_fts.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "message_receipts",
+        sql: include_str!("migrations/0006_message_receipts.sql"),
+    },
+    Migration {
+        version: 7,
+        name: "users_last_seen",
+        sql: include_str!("migrations/0007_users_last_seen.sql"),
+    },
+    Migration {
+        version: 8,
+        name: "session_device_name",
+        sql: include_str!("migrations/0008_session_device_name.sql"),
+    },
+    Migration {
+        version: 9,
+        name: "user_events",
+        sql: include_str!("migrations/0009_user_events.sql"),
+    },
+    Migration {
+        version: 10,
+        name: "message_edits",
+        sql: include_str!("migrations/0010_message_edits.sql"),
+    },
+    Migration {
+        version: 11,
+        name: "message_threads",
+        sql: include_str!("migrations/0011_message_threads.sql"),
+    },
+    Migration {
+        version: 12,
+        name: "message_reactions",
+        sql: include_str!("migrations/0012_message_reactions.sql"),
+    },
+    Migration {
+        version: 13,
+        name: "message_mentions",
+        sql: include_str!("migrations/0013_message_mentions.sql"),
+    },
+    Migration {
+        version: 14,
+        name: "attachments",
+        sql: include_str!("migrations/0014_attachments.sql"),
+    },
+    Migration {
+        version: 15,
+        name: "uploads",
+        sql: include_str!("migrations/0015_uploads.sql"),
+    },
+    Migration {
+        version: 16,
+        name: "blobs",
+        sql: include_str!("migrations/0016_blobs.sql"),
+    },
+    Migration {
+        version: 17,
+        name: "image_metadata",
+        sql: include_str!("migrations/0017_image_metadata.sql"),
+    },
+    Migration {
+        version: 18,
+        name: "friend_request_event_id",
+        sql: include_str!("migrations/0018_friend_request_event_id.sql"),
+    },
+    Migration {
+        version: 19,
+        name: "avatar_sha256_index",
+        sql: include_str!("migrations/0019_avatar_sha256_index.sql"),
+    },
+];
+
+/// 最新结构版本
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+// 创建版本记录表（若不存在）
+fn ensure_version_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            applied_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// 当前数据库的结构版本（未执行过任何迁移时为 0），不会创建版本记录表
+pub fn current_version(conn: &Connection) -> Result<i64> {
+    let exists: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'schema_version')",
+        [],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        return Ok(0);
+    }
+    conn.query_row(
+        "SELECT COALESCE(MAX(version), 0) FROM schema_version",
+        [],
+        |row| row.get(0),
+    )
+}
+
+/// 尚未执行的迁移
+pub fn pending_migrations(conn: &Connection) -> Result<Vec<&'static Migration>> {
+    let current = current_version(conn)?;
+    Ok(MIGRATIONS.iter().filter(|m| m.version > current).collect())
+}
+
+/// 依次执行尚未执行的迁移，返回本次执行的迁移
+///
+/// 每个迁移在独立事务中执行并记录到 schema_version 表；
+/// `dry_run` 为 true 时全部迁移在同一事务中试运行后回滚，也不创建版本记录表，数据库保持不变
+/// （连接本身也不能修改数据库，见 [`super::SqliteStorage::open_dry_run`]）。
+///
+/// 重建表的迁移需要先删除被引用的旧表，因此执行期间暂时关闭外键约束，
+/// 提交前用 `PRAGMA foreign_key_check` 确认没有破坏引用完整性。
+pub fn run_migrations(conn: &mut Connection, dry_run: bool) -> Result<Vec<&'static Migration>> {
+    let pending = pending_migrations(conn)?;
+    if pending.is_empty() {
+        return Ok(pending);
+    }
+    if !dry_run {
+        ensure_version_table(conn)?;
+    }
+
+    let foreign_keys: bool = conn.query_row("PRAGMA foreign_keys", [], |row| row.get(0))?;
+    conn.execute_batch("PRAGMA foreign_keys = OFF")?;
+    let result = apply(conn, &pending, dry_run);
+    if foreign_keys {
+        conn.execute_batch("PRAGMA foreign_keys = ON")?;
+    }
+    result?;
+
+    Ok(pending)
+}
+
+// 执行迁移脚本（调用方负责外键开关）
+fn apply(conn: &mut Connection, pending: &[&'static Migration], dry_run: bool) -> Result<()> {
+    if dry_run {
+        let tx = conn.transaction()?;
+        let violations = foreign_key_violations(&tx)?;
+        for migration in pending {
+            tx.execute_batch(migration.sql)?;
+        }
+        check_foreign_keys(&tx, violations)?;
+        tx.rollback()?;
+        return Ok(());
+    }
+
+    for migration in pending {
+        let tx = conn.transaction()?;
+        let violations = foreign_key_violations(&tx)?;
+        tx.execute_batch(migration.sql)?;
+        check_foreign_keys(&tx, violations)?;
+        tx.execute(
+            "INSERT INTO schema_version (version, name, applied_at) VALUES (?1, ?2, ?3)",
+            params![migration.version, migration.name, now_secs()],
+        )?;
+        tx.commit()?;
+        println!("已执行数据库迁移 {:04}_{}", migration.version, migration.name);
+    }
+
+    Ok(())
+}
+
+// 统计当前的外键约束冲突数
+fn foreign_key_violations(conn: &Connection) -> Result<i64> {
+    conn.query_row("SELECT COUNT(*) FROM pragma_foreign_key_check", [], |row| row.get(0))
+}
+
+// 检查迁移没有引入新的外键约束冲突（不处理迁移前已存在的孤儿记录）
+fn check_foreign_keys(conn: &Connection, before: i64) -> Result<()> {
+    let after = foreign_key_violations(conn)?;
+    if after > before {
+        return Err(rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CONSTRAINT),
+            Some(format!("迁移引入了 {} 条外键约束冲突", after - before)),
+        ));
+    }
+    Ok(())
+}
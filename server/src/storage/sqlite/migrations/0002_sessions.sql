@@ -0,0 +1,19 @@
+-- 登录会话表（只保存刷新令牌的哈希）
+CREATE TABLE IF NOT EXISTS sessions (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    refresh_token_hash TEXT UNIQUE NOT NULL,
+    created_at INTEGER NOT NULL,
+    refresh_expires_at INTEGER NOT NULL,
+    last_used_at INTEGER NOT NULL,
+    revoked INTEGER NOT NULL DEFAULT 0,
+    FOREIGN KEY(user_id) REFERENCES users(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_sessions_user_id ON sessions(user_id);
+
+-- 服务端密钥表（保存令牌签名密钥等）
+CREATE TABLE IF NOT EXISTS server_keys (
+    name TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
@@ -0,0 +1,18 @@
+-- 移除 groups 表中模型不再使用的 group_id 与 group_user_list 列
+-- （成员关系统一由 group_members 表维护）
+CREATE TABLE groups_new (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    creator_id TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    FOREIGN KEY(creator_id) REFERENCES users(id)
+);
+
+INSERT INTO groups_new (id, name, creator_id, created_at)
+SELECT id, name, creator_id, created_at FROM groups;
+
+DROP TABLE groups;
+
+ALTER TABLE groups_new RENAME TO groups;
+
+CREATE INDEX IF NOT EXISTS idx_group_members_user_id ON group_members(user_id);
@@ -0,0 +1,25 @@
+-- 消息全文索引（FTS5，不保存原文，只保存索引）
+-- 写入的是 yueling_segment() 预处理后的文本（CJK 按单字切分），该函数由连接池在每个连接上注册；
+-- 索引行的 rowid 与 messages 表的 rowid 对应
+CREATE VIRTUAL TABLE IF NOT EXISTS messages_fts USING fts5(
+    body,
+    content = '',
+    contentless_delete = 1,
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+
+INSERT INTO messages_fts (rowid, body)
+SELECT rowid, yueling_segment(content) FROM messages;
+
+CREATE TRIGGER IF NOT EXISTS messages_fts_insert AFTER INSERT ON messages BEGIN
+    INSERT INTO messages_fts (rowid, body) VALUES (new.rowid, yueling_segment(new.content));
+END;
+
+CREATE TRIGGER IF NOT EXISTS messages_fts_delete AFTER DELETE ON messages BEGIN
+    DELETE FROM messages_fts WHERE rowid = old.rowid;
+END;
+
+CREATE TRIGGER IF NOT EXISTS messages_fts_update AFTER UPDATE OF content ON messages BEGIN
+    DELETE FROM messages_fts WHERE rowid = old.rowid;
+    INSERT INTO messages_fts (rowid, body) VALUES (new.rowid, yueling_segment(new.content));
+END;
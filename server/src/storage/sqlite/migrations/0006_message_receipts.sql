@@ -0,0 +1,18 @@
+-- 私聊消息的送达与已读时间（NULL 表示尚未送达/已读）
+ALTER TABLE messages ADD COLUMN delivered_at INTEGER;
+ALTER TABLE messages ADD COLUMN read_at INTEGER;
+
+-- 已标记为已读的历史消息没有记录具体时间，以发送时间代替
+UPDATE messages SET delivered_at = created_at, read_at = created_at WHERE is_read = 1;
+
+-- 群成员的已读位置：已读到的最后一条群消息（按 (message_created_at, message_id) 排序）
+CREATE TABLE IF NOT EXISTS group_read_cursors (
+    group_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    message_id TEXT NOT NULL,
+    message_created_at INTEGER NOT NULL,
+    read_at INTEGER NOT NULL,
+    PRIMARY KEY (group_id, user_id),
+    FOREIGN KEY(group_id) REFERENCES groups(id) ON DELETE CASCADE,
+    FOREIGN KEY(user_id) REFERENCES users(id)
+);
@@ -0,0 +1,18 @@
+-- 每个用户的事件序号计数器
+CREATE TABLE IF NOT EXISTS user_event_sequences (
+    user_id TEXT PRIMARY KEY REFERENCES users(id),
+    last_seq INTEGER NOT NULL
+);
+
+-- 用户事件流（消息、好友事件、群变动、回执），离线后按序号补齐
+CREATE TABLE IF NOT EXISTS user_events (
+    user_id TEXT NOT NULL REFERENCES users(id),
+    seq INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    payload TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    PRIMARY KEY (user_id, seq)
+);
+
+-- 设备已同步到的事件序号
+ALTER TABLE sessions ADD COLUMN sync_seq INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,7 @@
+-- 回复与话题：被回复（引用）的消息；群聊中回复归入话题，话题根消息记录回复数
+ALTER TABLE messages ADD COLUMN reply_to_id TEXT;
+ALTER TABLE messages ADD COLUMN thread_root_id TEXT;
+ALTER TABLE messages ADD COLUMN reply_count INTEGER NOT NULL DEFAULT 0;
+
+-- 话题内按时间分页
+CREATE INDEX IF NOT EXISTS idx_messages_thread ON messages(thread_root_id, created_at, id) WHERE thread_root_id IS NOT NULL;
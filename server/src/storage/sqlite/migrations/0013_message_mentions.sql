@@ -0,0 +1,14 @@
+-- @提醒：消息保存@的成员（JSON数组）与是否@全体成员；提醒表按被提醒用户展开，供"@我的"查询
+ALTER TABLE messages ADD COLUMN mentions TEXT NOT NULL DEFAULT '[]';
+ALTER TABLE messages ADD COLUMN mention_all INTEGER NOT NULL DEFAULT 0;
+
+CREATE TABLE IF NOT EXISTS message_mentions (
+    message_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    PRIMARY KEY (user_id, message_id),
+    FOREIGN KEY(message_id) REFERENCES messages(id) ON DELETE CASCADE,
+    FOREIGN KEY(user_id) REFERENCES users(id)
+);
+
+-- 群消息免打扰（@提醒仍然推送）
+ALTER TABLE group_members ADD COLUMN muted INTEGER NOT NULL DEFAULT 0;
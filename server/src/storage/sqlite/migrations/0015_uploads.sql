@@ -0,0 +1,15 @@
+-- 分块（可续传）上传会话：已接收的字节数保存在库中，重启后从该位置继续
+CREATE TABLE IF NOT EXISTS uploads (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    filename TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    received INTEGER NOT NULL DEFAULT 0,
+    sha256 TEXT,
+    created_at INTEGER NOT NULL,
+    expires_at INTEGER NOT NULL,
+    FOREIGN KEY(user_id) REFERENCES users(id)
+);
+
+-- 清理过期的上传
+CREATE INDEX IF NOT EXISTS idx_uploads_expires_at ON uploads(expires_at);
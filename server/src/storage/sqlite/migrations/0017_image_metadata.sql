@@ -0,0 +1,4 @@
+-- 图片附件的尺寸与 BlurHash 占位（旧附件与非图片附件为空）
+ALTER TABLE attachments ADD COLUMN width INTEGER;
+ALTER TABLE attachments ADD COLUMN height INTEGER;
+ALTER TABLE attachments ADD COLUMN blurhash TEXT;
@@ -0,0 +1,4 @@
+-- 好友请求推送中的好友请求ID改名为 friend_request_id（request_id 只用于请求与回复的对应）
+UPDATE user_events
+SET payload = replace(payload, '"request_id":', '"friend_request_id":')
+WHERE kind = 'friend_request';
@@ -0,0 +1,1536 @@
+use rusqlite::{params, Connection, OptionalExtension, Row, ToSql};
+use uuid::Uuid;
+use std::path::Path;
+use super::{
+    ids_from_json,
+    ids_to_json,
+    now_secs,
+    reply_thread_root,
+    Attachment,
+    Blob,
+    Conversation,
+    FriendRequest,
+    Friendship,
+    Group,
+    GroupMember,
+    GroupReadCount,
+    HistoryCursor,
+    Message,
+    MessageEdit,
+    MessageExtras,
+    MessageSearch,
+    Migration,
+    NewAttachment,
+    PoolOptions,
+    ReactionCount,
+    Session,
+    Storage,
+    StorageError,
+    StorageResult,
+    Upload,
+    User,
+    UserEvent
+};
+
+pub mod fts;
+pub mod migrations;
+pub mod pool;
+
+use pool::SqliteConnectionManager;
+
+// 用户表查询列（与 user_from_row 对应）
+const USER_COLUMNS: &str = "id, username, email, password_hash, created_at, avatar_url, last_seen";
+
+// 从查询结果行构造用户
+fn user_from_row(row: &Row) -> rusqlite::Result<User> {
+    Ok(User {
+        id: row.get(0)?,
+        username: row.get(1)?,
+        email: row.get(2)?,
+        password_hash: row.get(3)?,
+        created_at: row.get(4)?,
+        avatar_url: row.get(5)?,
+        last_seen: row.get(6)?,
+    })
+}
+
+// 消息表查询列（与 message_from_row 对应）
+const MESSAGE_COLUMNS: &str = "id, sender_id, receiver_id, content, message_type, created_at, is_read, delivered_at, read_at, edited_at, deleted_at, deleted_by, reply_to_id, thread_root_id, reply_count, mentions, mention_all, attachments";
+
+// 从查询结果行构造消息
+fn message_from_row(row: &Row) -> rusqlite::Result<Message> {
+    Ok(Message {
+        id: row.get(0)?,
+        sender_id: row.get(1)?,
+        receiver_id: row.get(2)?,
+        content: row.get(3)?,
+        message_type: row.get(4)?,
+        created_at: row.get(5)?,
+        is_read: row.get(6)?,
+        delivered_at: row.get(7)?,
+        read_at: row.get(8)?,
+        edited_at: row.get(9)?,
+        deleted_at: row.get(10)?,
+        deleted_by: row.get(11)?,
+        reply_to_id: row.get(12)?,
+        thread_root_id: row.get(13)?,
+        reply_count: row.get(14)?,
+        mentions: ids_from_json(&row.get::<_, String>(15)?),
+        mention_all: row.get(16)?,
+        attachments: ids_from_json(&row.get::<_, String>(17)?),
+    })
+}
+
+// 附件表查询列（与 attachment_from_row 对应）
+const ATTACHMENT_COLUMNS: &str = "id, uploader_id, kind, mime_type, size, filename, created_at, sha256, width, height, blurhash";
+
+// 从查询结果行构造附件
+fn attachment_from_row(row: &Row) -> rusqlite::Result<Attachment> {
+    Ok(Attachment {
+        id: row.get(0)?,
+        uploader_id: row.get(1)?,
+        kind: row.get(2)?,
+        mime_type: row.get(3)?,
+        size: row.get(4)?,
+        filename: row.get(5)?,
+        created_at: row.get(6)?,
+        sha256: row.get(7)?,
+        width: row.get(8)?,
+        height: row.get(9)?,
+        blurhash: row.get(10)?,
+    })
+}
+
+// 文件库查询列（与 blob_from_row 对应）
+const BLOB_COLUMNS: &str = "sha256, size, ref_count, created_at, unreferenced_since";
+
+// 从查询结果行构造文件记录
+fn blob_from_row(row: &Row) -> rusqlite::Result<Blob> {
+    Ok(Blob {
+        sha256: row.get(0)?,
+        size: row.get(1)?,
+        ref_count: row.get(2)?,
+        created_at: row.get(3)?,
+        unreferenced_since: row.get(4)?,
+    })
+}
+
+// 增加文件引用（记录不存在时创建）
+fn acquire_blob(conn: &Connection, sha256: &str, size: i64) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO blobs (sha256, size, ref_count, created_at) VALUES (?1, ?2, 1, ?3)
+         ON CONFLICT(sha256) DO UPDATE SET ref_count = blobs.ref_count + 1, unreferenced_since = NULL",
+        params![sha256, size, now_secs()],
+    )?;
+    Ok(())
+}
+
+// 释放文件引用，引用数归零时记下时间供垃圾回收判断宽限期
+fn release_blob(conn: &Connection, sha256: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE blobs SET ref_count = ref_count - 1,
+             unreferenced_since = CASE WHEN ref_count <= 1 THEN ?1 ELSE unreferenced_since END
+         WHERE sha256 = ?2 AND ref_count > 0",
+        params![now_secs(), sha256],
+    )?;
+    Ok(())
+}
+
+// 分块上传表查询列（与 upload_from_row 对应）
+const UPLOAD_COLUMNS: &str = "id, user_id, filename, size, received, sha256, created_at, expires_at";
+
+// 从查询结果行构造分块上传会话
+fn upload_from_row(row: &Row) -> rusqlite::Result<Upload> {
+    Ok(Upload {
+        id: row.get(0)?,
+        user_id: row.get(1)?,
+        filename: row.get(2)?,
+        size: row.get(3)?,
+        received: row.get(4)?,
+        sha256: row.get(5)?,
+        created_at: row.get(6)?,
+        expires_at: row.get(7)?,
+    })
+}
+
+// 排除用户自己删除的消息（需绑定用户ID）
+const NOT_HIDDEN_FILTER: &str = "NOT EXISTS (SELECT 1 FROM message_hidden h WHERE h.user_id = ? AND h.message_id = messages.id)";
+
+// 会话按发送方向拆分的查询条件与参数（私聊两个方向各一条，便于分别走索引）
+fn conversation_branches(conversation: Conversation<'_>) -> Vec<(&'static str, Vec<&str>)> {
+    match conversation {
+        Conversation::Private(a, b) => vec![
+            ("message_type = 'private' AND sender_id = ? AND receiver_id = ?", vec![a, b]),
+            ("message_type = 'private' AND sender_id = ? AND receiver_id = ?", vec![b, a]),
+        ],
+        Conversation::Group(group_id) => vec![("message_type = 'group' AND receiver_id = ?", vec![group_id])],
+        Conversation::Thread(root_id) => vec![("thread_root_id = ?", vec![root_id])],
+    }
+}
+
+/// SQLite 存储后端（r2d2 连接池，WAL 模式）
+pub struct SqliteStorage {
+    pool: r2d2::Pool<SqliteConnectionManager>,
+}
+
+impl SqliteStorage {
+    /// 使用默认参数打开数据库（不执行迁移）
+    pub fn open(db_path: impl AsRef<Path>) -> StorageResult<Self> {
+        Self::open_with(db_path, PoolOptions::default())
+    }
+
+    /// 使用指定参数打开数据库（不执行迁移）
+    pub fn open_with(db_path: impl AsRef<Path>, options: PoolOptions) -> StorageResult<Self> {
+        let manager = SqliteConnectionManager::new(db_path.as_ref(), options.busy_timeout);
+        let pool = r2d2::Pool::builder()
+            .max_size(options.max_size)
+            .min_idle(Some(0))
+            .connection_timeout(options.busy_timeout * 2)
+            .build(manager)?;
+        Ok(Self { pool })
+    }
+
+    /// 打开数据库只用于试运行迁移（见 [`SqliteConnectionManager::dry_run`]）
+    ///
+    /// 只保留一个连接：数据库文件不存在时各操作共用同一个内存数据库。
+    pub fn open_dry_run(db_path: impl AsRef<Path>, options: PoolOptions) -> StorageResult<Self> {
+        let manager = SqliteConnectionManager::dry_run(db_path.as_ref(), options.busy_timeout);
+        let pool = r2d2::Pool::builder()
+            .max_size(1)
+            .min_idle(Some(0))
+            .connection_timeout(options.busy_timeout * 2)
+            .build(manager)?;
+        Ok(Self { pool })
+    }
+
+    // 从连接池取出一个连接
+    fn conn(&self) -> StorageResult<r2d2::PooledConnection<SqliteConnectionManager>> {
+        Ok(self.pool.get()?)
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn backend_name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    fn migrate(&self, dry_run: bool) -> StorageResult<Vec<&'static Migration>> {
+        let mut conn = self.conn()?;
+        Ok(migrations::run_migrations(&mut conn, dry_run)?)
+    }
+
+    fn schema_version(&self) -> StorageResult<i64> {
+        let conn = self.conn()?;
+        Ok(migrations::current_version(&conn)?)
+    }
+
+    fn latest_version(&self) -> i64 {
+        migrations::latest_version()
+    }
+
+    fn register_user(&self, username: &str, password_hash: &str) -> StorageResult<User> {
+        let conn = self.conn()?;
+
+        // 检查用户名是否已存在
+        let exists: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM users WHERE username = ?)",
+            [username],
+            |row| row.get(0),
+        )?;
+        if exists {
+            return Err(StorageError::UsernameTaken);
+        }
+
+        let user_id = Uuid::new_v4().to_string();
+        let created_at = now_secs();
+        // 生成唯一占位邮箱（避免使用空字符串导致 UNIQUE 约束冲突）
+        let email_placeholder = format!("{}@local", user_id);
+
+        conn.execute(
+            "INSERT INTO users (id, username, email, password_hash, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![user_id, username, &email_placeholder, password_hash, created_at],
+        )?;
+
+        Ok(User {
+            id: user_id,
+            username: username.to_string(),
+            email: email_placeholder,
+            password_hash: password_hash.to_string(),
+            created_at,
+            avatar_url: String::new(),
+            last_seen: None,
+        })
+    }
+
+    fn get_user_by_id(&self, user_id: &str) -> StorageResult<User> {
+        let conn = self.conn()?;
+        Ok(conn.query_row(
+            &format!("SELECT {} FROM users WHERE id = ?", USER_COLUMNS),
+            [user_id],
+            user_from_row,
+        )?)
+    }
+
+    fn get_user_by_username(&self, username: &str) -> StorageResult<User> {
+        let conn = self.conn()?;
+        Ok(conn.query_row(
+            &format!("SELECT {} FROM users WHERE username = ?", USER_COLUMNS),
+            [username],
+            user_from_row,
+        )?)
+    }
+
+    fn get_username(&self, user_id: &str) -> StorageResult<String> {
+        let conn = self.conn()?;
+        Ok(conn.query_row(
+            "SELECT username FROM users WHERE id = ?",
+            [user_id],
+            |row| row.get(0),
+        )?)
+    }
+
+    fn user_exists_by_id(&self, user_id: &str) -> StorageResult<bool> {
+        let conn = self.conn()?;
+        Ok(conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM users WHERE id = ?)",
+            [user_id],
+            |row| row.get(0),
+        )?)
+    }
+
+    fn search_users(&self, query: &str) -> StorageResult<Vec<User>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM users WHERE username LIKE ?1 OR id LIKE ?1 LIMIT 10",
+            USER_COLUMNS
+        ))?;
+
+        let search_pattern = format!("%{}%", query);
+        let users = stmt.query_map([&search_pattern], user_from_row)?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(users)
+    }
+
+    fn update_user_avatar(&self, user_id: &str, avatar_url: &str, sha256: &str, size: i64) -> StorageResult<String> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let (old_url, old_sha256): (String, Option<String>) = tx.query_row(
+            "SELECT avatar_url, avatar_sha256 FROM users WHERE id = ?",
+            [user_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        ).optional()?.ok_or(StorageError::NotFound)?;
+        acquire_blob(&tx, sha256, size)?;
+        if let Some(old_sha256) = &old_sha256 {
+            release_blob(&tx, old_sha256)?;
+        }
+        tx.execute(
+            "UPDATE users SET avatar_url = ?, avatar_sha256 = ? WHERE id = ?",
+            params![avatar_url, sha256, user_id],
+        )?;
+        tx.commit()?;
+        Ok(old_url)
+    }
+
+    fn is_avatar_blob(&self, sha256: &str) -> StorageResult<bool> {
+        let conn = self.conn()?;
+        Ok(conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM users WHERE avatar_sha256 = ?)",
+            [sha256],
+            |row| row.get(0),
+        )?)
+    }
+
+    fn update_user_info(&self, user_id: &str, username: &str, email: &str) -> StorageResult<()> {
+        let conn = self.conn()?;
+        conn.execute(
+            "UPDATE users SET username = ?, email = ? WHERE id = ?",
+            params![username, email, user_id],
+        )?;
+        Ok(())
+    }
+
+    fn update_last_seen(&self, user_id: &str, last_seen: i64) -> StorageResult<()> {
+        let conn = self.conn()?;
+        conn.execute("UPDATE users SET last_seen = ? WHERE id = ?", params![last_seen, user_id])?;
+        Ok(())
+    }
+
+    fn send_message(&self, sender_id: &str, receiver_id: &str, content: &str, message_type: &str, reply_to_id: Option<&str>, extras: &MessageExtras) -> StorageResult<Message> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+
+        // UUIDv7 随时间递增，同一秒内的消息按 id 排序即为发送顺序
+        let message_id = Uuid::now_v7().to_string();
+        let created_at = now_secs();
+
+        let thread_root_id = match reply_to_id {
+            Some(parent_id) => {
+                let parent = tx.query_row(
+                    &format!("SELECT {} FROM messages WHERE id = ?", MESSAGE_COLUMNS),
+                    [parent_id],
+                    message_from_row,
+                ).optional()?.ok_or(StorageError::NotFound)?;
+                reply_thread_root(&parent, sender_id, receiver_id, message_type)?
+            }
+            None => None,
+        };
+
+        tx.execute(
+            "INSERT INTO messages (id, sender_id, receiver_id, content, message_type, created_at, is_read, reply_to_id, thread_root_id, mentions, mention_all, attachments)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![
+                message_id, sender_id, receiver_id, content, message_type, created_at, false,
+                reply_to_id, thread_root_id, ids_to_json(&extras.mentions), extras.mention_all,
+                ids_to_json(&extras.attachment_ids),
+            ],
+        )?;
+        for attachment_id in &extras.attachment_ids {
+            tx.execute(
+                "INSERT OR IGNORE INTO message_attachments (message_id, attachment_id) VALUES (?, ?)",
+                params![message_id, attachment_id],
+            )?;
+        }
+        if let Some(root_id) = &thread_root_id {
+            tx.execute("UPDATE messages SET reply_count = reply_count + 1 WHERE id = ?", [root_id])?;
+        }
+        // 提醒表只记录群成员；@全体成员展开为除发送者外的全部成员
+        if message_type == "group" {
+            if extras.mention_all {
+                tx.execute(
+                    "INSERT OR IGNORE INTO message_mentions (message_id, user_id)
+                     SELECT ?, user_id FROM group_members WHERE group_id = ? AND user_id != ?",
+                    params![message_id, receiver_id, sender_id],
+                )?;
+            }
+            let mut stmt = tx.prepare(
+                "INSERT OR IGNORE INTO message_mentions (message_id, user_id)
+                 SELECT ?, user_id FROM group_members WHERE group_id = ? AND user_id = ?"
+            )?;
+            for user_id in &extras.mentions {
+                stmt.execute(params![message_id, receiver_id, user_id])?;
+            }
+        }
+        tx.commit()?;
+
+        Ok(Message {
+            id: message_id,
+            sender_id: sender_id.to_string(),
+            receiver_id: receiver_id.to_string(),
+            content: content.to_string(),
+            message_type: message_type.to_string(),
+            created_at,
+            is_read: false,
+            delivered_at: None,
+            read_at: None,
+            edited_at: None,
+            deleted_at: None,
+            deleted_by: None,
+            reply_to_id: reply_to_id.map(str::to_string),
+            thread_root_id,
+            reply_count: 0,
+            mentions: extras.mentions.clone(),
+            mention_all: extras.mention_all,
+            attachments: extras.attachment_ids.clone(),
+        })
+    }
+
+    fn get_unread_messages(&self, user_id: &str) -> StorageResult<Vec<Message>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM messages
+             WHERE receiver_id = ? AND is_read = 0 AND message_type = 'private' AND deleted_at IS NULL AND {}",
+            MESSAGE_COLUMNS, NOT_HIDDEN_FILTER
+        ))?;
+
+        let messages = stmt.query_map([user_id, user_id], message_from_row)?
+            .collect::<rusqlite::Result<_>>()?;
+
+        Ok(messages)
+    }
+
+    fn get_message_history(&self, viewer_id: &str, conversation: Conversation, cursor: HistoryCursor, limit: usize) -> StorageResult<Vec<Message>> {
+        let conn = self.conn()?;
+        let branches = conversation_branches(conversation);
+
+        // 游标消息的位置；最新一页从最大位置向前取
+        let (created_at, cursor_id, newer) = match cursor {
+            HistoryCursor::Latest => (i64::MAX, "", false),
+            HistoryCursor::Before(id) | HistoryCursor::After(id) => {
+                let filter = branches.iter().map(|(filter, _)| format!("({})", filter)).collect::<Vec<_>>().join(" OR ");
+                let mut args: Vec<&dyn ToSql> = vec![&id];
+                args.extend(branches.iter().flat_map(|(_, args)| args.iter().map(|arg| arg as &dyn ToSql)));
+                let created_at: i64 = conn.query_row(
+                    &format!("SELECT created_at FROM messages WHERE id = ? AND ({})", filter),
+                    args.as_slice(),
+                    |row| row.get(0),
+                ).optional()?.ok_or(StorageError::NotFound)?;
+                (created_at, id, matches!(cursor, HistoryCursor::After(_)))
+            }
+        };
+
+        // 每个方向各取一页再合并；向前翻页时倒序取，最后反转为升序
+        let (comparison, order) = if newer { (">", "ASC") } else { ("<", "DESC") };
+        let limit = limit as i64;
+        let mut selects = Vec::new();
+        let mut args: Vec<&dyn ToSql> = Vec::new();
+        for (filter, branch_args) in &branches {
+            selects.push(format!(
+                "SELECT * FROM (SELECT {} FROM messages
+                  WHERE {} AND {} AND (created_at, id) {} (?, ?)
+                  ORDER BY created_at {order}, id {order} LIMIT ?)",
+                MESSAGE_COLUMNS, filter, NOT_HIDDEN_FILTER, comparison, order = order
+            ));
+            args.extend(branch_args.iter().map(|arg| arg as &dyn ToSql));
+            args.extend([&viewer_id as &dyn ToSql, &created_at, &cursor_id, &limit]);
+        }
+        args.push(&limit);
+
+        let mut stmt = conn.prepare(&format!(
+            "{} ORDER BY created_at {order}, id {order} LIMIT ?",
+            selects.join(" UNION ALL "),
+            order = order
+        ))?;
+        let mut messages = stmt.query_map(args.as_slice(), message_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        if !newer {
+            messages.reverse();
+        }
+        Ok(messages)
+    }
+
+    fn search_messages(&self, search: &MessageSearch) -> StorageResult<Vec<Message>> {
+        let Some(match_query) = fts::match_query(search.query) else {
+            return Ok(Vec::new());
+        };
+        let conn = self.conn()?;
+
+        // 只在搜索者参与的私聊和所在的群中搜索
+        let mut sql = String::from(
+            "rowid IN (SELECT rowid FROM messages_fts WHERE messages_fts MATCH :query)
+             AND ((message_type = 'private' AND (sender_id = :user OR receiver_id = :user))
+               OR (message_type = 'group' AND receiver_id IN (SELECT group_id FROM group_members WHERE user_id = :user)))
+             AND NOT EXISTS (SELECT 1 FROM message_hidden h WHERE h.user_id = :user AND h.message_id = messages.id)"
+        );
+        let mut args: Vec<(&str, &dyn ToSql)> = vec![(":query", &match_query), (":user", &search.user_id)];
+
+        if let Some(sender_id) = &search.sender_id {
+            sql.push_str(" AND sender_id = :sender");
+            args.push((":sender", sender_id));
+        }
+        match &search.conversation {
+            Some(Conversation::Private(a, b)) => {
+                sql.push_str(
+                    " AND message_type = 'private'
+                      AND ((sender_id = :peer_a AND receiver_id = :peer_b) OR (sender_id = :peer_b AND receiver_id = :peer_a))"
+                );
+                args.extend([(":peer_a", a as &dyn ToSql), (":peer_b", b)]);
+            }
+            Some(Conversation::Group(group_id)) => {
+                sql.push_str(" AND message_type = 'group' AND receiver_id = :group");
+                args.push((":group", group_id));
+            }
+            Some(Conversation::Thread(root_id)) => {
+                sql.push_str(" AND thread_root_id = :thread");
+                args.push((":thread", root_id));
+            }
+            None => {}
+        }
+        if let Some(since) = &search.since {
+            sql.push_str(" AND created_at >= :since");
+            args.push((":since", since));
+        }
+        if let Some(until) = &search.until {
+            sql.push_str(" AND created_at <= :until");
+            args.push((":until", until));
+        }
+        let before_at: i64;
+        if let Some(before) = &search.before {
+            before_at = conn.query_row(
+                "SELECT created_at FROM messages WHERE id = ?",
+                [before],
+                |row| row.get(0),
+            ).optional()?.ok_or(StorageError::NotFound)?;
+            sql.push_str(" AND (created_at, id) < (:before_at, :before_id)");
+            args.extend([(":before_at", &before_at as &dyn ToSql), (":before_id", before)]);
+        }
+        let limit = search.limit as i64;
+        args.push((":limit", &limit));
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM messages WHERE {} ORDER BY created_at DESC, id DESC LIMIT :limit",
+            MESSAGE_COLUMNS, sql
+        ))?;
+        let messages = stmt.query_map(args.as_slice(), message_from_row)?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(messages)
+    }
+
+    fn get_message(&self, message_id: &str) -> StorageResult<Message> {
+        let conn = self.conn()?;
+        conn.query_row(
+            &format!("SELECT {} FROM messages WHERE id = ?", MESSAGE_COLUMNS),
+            [message_id],
+            message_from_row,
+        ).optional()?.ok_or(StorageError::NotFound)
+    }
+
+    fn get_visible_messages(&self, viewer_id: &str, message_ids: &[String]) -> StorageResult<Vec<Message>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM messages WHERE id = ? AND {}",
+            MESSAGE_COLUMNS, NOT_HIDDEN_FILTER
+        ))?;
+        let mut messages = Vec::with_capacity(message_ids.len());
+        for message_id in message_ids {
+            if let Some(message) = stmt.query_row(params![message_id, viewer_id], message_from_row).optional()? {
+                messages.push(message);
+            }
+        }
+        Ok(messages)
+    }
+
+    fn edit_message(&self, message_id: &str, content: &str) -> StorageResult<Message> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let now = now_secs();
+
+        let previous: String = tx.query_row(
+            "SELECT content FROM messages WHERE id = ? AND deleted_at IS NULL",
+            [message_id],
+            |row| row.get(0),
+        ).optional()?.ok_or(StorageError::NotFound)?;
+        tx.execute(
+            "INSERT INTO message_edits (id, message_id, content, edited_at) VALUES (?1, ?2, ?3, ?4)",
+            params![Uuid::now_v7().to_string(), message_id, previous, now],
+        )?;
+        let message = tx.query_row(
+            &format!("UPDATE messages SET content = ?, edited_at = ? WHERE id = ? RETURNING {}", MESSAGE_COLUMNS),
+            params![content, now, message_id],
+            message_from_row,
+        )?;
+
+        tx.commit()?;
+        Ok(message)
+    }
+
+    fn get_message_edits(&self, message_id: &str) -> StorageResult<Vec<MessageEdit>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, message_id, content, edited_at FROM message_edits WHERE message_id = ? ORDER BY id"
+        )?;
+        let edits = stmt.query_map([message_id], |row| {
+            Ok(MessageEdit {
+                id: row.get(0)?,
+                message_id: row.get(1)?,
+                content: row.get(2)?,
+                edited_at: row.get(3)?,
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+        Ok(edits)
+    }
+
+    fn recall_message(&self, message_id: &str, deleted_by: &str) -> StorageResult<Message> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+
+        let message = tx.query_row(
+            &format!(
+                "UPDATE messages SET content = '', attachments = '[]', deleted_at = ?, deleted_by = ?
+                 WHERE id = ? AND deleted_at IS NULL
+                 RETURNING {}",
+                MESSAGE_COLUMNS
+            ),
+            params![now_secs(), deleted_by, message_id],
+            message_from_row,
+        ).optional()?.ok_or(StorageError::NotFound)?;
+        tx.execute("DELETE FROM message_edits WHERE message_id = ?", [message_id])?;
+        tx.execute("DELETE FROM message_attachments WHERE message_id = ?", [message_id])?;
+        tx.execute("DELETE FROM message_reactions WHERE message_id = ?", [message_id])?;
+        // 事件流中保存的帧含有原文，一并清除（同步时按墓碑重建）
+        tx.execute("UPDATE user_events SET payload = '{}' WHERE message_id = ?", [message_id])?;
+
+        tx.commit()?;
+        Ok(message)
+    }
+
+    fn hide_message(&self, message_id: &str, user_id: &str) -> StorageResult<bool> {
+        let conn = self.conn()?;
+        let inserted = conn.execute(
+            "INSERT OR IGNORE INTO message_hidden (user_id, message_id, hidden_at) VALUES (?, ?, ?)",
+            params![user_id, message_id, now_secs()],
+        )?;
+        Ok(inserted > 0)
+    }
+
+    fn add_reaction(&self, message_id: &str, user_id: &str, emoji: &str) -> StorageResult<bool> {
+        let conn = self.conn()?;
+        let inserted = conn.execute(
+            "INSERT OR IGNORE INTO message_reactions (message_id, user_id, emoji, created_at) VALUES (?, ?, ?, ?)",
+            params![message_id, user_id, emoji, now_secs()],
+        )?;
+        Ok(inserted > 0)
+    }
+
+    fn remove_reaction(&self, message_id: &str, user_id: &str, emoji: &str) -> StorageResult<bool> {
+        let conn = self.conn()?;
+        let removed = conn.execute(
+            "DELETE FROM message_reactions WHERE message_id = ? AND user_id = ? AND emoji = ?",
+            params![message_id, user_id, emoji],
+        )?;
+        Ok(removed > 0)
+    }
+
+    fn get_reactions(&self, viewer_id: &str, message_ids: &[String]) -> StorageResult<Vec<ReactionCount>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT message_id, emoji, COUNT(*), MAX(user_id = ?1) FROM message_reactions
+             WHERE message_id = ?2
+             GROUP BY emoji
+             ORDER BY MIN(created_at), emoji",
+        )?;
+        let mut reactions = Vec::new();
+        for message_id in message_ids {
+            let rows = stmt.query_map(params![viewer_id, message_id], |row| {
+                Ok(ReactionCount {
+                    message_id: row.get(0)?,
+                    emoji: row.get(1)?,
+                    count: row.get(2)?,
+                    reacted: row.get(3)?,
+                })
+            })?;
+            for reaction in rows {
+                reactions.push(reaction?);
+            }
+        }
+        Ok(reactions)
+    }
+
+    fn get_mentions(&self, user_id: &str, before: Option<&str>, limit: usize) -> StorageResult<Vec<Message>> {
+        let conn = self.conn()?;
+        // 只看仍在群中的提醒
+        let mut sql = format!(
+            "SELECT {} FROM messages
+             WHERE id IN (SELECT message_id FROM message_mentions WHERE user_id = :user)
+               AND message_type = 'group' AND deleted_at IS NULL
+               AND receiver_id IN (SELECT group_id FROM group_members WHERE user_id = :user)
+               AND NOT EXISTS (SELECT 1 FROM message_hidden h WHERE h.user_id = :user AND h.message_id = messages.id)",
+            MESSAGE_COLUMNS
+        );
+        let mut args: Vec<(&str, &dyn ToSql)> = vec![(":user", &user_id)];
+        let before_at: i64;
+        if let Some(before) = &before {
+            before_at = conn.query_row(
+                "SELECT created_at FROM messages WHERE id = ?",
+                [before],
+                |row| row.get(0),
+            ).optional()?.ok_or(StorageError::NotFound)?;
+            sql.push_str(" AND (created_at, id) < (:before_at, :before_id)");
+            args.extend([(":before_at", &before_at as &dyn ToSql), (":before_id", before)]);
+        }
+        let limit = limit as i64;
+        args.push((":limit", &limit));
+        sql.push_str(" ORDER BY created_at DESC, id DESC LIMIT :limit");
+
+        let mut stmt = conn.prepare(&sql)?;
+        let messages = stmt.query_map(args.as_slice(), message_from_row)?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(messages)
+    }
+
+    fn mark_messages_delivered(&self, receiver_id: &str, message_ids: &[String]) -> StorageResult<Vec<Message>> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let now = now_secs();
+        let mut updated = Vec::new();
+        {
+            let mut stmt = tx.prepare(&format!(
+                "UPDATE messages SET delivered_at = ?
+                 WHERE id = ? AND receiver_id = ? AND message_type = 'private' AND delivered_at IS NULL
+                 RETURNING {}",
+                MESSAGE_COLUMNS
+            ))?;
+            for message_id in message_ids {
+                if let Some(message) = stmt.query_row(params![now, message_id, receiver_id], message_from_row).optional()? {
+                    updated.push(message);
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(updated)
+    }
+
+    fn mark_messages_as_read(&self, receiver_id: &str, message_ids: &[String]) -> StorageResult<Vec<Message>> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let now = now_secs();
+        let mut updated = Vec::new();
+        {
+            let mut stmt = tx.prepare(&format!(
+                "UPDATE messages SET is_read = 1, read_at = ?1, delivered_at = COALESCE(delivered_at, ?1)
+                 WHERE id = ?2 AND receiver_id = ?3 AND message_type = 'private' AND read_at IS NULL
+                 RETURNING {}",
+                MESSAGE_COLUMNS
+            ))?;
+            for message_id in message_ids {
+                if let Some(message) = stmt.query_row(params![now, message_id, receiver_id], message_from_row).optional()? {
+                    updated.push(message);
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(updated)
+    }
+
+    fn advance_group_read_cursor(&self, group_id: &str, user_id: &str, message_id: &str) -> StorageResult<bool> {
+        let conn = self.conn()?;
+        let created_at: i64 = conn.query_row(
+            "SELECT created_at FROM messages WHERE id = ? AND receiver_id = ? AND message_type = 'group'",
+            params![message_id, group_id],
+            |row| row.get(0),
+        ).optional()?.ok_or(StorageError::NotFound)?;
+
+        // 只向前推进：已有位置不早于该消息时不更新
+        let changed = conn.execute(
+            "INSERT INTO group_read_cursors (group_id, user_id, message_id, message_created_at, read_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(group_id, user_id) DO UPDATE SET
+                 message_id = excluded.message_id,
+                 message_created_at = excluded.message_created_at,
+                 read_at = excluded.read_at
+             WHERE (group_read_cursors.message_created_at, group_read_cursors.message_id)
+                 < (excluded.message_created_at, excluded.message_id)",
+            params![group_id, user_id, message_id, created_at, now_secs()],
+        )?;
+        Ok(changed > 0)
+    }
+
+    fn count_group_readers(&self, message: &Message) -> StorageResult<GroupReadCount> {
+        let conn = self.conn()?;
+        Ok(conn.query_row(
+            "SELECT COUNT(*),
+                    COUNT(*) FILTER (WHERE (c.message_created_at, c.message_id) >= (?3, ?4))
+             FROM group_members gm
+             LEFT JOIN group_read_cursors c ON c.group_id = gm.group_id AND c.user_id = gm.user_id
+             WHERE gm.group_id = ?1 AND gm.user_id != ?2",
+            params![message.receiver_id, message.sender_id, message.created_at, message.id],
+            |row| Ok(GroupReadCount { total: row.get(0)?, read: row.get(1)? }),
+        )?)
+    }
+
+    fn create_attachment(&self, uploader_id: &str, new: &NewAttachment) -> StorageResult<Attachment> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let attachment = Attachment {
+            id: Uuid::new_v4().to_string(),
+            uploader_id: uploader_id.to_string(),
+            kind: new.kind.clone(),
+            mime_type: new.mime_type.clone(),
+            size: new.size,
+            filename: new.filename.clone(),
+            created_at: now_secs(),
+            sha256: Some(new.sha256.clone()),
+            width: new.image.as_ref().map(|image| image.width),
+            height: new.image.as_ref().map(|image| image.height),
+            blurhash: new.image.as_ref().map(|image| image.blurhash.clone()),
+        };
+        acquire_blob(&tx, &new.sha256, new.size)?;
+        tx.execute(
+            &format!("INSERT INTO attachments ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)", ATTACHMENT_COLUMNS),
+            params![
+                attachment.id, attachment.uploader_id, attachment.kind, attachment.mime_type,
+                attachment.size, attachment.filename, attachment.created_at, attachment.sha256,
+                attachment.width, attachment.height, attachment.blurhash,
+            ],
+        )?;
+        tx.commit()?;
+        Ok(attachment)
+    }
+
+    fn get_attachment(&self, attachment_id: &str) -> StorageResult<Attachment> {
+        let conn = self.conn()?;
+        conn.query_row(
+            &format!("SELECT {} FROM attachments WHERE id = ?", ATTACHMENT_COLUMNS),
+            [attachment_id],
+            attachment_from_row,
+        ).optional()?.ok_or(StorageError::NotFound)
+    }
+
+    fn can_access_attachment(&self, user_id: &str, attachment_id: &str) -> StorageResult<bool> {
+        let conn = self.conn()?;
+        Ok(conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM attachments WHERE id = ?1 AND uploader_id = ?2)
+                 OR EXISTS(
+                    SELECT 1 FROM message_attachments a JOIN messages m ON m.id = a.message_id
+                    WHERE a.attachment_id = ?1
+                      AND ((m.message_type = 'private' AND (m.sender_id = ?2 OR m.receiver_id = ?2))
+                        OR (m.message_type = 'group' AND m.receiver_id IN (SELECT group_id FROM group_members WHERE user_id = ?2)))
+                 )",
+            params![attachment_id, user_id],
+            |row| row.get(0),
+        )?)
+    }
+
+    fn delete_orphan_attachments(&self, created_before: i64) -> StorageResult<Vec<Attachment>> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let removed = {
+            let mut stmt = tx.prepare(&format!(
+                "DELETE FROM attachments
+                 WHERE created_at < ?
+                   AND NOT EXISTS (SELECT 1 FROM message_attachments a WHERE a.attachment_id = attachments.id)
+                 RETURNING {}",
+                ATTACHMENT_COLUMNS
+            ))?;
+            stmt.query_map([created_before], attachment_from_row)?.collect::<Result<Vec<_>, _>>()?
+        };
+        for sha256 in removed.iter().filter_map(|a| a.sha256.as_deref()) {
+            release_blob(&tx, sha256)?;
+        }
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    fn register_blob(&self, sha256: &str, size: i64) -> StorageResult<()> {
+        let conn = self.conn()?;
+        let now = now_secs();
+        conn.execute(
+            "INSERT INTO blobs (sha256, size, ref_count, created_at, unreferenced_since) VALUES (?1, ?2, 0, ?3, ?3)
+             ON CONFLICT(sha256) DO NOTHING",
+            params![sha256, size, now],
+        )?;
+        Ok(())
+    }
+
+    fn get_blob(&self, sha256: &str) -> StorageResult<Blob> {
+        let conn = self.conn()?;
+        conn.query_row(
+            &format!("SELECT {} FROM blobs WHERE sha256 = ?", BLOB_COLUMNS),
+            [sha256],
+            blob_from_row,
+        ).optional()?.ok_or(StorageError::NotFound)
+    }
+
+    fn list_unreferenced_blobs(&self, unreferenced_before: i64) -> StorageResult<Vec<Blob>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM blobs WHERE ref_count = 0 AND unreferenced_since < ?",
+            BLOB_COLUMNS
+        ))?;
+        let blobs = stmt.query_map([unreferenced_before], blob_from_row)?.collect::<Result<Vec<_>, _>>()?;
+        Ok(blobs)
+    }
+
+    fn delete_unreferenced_blob(&self, sha256: &str) -> StorageResult<bool> {
+        let conn = self.conn()?;
+        let deleted = conn.execute("DELETE FROM blobs WHERE sha256 = ? AND ref_count = 0", [sha256])?;
+        Ok(deleted > 0)
+    }
+
+    fn create_upload(&self, user_id: &str, filename: &str, size: i64, sha256: Option<&str>, expires_at: i64) -> StorageResult<Upload> {
+        let conn = self.conn()?;
+        let upload = Upload {
+            id: Uuid::new_v4().to_string(),
+            user_id: user_id.to_string(),
+            filename: filename.to_string(),
+            size,
+            received: 0,
+            sha256: sha256.map(str::to_string),
+            created_at: now_secs(),
+            expires_at,
+        };
+        conn.execute(
+            &format!("INSERT INTO uploads ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)", UPLOAD_COLUMNS),
+            params![
+                upload.id, upload.user_id, upload.filename, upload.size,
+                upload.received, upload.sha256, upload.created_at, upload.expires_at,
+            ],
+        )?;
+        Ok(upload)
+    }
+
+    fn get_upload(&self, upload_id: &str) -> StorageResult<Upload> {
+        let conn = self.conn()?;
+        conn.query_row(
+            &format!("SELECT {} FROM uploads WHERE id = ?", UPLOAD_COLUMNS),
+            [upload_id],
+            upload_from_row,
+        ).optional()?.ok_or(StorageError::NotFound)
+    }
+
+    fn advance_upload(&self, upload_id: &str, from: i64, to: i64, expires_at: i64) -> StorageResult<bool> {
+        let conn = self.conn()?;
+        let updated = conn.execute(
+            "UPDATE uploads SET received = ?1, expires_at = ?2 WHERE id = ?3 AND received = ?4 AND ?1 <= size",
+            params![to, expires_at, upload_id, from],
+        )?;
+        Ok(updated > 0)
+    }
+
+    fn delete_upload(&self, upload_id: &str) -> StorageResult<bool> {
+        let conn = self.conn()?;
+        Ok(conn.execute("DELETE FROM uploads WHERE id = ?", [upload_id])? > 0)
+    }
+
+    fn delete_expired_uploads(&self, now: i64) -> StorageResult<Vec<String>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare("DELETE FROM uploads WHERE expires_at < ? RETURNING id")?;
+        let ids = stmt.query_map([now], |row| row.get(0))?.collect::<Result<Vec<String>, _>>()?;
+        Ok(ids)
+    }
+
+    fn are_friends(&self, user_id: &str, friend_id: &str) -> StorageResult<bool> {
+        let conn = self.conn()?;
+        Ok(conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM friendships WHERE user_id = ? AND friend_id = ? AND status = 'accepted')",
+            params![user_id, friend_id],
+            |row| row.get(0),
+        )?)
+    }
+
+    fn get_friends(&self, user_id: &str) -> StorageResult<Vec<User>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT u.id, u.username, u.email, u.password_hash, u.created_at, u.avatar_url, u.last_seen
+             FROM users u
+             JOIN friendships f ON u.id = f.friend_id
+             WHERE f.user_id = ? AND f.status = 'accepted'"
+        )?;
+
+        let friends = stmt.query_map([user_id], user_from_row)?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(friends)
+    }
+
+    fn send_friend_request(&self, from_user_id: &str, to_username: &str) -> StorageResult<FriendRequest> {
+        let conn = self.conn()?;
+
+        // 检查目标用户是否存在
+        let to_user_id: String = conn.query_row(
+            "SELECT id FROM users WHERE username = ?",
+            [to_username],
+            |row| row.get(0),
+        )?;
+
+        // 检查是否已经是好友
+        let is_friend: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM friendships WHERE user_id = ? AND friend_id = ? AND status = 'accepted')",
+            params![from_user_id, to_user_id],
+            |row| row.get(0),
+        )?;
+        if is_friend {
+            return Err(StorageError::AlreadyFriends);
+        }
+
+        // 检查是否已经发送过请求
+        let has_pending_request: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM friend_requests WHERE from_user_id = ? AND to_user_id = ? AND status = 'pending')",
+            params![from_user_id, to_user_id],
+            |row| row.get(0),
+        )?;
+        if has_pending_request {
+            return Err(StorageError::FriendRequestAlreadySent);
+        }
+
+        let request_id = Uuid::new_v4().to_string();
+        let created_at = now_secs();
+
+        conn.execute(
+            "INSERT INTO friend_requests (id, from_user_id, to_user_id, status, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![request_id, from_user_id, to_user_id, "pending", created_at],
+        )?;
+
+        Ok(FriendRequest {
+            id: request_id,
+            from_user_id: from_user_id.to_string(),
+            to_user_id,
+            status: "pending".to_string(),
+            created_at,
+        })
+    }
+
+    fn get_received_friend_requests(&self, user_id: &str) -> StorageResult<Vec<FriendRequest>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT fr.id, fr.from_user_id, fr.to_user_id, fr.status, fr.created_at
+             FROM friend_requests fr
+             WHERE fr.to_user_id = ? AND fr.status = 'pending'"
+        )?;
+
+        let requests = stmt.query_map([user_id], |row| {
+            Ok(FriendRequest {
+                id: row.get(0)?,
+                from_user_id: row.get(1)?,
+                to_user_id: row.get(2)?,
+                status: row.get(3)?,
+                created_at: row.get(4)?,
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+
+        Ok(requests)
+    }
+
+    fn respond_to_friend_request(&self, request_id: &str, responder_id: &str, response: &str) -> StorageResult<Friendship> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+
+        // 验证请求存在且由该接收方(responder)处理
+        let (from_user_id, current_status): (String, String) = tx.query_row(
+            "SELECT from_user_id, status FROM friend_requests WHERE id = ? AND to_user_id = ?",
+            params![request_id, responder_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+
+        if current_status != "pending" {
+            return Err(StorageError::FriendRequestAlreadyProcessed);
+        }
+
+        tx.execute(
+            "UPDATE friend_requests SET status = ? WHERE id = ?",
+            params![response, request_id],
+        )?;
+
+        let friendship = if response == "accepted" {
+            // 创建双向好友关系（from_user_id <-> responder_id）
+            let friendship_id = Uuid::new_v4().to_string();
+            let created_at = now_secs();
+
+            tx.execute(
+                "INSERT INTO friendships (id, user_id, friend_id, status, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![friendship_id, from_user_id, responder_id, "accepted", created_at],
+            )?;
+            tx.execute(
+                "INSERT INTO friendships (id, user_id, friend_id, status, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![Uuid::new_v4().to_string(), responder_id, from_user_id, "accepted", created_at],
+            )?;
+
+            Friendship {
+                id: friendship_id,
+                user_id: from_user_id,
+                friend_id: responder_id.to_string(),
+                status: "accepted".to_string(),
+                created_at,
+            }
+        } else {
+            // 拒绝请求，只更新状态，不创建好友关系
+            Friendship {
+                id: request_id.to_string(),
+                user_id: from_user_id,
+                friend_id: responder_id.to_string(),
+                status: "rejected".to_string(),
+                created_at: 0,
+            }
+        };
+
+        tx.commit()?;
+        Ok(friendship)
+    }
+
+    fn remove_friend(&self, user_id: &str, friend_id: &str) -> StorageResult<()> {
+        let conn = self.conn()?;
+        conn.execute(
+            "DELETE FROM friendships WHERE (user_id = ?1 AND friend_id = ?2) OR (user_id = ?2 AND friend_id = ?1)",
+            params![user_id, friend_id],
+        )?;
+        Ok(())
+    }
+
+    fn create_group(&self, creator_id: &str, name: &str, member_ids: &[String]) -> StorageResult<Group> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+
+        let group = Group {
+            id: Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            creator_id: creator_id.to_string(),
+            created_at: now_secs(),
+        };
+        tx.execute(
+            "INSERT INTO groups (id, name, creator_id, created_at) VALUES (?1, ?2, ?3, ?4)",
+            params![group.id, group.name, group.creator_id, group.created_at],
+        )?;
+
+        // 群主在前，重复的成员ID只插入一次
+        let members = std::iter::once((creator_id, "owner"))
+            .chain(member_ids.iter().map(|id| (id.as_str(), "member")));
+        for (user_id, role) in members {
+            tx.execute(
+                "INSERT OR IGNORE INTO group_members (id, group_id, user_id, joined_at, role)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![Uuid::new_v4().to_string(), group.id, user_id, group.created_at, role],
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(group)
+    }
+
+    fn get_group(&self, group_id: &str) -> StorageResult<Group> {
+        let conn = self.conn()?;
+        Ok(conn.query_row(
+            "SELECT id, name, creator_id, created_at FROM groups WHERE id = ?",
+            [group_id],
+            |row| Ok(Group {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                creator_id: row.get(2)?,
+                created_at: row.get(3)?,
+            }),
+        )?)
+    }
+
+    fn get_user_groups(&self, user_id: &str) -> StorageResult<Vec<(Group, GroupMember)>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT g.id, g.name, g.creator_id, g.created_at, m.id, m.joined_at, m.role, m.muted
+             FROM groups g
+             JOIN group_members m ON m.group_id = g.id
+             WHERE m.user_id = ?
+             ORDER BY m.joined_at"
+        )?;
+        let groups = stmt.query_map([user_id], |row| {
+            let group = Group {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                creator_id: row.get(2)?,
+                created_at: row.get(3)?,
+            };
+            let member = GroupMember {
+                id: row.get(4)?,
+                group_id: group.id.clone(),
+                user_id: user_id.to_string(),
+                joined_at: row.get(5)?,
+                role: row.get(6)?,
+                muted: row.get(7)?,
+            };
+            Ok((group, member))
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+        Ok(groups)
+    }
+
+    fn get_group_members(&self, group_id: &str) -> StorageResult<Vec<GroupMember>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, group_id, user_id, joined_at, role, muted FROM group_members
+             WHERE group_id = ? ORDER BY joined_at, role DESC, user_id"
+        )?;
+        let members = stmt.query_map([group_id], |row| {
+            Ok(GroupMember {
+                id: row.get(0)?,
+                group_id: row.get(1)?,
+                user_id: row.get(2)?,
+                joined_at: row.get(3)?,
+                role: row.get(4)?,
+                muted: row.get(5)?,
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+        Ok(members)
+    }
+
+    fn get_member_role(&self, group_id: &str, user_id: &str) -> StorageResult<Option<String>> {
+        let conn = self.conn()?;
+        Ok(conn.query_row(
+            "SELECT role FROM group_members WHERE group_id = ? AND user_id = ?",
+            params![group_id, user_id],
+            |row| row.get(0),
+        ).optional()?)
+    }
+
+    fn add_group_member(&self, group_id: &str, user_id: &str, role: &str) -> StorageResult<GroupMember> {
+        let conn = self.conn()?;
+        let member = GroupMember {
+            id: Uuid::new_v4().to_string(),
+            group_id: group_id.to_string(),
+            user_id: user_id.to_string(),
+            joined_at: now_secs(),
+            role: role.to_string(),
+            muted: false,
+        };
+        conn.execute(
+            "INSERT INTO group_members (id, group_id, user_id, joined_at, role) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![member.id, member.group_id, member.user_id, member.joined_at, member.role],
+        )?;
+        Ok(member)
+    }
+
+    fn remove_group_member(&self, group_id: &str, user_id: &str) -> StorageResult<bool> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let removed = tx.execute(
+            "DELETE FROM group_members WHERE group_id = ? AND user_id = ?",
+            params![group_id, user_id],
+        )?;
+        tx.execute(
+            "DELETE FROM group_read_cursors WHERE group_id = ? AND user_id = ?",
+            params![group_id, user_id],
+        )?;
+        tx.commit()?;
+        Ok(removed > 0)
+    }
+
+    fn set_member_role(&self, group_id: &str, user_id: &str, role: &str) -> StorageResult<()> {
+        let conn = self.conn()?;
+        let updated = conn.execute(
+            "UPDATE group_members SET role = ? WHERE group_id = ? AND user_id = ? AND role <> 'owner'",
+            params![role, group_id, user_id],
+        )?;
+        if updated == 0 {
+            return Err(StorageError::NotFound);
+        }
+        Ok(())
+    }
+
+    fn set_group_muted(&self, group_id: &str, user_id: &str, muted: bool) -> StorageResult<()> {
+        let conn = self.conn()?;
+        let updated = conn.execute(
+            "UPDATE group_members SET muted = ? WHERE group_id = ? AND user_id = ?",
+            params![muted, group_id, user_id],
+        )?;
+        if updated == 0 {
+            return Err(StorageError::NotFound);
+        }
+        Ok(())
+    }
+
+    fn rename_group(&self, group_id: &str, name: &str) -> StorageResult<()> {
+        let conn = self.conn()?;
+        conn.execute("UPDATE groups SET name = ? WHERE id = ?", params![name, group_id])?;
+        Ok(())
+    }
+
+    fn delete_group(&self, group_id: &str) -> StorageResult<()> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM group_members WHERE group_id = ?", [group_id])?;
+        tx.execute("DELETE FROM messages WHERE message_type = 'group' AND receiver_id = ?", [group_id])?;
+        tx.execute("DELETE FROM groups WHERE id = ?", [group_id])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn group_exists(&self, group_id: &str) -> StorageResult<bool> {
+        let conn = self.conn()?;
+        Ok(conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM groups WHERE id = ?)",
+            [group_id],
+            |row| row.get(0),
+        )?)
+    }
+
+    fn is_group_member(&self, group_id: &str, user_id: &str) -> StorageResult<bool> {
+        let conn = self.conn()?;
+        Ok(conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM group_members WHERE group_id = ? AND user_id = ?)",
+            params![group_id, user_id],
+            |row| row.get(0),
+        )?)
+    }
+
+    fn get_user_group_ids(&self, user_id: &str) -> StorageResult<Vec<String>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare("SELECT group_id FROM group_members WHERE user_id = ?")?;
+        let group_ids = stmt.query_map([user_id], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(group_ids)
+    }
+
+    fn get_or_create_token_secret(&self, candidate: &str) -> StorageResult<String> {
+        let conn = self.conn()?;
+        // 并发启动时只有第一个写入生效，其余实例读取已保存的密钥
+        conn.execute(
+            "INSERT OR IGNORE INTO server_keys (name, value) VALUES ('token_secret', ?)",
+            [candidate],
+        )?;
+        Ok(conn.query_row(
+            "SELECT value FROM server_keys WHERE name = 'token_secret'",
+            [],
+            |row| row.get(0),
+        )?)
+    }
+
+    fn create_session(&self, user_id: &str, refresh_token_hash: &str, refresh_expires_at: i64, device_name: Option<&str>) -> StorageResult<Session> {
+        let conn = self.conn()?;
+
+        let session_id = Uuid::new_v4().to_string();
+        let created_at = now_secs();
+
+        conn.execute(
+            "INSERT INTO sessions (id, user_id, refresh_token_hash, created_at, refresh_expires_at, last_used_at, device_name)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![session_id, user_id, refresh_token_hash, created_at, refresh_expires_at, created_at, device_name],
+        )?;
+
+        Ok(Session {
+            id: session_id,
+            user_id: user_id.to_string(),
+            created_at,
+            refresh_expires_at,
+            last_used_at: created_at,
+            device_name: device_name.map(str::to_string),
+        })
+    }
+
+    fn rotate_refresh_token(&self, old_token_hash: &str, new_token_hash: &str, refresh_expires_at: i64) -> StorageResult<Option<Session>> {
+        let conn = self.conn()?;
+        let now = now_secs();
+
+        let session = conn.query_row(
+            "SELECT id, user_id, created_at, device_name FROM sessions
+             WHERE refresh_token_hash = ? AND revoked = 0 AND refresh_expires_at > ?",
+            params![old_token_hash, now],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, i64>(2)?, row.get::<_, Option<String>>(3)?)),
+        ).optional()?;
+
+        let Some((id, user_id, created_at, device_name)) = session else {
+            return Ok(None);
+        };
+
+        // 以旧哈希为条件更新，并发使用同一刷新令牌时只有一个请求成功
+        let updated = conn.execute(
+            "UPDATE sessions SET refresh_token_hash = ?, refresh_expires_at = ?, last_used_at = ?
+             WHERE id = ? AND refresh_token_hash = ?",
+            params![new_token_hash, refresh_expires_at, now, id, old_token_hash],
+        )?;
+        if updated == 0 {
+            return Ok(None);
+        }
+
+        Ok(Some(Session {
+            id,
+            user_id,
+            created_at,
+            refresh_expires_at,
+            last_used_at: now,
+            device_name,
+        }))
+    }
+
+    fn is_session_active(&self, session_id: &str, user_id: &str) -> StorageResult<bool> {
+        let conn = self.conn()?;
+        Ok(conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sessions WHERE id = ? AND user_id = ? AND revoked = 0 AND refresh_expires_at > ?)",
+            params![session_id, user_id, now_secs()],
+            |row| row.get(0),
+        )?)
+    }
+
+    fn get_active_sessions(&self, user_id: &str) -> StorageResult<Vec<Session>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, user_id, created_at, refresh_expires_at, last_used_at, device_name FROM sessions
+             WHERE user_id = ? AND revoked = 0 AND refresh_expires_at > ?
+             ORDER BY last_used_at DESC, created_at DESC",
+        )?;
+
+        let sessions = stmt
+            .query_map(params![user_id, now_secs()], |row| {
+                Ok(Session {
+                    id: row.get(0)?,
+                    user_id: row.get(1)?,
+                    created_at: row.get(2)?,
+                    refresh_expires_at: row.get(3)?,
+                    last_used_at: row.get(4)?,
+                    device_name: row.get(5)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(sessions)
+    }
+
+    fn revoke_session(&self, session_id: &str) -> StorageResult<()> {
+        let conn = self.conn()?;
+        conn.execute(
+            "UPDATE sessions SET revoked = 1 WHERE id = ?",
+            [session_id],
+        )?;
+        Ok(())
+    }
+
+    fn append_user_events(&self, user_ids: &[String], kind: &str, payload: &str, message_id: Option<&str>) -> StorageResult<Vec<UserEvent>> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let created_at = now_secs();
+
+        let mut user_ids: Vec<&str> = user_ids.iter().map(String::as_str).collect();
+        user_ids.sort_unstable();
+        user_ids.dedup();
+
+        let mut events = Vec::with_capacity(user_ids.len());
+        {
+            let mut next_seq = tx.prepare(
+                "INSERT INTO user_event_sequences (user_id, last_seq) VALUES (?, 1)
+                 ON CONFLICT(user_id) DO UPDATE SET last_seq = last_seq + 1
+                 RETURNING last_seq",
+            )?;
+            let mut insert = tx.prepare(
+                "INSERT INTO user_events (user_id, seq, kind, payload, message_id, created_at) VALUES (?, ?, ?, ?, ?, ?)",
+            )?;
+            for user_id in user_ids {
+                let seq: i64 = next_seq.query_row([user_id], |row| row.get(0))?;
+                insert.execute(params![user_id, seq, kind, payload, message_id, created_at])?;
+                events.push(UserEvent {
+                    user_id: user_id.to_string(),
+                    seq,
+                    kind: kind.to_string(),
+                    payload: payload.to_string(),
+                    message_id: message_id.map(str::to_string),
+                    created_at,
+                });
+            }
+        }
+        tx.commit()?;
+        Ok(events)
+    }
+
+    fn get_user_events(&self, user_id: &str, after_seq: i64, limit: usize) -> StorageResult<Vec<UserEvent>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT user_id, seq, kind, payload, message_id, created_at FROM user_events
+             WHERE user_id = ? AND seq > ?
+             ORDER BY seq
+             LIMIT ?",
+        )?;
+
+        let events = stmt
+            .query_map(params![user_id, after_seq, limit as i64], |row| {
+                Ok(UserEvent {
+                    user_id: row.get(0)?,
+                    seq: row.get(1)?,
+                    kind: row.get(2)?,
+                    payload: row.get(3)?,
+                    message_id: row.get(4)?,
+                    created_at: row.get(5)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(events)
+    }
+
+    fn get_sync_cursor(&self, session_id: &str) -> StorageResult<i64> {
+        let conn = self.conn()?;
+        conn.query_row(
+            "SELECT sync_seq FROM sessions WHERE id = ?",
+            [session_id],
+            |row| row.get(0),
+        ).optional()?.ok_or(StorageError::NotFound)
+    }
+
+    fn advance_sync_cursor(&self, session_id: &str, seq: i64) -> StorageResult<i64> {
+        let conn = self.conn()?;
+        conn.query_row(
+            "UPDATE sessions SET sync_seq = MAX(sync_seq, ?) WHERE id = ? RETURNING sync_seq",
+            params![seq, session_id],
+            |row| row.get(0),
+        ).optional()?.ok_or(StorageError::NotFound)
+    }
+}
@@ -0,0 +1,79 @@
+use rusqlite::{Connection, OpenFlags};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// r2d2 的 SQLite 连接管理器
+///
+/// 每个新连接都会开启 WAL 模式、外键约束和忙等待，
+/// 使读操作不被写操作阻塞，多个连接并发写入时排队而不是立即报错；
+/// 同时注册全文索引触发器使用的分词函数。
+#[derive(Debug)]
+pub struct SqliteConnectionManager {
+    path: PathBuf,
+    busy_timeout: Duration,
+    dry_run: bool,
+}
+
+impl SqliteConnectionManager {
+    pub fn new(path: impl Into<PathBuf>, busy_timeout: Duration) -> Self {
+        Self {
+            path: path.into(),
+            busy_timeout,
+            dry_run: false,
+        }
+    }
+
+    /// 试运行迁移用的连接：不创建数据库文件，也不切换日志模式等持久设置
+    ///
+    /// 数据库文件不存在时连接到空的内存数据库，相当于对新数据库试运行。
+    pub fn dry_run(path: impl Into<PathBuf>, busy_timeout: Duration) -> Self {
+        Self {
+            dry_run: true,
+            ..Self::new(path, busy_timeout)
+        }
+    }
+}
+
+impl r2d2::ManageConnection for SqliteConnectionManager {
+    type Connection = Connection;
+    type Error = rusqlite::Error;
+
+    fn connect(&self) -> Result<Connection, rusqlite::Error> {
+        if self.dry_run {
+            let conn = if self.path.exists() {
+                Connection::open_with_flags(
+                    &self.path,
+                    OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_URI | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+                )?
+            } else {
+                Connection::open_in_memory()?
+            };
+            conn.busy_timeout(self.busy_timeout)?;
+            super::fts::register_functions(&conn)?;
+            return Ok(conn);
+        }
+        let conn = Connection::open_with_flags(
+            &self.path,
+            OpenFlags::SQLITE_OPEN_READ_WRITE
+                | OpenFlags::SQLITE_OPEN_CREATE
+                | OpenFlags::SQLITE_OPEN_URI
+                | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+        conn.busy_timeout(self.busy_timeout)?;
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             PRAGMA synchronous = NORMAL;
+             PRAGMA foreign_keys = ON;",
+        )?;
+        super::fts::register_functions(&conn)?;
+        Ok(conn)
+    }
+
+    fn is_valid(&self, conn: &mut Connection) -> Result<(), rusqlite::Error> {
+        conn.execute_batch("SELECT 1")
+    }
+
+    fn has_broken(&self, _conn: &mut Connection) -> bool {
+        false
+    }
+}
@@ -0,0 +1,164 @@
+mod common;
+
+use std::sync::atomic::{
+    AtomicUsize,
+    Ordering
+};
+use axum::{
+    body::Bytes,
+    http::HeaderValue
+};
+use common::TestApp;
+use futures_util::StreamExt;
+use server::{
+    attachments::{
+        content_disposition,
+        sanitize_filename,
+        save_attachment,
+        save_attachment_stream,
+        sniff
+    },
+    blobs,
+    AppError,
+    Attachment
+};
+
+/// 最小的 PE 可执行文件开头（MZ 头）
+const EXE: &[u8] = b"MZ\x90\x00\x03\x00\x00\x00\x04\x00\x00\x00\xff\xff\x00\x00This program cannot be run in DOS mode.";
+const HTML: &[u8] = b"<!DOCTYPE html><html><script>alert(document.cookie)</script></html>";
+const PNG_HEADER: &[u8] = b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR";
+
+fn attachment(kind: &str, filename: &str) -> Attachment {
+    Attachment {
+        id: "a1".into(),
+        uploader_id: "u1".into(),
+        kind: kind.into(),
+        mime_type: "application/octet-stream".into(),
+        size: 1,
+        filename: filename.into(),
+        created_at: 0,
+        sha256: None,
+        width: None,
+        height: None,
+        blurhash: None,
+    }
+}
+
+#[test]
+fn sniffs_content_instead_of_trusting_names() {
+    assert_eq!(sniff(PNG_HEADER), ("image", "image/png"));
+    assert_eq!(sniff(b"ID3\x03\x00\x00\x00\x00\x00\x00"), ("voice", "audio/mpeg"));
+    assert_eq!(sniff(EXE), ("file", "application/vnd.microsoft.portable-executable"));
+    // HTML、XML、脚本降级为纯文本，不会被浏览器当作页面执行
+    assert_eq!(sniff(HTML), ("file", "text/plain"));
+    assert_eq!(sniff(b"<?xml version=\"1.0\"?><svg onload=\"alert(1)\"/>"), ("file", "text/plain"));
+    assert_eq!(sniff(b"#!/bin/sh\nrm -rf /\n"), ("file", "text/plain"));
+    assert_eq!(sniff("纯文本".as_bytes()), ("file", "text/plain"));
+    // 截断在多字节字符中间的文本开头仍视为文本
+    assert_eq!(sniff(&"纯文本".as_bytes()[..4]), ("file", "text/plain"));
+    assert_eq!(sniff(b"\x00\x01\x02binary"), ("file", "application/octet-stream"));
+}
+
+#[tokio::test]
+async fn disguised_files_are_not_stored_as_images() {
+    let app = TestApp::new();
+    let user = app.state.db_pool.register_user("att-user", "hash").unwrap();
+
+    let exe = save_attachment(&app.state, &user.id, "cat.png", EXE).await.unwrap();
+    assert_eq!((exe.kind.as_str(), exe.mime_type.as_str()), ("file", "application/vnd.microsoft.portable-executable"));
+    let html = save_attachment(&app.state, &user.id, "cat.png", HTML).await.unwrap();
+    assert_eq!((html.kind.as_str(), html.mime_type.as_str()), ("file", "text/plain"));
+    assert_eq!(content_disposition(&html), "attachment; filename=\"cat.png\"; filename*=UTF-8''cat.png");
+
+    // 只有 PNG 文件头、无法解码的“图片”被拒绝
+    assert!(save_attachment(&app.state, &user.id, "cat.png", PNG_HEADER).await.is_err());
+    assert!(save_attachment(&app.state, &user.id, "empty.png", b"").await.is_err());
+}
+
+/// 把内容切成固定大小的块，记录被读取的块数
+fn chunks<'a>(content: &'a [u8], size: usize, polled: &'a AtomicUsize) -> impl futures_util::Stream<Item = Result<Bytes, std::io::Error>> + Unpin + 'a {
+    futures_util::stream::iter(content.chunks(size)).map(move |chunk| {
+        polled.fetch_add(1, Ordering::SeqCst);
+        Ok(Bytes::copy_from_slice(chunk))
+    })
+}
+
+#[tokio::test]
+async fn streams_uploads_and_enforces_limits_per_kind() {
+    let app = TestApp::with_settings(|s| {
+        s.limits.max_file_bytes = 20 * 1024;
+        s.limits.max_image_bytes = 40 * 1024;
+    });
+    let user = app.state.db_pool.register_user("att-stream", "hash").unwrap();
+    let polled = AtomicUsize::new(0);
+
+    let text = "流式上传的文本\n".repeat(500);
+    let attachment = save_attachment_stream(&app.state, &user.id, "notes.txt", chunks(text.as_bytes(), 1000, &polled)).await.unwrap();
+    assert_eq!((attachment.kind.as_str(), attachment.mime_type.as_str()), ("file", "text/plain"));
+    assert_eq!(attachment.size as usize, text.len());
+    let sha256 = attachment.sha256.clone().unwrap();
+    assert_eq!(sha256, blobs::sha256_hex(text.as_bytes()));
+    assert_eq!(std::fs::read(blobs::blob_path(&app.state.settings.storage, &sha256)).unwrap(), text.as_bytes());
+
+    // 识别为普通文件后按文件上限拒绝，不再读取后面的块
+    polled.store(0, Ordering::SeqCst);
+    let large = vec![0u8; 100 * 1024];
+    let result = save_attachment_stream(&app.state, &user.id, "big.bin", chunks(&large, 1024, &polled)).await;
+    assert!(matches!(result, Err(AppError::PayloadTooLarge(_))));
+    assert_eq!(polled.load(Ordering::SeqCst), 21);
+
+    // 图片按图片上限，收完后照常校验
+    let mut png = std::io::Cursor::new(Vec::new());
+    image::RgbImage::new(4, 4).write_to(&mut png, image::ImageFormat::Png).unwrap();
+    let image = save_attachment_stream(&app.state, &user.id, "cat.png", chunks(png.get_ref(), 7, &polled)).await.unwrap();
+    assert_eq!((image.kind.as_str(), image.width), ("image", Some(4)));
+    let result = save_attachment_stream(&app.state, &user.id, "empty.txt", chunks(b"", 1, &polled)).await;
+    assert!(matches!(result, Err(AppError::BadRequest(_))));
+
+    // 临时文件都已删除
+    let temp_files = std::fs::read_dir(app.state.settings.storage.blob_dir()).unwrap()
+        .filter(|entry| entry.as_ref().unwrap().file_name().to_string_lossy().starts_with(".upload-"))
+        .count();
+    assert_eq!(temp_files, 0);
+}
+
+#[test]
+fn sanitizes_filenames() {
+    assert_eq!(sanitize_filename("photo.jpg"), "photo.jpg");
+    assert_eq!(sanitize_filename("../../etc/passwd"), "passwd");
+    assert_eq!(sanitize_filename("C:\\Users\\me\\secret.txt"), "secret.txt");
+    assert_eq!(sanitize_filename("dir/"), "file");
+    assert_eq!(sanitize_filename(".."), "file");
+    assert_eq!(sanitize_filename("  "), "file");
+    assert_eq!(sanitize_filename("evil\r\nname\0.txt"), "evilname.txt");
+    assert_eq!(sanitize_filename("report.pdf. . "), "report.pdf");
+    // 双向文本控制符可以把 "invoice\u{202E}gpj.exe" 显示成 "invoiceexe.jpg"
+    assert_eq!(sanitize_filename("invoice\u{202E}gpj.exe"), "invoicegpj.exe");
+    // Windows 保留设备名
+    assert_eq!(sanitize_filename("CON"), "_CON");
+    assert_eq!(sanitize_filename("nul.txt"), "_nul.txt");
+    assert_eq!(sanitize_filename("Com1 .tar.gz"), "_Com1 .tar.gz");
+    assert_eq!(sanitize_filename("console.log"), "console.log");
+    assert_eq!(sanitize_filename("猫.png"), "猫.png");
+    assert_eq!(sanitize_filename(&"长".repeat(300)).chars().count(), 255);
+}
+
+#[test]
+fn encodes_content_disposition_safely() {
+    assert_eq!(
+        content_disposition(&attachment("image", "猫 1.png")),
+        "inline; filename=\"_ 1.png\"; filename*=UTF-8''%E7%8C%AB%201.png"
+    );
+    assert_eq!(
+        content_disposition(&attachment("file", "notes.pdf")),
+        "attachment; filename=\"notes.pdf\"; filename*=UTF-8''notes.pdf"
+    );
+
+    // 引号、反斜杠与换行不能截断参数或注入新的响应头
+    let hostile = attachment("file", "a\"b\\c\r\nSet-Cookie: x=1;.txt");
+    let value = content_disposition(&hostile);
+    assert!(HeaderValue::from_str(&value).is_ok());
+    assert!(!value.contains(['\r', '\n']));
+    assert_eq!(value.matches('"').count(), 2);
+    assert!(value.ends_with("filename*=UTF-8''a%22b%5Cc%0D%0ASet-Cookie%3A%20x%3D1%3B.txt"));
+}
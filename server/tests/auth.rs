@@ -0,0 +1,108 @@
+mod common;
+
+use axum::{
+    extract::FromRequestParts,
+    http::{
+        header::AUTHORIZATION,
+        Request
+    }
+};
+use base64::{
+    engine::general_purpose::URL_SAFE_NO_PAD,
+    Engine
+};
+use common::{
+    TestApp,
+    TOKEN_SECRET
+};
+use server::{
+    auth::{
+        self,
+        AuthUser,
+        TokenSigner
+    },
+    AppError
+};
+
+fn now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
+
+/// 用请求头中的令牌调用认证提取器
+async fn extract(app: &TestApp, authorization: Option<&str>) -> Result<AuthUser, AppError> {
+    let mut request = Request::builder();
+    if let Some(value) = authorization {
+        request = request.header(AUTHORIZATION, value);
+    }
+    let (mut parts, _) = request.body(()).unwrap().into_parts();
+    AuthUser::from_request_parts(&mut parts, &app.state).await
+}
+
+#[test]
+fn verifies_signature_and_expiry() {
+    let signer = TokenSigner::new(TOKEN_SECRET.to_vec());
+    let token = signer.issue_access_token("session-1", "user-1", now() + 60);
+    assert_eq!(
+        signer.verify_access_token(&token).unwrap(),
+        ("session-1".to_string(), "user-1".to_string())
+    );
+
+    // 篡改签名
+    let (payload, signature) = token.split_once('.').unwrap();
+    let mut forged = URL_SAFE_NO_PAD.decode(signature).unwrap();
+    forged[0] ^= 1;
+    let tampered = format!("{}.{}", payload, URL_SAFE_NO_PAD.encode(forged));
+    assert!(matches!(signer.verify_access_token(&tampered), Err(AppError::Unauthorized(_))));
+
+    // 篡改载荷（冒充其他用户）但沿用原签名
+    let impersonated = format!("{}.{}", URL_SAFE_NO_PAD.encode(format!("session-1:user-2:{}", now() + 60)), signature);
+    assert!(matches!(signer.verify_access_token(&impersonated), Err(AppError::Unauthorized(_))));
+
+    // 格式错误
+    for malformed in ["", "no-dot", "!!.!!", "a.b.c"] {
+        assert!(matches!(signer.verify_access_token(malformed), Err(AppError::Unauthorized(_))), "{:?}", malformed);
+    }
+
+    // 其他密钥签发的令牌
+    let other = TokenSigner::new(b"another-secret-0123456789abcdef!!".to_vec());
+    assert!(matches!(other.verify_access_token(&token), Err(AppError::Unauthorized(_))));
+
+    // 已过期
+    let expired = signer.issue_access_token("session-1", "user-1", now() - 1);
+    match signer.verify_access_token(&expired) {
+        Err(AppError::Unauthorized(message)) => assert_eq!(message, "访问令牌已过期"),
+        other => panic!("过期令牌应被拒绝: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn extractor_rejects_invalid_and_revoked_sessions() {
+    let app = TestApp::new();
+    let user = app.state.db_pool.register_user("auth-user", "hash").unwrap();
+    let tokens = auth::create_session(&app.state, &user.id, Some("laptop".into())).await.unwrap();
+
+    let bearer = format!("Bearer {}", tokens.access_token);
+    let caller = extract(&app, Some(&bearer)).await.unwrap();
+    assert_eq!(caller.user_id, user.id);
+
+    assert!(matches!(extract(&app, None).await, Err(AppError::Unauthorized(_))));
+    assert!(matches!(extract(&app, Some(&tokens.access_token)).await, Err(AppError::Unauthorized(_))));
+    let expired = app.state.token_signer.issue_access_token(&caller.session_id, &user.id, now() - 1);
+    assert!(matches!(extract(&app, Some(&format!("Bearer {}", expired))).await, Err(AppError::Unauthorized(_))));
+
+    // 签名有效但会话不属于该用户
+    let other = app.state.db_pool.register_user("auth-other", "hash").unwrap();
+    let mismatched = app.state.token_signer.issue_access_token(&caller.session_id, &other.id, now() + 60);
+    assert!(matches!(extract(&app, Some(&format!("Bearer {}", mismatched))).await, Err(AppError::Unauthorized(_))));
+
+    // 吊销会话后，未过期的访问令牌也立即失效，刷新令牌同样不可用
+    app.state.db_pool.revoke_session(&caller.session_id).unwrap();
+    match extract(&app, Some(&bearer)).await {
+        Err(AppError::Unauthorized(message)) => assert_eq!(message, "会话已失效，请重新登录"),
+        other => panic!("已吊销的会话应被拒绝: {:?}", other),
+    }
+    assert!(matches!(auth::refresh_session(&app.state, &tokens.refresh_token).await, Err(AppError::Unauthorized(_))));
+}
@@ -0,0 +1,137 @@
+mod common;
+
+use std::path::Path;
+use common::{
+    TestApp,
+    TOKEN_SECRET
+};
+use server::{
+    auth,
+    blobs,
+    register_routes
+};
+use tokio::io::{
+    AsyncReadExt,
+    AsyncWriteExt
+};
+
+/// 在随机端口上启动完整的路由，返回地址
+async fn serve(app: &TestApp) -> std::net::SocketAddr {
+    let router = register_routes(app.state.db_pool.clone(), (*app.state.settings).clone()).await.unwrap();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+    addr
+}
+
+/// 发送 GET 请求，返回状态码与响应头（名称为小写）
+async fn get(addr: std::net::SocketAddr, path: &str) -> (u16, Vec<(String, String)>) {
+    request(addr, format!("GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n", path).into_bytes()).await
+}
+
+/// 以 multipart 上传头像，返回状态码
+async fn upload_avatar(addr: std::net::SocketAddr, user_id: &str, token: &str, content: &[u8]) -> u16 {
+    let mut body = b"--BOUNDARY\r\nContent-Disposition: form-data; name=\"avatar\"; filename=\"a.png\"\r\n\r\n".to_vec();
+    body.extend_from_slice(content);
+    body.extend_from_slice(b"\r\n--BOUNDARY--\r\n");
+    let mut raw = format!(
+        "POST /user/{}/avatar HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nAuthorization: Bearer {}\r\n\
+         Content-Type: multipart/form-data; boundary=BOUNDARY\r\nContent-Length: {}\r\n\r\n",
+        user_id, token, body.len()
+    ).into_bytes();
+    raw.extend(body);
+    request(addr, raw).await.0
+}
+
+async fn request(addr: std::net::SocketAddr, raw: Vec<u8>) -> (u16, Vec<(String, String)>) {
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream.write_all(&raw).await.unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    let response = String::from_utf8_lossy(&response);
+    let head = response.split("\r\n\r\n").next().unwrap();
+    let mut lines = head.lines();
+    let status = lines.next().unwrap().split(' ').nth(1).unwrap().parse().unwrap();
+    let headers = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+    (status, headers)
+}
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+}
+
+fn png() -> Vec<u8> {
+    let mut content = std::io::Cursor::new(Vec::new());
+    image::RgbImage::new(2, 2).write_to(&mut content, image::ImageFormat::Png).unwrap();
+    content.into_inner()
+}
+
+fn write(path: &Path, content: &[u8]) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, content).unwrap();
+}
+
+#[tokio::test]
+async fn serves_only_current_avatars_as_images() {
+    let app = TestApp::new();
+    let storage = &app.state.settings.storage;
+    let user = app.state.db_pool.register_user("avatar-user", "hash").unwrap();
+
+    // 头像与私聊附件都在文件库中
+    let avatar = png();
+    let avatar_sha256 = blobs::sha256_hex(&avatar);
+    write(&blobs::blob_path(storage, &avatar_sha256), &avatar);
+    write(&blobs::variant_path(storage, &avatar_sha256, "64.png"), &avatar);
+    let url = format!("/uploads/avatars/{}.png", avatar_sha256);
+    app.state.db_pool.update_user_avatar(&user.id, &url, &avatar_sha256, avatar.len() as i64).unwrap();
+    let secret = b"<html><script>alert(1)</script></html>";
+    let secret_sha256 = blobs::sha256_hex(secret);
+    write(&blobs::blob_path(storage, &secret_sha256), secret);
+
+    // 旧版头像直接放在头像目录下
+    write(&storage.avatar_dir().join("legacy.png"), &avatar);
+    write(&storage.avatar_dir().join("fake.png"), secret);
+
+    let addr = serve(&app).await;
+    for path in [url.clone(), format!("{}?size=32", url), format!("/uploads/avatars/{}.html", avatar_sha256), "/uploads/avatars/legacy.png".into()] {
+        let (status, headers) = get(addr, &path).await;
+        assert_eq!(status, 200, "{}", path);
+        assert_eq!(header(&headers, "content-type"), Some("image/png"), "{}", path);
+        assert_eq!(header(&headers, "x-content-type-options"), Some("nosniff"), "{}", path);
+    }
+
+    // 不是头像的文件（如附件）与内容不是图片的旧版头像都不公开
+    for path in [
+        format!("/uploads/avatars/{}.html", secret_sha256),
+        format!("/uploads/avatars/{}.svg", secret_sha256),
+        "/uploads/avatars/fake.png".into(),
+        "/uploads/avatars/missing.png".into(),
+    ] {
+        assert_eq!(get(addr, &path).await.0, 404, "{}", path);
+    }
+
+    // 换成其他头像后，旧头像不再公开
+    let other = blobs::sha256_hex(b"other");
+    app.state.db_pool.update_user_avatar(&user.id, "/uploads/avatars/other.png", &other, 5).unwrap();
+    assert_eq!(get(addr, &url).await.0, 404);
+}
+
+#[tokio::test]
+async fn rejects_avatars_over_the_limit() {
+    let app = TestApp::with_settings(|s| {
+        s.auth.token_secret = Some(String::from_utf8(TOKEN_SECRET.to_vec()).unwrap());
+        s.limits.max_avatar_bytes = 1024;
+    });
+    let user = app.state.db_pool.register_user("avatar-limit", "hash").unwrap();
+    let tokens = auth::create_session(&app.state, &user.id, None).await.unwrap();
+    let addr = serve(&app).await;
+
+    // 超过头像上限（仍在请求体上限内）时返回 413，头像不变
+    assert_eq!(upload_avatar(addr, &user.id, &tokens.access_token, &vec![0u8; 4096]).await, 413);
+    assert_eq!(app.state.db_pool.get_user_by_id(&user.id).unwrap().avatar_url, "");
+    assert_eq!(upload_avatar(addr, &user.id, &tokens.access_token, &png()).await, 200);
+    assert!(app.state.db_pool.get_user_by_id(&user.id).unwrap().avatar_url.starts_with("/uploads/avatars/"));
+}
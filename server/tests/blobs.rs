@@ -0,0 +1,117 @@
+mod common;
+
+use common::TestApp;
+use server::{
+    blobs,
+    StorageError
+};
+
+#[tokio::test]
+async fn stores_references_and_collects_blobs() {
+    // 宽限期为负数，引用数归零后立即可以回收
+    let app = TestApp::with_settings(|s| s.storage.gc_grace_secs = -1);
+    let (db, storage) = (&app.state.db_pool, &app.state.settings.storage);
+    let alice = db.register_user("blob-alice", "hash").unwrap();
+    let bob = db.register_user("blob-bob", "hash").unwrap();
+    let cat = blobs::sha256_hex(b"cat");
+    let dog = blobs::sha256_hex(b"dog");
+    let variants = || vec![("64.png".to_string(), b"small cat".to_vec())];
+
+    // 两个用户使用相同内容的头像：只保存一份，引用数为 2
+    for user_id in [alice.id.clone(), bob.id.clone()] {
+        let temp_path = blobs::write_temp(storage, b"cat").await.unwrap();
+        let hash = cat.clone();
+        blobs::store(db, storage, &temp_path, &cat, variants(), move |db| db.update_user_avatar(&user_id, "/c", &hash, 3)).await.unwrap();
+        assert!(!temp_path.exists());
+    }
+    assert_eq!(std::fs::read(blobs::blob_path(storage, &cat)).unwrap(), b"cat");
+    assert_eq!(std::fs::read(blobs::variant_path(storage, &cat, "64.png")).unwrap(), b"small cat");
+    assert_eq!(db.get_blob(&cat).unwrap().ref_count, 2);
+
+    // 引用登记失败：临时文件删除，文件已放入但没有引用，随后被回收
+    let temp_path = blobs::write_temp(storage, b"dog").await.unwrap();
+    let hash = dog.clone();
+    let result = blobs::store(db, storage, &temp_path, &dog, Vec::new(), move |db| db.update_user_avatar("missing", "/d", &hash, 3)).await;
+    assert!(result.is_err());
+    assert!(!temp_path.exists());
+    assert_eq!(db.get_blob(&dog).unwrap().ref_count, 0);
+    let report = blobs::collect_garbage(db, storage).await.unwrap();
+    assert_eq!((report.blobs_removed, report.bytes_reclaimed), (1, 3));
+    assert!(!blobs::blob_path(storage, &dog).exists());
+    assert!(matches!(db.get_blob(&dog), Err(StorageError::NotFound)));
+
+    // 放入文件失败（临时文件不存在）时不登记引用
+    let (user_id, hash) = (alice.id.clone(), dog.clone());
+    let missing = storage.blob_dir().join(".upload-missing");
+    let result = blobs::store(db, storage, &missing, &dog, Vec::new(), move |db| db.update_user_avatar(&user_id, "/d", &hash, 3)).await;
+    assert!(result.is_err());
+    assert!(matches!(db.get_blob(&dog), Err(StorageError::NotFound)));
+    assert_eq!(db.get_user_by_id(&alice.id).unwrap().avatar_url, "/c");
+
+    // 仍被引用的文件不回收；两个引用都释放后连同派生文件一起删除
+    let temp_path = blobs::write_temp(storage, b"dog").await.unwrap();
+    let (user_id, hash) = (alice.id.clone(), dog.clone());
+    blobs::store(db, storage, &temp_path, &dog, Vec::new(), move |db| db.update_user_avatar(&user_id, "/d", &hash, 3)).await.unwrap();
+    assert_eq!(blobs::collect_garbage(db, storage).await.unwrap().blobs_removed, 0);
+    assert_eq!(db.get_blob(&cat).unwrap().ref_count, 1);
+
+    let temp_path = blobs::write_temp(storage, b"dog").await.unwrap();
+    let (user_id, hash) = (bob.id.clone(), dog.clone());
+    blobs::store(db, storage, &temp_path, &dog, Vec::new(), move |db| db.update_user_avatar(&user_id, "/d", &hash, 3)).await.unwrap();
+    let report = blobs::collect_garbage(db, storage).await.unwrap();
+    assert_eq!((report.blobs_removed, report.bytes_reclaimed), (1, 3 + 9));
+    assert!(!blobs::blob_path(storage, &cat).exists());
+    assert!(!blobs::variant_path(storage, &cat, "64.png").parent().unwrap().exists());
+    assert_eq!(db.get_blob(&dog).unwrap().ref_count, 2);
+    assert_eq!(std::fs::read(blobs::blob_path(storage, &dog)).unwrap(), b"dog");
+}
+
+#[tokio::test]
+async fn retries_blobs_that_could_not_be_removed() {
+    let app = TestApp::with_settings(|s| s.storage.gc_grace_secs = -1);
+    let (db, storage) = (&app.state.db_pool, &app.state.settings.storage);
+    let cat = blobs::sha256_hex(b"cat");
+    db.register_blob(&cat, 3).unwrap();
+
+    // 文件位置被目录占用而无法删除：保留记录，下次回收重试
+    let path = blobs::blob_path(storage, &cat);
+    std::fs::create_dir_all(&path).unwrap();
+    let report = blobs::collect_garbage(db, storage).await.unwrap();
+    assert_eq!(report.blobs_removed, 0);
+    assert_eq!(db.get_blob(&cat).unwrap().ref_count, 0);
+
+    std::fs::remove_dir(&path).unwrap();
+    std::fs::write(&path, b"cat").unwrap();
+    let report = blobs::collect_garbage(db, storage).await.unwrap();
+    assert_eq!((report.blobs_removed, report.bytes_reclaimed), (1, 3));
+    assert!(!path.exists());
+    assert!(matches!(db.get_blob(&cat), Err(StorageError::NotFound)));
+
+    // 文件已不存在时同样删除记录
+    db.register_blob(&cat, 3).unwrap();
+    assert_eq!(blobs::collect_garbage(db, storage).await.unwrap().blobs_removed, 0);
+    assert!(matches!(db.get_blob(&cat), Err(StorageError::NotFound)));
+}
+
+#[tokio::test]
+async fn removes_files_of_legacy_orphan_attachments() {
+    let app = TestApp::with_settings(|s| s.storage.gc_grace_secs = -1);
+    let (db, storage) = (&app.state.db_pool, &app.state.settings.storage);
+    let user = db.register_user("blob-legacy", "hash").unwrap();
+
+    // 文件库之前的附件没有 SHA-256，文件按附件ID保存在附件目录
+    let conn = rusqlite::Connection::open(&app.state.settings.database.path).unwrap();
+    conn.execute(
+        "INSERT INTO attachments (id, uploader_id, kind, mime_type, size, filename, created_at)
+         VALUES ('legacy-1', ?1, 'file', 'text/plain', 6, 'old.txt', 0)",
+        [&user.id],
+    ).unwrap();
+    let path = storage.attachment_dir().join("legacy-1");
+    std::fs::create_dir_all(storage.attachment_dir()).unwrap();
+    std::fs::write(&path, b"legacy").unwrap();
+
+    let report = blobs::collect_garbage(db, storage).await.unwrap();
+    assert_eq!((report.attachments_removed, report.bytes_reclaimed), (1, 6));
+    assert!(!path.exists());
+    assert!(matches!(db.get_attachment("legacy-1"), Err(StorageError::NotFound)));
+}
@@ -0,0 +1,48 @@
+//! 需要完整应用状态的测试共用的临时环境
+#![allow(dead_code)]
+
+use server::{
+    auth::TokenSigner,
+    settings::Settings,
+    sqlite::SqliteStorage,
+    AppState,
+    DbPool,
+    Storage
+};
+use std::path::PathBuf;
+
+/// 测试使用的令牌签名密钥
+pub const TOKEN_SECRET: &[u8] = b"test-token-secret-0123456789abcdef";
+
+/// 临时目录中的 SQLite 数据库与上传目录，测试结束时删除
+pub struct TestApp {
+    pub dir: PathBuf,
+    pub state: AppState,
+}
+
+impl TestApp {
+    pub fn new() -> Self {
+        Self::with_settings(|_| {})
+    }
+
+    /// 在默认配置上调整后创建（数据库与上传目录固定在临时目录中）
+    pub fn with_settings(configure: impl FnOnce(&mut Settings)) -> Self {
+        let dir = std::env::temp_dir().join(format!("yueling-app-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut settings = Settings::default();
+        settings.database.path = dir.join("server.db");
+        settings.storage.upload_root = dir.join("uploads");
+        configure(&mut settings);
+
+        let storage = SqliteStorage::open(&settings.database.path).unwrap();
+        storage.migrate(false).unwrap();
+        let state = AppState::new(DbPool::new(storage), settings, TokenSigner::new(TOKEN_SECRET.to_vec()));
+        Self { dir, state }
+    }
+}
+
+impl Drop for TestApp {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
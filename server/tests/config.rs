@@ -0,0 +1,136 @@
+use server::{
+    loader::{
+        load_with,
+        Cli
+    },
+    settings::{
+        ConfigError,
+        Settings
+    }
+};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// 临时配置文件，测试结束时删除
+struct TempConfig(PathBuf);
+
+impl TempConfig {
+    fn new(text: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("yueling-config-{}.toml", uuid::Uuid::new_v4()));
+        std::fs::write(&path, text).unwrap();
+        Self(path)
+    }
+
+    fn cli(&self) -> Cli {
+        Cli { config: Some(self.0.clone()), ..Default::default() }
+    }
+}
+
+impl Drop for TempConfig {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// 以给定的环境变量加载配置
+fn load(cli: &Cli, vars: &[(&str, &str)]) -> Result<Settings, ConfigError> {
+    let vars: HashMap<String, String> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+    load_with(cli, move |key| vars.get(key).cloned())
+}
+
+const FILE: &str = r#"
+[server]
+bind = "127.0.0.1:3000"
+
+[database]
+pool_size = 4
+
+[limits]
+max_message_chars = 100
+"#;
+
+#[test]
+fn applies_defaults_file_env_and_cli_in_order() {
+    let file = TempConfig::new(FILE);
+    let defaults = Settings::default();
+
+    // 配置文件覆盖默认值，未配置的项保持默认
+    let settings = load(&file.cli(), &[]).unwrap();
+    assert_eq!(settings.server.bind.to_string(), "127.0.0.1:3000");
+    assert_eq!(settings.database.pool_size, 4);
+    assert_eq!(settings.limits.max_message_chars, 100);
+    assert_eq!(settings.limits.max_file_bytes, defaults.limits.max_file_bytes);
+
+    // 环境变量覆盖配置文件
+    let env = [("YUELING_BIND", "127.0.0.1:4000"), ("YUELING_DB_POOL_SIZE", "8"), ("YUELING_CORS_ORIGINS", "https://a.example, https://b.example")];
+    let settings = load(&file.cli(), &env).unwrap();
+    assert_eq!(settings.server.bind.to_string(), "127.0.0.1:4000");
+    assert_eq!(settings.database.pool_size, 8);
+    assert_eq!(settings.limits.max_message_chars, 100);
+    assert_eq!(settings.cors.allowed_origins, vec!["https://a.example", "https://b.example"]);
+
+    // 命令行参数覆盖环境变量
+    let cli = Cli {
+        bind: Some("127.0.0.1:5000".parse().unwrap()),
+        cors_origins: vec!["https://c.example".into()],
+        ..file.cli()
+    };
+    let settings = load(&cli, &env).unwrap();
+    assert_eq!(settings.server.bind.to_string(), "127.0.0.1:5000");
+    assert_eq!(settings.database.pool_size, 8);
+    assert_eq!(settings.cors.allowed_origins, vec!["https://c.example"]);
+
+    // 未指定命令行参数时由 YUELING_CONFIG 选择配置文件
+    let path = file.0.to_str().unwrap();
+    let settings = load(&Cli::default(), &[("YUELING_CONFIG", path)]).unwrap();
+    assert_eq!(settings.server.bind.to_string(), "127.0.0.1:3000");
+}
+
+#[test]
+fn rejects_bad_env_values_and_files() {
+    let file = TempConfig::new(FILE);
+    match load(&file.cli(), &[("YUELING_DB_POOL_SIZE", "many")]) {
+        Err(ConfigError::Env { key, value }) => {
+            assert_eq!(key, "YUELING_DB_POOL_SIZE");
+            assert_eq!(value, "many");
+        }
+        other => panic!("应返回 ConfigError::Env: {:?}", other),
+    }
+    assert!(matches!(load(&file.cli(), &[("YUELING_BIND", "not-an-address")]), Err(ConfigError::Env { .. })));
+    assert!(matches!(load(&file.cli(), &[("YUELING_DB_BACKEND", "mysql")]), Err(ConfigError::Env { .. })));
+
+    let unknown = TempConfig::new("[server]\nport = 1\n");
+    assert!(matches!(load(&unknown.cli(), &[]), Err(ConfigError::Parse { .. })));
+    let missing = Cli { config: Some(std::env::temp_dir().join("yueling-missing.toml")), ..Default::default() };
+    assert!(matches!(load(&missing, &[]), Err(ConfigError::Read { .. })));
+}
+
+#[test]
+fn validates_secret_length_and_cors_origins() {
+    let file = TempConfig::new(FILE);
+    let invalid_field = |result: Result<Settings, ConfigError>| match result {
+        Err(ConfigError::Invalid { field, .. }) => field,
+        other => panic!("应返回 ConfigError::Invalid: {:?}", other),
+    };
+
+    assert_eq!(invalid_field(load(&file.cli(), &[("YUELING_TOKEN_SECRET", "too-short")])), "auth.token_secret");
+    assert!(load(&file.cli(), &[("YUELING_TOKEN_SECRET", &"s".repeat(32))]).is_ok());
+
+    assert_eq!(invalid_field(load(&file.cli(), &[("YUELING_CORS_ORIGINS", "example.com")])), "cors.allowed_origins");
+    assert_eq!(invalid_field(load(&file.cli(), &[("YUELING_CORS_ORIGINS", "https://ok.example,ftp://x.example")])), "cors.allowed_origins");
+    assert_eq!(invalid_field(load(&file.cli(), &[("YUELING_CORS_ORIGINS", " , ")])), "cors.allowed_origins");
+    assert!(load(&file.cli(), &[("YUELING_CORS_ORIGINS", "*")]).is_ok());
+
+    let mut settings = Settings::default();
+    settings.database.pool_size = 0;
+    assert!(matches!(settings.validate(), Err(ConfigError::Invalid { field: "database.pool_size", .. })));
+}
+
+#[test]
+fn ignores_deprecated_broadcast_capacity() {
+    // 旧配置文件中的广播通道容量仍可加载，即使是 0 也不报错；对应的环境变量不再读取
+    let file = TempConfig::new("[channels]\nbroadcast_capacity = 0\n");
+    let settings = load(&file.cli(), &[("YUELING_BROADCAST_CAPACITY", "invalid")]).unwrap();
+    assert_eq!(settings.channels.broadcast_capacity, Some(0));
+    assert_eq!(settings.channels.client_capacity, Settings::default().channels.client_capacity);
+}
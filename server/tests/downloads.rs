@@ -0,0 +1,125 @@
+use axum::{
+    body::to_bytes,
+    http::{
+        header::{
+            CONTENT_RANGE,
+            ETAG,
+            IF_MODIFIED_SINCE,
+            IF_NONE_MATCH,
+            IF_RANGE,
+            LAST_MODIFIED,
+            RANGE
+        },
+        HeaderMap,
+        StatusCode
+    },
+    response::Response
+};
+use server::downloads::{
+    self,
+    Download
+};
+use std::path::PathBuf;
+
+/// 临时文件，测试结束时删除
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(content: &[u8]) -> Self {
+        let path = std::env::temp_dir().join(format!("yueling-download-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, content).unwrap();
+        Self(path)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+fn download() -> Download {
+    Download {
+        content_type: "text/plain".into(),
+        content_disposition: Some("attachment; filename=\"a.txt\"".into()),
+        cache_control: downloads::CACHE_PRIVATE,
+        not_found: "文件不存在",
+    }
+}
+
+async fn get(file: &TempFile, headers: &[(axum::http::HeaderName, &str)]) -> Response {
+    let mut map = HeaderMap::new();
+    for (name, value) in headers {
+        map.insert(name.clone(), value.parse().unwrap());
+    }
+    downloads::serve_file(&map, &file.0, download()).await.unwrap()
+}
+
+async fn body(response: Response) -> Vec<u8> {
+    to_bytes(response.into_body(), usize::MAX).await.unwrap().to_vec()
+}
+
+#[tokio::test]
+async fn serves_whole_file_and_byte_ranges() {
+    let file = TempFile::new(b"0123456789");
+
+    let response = get(&file, &[]).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["accept-ranges"], "bytes");
+    assert_eq!(response.headers()["content-disposition"], "attachment; filename=\"a.txt\"");
+    assert_eq!(body(response).await, b"0123456789");
+
+    let response = get(&file, &[(RANGE, "bytes=2-4")]).await;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(response.headers()[CONTENT_RANGE], "bytes 2-4/10");
+    assert_eq!(body(response).await, b"234");
+
+    assert_eq!(body(get(&file, &[(RANGE, "bytes=7-")]).await).await, b"789");
+    assert_eq!(body(get(&file, &[(RANGE, "bytes=-3")]).await).await, b"789");
+    assert_eq!(body(get(&file, &[(RANGE, "bytes=8-100")]).await).await, b"89");
+
+    // 超出文件的范围返回 416，不支持的多段范围与格式错误的范围返回完整内容
+    let response = get(&file, &[(RANGE, "bytes=10-")]).await;
+    assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(response.headers()[CONTENT_RANGE], "bytes */10");
+    assert_eq!(get(&file, &[(RANGE, "bytes=0-1,4-5")]).await.status(), StatusCode::OK);
+    assert_eq!(get(&file, &[(RANGE, "bytes=5-2")]).await.status(), StatusCode::OK);
+    assert_eq!(get(&file, &[(RANGE, "items=0-1")]).await.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn honours_conditional_requests() {
+    let file = TempFile::new(b"hello world");
+    let response = get(&file, &[]).await;
+    let etag = response.headers()[ETAG].to_str().unwrap().to_string();
+    let last_modified = response.headers()[LAST_MODIFIED].to_str().unwrap().to_string();
+
+    let response = get(&file, &[(IF_NONE_MATCH, &etag)]).await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers()[ETAG], etag.as_str());
+    assert!(body(response).await.is_empty());
+    assert_eq!(get(&file, &[(IF_NONE_MATCH, &format!("W/{}", etag))]).await.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(get(&file, &[(IF_NONE_MATCH, "\"other\"")]).await.status(), StatusCode::OK);
+    assert_eq!(
+        get(&file, &[(IF_MODIFIED_SINCE, &last_modified)]).await.status(),
+        StatusCode::NOT_MODIFIED
+    );
+
+    // If-Range 与当前文件不一致时忽略 Range
+    assert_eq!(get(&file, &[(RANGE, "bytes=0-4"), (IF_RANGE, &etag)]).await.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(get(&file, &[(RANGE, "bytes=0-4"), (IF_RANGE, &last_modified)]).await.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(get(&file, &[(RANGE, "bytes=0-4"), (IF_RANGE, "\"stale\"")]).await.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn rejects_missing_files_and_unsafe_names() {
+    let missing = std::env::temp_dir().join(format!("yueling-missing-{}", uuid::Uuid::new_v4()));
+    let error = downloads::serve_file(&HeaderMap::new(), &missing, download()).await.unwrap_err();
+    assert!(matches!(error, server::AppError::NotFound(_)));
+
+    assert!(downloads::is_safe_filename("0f3a.png"));
+    assert!(downloads::is_safe_filename("avatar_1-b.jpeg"));
+    for name in ["", ".", "..", "../secret", "..\\secret", "a/b.png", ".hidden", "a b.png", "头像.png", "a\0.png"] {
+        assert!(!downloads::is_safe_filename(name), "{:?}", name);
+    }
+}
@@ -0,0 +1,82 @@
+-- 引入迁移机制之前的数据库结构与示例数据
+CREATE TABLE users (
+    id TEXT PRIMARY KEY,
+    username TEXT UNIQUE NOT NULL,
+    email TEXT UNIQUE NOT NULL,
+    password_hash TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    avatar_url TEXT DEFAULT '',
+    user_data TEXT
+);
+
+CREATE TABLE messages (
+    id TEXT PRIMARY KEY,
+    sender_id TEXT NOT NULL,
+    receiver_id TEXT NOT NULL,
+    content TEXT NOT NULL,
+    message_type TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    is_read INTEGER NOT NULL DEFAULT 0,
+    FOREIGN KEY(sender_id) REFERENCES users(id)
+);
+
+CREATE TABLE friendships (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    friend_id TEXT NOT NULL,
+    status TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    FOREIGN KEY(user_id) REFERENCES users(id),
+    FOREIGN KEY(friend_id) REFERENCES users(id),
+    UNIQUE(user_id, friend_id)
+);
+
+CREATE TABLE friend_requests (
+    id TEXT PRIMARY KEY,
+    from_user_id TEXT NOT NULL,
+    to_user_id TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending',
+    created_at INTEGER NOT NULL,
+    FOREIGN KEY(from_user_id) REFERENCES users(id),
+    FOREIGN KEY(to_user_id) REFERENCES users(id),
+    UNIQUE(from_user_id, to_user_id)
+);
+
+CREATE TABLE groups (
+    id TEXT PRIMARY KEY,
+    group_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    creator_id TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    group_user_list TEXT,
+    FOREIGN KEY(creator_id) REFERENCES users(id)
+);
+
+CREATE TABLE group_members (
+    id TEXT PRIMARY KEY,
+    group_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    joined_at INTEGER NOT NULL,
+    role TEXT NOT NULL,
+    FOREIGN KEY(group_id) REFERENCES groups(id),
+    FOREIGN KEY(user_id) REFERENCES users(id),
+    UNIQUE(group_id, user_id)
+);
+
+INSERT INTO users (id, username, email, password_hash, created_at, avatar_url)
+VALUES ('u1', 'alice', 'u1@local', 'hash', 1700000000, ''),
+       ('u2', 'bob', 'u2@local', 'hash', 1700000001, '/uploads/avatars/bob.png');
+
+INSERT INTO friendships (id, user_id, friend_id, status, created_at)
+VALUES ('f1', 'u1', 'u2', 'accepted', 1700000100),
+       ('f2', 'u2', 'u1', 'accepted', 1700000100);
+
+INSERT INTO messages (id, sender_id, receiver_id, content, message_type, created_at, is_read)
+VALUES ('m1', 'u1', 'u2', '你好', 'private', 1700000200, 0);
+
+INSERT INTO groups (id, group_id, name, creator_id, created_at, group_user_list)
+VALUES ('g1', 'legacy-g1', '月灵开发组', 'u1', 1700000300, 'u1,u2');
+
+INSERT INTO group_members (id, group_id, user_id, joined_at, role)
+VALUES ('gm1', 'g1', 'u1', 1700000300, 'owner'),
+       ('gm2', 'g1', 'u2', 1700000301, 'member');
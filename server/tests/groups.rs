@@ -0,0 +1,52 @@
+mod common;
+
+use common::TestApp;
+use server::{
+    messaging,
+    AppError,
+    MessageExtras
+};
+
+#[tokio::test]
+async fn admins_moderate_group_messages() {
+    let app = TestApp::new();
+    let db = &app.state.db_pool;
+    let owner = db.register_user("grp-owner", "hash").unwrap();
+    let admin = db.register_user("grp-admin", "hash").unwrap();
+    let member = db.register_user("grp-member", "hash").unwrap();
+    let group = db.create_group(&owner.id, "管理测试", &[admin.id.clone(), member.id.clone()]).unwrap();
+    let send = |sender: &str| db.send_message(sender, &group.id, "hi", "group", None, &MessageExtras::default()).unwrap();
+
+    // 普通成员不能撤回别人的消息，也不能@全体成员
+    let first = send(&owner.id);
+    assert!(matches!(messaging::recall_message(&app.state, &admin.id, &first.id).await, Err(AppError::Forbidden(_))));
+    let everyone = MessageExtras { mention_all: true, ..Default::default() };
+    assert!(matches!(
+        messaging::send_group_message(&app.state, &admin.id, &group.id, "@全体", None, everyone.clone()).await,
+        Err(AppError::Forbidden(_))
+    ));
+
+    // 任命为管理员后可以撤回群内任意消息（包括群主的）
+    db.set_member_role(&group.id, &admin.id, "admin").unwrap();
+    assert!(messaging::is_group_moderator(&**db, &group.id, &admin.id).unwrap());
+    assert!(!messaging::is_group_moderator(&**db, &group.id, &member.id).unwrap());
+    let recalled = messaging::recall_message(&app.state, &admin.id, &first.id).await.unwrap();
+    assert_eq!(recalled.deleted_by.as_deref(), Some(admin.id.as_str()));
+    let second = send(&member.id);
+    messaging::recall_message(&app.state, &admin.id, &second.id).await.unwrap();
+
+    // 管理员与群主可以@全体成员
+    let announcement = messaging::send_group_message(&app.state, &admin.id, &group.id, "@全体", None, everyone.clone()).await.unwrap();
+    assert!(announcement.mention_all);
+    messaging::send_group_message(&app.state, &owner.id, &group.id, "@全体", None, everyone.clone()).await.unwrap();
+
+    // 撤销管理员后失去权限
+    db.set_member_role(&group.id, &admin.id, "member").unwrap();
+    let third = send(&member.id);
+    assert!(matches!(messaging::recall_message(&app.state, &admin.id, &third.id).await, Err(AppError::Forbidden(_))));
+    assert!(matches!(
+        messaging::send_group_message(&app.state, &admin.id, &group.id, "@全体", None, everyone).await,
+        Err(AppError::Forbidden(_))
+    ));
+    messaging::recall_message(&app.state, &owner.id, &third.id).await.unwrap();
+}
@@ -0,0 +1,224 @@
+use std::io::Cursor;
+use image::{
+    DynamicImage,
+    ImageFormat,
+    RgbaImage
+};
+use server::{
+    images,
+    AppError
+};
+
+/// 带噪点的小图（压缩数据中会出现需要转义的 0xFF）
+fn noisy_image() -> DynamicImage {
+    let mut seed = 1u32;
+    DynamicImage::ImageRgba8(RgbaImage::from_fn(24, 16, |_, _| {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        let [a, b, c, _] = seed.to_be_bytes();
+        image::Rgba([a, b, c, 255])
+    }))
+}
+
+fn encode(format: ImageFormat) -> Vec<u8> {
+    let image = noisy_image();
+    let image = if format == ImageFormat::Jpeg { DynamicImage::ImageRgb8(image.to_rgb8()) } else { image };
+    let mut content = Cursor::new(Vec::new());
+    image.write_to(&mut content, format).unwrap();
+    content.into_inner()
+}
+
+fn contains(content: &[u8], needle: &[u8]) -> bool {
+    content.windows(needle.len()).any(|w| w == needle)
+}
+
+// JPEG 标记段：标记与包含自身长度字段的长度
+fn jpeg_segment(marker: u8, payload: &[u8]) -> Vec<u8> {
+    let mut segment = vec![0xFF, marker];
+    segment.extend_from_slice(&(payload.len() as u16 + 2).to_be_bytes());
+    segment.extend_from_slice(payload);
+    segment
+}
+
+// PNG 块（CRC-32 覆盖类型与数据）
+fn png_chunk(chunk_type: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in chunk_type.iter().chain(data) {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+    chunk.extend_from_slice(chunk_type);
+    chunk.extend_from_slice(data);
+    chunk.extend_from_slice(&(!crc).to_be_bytes());
+    chunk
+}
+
+// RIFF 块（数据按偶数字节对齐）
+fn riff_chunk(fourcc: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = fourcc.to_vec();
+    chunk.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    chunk.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        chunk.push(0);
+    }
+    chunk
+}
+
+// GIF 扩展块：标签与按 255 字节分割的子块
+fn gif_extension(label: u8, blocks: &[&[u8]]) -> Vec<u8> {
+    let mut extension = vec![0x21, label];
+    for block in blocks {
+        extension.push(block.len() as u8);
+        extension.extend_from_slice(block);
+    }
+    extension.push(0);
+    extension
+}
+
+#[test]
+fn strips_jpeg_metadata_and_trailing_images() {
+    let plain = encode(ImageFormat::Jpeg);
+    // 手机照片：EXIF（含位置）、注释、MPF 与 ICC 色彩配置，EOI 后附带一张有自己 EXIF 的副图
+    let mut secondary = plain[..2].to_vec();
+    secondary.extend(jpeg_segment(0xE1, b"Exif\0\0SECONDARY-GPS"));
+    secondary.extend_from_slice(&plain[2..]);
+    let mut photo = plain[..2].to_vec();
+    photo.extend(jpeg_segment(0xE1, b"Exif\0\0MM\0*GPS-31.2304N"));
+    photo.extend(jpeg_segment(0xE1, b"http://ns.adobe.com/xap/1.0/\0<x:xmpmeta/>"));
+    photo.extend(jpeg_segment(0xFE, b"secret comment"));
+    photo.extend(jpeg_segment(0xE2, b"MPF\0II*\0"));
+    photo.extend(jpeg_segment(0xE2, b"ICC_PROFILE\0\x01\x01profile"));
+    photo.extend_from_slice(&plain[2..]);
+    photo.extend_from_slice(&secondary);
+
+    let stripped = images::strip_metadata(&photo, ImageFormat::Jpeg).unwrap();
+    for needle in [&b"Exif"[..], b"GPS", b"xmpmeta", b"secret comment", b"MPF\0"] {
+        assert!(!contains(&stripped, needle), "{}", String::from_utf8_lossy(needle));
+    }
+    assert!(contains(&stripped, b"ICC_PROFILE\0"));
+    // 压缩数据完整保留，副图整个丢弃
+    assert_eq!(stripped.len(), plain.len() + jpeg_segment(0xE2, b"ICC_PROFILE\0\x01\x01profile").len());
+    assert!(stripped.ends_with(&[0xFF, 0xD9]));
+    assert_eq!(image::load_from_memory(&stripped).unwrap().to_rgb8(), image::load_from_memory(&plain).unwrap().to_rgb8());
+
+    let processed = images::process_image(&photo, "image/jpeg").unwrap();
+    assert_eq!(processed.content, stripped);
+    assert_eq!((processed.info.width, processed.info.height), (24, 16));
+
+    // 没有 EOI 的截断文件不合法
+    assert!(images::strip_metadata(&plain[..plain.len() - 2], ImageFormat::Jpeg).is_none());
+}
+
+#[test]
+fn rotates_jpeg_and_keeps_icc_profile() {
+    let plain = encode(ImageFormat::Jpeg);
+    // EXIF 中的拍摄方向为 6（顺时针旋转 90 度）
+    let exif = b"Exif\0\0MM\0*\0\0\0\x08\0\x01\x01\x12\0\x03\0\0\0\x01\0\x06\0\0\0\0\0\0";
+    let icc_profile = b"fake-icc-profile-data";
+    let mut icc = b"ICC_PROFILE\0\x01\x01".to_vec();
+    icc.extend_from_slice(icc_profile);
+    let mut photo = plain[..2].to_vec();
+    photo.extend(jpeg_segment(0xE1, exif));
+    photo.extend(jpeg_segment(0xE2, &icc));
+    photo.extend_from_slice(&plain[2..]);
+
+    let processed = images::process_image(&photo, "image/jpeg").unwrap();
+    assert_eq!((processed.info.width, processed.info.height), (16, 24));
+    assert!(!contains(&processed.content, b"Exif"));
+    assert!(contains(&processed.content, &icc));
+    let rotated = image::load_from_memory_with_format(&processed.content, ImageFormat::Jpeg).unwrap();
+    assert_eq!((rotated.width(), rotated.height()), (16, 24));
+    let thumbnail = image::load_from_memory(&processed.thumbnail).unwrap();
+    assert_eq!((thumbnail.width(), thumbnail.height()), (16, 24));
+}
+
+#[test]
+fn strips_png_metadata() {
+    let plain = encode(ImageFormat::Png);
+    let iend = plain.len() - 12;
+    let mut photo = plain[..iend].to_vec();
+    photo.extend(png_chunk(b"tEXt", b"Comment\0secret comment"));
+    photo.extend(png_chunk(b"iTXt", b"XML:com.adobe.xmp\0\0\0\0\0<x:xmpmeta/>"));
+    photo.extend(png_chunk(b"eXIf", b"MM\0*GPS"));
+    photo.extend(png_chunk(b"tIME", &[7, 234, 10, 18, 12, 0, 0]));
+    photo.extend_from_slice(&plain[iend..]);
+    photo.extend_from_slice(b"trailing");
+
+    let stripped = images::strip_metadata(&photo, ImageFormat::Png).unwrap();
+    assert_eq!(stripped, plain);
+    assert_eq!(images::process_image(&photo, "image/png").unwrap().content, plain);
+}
+
+#[test]
+fn strips_webp_metadata() {
+    let plain = encode(ImageFormat::WebP);
+    let bitstream = &plain[12..];
+    // 扩展格式：VP8X 标志位声明了 EXIF 与 XMP
+    let mut vp8x = vec![0x08 | 0x04, 0, 0, 0];
+    vp8x.extend_from_slice(&(24u32 - 1).to_le_bytes()[..3]);
+    vp8x.extend_from_slice(&(16u32 - 1).to_le_bytes()[..3]);
+    let mut body = b"WEBP".to_vec();
+    body.extend(riff_chunk(b"VP8X", &vp8x));
+    body.extend_from_slice(bitstream);
+    body.extend(riff_chunk(b"EXIF", b"MM\0*GPS"));
+    body.extend(riff_chunk(b"XMP ", b"<x:xmpmeta/>"));
+    let mut photo = b"RIFF".to_vec();
+    photo.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    photo.extend(body);
+
+    let stripped = images::strip_metadata(&photo, ImageFormat::WebP).unwrap();
+    assert!(!contains(&stripped, b"GPS") && !contains(&stripped, b"xmpmeta"));
+    assert_eq!(stripped[20] & (0x08 | 0x04), 0);
+    assert_eq!(u32::from_le_bytes(stripped[4..8].try_into().unwrap()) as usize, stripped.len() - 8);
+    assert_eq!(stripped.len(), 12 + 18 + bitstream.len());
+    assert!(images::process_image(&photo, "image/webp").is_ok());
+}
+
+#[test]
+fn strips_gif_metadata() {
+    let plain = encode(ImageFormat::Gif);
+    let trailer = plain.len() - 1;
+    assert_eq!(plain[trailer], 0x3B);
+    let looping = gif_extension(0xFF, &[b"NETSCAPE2.0", &[1, 0, 0]]);
+    let mut photo = plain[..trailer].to_vec();
+    photo.extend(gif_extension(0xFE, &[b"secret comment"]));
+    photo.extend(gif_extension(0xFF, &[b"XMP DataXMP", b"<x:xmpmeta/>"]));
+    photo.extend_from_slice(&looping);
+    photo.extend_from_slice(b";trailing secret");
+
+    let stripped = images::strip_metadata(&photo, ImageFormat::Gif).unwrap();
+    assert!(!contains(&stripped, b"secret") && !contains(&stripped, b"xmpmeta"));
+    let mut expected = plain[..trailer].to_vec();
+    expected.extend(looping);
+    expected.push(0x3B);
+    assert_eq!(stripped, expected);
+    assert_eq!(images::process_image(&photo, "image/gif").unwrap().content, expected);
+
+    // 块结构不完整时不合法
+    assert!(images::strip_metadata(&plain[..trailer - 1], ImageFormat::Gif).is_none());
+}
+
+#[test]
+fn rejects_files_disguised_as_images() {
+    let png = encode(ImageFormat::Png);
+    let fakes: [(&[u8], &str); 4] = [
+        (b"MZ\x90\0\x03\0\0\0\x04\0\0\0\xFF\xFF\0\0", "image/png"),
+        (b"<html><script>alert(1)</script></html>", "image/jpeg"),
+        (&png, "image/jpeg"),
+        (&png[..png.len() / 2], "image/png"),
+    ];
+    for (content, mime_type) in fakes {
+        assert!(matches!(images::process_image(content, mime_type), Err(AppError::BadRequest(_))), "{}", mime_type);
+        assert!(matches!(images::process_avatar(content, mime_type), Err(AppError::BadRequest(_))), "{}", mime_type);
+    }
+    for mime_type in ["text/html", "image/svg+xml"] {
+        assert!(matches!(images::process_image(&png, mime_type), Err(AppError::BadRequest(_))));
+        assert!(matches!(images::process_avatar(&png, mime_type), Err(AppError::BadRequest(_))));
+    }
+
+    let avatar = images::process_avatar(&png, "image/png").unwrap();
+    assert_eq!(image::load_from_memory(&avatar.content).unwrap().width(), images::AVATAR_SIZE);
+    assert_eq!(avatar.variants.len(), images::AVATAR_VARIANT_SIZES.len());
+}
@@ -0,0 +1,139 @@
+use rusqlite::Connection;
+use server::{
+    sqlite::{
+        migrations,
+        SqliteStorage
+    },
+    PoolOptions,
+    Storage
+};
+use std::path::PathBuf;
+
+/// 临时数据库文件，测试结束时删除
+struct TempDb(PathBuf);
+
+impl TempDb {
+    /// 用引入迁移机制之前的结构与数据创建数据库
+    fn from_baseline_fixture() -> Self {
+        let path = std::env::temp_dir().join(format!("yueling-migrate-{}.db", uuid::Uuid::new_v4()));
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(include_str!("fixtures/baseline.sql")).unwrap();
+        Self(path)
+    }
+
+    fn connect(&self) -> Connection {
+        Connection::open(&self.0).unwrap()
+    }
+}
+
+impl Drop for TempDb {
+    fn drop(&mut self) {
+        // 连接池以 WAL 模式打开数据库，一并删除 -wal 与 -shm 文件
+        for suffix in ["", "-wal", "-shm"] {
+            let mut path = self.0.clone().into_os_string();
+            path.push(suffix);
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+fn columns(conn: &Connection, table: &str) -> Vec<String> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table)).unwrap();
+    stmt.query_map([], |row| row.get::<_, String>(1))
+        .unwrap()
+        .map(Result::unwrap)
+        .collect()
+}
+
+#[test]
+fn upgrades_baseline_database_to_latest_version() {
+    let db = TempDb::from_baseline_fixture();
+
+    let pool = SqliteStorage::open(&db.0).unwrap();
+    assert_eq!(pool.schema_version().unwrap(), 0);
+
+    let applied = pool.migrate(false).unwrap();
+    assert_eq!(applied.len(), migrations::MIGRATIONS.len());
+    assert_eq!(pool.schema_version().unwrap(), migrations::latest_version());
+    drop(pool);
+
+    let conn = db.connect();
+
+    // groups 表中遗留的列已移除，数据保留
+    let group_columns = columns(&conn, "groups");
+    assert!(!group_columns.contains(&"group_id".to_string()));
+    assert!(!group_columns.contains(&"group_user_list".to_string()));
+    let group_name: String = conn
+        .query_row("SELECT name FROM groups WHERE id = 'g1'", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(group_name, "月灵开发组");
+
+    // 原有数据未受影响
+    let users: i64 = conn.query_row("SELECT COUNT(*) FROM users", [], |row| row.get(0)).unwrap();
+    let members: i64 = conn.query_row("SELECT COUNT(*) FROM group_members", [], |row| row.get(0)).unwrap();
+    let content: String = conn
+        .query_row("SELECT content FROM messages WHERE id = 'm1'", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(users, 2);
+    assert_eq!(members, 2);
+    assert_eq!(content, "你好");
+
+    // 新表已创建
+    assert!(columns(&conn, "sessions").contains(&"refresh_token_hash".to_string()));
+
+    // 已有消息写入了全文索引（中文按单字切分）
+    let indexed: String = conn
+        .query_row(
+            "SELECT m.id FROM messages_fts f JOIN messages m ON m.rowid = f.rowid WHERE messages_fts MATCH '\"你 好\"'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(indexed, "m1");
+}
+
+#[test]
+fn migrating_twice_is_a_no_op() {
+    let db = TempDb::from_baseline_fixture();
+
+    SqliteStorage::open(&db.0).unwrap().migrate(false).unwrap();
+    let pool = SqliteStorage::open(&db.0).unwrap();
+    assert!(pool.migrate(false).unwrap().is_empty());
+    assert_eq!(pool.schema_version().unwrap(), migrations::latest_version());
+}
+
+#[test]
+fn dry_run_leaves_database_unchanged() {
+    let db = TempDb::from_baseline_fixture();
+    let before = std::fs::read(&db.0).unwrap();
+
+    let pool = SqliteStorage::open_dry_run(&db.0, PoolOptions::default()).unwrap();
+    let pending = pool.migrate(true).unwrap();
+    assert_eq!(pending.len(), migrations::MIGRATIONS.len());
+    assert_eq!(pool.schema_version().unwrap(), 0);
+    drop(pool);
+
+    // 文件内容不变，没有切换到 WAL 模式，也没有创建版本记录表
+    assert_eq!(std::fs::read(&db.0).unwrap(), before);
+    let mut wal = db.0.clone().into_os_string();
+    wal.push("-wal");
+    assert!(!PathBuf::from(wal).exists());
+    let conn = db.connect();
+    let journal_mode: String = conn.query_row("PRAGMA journal_mode", [], |row| row.get(0)).unwrap();
+    assert_eq!(journal_mode, "delete");
+    assert!(columns(&conn, "schema_version").is_empty());
+    assert!(columns(&conn, "groups").contains(&"group_id".to_string()));
+    assert!(columns(&conn, "sessions").is_empty());
+}
+
+#[test]
+fn dry_run_does_not_create_missing_database() {
+    let path = std::env::temp_dir().join(format!("yueling-missing-{}.db", uuid::Uuid::new_v4()));
+
+    let pool = SqliteStorage::open_dry_run(&path, PoolOptions::default()).unwrap();
+    assert_eq!(pool.schema_version().unwrap(), 0);
+    assert_eq!(pool.migrate(true).unwrap().len(), migrations::MIGRATIONS.len());
+    assert_eq!(pool.schema_version().unwrap(), 0);
+    drop(pool);
+    assert!(!path.exists());
+}
@@ -0,0 +1,151 @@
+use serde_json::{
+    json,
+    Value
+};
+use server::{
+    protocol::{
+        negotiate_version,
+        ClientEnvelope,
+        ClientFrame,
+        PresenceStatus,
+        ServerFrame,
+        MIN_PROTOCOL_VERSION,
+        PROTOCOL_VERSION
+    },
+    AppError
+};
+
+fn parse(frame: Value) -> ClientEnvelope {
+    serde_json::from_str(&frame.to_string()).unwrap()
+}
+
+/// 序列化为发送给客户端的文本后再解析，与期望的 JSON 比较
+fn encoded(frame: &ServerFrame) -> Value {
+    serde_json::from_str(&frame.encode()).unwrap()
+}
+
+#[test]
+fn negotiates_protocol_version() {
+    assert_eq!(negotiate_version(None), Some(PROTOCOL_VERSION));
+    assert_eq!(negotiate_version(Some(PROTOCOL_VERSION)), Some(PROTOCOL_VERSION));
+    assert_eq!(negotiate_version(Some(PROTOCOL_VERSION + 5)), Some(PROTOCOL_VERSION));
+    assert_eq!(negotiate_version(Some(MIN_PROTOCOL_VERSION)), Some(MIN_PROTOCOL_VERSION));
+    assert_eq!(negotiate_version(Some(MIN_PROTOCOL_VERSION - 1)), None);
+
+    let envelope = parse(json!({"type": "identify", "token": "t", "version": 7}));
+    assert!(matches!(envelope.frame, ClientFrame::Identify { ref token, version: Some(7) } if token == "t"));
+    // 旧客户端使用 auth 类型且不声明版本
+    let envelope = parse(json!({"type": "auth", "token": "t"}));
+    assert!(matches!(envelope.frame, ClientFrame::Identify { version: None, .. }));
+
+    let identified = ServerFrame::Identified {
+        protocol_version: PROTOCOL_VERSION,
+        user_id: "u1".into(),
+        session_id: "s1".into(),
+        group_ids: vec!["g1".into()],
+    };
+    assert_eq!(
+        encoded(&identified),
+        json!({"type": "identified", "protocol_version": PROTOCOL_VERSION, "user_id": "u1", "session_id": "s1", "group_ids": ["g1"]})
+    );
+}
+
+#[test]
+fn parses_client_frames_with_request_ids() {
+    let envelope = parse(json!({
+        "type": "friend_message",
+        "request_id": "r1",
+        "receiver_id": "u2",
+        "content": "你好",
+    }));
+    assert_eq!(envelope.request_id.as_deref(), Some("r1"));
+    match envelope.frame {
+        ClientFrame::FriendMessage { receiver_id, content, reply_to_id, attachments } => {
+            assert_eq!((receiver_id.as_str(), content.as_str()), ("u2", "你好"));
+            assert!(reply_to_id.is_none() && attachments.is_empty());
+        }
+        other => panic!("应解析为私聊消息: {:?}", other),
+    }
+
+    // 兼容旧字段名 client_msg_id
+    let envelope = parse(json!({"type": "delivered", "client_msg_id": "old", "message_ids": ["m1"]}));
+    assert_eq!(envelope.request_id.as_deref(), Some("old"));
+    assert!(matches!(envelope.frame, ClientFrame::Delivered { ref message_ids } if message_ids == &["m1"]));
+
+    let envelope = parse(json!({"type": "group_chat", "group_id": "g1", "mentions": ["u3"], "mention_all": true, "attachments": ["a1"]}));
+    assert!(envelope.request_id.is_none());
+    assert!(matches!(
+        envelope.frame,
+        ClientFrame::GroupChat { ref mentions, mention_all: true, ref attachments, .. } if mentions == &["u3"] && attachments == &["a1"]
+    ));
+
+    assert!(matches!(parse(json!({"type": "typing", "group_id": "g1"})).frame, ClientFrame::Typing { typing: true, .. }));
+    assert!(matches!(parse(json!({"type": "presence", "status": "away"})).frame, ClientFrame::Presence { status: PresenceStatus::Away }));
+    assert!(matches!(parse(json!({"type": "react", "message_id": "m1", "emoji": "👍"})).frame, ClientFrame::React { remove: false, .. }));
+    assert!(matches!(parse(json!({"type": "sync", "since": 3})).frame, ClientFrame::Sync { since: Some(3), limit: None }));
+    assert!(matches!(parse(json!({"type": "something_new", "request_id": "r9"})).frame, ClientFrame::Unknown));
+
+    // 缺少必填字段、状态取值无效时解析失败
+    assert!(serde_json::from_value::<ClientEnvelope>(json!({"type": "friend_message"})).is_err());
+    assert!(serde_json::from_value::<ClientEnvelope>(json!({"type": "presence", "status": "busy"})).is_err());
+}
+
+#[test]
+fn replies_carry_request_id_and_errors_carry_codes() {
+    assert_eq!(encoded(&ServerFrame::Ack { request_id: Some("r1".into()) }), json!({"type": "ack", "request_id": "r1"}));
+    assert_eq!(encoded(&ServerFrame::Ack { request_id: None }), json!({"type": "ack", "request_id": null}));
+
+    let error = ServerFrame::from_error(Some("r2".into()), &AppError::NotFound("消息不存在".into()));
+    assert_eq!(
+        encoded(&error),
+        json!({"type": "error", "request_id": "r2", "code": "not_found", "message": "资源未找到: 消息不存在"})
+    );
+    let error = ServerFrame::error(None, "bad_frame", "帧格式错误");
+    assert_eq!(encoded(&error), json!({"type": "error", "request_id": null, "code": "bad_frame", "message": "帧格式错误"}));
+
+    let ack = ServerFrame::MessageAck {
+        request_id: Some("r3".into()),
+        message_id: "m1".into(),
+        receiver_id: None,
+        group_id: Some("g1".into()),
+        created_at: 10,
+    };
+    assert_eq!(
+        encoded(&ack),
+        json!({"type": "message_ack", "request_id": "r3", "message_id": "m1", "group_id": "g1", "created_at": 10})
+    );
+}
+
+#[test]
+fn pushes_do_not_use_request_id() {
+    let push = ServerFrame::FriendRequest {
+        friend_request_id: "fr1".into(),
+        from_user_id: "u1".into(),
+        to_user_id: "u2".into(),
+        message: "您收到新的好友请求".into(),
+    };
+    let value = encoded(&push);
+    assert_eq!(value["type"], "friend_request");
+    assert_eq!(value["friend_request_id"], "fr1");
+    assert!(value.get("request_id").is_none());
+
+    // 可选字段为空时省略
+    let message = ServerFrame::FriendMessage {
+        message_id: "m1".into(),
+        sender_id: "u1".into(),
+        receiver_id: "u2".into(),
+        content: "hi".into(),
+        created_at: 1,
+        edited_at: None,
+        deleted_at: None,
+        deleted_by: None,
+        reply_to_id: None,
+        attachments: Vec::new(),
+    };
+    assert_eq!(
+        encoded(&message),
+        json!({"type": "friend_message", "message_id": "m1", "sender_id": "u1", "receiver_id": "u2", "content": "hi", "created_at": 1})
+    );
+    assert_eq!(message.message_id(), Some("m1"));
+    assert_eq!(push.message_id(), None);
+}
@@ -0,0 +1,965 @@
+//! 存储后端一致性测试
+//!
+//! 同一组用例分别在 SQLite 和 PostgreSQL 后端上运行。
+//! PostgreSQL 用例需要设置 `YUELING_TEST_POSTGRES_URL`（例如
+//! `postgres://postgres@127.0.0.1:5432/postgres`），每次测试创建并删除一个临时数据库；
+//! 未设置时跳过。
+use postgres::NoTls;
+use server::{
+    postgres::PostgresStorage,
+    sqlite::SqliteStorage,
+    Conversation,
+    HistoryCursor,
+    ImageInfo,
+    MessageExtras,
+    MessageSearch,
+    NewAttachment,
+    Storage,
+    StorageError
+};
+use std::path::PathBuf;
+
+/// 临时 SQLite 数据库文件，测试结束时删除
+struct TempSqlite(PathBuf);
+
+impl TempSqlite {
+    fn new() -> Self {
+        Self(std::env::temp_dir().join(format!("yueling-storage-{}.db", uuid::Uuid::new_v4())))
+    }
+}
+
+impl Drop for TempSqlite {
+    fn drop(&mut self) {
+        for suffix in ["", "-wal", "-shm"] {
+            let mut path = self.0.clone().into_os_string();
+            path.push(suffix);
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// 临时 PostgreSQL 数据库，测试结束时删除
+struct TempPostgres {
+    admin_url: String,
+    name: String,
+}
+
+impl TempPostgres {
+    fn new() -> Option<Self> {
+        let admin_url = std::env::var("YUELING_TEST_POSTGRES_URL").ok()?;
+        let name = format!("yueling_test_{}", uuid::Uuid::new_v4().simple());
+        let mut client = postgres::Client::connect(&admin_url, NoTls).unwrap();
+        client.batch_execute(&format!("CREATE DATABASE {}", name)).unwrap();
+        Some(Self { admin_url, name })
+    }
+
+    fn url(&self) -> String {
+        let config: postgres::Config = self.admin_url.parse().unwrap();
+        // postgres::Config 没有序列化方法，按关键字格式重新拼接
+        let mut url = format!("dbname={}", self.name);
+        for host in config.get_hosts() {
+            if let postgres::config::Host::Tcp(host) = host {
+                url.push_str(&format!(" host={}", host));
+            }
+        }
+        if let Some(port) = config.get_ports().first() {
+            url.push_str(&format!(" port={}", port));
+        }
+        if let Some(user) = config.get_user() {
+            url.push_str(&format!(" user={}", user));
+        }
+        if let Some(password) = config.get_password() {
+            url.push_str(&format!(" password={}", String::from_utf8_lossy(password)));
+        }
+        url
+    }
+}
+
+impl Drop for TempPostgres {
+    fn drop(&mut self) {
+        if let Ok(mut client) = postgres::Client::connect(&self.admin_url, NoTls) {
+            let _ = client.batch_execute(&format!("DROP DATABASE IF EXISTS {} WITH (FORCE)", self.name));
+        }
+    }
+}
+
+// 注册、好友请求、好友关系与消息的完整流程
+fn exercise_users_and_friends(db: &dyn Storage) {
+    db.migrate(false).unwrap();
+    assert_eq!(db.schema_version().unwrap(), db.latest_version());
+
+    let alice = db.register_user("alice", "hash-a").unwrap();
+    let bob = db.register_user("bob", "hash-b").unwrap();
+    assert!(matches!(db.register_user("alice", "other"), Err(StorageError::UsernameTaken)));
+
+    assert_eq!(db.get_user_by_username("alice").unwrap().id, alice.id);
+    assert_eq!(db.get_username(&bob.id).unwrap(), "bob");
+    assert!(db.user_exists_by_id(&alice.id).unwrap());
+    assert!(matches!(db.get_user_by_username("nobody"), Err(StorageError::NotFound)));
+    assert_eq!(db.search_users("ALI").unwrap().len(), 1);
+    assert_eq!(db.get_user_by_id(&alice.id).unwrap().last_seen, None);
+    db.update_last_seen(&alice.id, 1_700_000_000).unwrap();
+    assert_eq!(db.get_user_by_id(&alice.id).unwrap().last_seen, Some(1_700_000_000));
+
+    assert!(matches!(db.send_friend_request(&alice.id, "nobody"), Err(StorageError::NotFound)));
+    let request = db.send_friend_request(&alice.id, "bob").unwrap();
+    assert!(matches!(
+        db.send_friend_request(&alice.id, "bob"),
+        Err(StorageError::FriendRequestAlreadySent)
+    ));
+    assert_eq!(db.get_received_friend_requests(&bob.id).unwrap().len(), 1);
+
+    // 只有接收方可以处理请求
+    assert!(matches!(
+        db.respond_to_friend_request(&request.id, &alice.id, "accepted"),
+        Err(StorageError::NotFound)
+    ));
+    db.respond_to_friend_request(&request.id, &bob.id, "accepted").unwrap();
+    assert!(matches!(
+        db.respond_to_friend_request(&request.id, &bob.id, "accepted"),
+        Err(StorageError::FriendRequestAlreadyProcessed)
+    ));
+    assert!(db.are_friends(&alice.id, &bob.id).unwrap() && db.are_friends(&bob.id, &alice.id).unwrap());
+    assert_eq!(db.get_friends(&alice.id).unwrap()[0].id, bob.id);
+    assert_eq!(db.get_friends(&bob.id).unwrap()[0].last_seen, Some(1_700_000_000));
+    assert_eq!(db.get_friends(&bob.id).unwrap()[0].id, alice.id);
+    assert!(matches!(db.send_friend_request(&bob.id, "alice"), Err(StorageError::AlreadyFriends)));
+
+    let message = db.send_message(&alice.id, &bob.id, "你好", "private", None, &MessageExtras::default()).unwrap();
+    assert_eq!(db.get_unread_messages(&bob.id).unwrap().len(), 1);
+    // 只有接收方可以标记已读
+    assert!(db.mark_messages_as_read(&alice.id, std::slice::from_ref(&message.id)).unwrap().is_empty());
+    assert_eq!(db.mark_messages_as_read(&bob.id, std::slice::from_ref(&message.id)).unwrap().len(), 1);
+    assert!(db.get_unread_messages(&bob.id).unwrap().is_empty());
+
+    db.remove_friend(&bob.id, &alice.id).unwrap();
+    assert!(db.get_friends(&alice.id).unwrap().is_empty());
+    assert!(db.get_friends(&bob.id).unwrap().is_empty());
+    assert!(!db.are_friends(&alice.id, &bob.id).unwrap());
+}
+
+// 群聊创建、成员管理与解散
+fn exercise_groups(db: &dyn Storage) {
+    db.migrate(false).unwrap();
+    let owner = db.register_user("dave", "hash-d").unwrap();
+    let member = db.register_user("erin", "hash-e").unwrap();
+
+    let group = db.create_group(&owner.id, "月灵开发组", std::slice::from_ref(&member.id)).unwrap();
+    assert_eq!(db.get_member_role(&group.id, &owner.id).unwrap().as_deref(), Some("owner"));
+    assert_eq!(db.get_member_role(&group.id, &member.id).unwrap().as_deref(), Some("member"));
+    assert_eq!(db.get_group_members(&group.id).unwrap()[0].user_id, owner.id);
+    assert_eq!(db.get_user_groups(&member.id).unwrap()[0].1.role, "member");
+    assert!(db.add_group_member(&group.id, &member.id, "member").is_err());
+
+    // 任命、撤销管理员；群主的角色不能被修改
+    db.set_member_role(&group.id, &member.id, "admin").unwrap();
+    assert_eq!(db.get_member_role(&group.id, &member.id).unwrap().as_deref(), Some("admin"));
+    db.set_member_role(&group.id, &member.id, "member").unwrap();
+    assert_eq!(db.get_member_role(&group.id, &member.id).unwrap().as_deref(), Some("member"));
+    assert!(matches!(db.set_member_role(&group.id, &owner.id, "member"), Err(StorageError::NotFound)));
+    assert!(matches!(db.set_member_role(&group.id, "missing", "admin"), Err(StorageError::NotFound)));
+    assert_eq!(db.get_member_role(&group.id, &owner.id).unwrap().as_deref(), Some("owner"));
+
+    db.rename_group(&group.id, "新名字").unwrap();
+    assert_eq!(db.get_group(&group.id).unwrap().name, "新名字");
+
+    assert!(db.remove_group_member(&group.id, &member.id).unwrap());
+    assert!(!db.remove_group_member(&group.id, &member.id).unwrap());
+    assert!(!db.is_group_member(&group.id, &member.id).unwrap());
+
+    db.send_message(&owner.id, &group.id, "大家好", "group", None, &MessageExtras::default()).unwrap();
+    db.delete_group(&group.id).unwrap();
+    assert!(!db.group_exists(&group.id).unwrap());
+    assert!(db.get_user_group_ids(&owner.id).unwrap().is_empty());
+    assert!(matches!(db.get_group(&group.id), Err(StorageError::NotFound)));
+}
+
+// 历史消息游标分页
+fn exercise_message_history(db: &dyn Storage) {
+    db.migrate(false).unwrap();
+    let alice = db.register_user("frank", "hash-f").unwrap();
+    let bob = db.register_user("grace", "hash-g").unwrap();
+    let private = Conversation::Private(&alice.id, &bob.id);
+
+    let mut sent = Vec::new();
+    for i in 0..5 {
+        let (from, to) = if i % 2 == 0 { (&alice.id, &bob.id) } else { (&bob.id, &alice.id) };
+        sent.push(db.send_message(from, to, &format!("消息{}", i), "private", None, &MessageExtras::default()).unwrap().id);
+    }
+    let group = db.create_group(&alice.id, "历史", &[]).unwrap();
+    let group_message = db.send_message(&alice.id, &group.id, "群消息", "group", None, &MessageExtras::default()).unwrap();
+
+    // 最新一页包含全部私聊消息，按发送顺序排列
+    let all = db.get_message_history(&alice.id, private, HistoryCursor::Latest, 10).unwrap();
+    let ids: Vec<&str> = all.iter().map(|m| m.id.as_str()).collect();
+    assert_eq!(ids, sent);
+    // 双方顺序无关
+    assert_eq!(db.get_message_history(&bob.id, Conversation::Private(&bob.id, &alice.id), HistoryCursor::Latest, 10).unwrap().len(), 5);
+
+    let latest = db.get_message_history(&alice.id, private, HistoryCursor::Latest, 2).unwrap();
+    assert_eq!(latest.iter().map(|m| m.id.as_str()).collect::<Vec<_>>(), ids[3..]);
+    let before = db.get_message_history(&alice.id, private, HistoryCursor::Before(ids[3]), 2).unwrap();
+    assert_eq!(before.iter().map(|m| m.id.as_str()).collect::<Vec<_>>(), ids[1..3]);
+    let after = db.get_message_history(&alice.id, private, HistoryCursor::After(ids[1]), 2).unwrap();
+    assert_eq!(after.iter().map(|m| m.id.as_str()).collect::<Vec<_>>(), ids[2..4]);
+    assert!(db.get_message_history(&alice.id, private, HistoryCursor::After(ids[4]), 2).unwrap().is_empty());
+
+    // 游标必须属于同一会话
+    assert!(matches!(
+        db.get_message_history(&alice.id, private, HistoryCursor::Before(&group_message.id), 2),
+        Err(StorageError::NotFound)
+    ));
+    let group_history = db.get_message_history(&alice.id, Conversation::Group(&group.id), HistoryCursor::Latest, 10).unwrap();
+    assert_eq!(group_history.len(), 1);
+    assert_eq!(group_history[0].id, group_message.id);
+}
+
+// 消息搜索：中文子串、权限范围与过滤条件
+fn exercise_message_search(db: &dyn Storage) {
+    db.migrate(false).unwrap();
+    let alice = db.register_user("heidi", "hash-h").unwrap();
+    let bob = db.register_user("ivan", "hash-i").unwrap();
+    let mallory = db.register_user("judy", "hash-j").unwrap();
+
+    let first = db.send_message(&alice.id, &bob.id, "明天一起去图书馆吗", "private", None, &MessageExtras::default()).unwrap();
+    let second = db.send_message(&bob.id, &alice.id, "好的，图书馆见 Library", "private", None, &MessageExtras::default()).unwrap();
+    db.send_message(&mallory.id, &bob.id, "图书馆关门了", "private", None, &MessageExtras::default()).unwrap();
+    let group = db.create_group(&alice.id, "读书会", std::slice::from_ref(&bob.id)).unwrap();
+    let in_group = db.send_message(&bob.id, &group.id, "下周图书馆读书会", "group", None, &MessageExtras::default()).unwrap();
+
+    let search = |query, limit| MessageSearch {
+        user_id: &alice.id,
+        query,
+        sender_id: None,
+        conversation: None,
+        since: None,
+        until: None,
+        before: None,
+        limit,
+    };
+    let ids = |messages: Vec<server::Message>| messages.into_iter().map(|m| m.id).collect::<Vec<_>>();
+
+    // 只返回搜索者参与的会话，从新到旧
+    assert_eq!(ids(db.search_messages(&search("图书馆", 10)).unwrap()), [in_group.id.as_str(), second.id.as_str(), first.id.as_str()]);
+    // 多个检索词需全部匹配；英文不区分大小写且支持前缀
+    assert_eq!(ids(db.search_messages(&search("图书 lib", 10)).unwrap()), [second.id.as_str()]);
+    assert!(db.search_messages(&search("书图", 10)).unwrap().is_empty());
+    assert!(db.search_messages(&search("！？", 10)).unwrap().is_empty());
+
+    assert_eq!(ids(db.search_messages(&MessageSearch { sender_id: Some(&alice.id), ..search("图书馆", 10) }).unwrap()), [first.id.as_str()]);
+    assert_eq!(
+        ids(db.search_messages(&MessageSearch { conversation: Some(Conversation::Group(&group.id)), ..search("图书馆", 10) }).unwrap()),
+        [in_group.id.as_str()]
+    );
+    assert_eq!(
+        db.search_messages(&MessageSearch { conversation: Some(Conversation::Private(&alice.id, &bob.id)), ..search("图书馆", 10) }).unwrap().len(),
+        2
+    );
+    assert!(db.search_messages(&MessageSearch { since: Some(in_group.created_at + 1), ..search("图书馆", 10) }).unwrap().is_empty());
+
+    // 游标分页
+    assert_eq!(ids(db.search_messages(&search("图书馆", 2)).unwrap()), [in_group.id.as_str(), second.id.as_str()]);
+    assert_eq!(ids(db.search_messages(&MessageSearch { before: Some(&second.id), ..search("图书馆", 2) }).unwrap()), [first.id.as_str()]);
+
+    // 删除群聊后其消息不再出现在搜索结果中
+    db.delete_group(&group.id).unwrap();
+    assert_eq!(db.search_messages(&search("读书会", 10)).unwrap().len(), 0);
+}
+
+// 私聊送达/已读回执与群已读位置
+fn exercise_receipts(db: &dyn Storage) {
+    db.migrate(false).unwrap();
+    let alice = db.register_user("kate", "hash-k").unwrap();
+    let bob = db.register_user("leo", "hash-l").unwrap();
+    let carol = db.register_user("mia", "hash-m").unwrap();
+
+    let message = db.send_message(&alice.id, &bob.id, "在吗", "private", None, &MessageExtras::default()).unwrap();
+    let ids = std::slice::from_ref(&message.id);
+    assert!(db.mark_messages_delivered(&alice.id, ids).unwrap().is_empty());
+    let delivered = db.mark_messages_delivered(&bob.id, ids).unwrap();
+    assert!(delivered[0].delivered_at.is_some() && delivered[0].read_at.is_none());
+    assert!(db.mark_messages_delivered(&bob.id, ids).unwrap().is_empty());
+    db.mark_messages_as_read(&bob.id, ids).unwrap();
+    let stored = db.get_message(&message.id).unwrap();
+    assert!(stored.is_read && stored.read_at.is_some());
+    assert!(matches!(db.get_message("nope"), Err(StorageError::NotFound)));
+
+    let group = db.create_group(&alice.id, "回执", &[bob.id.clone(), carol.id.clone()]).unwrap();
+    let first = db.send_message(&alice.id, &group.id, "一", "group", None, &MessageExtras::default()).unwrap();
+    let second = db.send_message(&alice.id, &group.id, "二", "group", None, &MessageExtras::default()).unwrap();
+    let count = db.count_group_readers(&first).unwrap();
+    assert_eq!((count.read, count.total), (0, 2));
+
+    assert!(db.advance_group_read_cursor(&group.id, &bob.id, &second.id).unwrap());
+    // 已读位置只前进不后退
+    assert!(!db.advance_group_read_cursor(&group.id, &bob.id, &first.id).unwrap());
+    assert!(db.advance_group_read_cursor(&group.id, &carol.id, &first.id).unwrap());
+    assert!(matches!(
+        db.advance_group_read_cursor(&group.id, &bob.id, &message.id),
+        Err(StorageError::NotFound)
+    ));
+    let count = db.count_group_readers(&first).unwrap();
+    assert_eq!((count.read, count.total), (2, 2));
+    let count = db.count_group_readers(&second).unwrap();
+    assert_eq!((count.read, count.total), (1, 2));
+
+    // 退群后已读位置清除，删除群聊时一并删除
+    db.remove_group_member(&group.id, &bob.id).unwrap();
+    let count = db.count_group_readers(&second).unwrap();
+    assert_eq!((count.read, count.total), (0, 1));
+    db.delete_group(&group.id).unwrap();
+}
+
+// 会话与签名密钥
+fn exercise_sessions(db: &dyn Storage) {
+    db.migrate(false).unwrap();
+    let user = db.register_user("carol", "hash-c").unwrap();
+
+    let secret = db.get_or_create_token_secret("first").unwrap();
+    assert_eq!(secret, "first");
+    assert_eq!(db.get_or_create_token_secret("second").unwrap(), "first");
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    let far = now + 3600;
+    let session = db.create_session(&user.id, "h1", far, Some("phone")).unwrap();
+    assert!(db.is_session_active(&session.id, &user.id).unwrap());
+
+    let rotated = db.rotate_refresh_token("h1", "h2", far).unwrap().unwrap();
+    assert_eq!(rotated.id, session.id);
+    assert_eq!(rotated.device_name.as_deref(), Some("phone"));
+    assert!(db.rotate_refresh_token("h1", "h3", far).unwrap().is_none());
+
+    // 同一用户可同时保持多个设备会话，过期会话不计入
+    let laptop = db.create_session(&user.id, "l1", far, None).unwrap();
+    db.create_session(&user.id, "old", now - 1, Some("stale")).unwrap();
+    let active: Vec<String> = db.get_active_sessions(&user.id).unwrap().into_iter().map(|s| s.id).collect();
+    assert_eq!(active.len(), 2);
+    assert!(active.contains(&session.id) && active.contains(&laptop.id));
+
+    db.revoke_session(&session.id).unwrap();
+    assert!(!db.is_session_active(&session.id, &user.id).unwrap());
+    assert!(db.rotate_refresh_token("h2", "h3", far).unwrap().is_none());
+    let active = db.get_active_sessions(&user.id).unwrap();
+    assert_eq!(active.len(), 1);
+    assert_eq!(active[0].id, laptop.id);
+    assert!(active[0].device_name.is_none());
+}
+
+fn exercise_user_events(db: &dyn Storage) {
+    db.migrate(false).unwrap();
+    let alice = db.register_user("alice", "hash-a").unwrap();
+    let bob = db.register_user("bob", "hash-b").unwrap();
+
+    // 每个用户的序号独立递增，重复的接收者只记录一次
+    let both = vec![alice.id.clone(), bob.id.clone(), alice.id.clone()];
+    let first = db.append_user_events(&both, "friend_message", r#"{"type":"friend_message"}"#, Some("m1")).unwrap();
+    assert_eq!(first.len(), 2);
+    assert!(first.iter().all(|e| e.seq == 1));
+    let second = db.append_user_events(std::slice::from_ref(&alice.id), "receipt", r#"{"type":"receipt"}"#, None).unwrap();
+    assert_eq!(second[0].seq, 2);
+    db.append_user_events(std::slice::from_ref(&alice.id), "group_joined", r#"{"type":"group_joined"}"#, None).unwrap();
+
+    let all: Vec<i64> = db.get_user_events(&alice.id, 0, 10).unwrap().iter().map(|e| e.seq).collect();
+    assert_eq!(all, [1, 2, 3]);
+    let page = db.get_user_events(&alice.id, 1, 1).unwrap();
+    assert_eq!(page.len(), 1);
+    assert_eq!((page[0].seq, page[0].kind.as_str()), (2, "receipt"));
+    assert_eq!(db.get_user_events(&bob.id, 0, 10).unwrap()[0].message_id.as_deref(), Some("m1"));
+    assert_eq!(db.get_user_events(&bob.id, 1, 10).unwrap().len(), 0);
+
+    // 设备同步位置只前进不后退，各设备互不影响
+    let far = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+        + 3600;
+    let phone = db.create_session(&alice.id, "p1", far, None).unwrap();
+    let laptop = db.create_session(&alice.id, "l1", far, None).unwrap();
+    assert_eq!(db.get_sync_cursor(&phone.id).unwrap(), 0);
+    assert_eq!(db.advance_sync_cursor(&phone.id, 2).unwrap(), 2);
+    assert_eq!(db.advance_sync_cursor(&phone.id, 1).unwrap(), 2);
+    assert_eq!(db.get_sync_cursor(&laptop.id).unwrap(), 0);
+    assert!(matches!(db.get_sync_cursor("missing"), Err(StorageError::NotFound)));
+}
+
+// 消息编辑、撤回与仅对自己删除
+fn exercise_message_edits(db: &dyn Storage) {
+    db.migrate(false).unwrap();
+    let alice = db.register_user("kate", "hash-k").unwrap();
+    let bob = db.register_user("leo", "hash-l").unwrap();
+    let private = Conversation::Private(&alice.id, &bob.id);
+
+    let message = db.send_message(&alice.id, &bob.id, "初稿", "private", None, &MessageExtras::default()).unwrap();
+    let other = db.send_message(&bob.id, &alice.id, "收到", "private", None, &MessageExtras::default()).unwrap();
+    let both = vec![alice.id.clone(), bob.id.clone()];
+    db.append_user_events(&both, "friend_message", r#"{"content":"初稿"}"#, Some(&message.id)).unwrap();
+
+    // 编辑保存此前的内容，搜索只匹配新内容
+    db.edit_message(&message.id, "二稿").unwrap();
+    let edited = db.edit_message(&message.id, "定稿").unwrap();
+    assert_eq!(edited.content, "定稿");
+    assert!(edited.edited_at.is_some());
+    let edits: Vec<String> = db.get_message_edits(&message.id).unwrap().into_iter().map(|e| e.content).collect();
+    assert_eq!(edits, ["初稿", "二稿"]);
+    let search = |query| MessageSearch {
+        user_id: &bob.id,
+        query,
+        sender_id: None,
+        conversation: None,
+        since: None,
+        until: None,
+        before: None,
+        limit: 10,
+    };
+    assert!(db.search_messages(&search("初稿")).unwrap().is_empty());
+    assert_eq!(db.search_messages(&search("定稿")).unwrap().len(), 1);
+
+    // 撤回后只剩墓碑：内容、编辑历史和事件中的原文都被清除
+    let recalled = db.recall_message(&message.id, &alice.id).unwrap();
+    assert_eq!(recalled.content, "");
+    assert!(recalled.deleted_at.is_some());
+    assert_eq!(recalled.deleted_by.as_deref(), Some(alice.id.as_str()));
+    assert!(db.get_message_edits(&message.id).unwrap().is_empty());
+    assert!(db.search_messages(&search("定稿")).unwrap().is_empty());
+    assert!(db.get_user_events(&bob.id, 0, 10).unwrap().iter().all(|e| e.payload == "{}"));
+    assert!(matches!(db.recall_message(&message.id, &alice.id), Err(StorageError::NotFound)));
+    assert!(matches!(db.edit_message(&message.id, "复活"), Err(StorageError::NotFound)));
+    let history = db.get_message_history(&bob.id, private, HistoryCursor::Latest, 10).unwrap();
+    assert_eq!(history.len(), 2);
+    assert!(history[0].deleted_at.is_some());
+
+    // 仅对自己删除：自己看不到，对方不受影响
+    assert!(db.hide_message(&other.id, &alice.id).unwrap());
+    assert!(!db.hide_message(&other.id, &alice.id).unwrap());
+    let mine = db.get_message_history(&alice.id, private, HistoryCursor::Latest, 10).unwrap();
+    assert_eq!(mine.iter().map(|m| m.id.as_str()).collect::<Vec<_>>(), [message.id.as_str()]);
+    assert_eq!(db.get_message_history(&bob.id, private, HistoryCursor::Latest, 10).unwrap().len(), 2);
+    let ids = vec![message.id.clone(), other.id.clone()];
+    assert_eq!(db.get_visible_messages(&alice.id, &ids).unwrap().len(), 1);
+    assert_eq!(db.get_visible_messages(&bob.id, &ids).unwrap().len(), 2);
+    db.hide_message(&message.id, &bob.id).unwrap();
+    assert!(db.get_unread_messages(&bob.id).unwrap().is_empty());
+}
+
+// 回复与话题：同一会话校验、话题归属与回复数、话题分页
+fn exercise_message_threads(db: &dyn Storage) {
+    db.migrate(false).unwrap();
+    let alice = db.register_user("mia", "hash-m").unwrap();
+    let bob = db.register_user("noah", "hash-n").unwrap();
+    let carol = db.register_user("olivia", "hash-o").unwrap();
+
+    // 私聊回复只引用，不形成话题
+    let question = db.send_message(&alice.id, &bob.id, "几点开会", "private", None, &MessageExtras::default()).unwrap();
+    let answer = db.send_message(&bob.id, &alice.id, "三点", "private", Some(&question.id), &MessageExtras::default()).unwrap();
+    assert_eq!(answer.reply_to_id.as_deref(), Some(question.id.as_str()));
+    assert!(answer.thread_root_id.is_none());
+    assert!(matches!(
+        db.send_message(&carol.id, &bob.id, "插话", "private", Some(&question.id), &MessageExtras::default()),
+        Err(StorageError::NotFound)
+    ));
+
+    // 群聊回复归入根消息的话题，回复的回复仍在同一话题
+    let group = db.create_group(&alice.id, "话题", std::slice::from_ref(&bob.id)).unwrap();
+    let other = db.create_group(&alice.id, "别的群", &[]).unwrap();
+    let root = db.send_message(&alice.id, &group.id, "周末去哪", "group", None, &MessageExtras::default()).unwrap();
+    let first = db.send_message(&bob.id, &group.id, "爬山", "group", Some(&root.id), &MessageExtras::default()).unwrap();
+    let second = db.send_message(&alice.id, &group.id, "好", "group", Some(&first.id), &MessageExtras::default()).unwrap();
+    assert_eq!(first.thread_root_id.as_deref(), Some(root.id.as_str()));
+    assert_eq!(second.thread_root_id.as_deref(), Some(root.id.as_str()));
+    assert_eq!(second.reply_to_id.as_deref(), Some(first.id.as_str()));
+    assert_eq!(db.get_message(&root.id).unwrap().reply_count, 2);
+    assert!(matches!(
+        db.send_message(&alice.id, &other.id, "串群", "group", Some(&root.id), &MessageExtras::default()),
+        Err(StorageError::NotFound)
+    ));
+    assert!(matches!(
+        db.send_message(&alice.id, &bob.id, "跨会话", "private", Some(&root.id), &MessageExtras::default()),
+        Err(StorageError::NotFound)
+    ));
+
+    // 话题历史只含回复，群历史仍包含全部消息
+    let thread = Conversation::Thread(&root.id);
+    let replies = db.get_message_history(&alice.id, thread, HistoryCursor::Latest, 10).unwrap();
+    assert_eq!(replies.iter().map(|m| m.id.as_str()).collect::<Vec<_>>(), [first.id.as_str(), second.id.as_str()]);
+    let page = db.get_message_history(&alice.id, thread, HistoryCursor::Before(&second.id), 10).unwrap();
+    assert_eq!(page.iter().map(|m| m.id.as_str()).collect::<Vec<_>>(), [first.id.as_str()]);
+    assert!(matches!(
+        db.get_message_history(&alice.id, thread, HistoryCursor::Before(&root.id), 10),
+        Err(StorageError::NotFound)
+    ));
+    assert_eq!(db.get_message_history(&alice.id, Conversation::Group(&group.id), HistoryCursor::Latest, 10).unwrap().len(), 3);
+
+    // 不能回复已撤回的消息
+    db.recall_message(&first.id, &bob.id).unwrap();
+    assert!(matches!(
+        db.send_message(&alice.id, &group.id, "还在吗", "group", Some(&first.id), &MessageExtras::default()),
+        Err(StorageError::NotFound)
+    ));
+}
+
+// 表情回应：去重、汇总与撤回后清除
+fn exercise_reactions(db: &dyn Storage) {
+    db.migrate(false).unwrap();
+    let alice = db.register_user("paul", "hash-p").unwrap();
+    let bob = db.register_user("quinn", "hash-q").unwrap();
+    let first = db.send_message(&alice.id, &bob.id, "好消息", "private", None, &MessageExtras::default()).unwrap();
+    let second = db.send_message(&bob.id, &alice.id, "太好了", "private", None, &MessageExtras::default()).unwrap();
+
+    assert!(db.add_reaction(&first.id, &alice.id, "👍").unwrap());
+    assert!(!db.add_reaction(&first.id, &alice.id, "👍").unwrap());
+    db.add_reaction(&first.id, &bob.id, "👍").unwrap();
+    db.add_reaction(&first.id, &bob.id, "🎉").unwrap();
+    db.add_reaction(&second.id, &alice.id, "❤️").unwrap();
+
+    let ids = vec![first.id.clone(), second.id.clone()];
+    let mut summary: Vec<(String, String, i64, bool)> = db.get_reactions(&alice.id, &ids).unwrap()
+        .into_iter()
+        .map(|r| (r.message_id, r.emoji, r.count, r.reacted))
+        .collect();
+    summary.sort();
+    let mut expected = vec![
+        (first.id.clone(), "👍".to_string(), 2, true),
+        (first.id.clone(), "🎉".to_string(), 1, false),
+        (second.id.clone(), "❤️".to_string(), 1, true),
+    ];
+    expected.sort();
+    assert_eq!(summary, expected);
+
+    assert!(db.remove_reaction(&first.id, &alice.id, "👍").unwrap());
+    assert!(!db.remove_reaction(&first.id, &alice.id, "👍").unwrap());
+    let first_only = db.get_reactions(&alice.id, std::slice::from_ref(&first.id)).unwrap();
+    assert_eq!(first_only[0].count, 1);
+    assert!(!first_only[0].reacted);
+
+    // 撤回后回应一并清除
+    db.recall_message(&first.id, &alice.id).unwrap();
+    assert!(db.get_reactions(&alice.id, std::slice::from_ref(&first.id)).unwrap().is_empty());
+}
+
+fn exercise_mentions(db: &dyn Storage) {
+    db.migrate(false).unwrap();
+    let owner = db.register_user("rose", "hash-r").unwrap();
+    let bob = db.register_user("sam", "hash-s").unwrap();
+    let carol = db.register_user("tina", "hash-t").unwrap();
+    let outsider = db.register_user("uma", "hash-u").unwrap();
+    let group = db.create_group(&owner.id, "读书会", &[bob.id.clone(), carol.id.clone()]).unwrap();
+
+    // 非成员被忽略，@全体成员展开为除发送者外的全部成员
+    let to_bob = MessageExtras { mentions: vec![bob.id.clone(), outsider.id.clone()], ..Default::default() };
+    let direct = db.send_message(&owner.id, &group.id, "@sam 看一下", "group", None, &to_bob).unwrap();
+    assert_eq!(direct.mentions, vec![bob.id.clone(), outsider.id.clone()]);
+    let everyone = db.send_message(&owner.id, &group.id, "@全体成员 开会", "group", None, &MessageExtras { mention_all: true, ..Default::default() }).unwrap();
+    assert!(db.get_message(&everyone.id).unwrap().mention_all);
+    db.send_message(&owner.id, &group.id, "没有@", "group", None, &MessageExtras::default()).unwrap();
+
+    let ids = |messages: Vec<server::Message>| messages.into_iter().map(|m| m.id).collect::<Vec<_>>();
+    assert_eq!(ids(db.get_mentions(&bob.id, None, 10).unwrap()), vec![everyone.id.clone(), direct.id.clone()]);
+    assert_eq!(ids(db.get_mentions(&bob.id, Some(&everyone.id), 10).unwrap()), vec![direct.id.clone()]);
+    assert_eq!(ids(db.get_mentions(&carol.id, None, 10).unwrap()), vec![everyone.id.clone()]);
+    assert!(db.get_mentions(&owner.id, None, 10).unwrap().is_empty());
+    assert!(db.get_mentions(&outsider.id, None, 10).unwrap().is_empty());
+    assert!(matches!(db.get_mentions(&bob.id, Some("missing"), 10), Err(StorageError::NotFound)));
+
+    // 撤回、自己删除和退群后不再出现
+    db.recall_message(&direct.id, &owner.id).unwrap();
+    db.hide_message(&everyone.id, &carol.id).unwrap();
+    assert_eq!(ids(db.get_mentions(&bob.id, None, 10).unwrap()), vec![everyone.id.clone()]);
+    assert!(db.get_mentions(&carol.id, None, 10).unwrap().is_empty());
+    db.remove_group_member(&group.id, &bob.id).unwrap();
+    assert!(db.get_mentions(&bob.id, None, 10).unwrap().is_empty());
+
+    // 免打扰只对自己生效
+    db.set_group_muted(&group.id, &carol.id, true).unwrap();
+    let members = db.get_group_members(&group.id).unwrap();
+    assert!(members.iter().all(|m| m.muted == (m.user_id == carol.id)));
+    assert!(db.get_user_groups(&carol.id).unwrap()[0].1.muted);
+    assert!(matches!(db.set_group_muted(&group.id, &outsider.id, true), Err(StorageError::NotFound)));
+}
+
+fn exercise_attachments(db: &dyn Storage) {
+    db.migrate(false).unwrap();
+    let alice = db.register_user("vera", "hash-v").unwrap();
+    let bob = db.register_user("walt", "hash-w").unwrap();
+    let carol = db.register_user("xena", "hash-x").unwrap();
+    let info = ImageInfo { width: 640, height: 480, blurhash: "LEHV6nWB2yk8pyo0adR*.7kCMdnj".into() };
+    let photo = db.create_attachment(&alice.id, &NewAttachment {
+        kind: "image".into(),
+        mime_type: "image/png".into(),
+        size: 1024,
+        filename: "猫.png".into(),
+        sha256: "a".repeat(64),
+        image: Some(info.clone()),
+    }).unwrap();
+    let doc = db.create_attachment(&alice.id, &NewAttachment {
+        kind: "file".into(),
+        mime_type: "application/pdf".into(),
+        size: 2048,
+        filename: "notes.pdf".into(),
+        sha256: "b".repeat(64),
+        image: None,
+    }).unwrap();
+    let stored = db.get_attachment(&photo.id).unwrap();
+    assert_eq!(stored.filename, "猫.png");
+    assert_eq!((stored.width, stored.height), (Some(640), Some(480)));
+    assert_eq!(stored.blurhash, Some(info.blurhash));
+    assert_eq!(db.get_attachment(&doc.id).unwrap().width, None);
+    assert!(matches!(db.get_attachment("missing"), Err(StorageError::NotFound)));
+
+    // 未发送前只有上传者可以访问
+    assert!(db.can_access_attachment(&alice.id, &photo.id).unwrap());
+    assert!(!db.can_access_attachment(&bob.id, &photo.id).unwrap());
+    assert!(!db.can_access_attachment(&alice.id, "missing").unwrap());
+
+    let extras = MessageExtras { attachment_ids: vec![photo.id.clone()], ..Default::default() };
+    let message = db.send_message(&alice.id, &bob.id, "", "private", None, &extras).unwrap();
+    assert_eq!(db.get_message(&message.id).unwrap().attachments, vec![photo.id.clone()]);
+    assert!(db.can_access_attachment(&bob.id, &photo.id).unwrap());
+    assert!(!db.can_access_attachment(&carol.id, &photo.id).unwrap());
+
+    let group = db.create_group(&alice.id, "相册", std::slice::from_ref(&carol.id)).unwrap();
+    let extras = MessageExtras { attachment_ids: vec![doc.id.clone()], ..Default::default() };
+    db.send_message(&alice.id, &group.id, "资料", "group", None, &extras).unwrap();
+    assert!(db.can_access_attachment(&carol.id, &doc.id).unwrap());
+    assert!(!db.can_access_attachment(&bob.id, &doc.id).unwrap());
+    db.remove_group_member(&group.id, &carol.id).unwrap();
+    assert!(!db.can_access_attachment(&carol.id, &doc.id).unwrap());
+
+    // 撤回后会话参与者失去访问权限
+    let recalled = db.recall_message(&message.id, &alice.id).unwrap();
+    assert!(recalled.attachments.is_empty());
+    assert!(!db.can_access_attachment(&bob.id, &photo.id).unwrap());
+    assert!(db.can_access_attachment(&alice.id, &photo.id).unwrap());
+}
+
+fn exercise_blobs(db: &dyn Storage) {
+    db.migrate(false).unwrap();
+    let alice = db.register_user("zack", "hash-z").unwrap();
+    let bob = db.register_user("amy", "hash-a").unwrap();
+    let (cat, dog) = ("c".repeat(64), "d".repeat(64));
+
+    // 相同内容的多个附件共享同一个文件
+    let new = NewAttachment {
+        kind: "image".into(),
+        mime_type: "image/png".into(),
+        size: 10,
+        filename: "cat.png".into(),
+        sha256: cat.clone(),
+        image: None,
+    };
+    let sent = db.create_attachment(&alice.id, &new).unwrap();
+    let unsent = db.create_attachment(&bob.id, &NewAttachment { filename: "copy.png".into(), ..new }).unwrap();
+    assert_eq!(unsent.sha256.as_deref(), Some(cat.as_str()));
+    assert_eq!(db.get_blob(&cat).unwrap().ref_count, 2);
+    let extras = MessageExtras { attachment_ids: vec![sent.id.clone()], ..Default::default() };
+    db.send_message(&alice.id, &bob.id, "", "private", None, &extras).unwrap();
+
+    // 头像替换后释放旧文件的引用
+    assert_eq!(db.update_user_avatar(&alice.id, "/uploads/avatars/c.png", &cat, 10).unwrap(), "");
+    assert_eq!(db.get_blob(&cat).unwrap().ref_count, 3);
+    assert_eq!(db.update_user_avatar(&alice.id, "/uploads/avatars/d.png", &dog, 20).unwrap(), "/uploads/avatars/c.png");
+    assert_eq!(db.get_blob(&cat).unwrap().ref_count, 2);
+    assert!(matches!(db.update_user_avatar("missing", "/x", &dog, 20), Err(StorageError::NotFound)));
+    assert!(db.is_avatar_blob(&dog).unwrap());
+    assert!(!db.is_avatar_blob(&cat).unwrap());
+
+    // 只回收未被消息引用的附件；引用数归零的文件要等宽限期过后才删除
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    let later = now + 10;
+    let removed = db.delete_orphan_attachments(later).unwrap();
+    assert_eq!(removed.iter().map(|a| a.id.as_str()).collect::<Vec<_>>(), [unsent.id.as_str()]);
+    assert!(db.get_attachment(&sent.id).is_ok());
+    assert!(matches!(db.get_attachment(&unsent.id), Err(StorageError::NotFound)));
+    assert_eq!(db.get_blob(&cat).unwrap().ref_count, 1);
+    assert!(db.list_unreferenced_blobs(later).unwrap().is_empty());
+
+    db.update_user_avatar(&alice.id, "/uploads/avatars/c.png", &cat, 10).unwrap();
+    let dog_blob = db.get_blob(&dog).unwrap();
+    assert_eq!(dog_blob.ref_count, 0);
+    assert!(dog_blob.unreferenced_since.is_some());
+    assert!(db.list_unreferenced_blobs(now - 10).unwrap().is_empty());
+    let unreferenced = db.list_unreferenced_blobs(later).unwrap();
+    assert_eq!((unreferenced.len(), unreferenced[0].size), (1, 20));
+    // 只删除引用数仍为零的记录
+    assert!(!db.delete_unreferenced_blob(&cat).unwrap());
+    assert!(db.delete_unreferenced_blob(&dog).unwrap());
+    assert!(!db.delete_unreferenced_blob(&dog).unwrap());
+    assert!(matches!(db.get_blob(&dog), Err(StorageError::NotFound)));
+    assert_eq!(db.get_blob(&cat).unwrap().ref_count, 2);
+
+    // 放入文件前登记的记录引用数为零，可以被回收；已有的记录不受影响
+    let bird = "b".repeat(64);
+    db.register_blob(&bird, 7).unwrap();
+    db.register_blob(&cat, 99).unwrap();
+    let bird_blob = db.get_blob(&bird).unwrap();
+    assert_eq!(bird_blob.ref_count, 0);
+    assert!(bird_blob.unreferenced_since.is_some());
+    let cat_blob = db.get_blob(&cat).unwrap();
+    assert_eq!((cat_blob.ref_count, cat_blob.size), (2, 10));
+    assert_eq!(db.list_unreferenced_blobs(later).unwrap().len(), 1);
+}
+
+fn exercise_uploads(db: &dyn Storage) {
+    db.migrate(false).unwrap();
+    let alice = db.register_user("yuri", "hash-y").unwrap();
+    let upload = db.create_upload(&alice.id, "video.mp4", 100, None, 1_000).unwrap();
+    let stale = db.create_upload(&alice.id, "old.bin", 10, Some("ab"), 10).unwrap();
+    assert_eq!(upload.received, 0);
+    assert_eq!(db.get_upload(&stale.id).unwrap().sha256.as_deref(), Some("ab"));
+    assert!(matches!(db.get_upload("missing"), Err(StorageError::NotFound)));
+
+    // 只有起始位置与已接收字节数一致时才能推进，且不能超过声明的大小
+    assert!(db.advance_upload(&upload.id, 0, 40, 2_000).unwrap());
+    assert!(!db.advance_upload(&upload.id, 0, 40, 2_000).unwrap());
+    assert!(!db.advance_upload(&upload.id, 40, 101, 2_000).unwrap());
+    let current = db.get_upload(&upload.id).unwrap();
+    assert_eq!((current.received, current.expires_at), (40, 2_000));
+
+    // 清理只删除过期的上传
+    assert_eq!(db.delete_expired_uploads(500).unwrap(), vec![stale.id.clone()]);
+    assert!(matches!(db.get_upload(&stale.id), Err(StorageError::NotFound)));
+    assert!(db.delete_upload(&upload.id).unwrap());
+    assert!(!db.delete_upload(&upload.id).unwrap());
+}
+
+#[test]
+fn sqlite_users_and_friends() {
+    let file = TempSqlite::new();
+    exercise_users_and_friends(&SqliteStorage::open(&file.0).unwrap());
+}
+
+#[test]
+fn sqlite_groups() {
+    let file = TempSqlite::new();
+    exercise_groups(&SqliteStorage::open(&file.0).unwrap());
+}
+
+#[test]
+fn sqlite_message_history() {
+    let file = TempSqlite::new();
+    exercise_message_history(&SqliteStorage::open(&file.0).unwrap());
+}
+
+#[test]
+fn sqlite_message_search() {
+    let file = TempSqlite::new();
+    exercise_message_search(&SqliteStorage::open(&file.0).unwrap());
+}
+
+#[test]
+fn sqlite_receipts() {
+    let file = TempSqlite::new();
+    exercise_receipts(&SqliteStorage::open(&file.0).unwrap());
+}
+
+#[test]
+fn sqlite_sessions() {
+    let file = TempSqlite::new();
+    exercise_sessions(&SqliteStorage::open(&file.0).unwrap());
+}
+
+#[test]
+fn sqlite_user_events() {
+    let file = TempSqlite::new();
+    exercise_user_events(&SqliteStorage::open(&file.0).unwrap());
+}
+
+#[test]
+fn sqlite_message_edits() {
+    let file = TempSqlite::new();
+    exercise_message_edits(&SqliteStorage::open(&file.0).unwrap());
+}
+
+#[test]
+fn sqlite_message_threads() {
+    let file = TempSqlite::new();
+    exercise_message_threads(&SqliteStorage::open(&file.0).unwrap());
+}
+
+#[test]
+fn sqlite_reactions() {
+    let file = TempSqlite::new();
+    exercise_reactions(&SqliteStorage::open(&file.0).unwrap());
+}
+
+#[test]
+fn sqlite_mentions() {
+    let file = TempSqlite::new();
+    exercise_mentions(&SqliteStorage::open(&file.0).unwrap());
+}
+
+#[test]
+fn sqlite_attachments() {
+    let file = TempSqlite::new();
+    exercise_attachments(&SqliteStorage::open(&file.0).unwrap());
+}
+
+#[test]
+fn sqlite_blobs() {
+    let file = TempSqlite::new();
+    exercise_blobs(&SqliteStorage::open(&file.0).unwrap());
+}
+
+#[test]
+fn sqlite_uploads() {
+    let file = TempSqlite::new();
+    exercise_uploads(&SqliteStorage::open(&file.0).unwrap());
+}
+
+#[test]
+fn postgres_users_and_friends() {
+    let Some(database) = TempPostgres::new() else {
+        eprintln!("未设置 YUELING_TEST_POSTGRES_URL，跳过 PostgreSQL 测试");
+        return;
+    };
+    let storage = PostgresStorage::open(&database.url()).unwrap();
+    exercise_users_and_friends(&storage);
+}
+
+#[test]
+fn postgres_groups() {
+    let Some(database) = TempPostgres::new() else {
+        eprintln!("未设置 YUELING_TEST_POSTGRES_URL，跳过 PostgreSQL 测试");
+        return;
+    };
+    let storage = PostgresStorage::open(&database.url()).unwrap();
+    exercise_groups(&storage);
+}
+
+#[test]
+fn postgres_message_history() {
+    let Some(database) = TempPostgres::new() else {
+        eprintln!("未设置 YUELING_TEST_POSTGRES_URL，跳过 PostgreSQL 测试");
+        return;
+    };
+    let storage = PostgresStorage::open(&database.url()).unwrap();
+    exercise_message_history(&storage);
+}
+
+#[test]
+fn postgres_message_search() {
+    let Some(database) = TempPostgres::new() else {
+        eprintln!("未设置 YUELING_TEST_POSTGRES_URL，跳过 PostgreSQL 测试");
+        return;
+    };
+    let storage = PostgresStorage::open(&database.url()).unwrap();
+    exercise_message_search(&storage);
+}
+
+#[test]
+fn postgres_receipts() {
+    let Some(database) = TempPostgres::new() else {
+        eprintln!("未设置 YUELING_TEST_POSTGRES_URL，跳过 PostgreSQL 测试");
+        return;
+    };
+    let storage = PostgresStorage::open(&database.url()).unwrap();
+    exercise_receipts(&storage);
+}
+
+#[test]
+fn postgres_sessions() {
+    let Some(database) = TempPostgres::new() else {
+        eprintln!("未设置 YUELING_TEST_POSTGRES_URL，跳过 PostgreSQL 测试");
+        return;
+    };
+    let storage = PostgresStorage::open(&database.url()).unwrap();
+    exercise_sessions(&storage);
+}
+
+#[test]
+fn postgres_user_events() {
+    let Some(database) = TempPostgres::new() else {
+        eprintln!("未设置 YUELING_TEST_POSTGRES_URL，跳过 PostgreSQL 测试");
+        return;
+    };
+    let storage = PostgresStorage::open(&database.url()).unwrap();
+    exercise_user_events(&storage);
+}
+
+#[test]
+fn postgres_message_edits() {
+    let Some(database) = TempPostgres::new() else {
+        eprintln!("未设置 YUELING_TEST_POSTGRES_URL，跳过 PostgreSQL 测试");
+        return;
+    };
+    let storage = PostgresStorage::open(&database.url()).unwrap();
+    exercise_message_edits(&storage);
+}
+
+#[test]
+fn postgres_message_threads() {
+    let Some(database) = TempPostgres::new() else {
+        eprintln!("未设置 YUELING_TEST_POSTGRES_URL，跳过 PostgreSQL 测试");
+        return;
+    };
+    let storage = PostgresStorage::open(&database.url()).unwrap();
+    exercise_message_threads(&storage);
+}
+
+#[test]
+fn postgres_reactions() {
+    let Some(database) = TempPostgres::new() else {
+        eprintln!("未设置 YUELING_TEST_POSTGRES_URL，跳过 PostgreSQL 测试");
+        return;
+    };
+    let storage = PostgresStorage::open(&database.url()).unwrap();
+    exercise_reactions(&storage);
+}
+
+#[test]
+fn postgres_mentions() {
+    let Some(database) = TempPostgres::new() else {
+        eprintln!("未设置 YUELING_TEST_POSTGRES_URL，跳过 PostgreSQL 测试");
+        return;
+    };
+    let storage = PostgresStorage::open(&database.url()).unwrap();
+    exercise_mentions(&storage);
+}
+
+#[test]
+fn postgres_attachments() {
+    let Some(database) = TempPostgres::new() else {
+        eprintln!("未设置 YUELING_TEST_POSTGRES_URL，跳过 PostgreSQL 测试");
+        return;
+    };
+    let storage = PostgresStorage::open(&database.url()).unwrap();
+    exercise_attachments(&storage);
+}
+
+#[test]
+fn postgres_blobs() {
+    let Some(database) = TempPostgres::new() else {
+        eprintln!("未设置 YUELING_TEST_POSTGRES_URL，跳过 PostgreSQL 测试");
+        return;
+    };
+    let storage = PostgresStorage::open(&database.url()).unwrap();
+    exercise_blobs(&storage);
+}
+
+#[test]
+fn postgres_uploads() {
+    let Some(database) = TempPostgres::new() else {
+        eprintln!("未设置 YUELING_TEST_POSTGRES_URL，跳过 PostgreSQL 测试");
+        return;
+    };
+    let storage = PostgresStorage::open(&database.url()).unwrap();
+    exercise_uploads(&storage);
+}
+
+#[test]
+fn postgres_dry_run_leaves_database_unchanged() {
+    let Some(database) = TempPostgres::new() else {
+        eprintln!("未设置 YUELING_TEST_POSTGRES_URL，跳过 PostgreSQL 测试");
+        return;
+    };
+    let storage = PostgresStorage::open(&database.url()).unwrap();
+    assert_eq!(storage.migrate(true).unwrap().len(), server::postgres::migrations::MIGRATIONS.len());
+    assert_eq!(storage.schema_version().unwrap(), 0);
+    assert_eq!(storage.migrate(false).unwrap().len(), server::postgres::migrations::MIGRATIONS.len());
+    assert!(storage.migrate(false).unwrap().is_empty());
+}
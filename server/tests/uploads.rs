@@ -0,0 +1,132 @@
+mod common;
+
+use std::time::Duration;
+use axum::body::{
+    Body,
+    Bytes
+};
+use common::TestApp;
+use server::{
+    blobs,
+    uploads,
+    AppError
+};
+
+fn now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
+
+/// 逐字节慢慢发送的请求体，让并发请求的写入有机会交错
+fn slow_body(content: &'static [u8]) -> Body {
+    let stream = futures_util::stream::unfold(0, move |i| async move {
+        if i == content.len() {
+            return None;
+        }
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        Some((Ok::<_, std::io::Error>(Bytes::from_static(&content[i..i + 1])), i + 1))
+    });
+    Body::from_stream(stream)
+}
+
+#[tokio::test]
+async fn appends_chunks_in_order_and_completes() {
+    let app = TestApp::new();
+    let user = app.state.db_pool.register_user("up-user", "hash").unwrap();
+    let other = app.state.db_pool.register_user("up-other", "hash").unwrap();
+    let content = b"hello, resumable world";
+    let sha256 = blobs::sha256_hex(content);
+
+    let upload = uploads::create_upload(&app.state, &user.id, "notes.txt", content.len() as i64, Some(&sha256)).await.unwrap();
+    let upload = uploads::append_chunk(&app.state, &user.id, &upload.id, 0, Body::from(&content[..10])).await.unwrap();
+    assert_eq!(upload.received, 10);
+
+    // 位置不匹配（重复发送或跳过）返回 Conflict，进度不变
+    for offset in [0, 5, 12] {
+        let result = uploads::append_chunk(&app.state, &user.id, &upload.id, offset, Body::from(&content[..2])).await;
+        assert!(matches!(result, Err(AppError::Conflict(_))), "offset {}", offset);
+    }
+    // 超出声明的大小
+    let result = uploads::append_chunk(&app.state, &user.id, &upload.id, 10, Body::from(vec![b'x'; 40])).await;
+    assert!(matches!(result, Err(AppError::BadRequest(_))));
+    assert_eq!(uploads::upload_progress(&app.state, &user.id, &upload.id).await.unwrap().received, 10);
+
+    // 其他用户看不到这个上传；未收完时不能完成
+    assert!(matches!(uploads::upload_progress(&app.state, &other.id, &upload.id).await, Err(AppError::NotFound(_))));
+    assert!(matches!(uploads::complete_upload(&app.state, &user.id, &upload.id, None).await, Err(AppError::Conflict(_))));
+
+    let upload = uploads::append_chunk(&app.state, &user.id, &upload.id, 10, Body::from(&content[10..])).await.unwrap();
+    assert_eq!(upload.received, content.len() as i64);
+    let attachment = uploads::complete_upload(&app.state, &user.id, &upload.id, None).await.unwrap();
+    assert_eq!(attachment.sha256.as_deref(), Some(sha256.as_str()));
+    assert_eq!((attachment.kind.as_str(), attachment.filename.as_str()), ("file", "notes.txt"));
+    assert_eq!(std::fs::read(blobs::blob_path(&app.state.settings.storage, &sha256)).unwrap(), content);
+    assert!(!uploads::partial_path(&app.state.settings, &upload.id).exists());
+    assert!(matches!(uploads::upload_progress(&app.state, &user.id, &upload.id).await, Err(AppError::NotFound(_))));
+}
+
+#[tokio::test]
+async fn discards_upload_on_sha256_mismatch() {
+    let app = TestApp::new();
+    let user = app.state.db_pool.register_user("up-sha", "hash").unwrap();
+
+    let upload = uploads::create_upload(&app.state, &user.id, "a.bin", 4, None).await.unwrap();
+    uploads::append_chunk(&app.state, &user.id, &upload.id, 0, Body::from("abcd")).await.unwrap();
+    assert!(matches!(uploads::complete_upload(&app.state, &user.id, &upload.id, None).await, Err(AppError::BadRequest(_))));
+    assert!(matches!(uploads::complete_upload(&app.state, &user.id, &upload.id, Some("not-hex")).await, Err(AppError::BadRequest(_))));
+
+    let wrong = blobs::sha256_hex(b"abce");
+    match uploads::complete_upload(&app.state, &user.id, &upload.id, Some(&wrong)).await {
+        Err(AppError::BadRequest(message)) => assert_eq!(message, "SHA-256 校验失败，上传已丢弃"),
+        other => panic!("SHA-256 不一致时应拒绝: {:?}", other),
+    }
+    assert!(matches!(uploads::upload_progress(&app.state, &user.id, &upload.id).await, Err(AppError::NotFound(_))));
+    assert!(!uploads::partial_path(&app.state.settings, &upload.id).exists());
+
+    // 完成时提供的值与创建时声明的不一致
+    let declared = blobs::sha256_hex(b"abcd");
+    let upload = uploads::create_upload(&app.state, &user.id, "a.bin", 4, Some(&declared)).await.unwrap();
+    uploads::append_chunk(&app.state, &user.id, &upload.id, 0, Body::from("abcd")).await.unwrap();
+    assert!(matches!(uploads::complete_upload(&app.state, &user.id, &upload.id, Some(&wrong)).await, Err(AppError::BadRequest(_))));
+    uploads::complete_upload(&app.state, &user.id, &upload.id, Some(&declared.to_uppercase())).await.unwrap();
+}
+
+#[tokio::test]
+async fn concurrent_chunks_at_the_same_offset_do_not_interleave() {
+    let app = TestApp::new();
+    let user = app.state.db_pool.register_user("up-race", "hash").unwrap();
+    let upload = uploads::create_upload(&app.state, &user.id, "race.txt", 16, None).await.unwrap();
+
+    let (first, second) = tokio::join!(
+        uploads::append_chunk(&app.state, &user.id, &upload.id, 0, slow_body(b"AAAAAAAA")),
+        uploads::append_chunk(&app.state, &user.id, &upload.id, 0, slow_body(b"BBBBBBBB")),
+    );
+    let winner: &[u8] = match (&first, &second) {
+        (Ok(_), Err(AppError::Conflict(_))) => b"AAAAAAAA",
+        (Err(AppError::Conflict(_)), Ok(_)) => b"BBBBBBBB",
+        other => panic!("应恰好有一个请求成功: {:?}", other),
+    };
+    assert_eq!(uploads::upload_progress(&app.state, &user.id, &upload.id).await.unwrap().received, 8);
+    assert_eq!(std::fs::read(uploads::partial_path(&app.state.settings, &upload.id)).unwrap(), winner);
+}
+
+#[tokio::test]
+async fn sweeps_expired_uploads() {
+    let app = TestApp::new();
+    let user = app.state.db_pool.register_user("up-expiry", "hash").unwrap();
+    let live = uploads::create_upload(&app.state, &user.id, "live.bin", 4, None).await.unwrap();
+    let stale = app.state.db_pool.create_upload(&user.id, "stale.bin", 4, None, now() - 1).unwrap();
+    std::fs::write(uploads::partial_path(&app.state.settings, &stale.id), b"ab").unwrap();
+
+    // 过期的上传立即不可用，清理后文件一并删除
+    assert!(matches!(uploads::upload_progress(&app.state, &user.id, &stale.id).await, Err(AppError::NotFound(_))));
+    assert_eq!(uploads::sweep_expired_uploads(&app.state).await.unwrap(), 1);
+    assert!(!uploads::partial_path(&app.state.settings, &stale.id).exists());
+    assert!(uploads::partial_path(&app.state.settings, &live.id).exists());
+    assert_eq!(uploads::sweep_expired_uploads(&app.state).await.unwrap(), 0);
+
+    uploads::cancel_upload(&app.state, &user.id, &live.id).await.unwrap();
+    assert!(!uploads::partial_path(&app.state.settings, &live.id).exists());
+}